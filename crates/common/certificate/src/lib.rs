@@ -77,6 +77,38 @@ impl PemCertificate {
             .map_err(CertificateError::X509Error)
     }
 
+    /// The validity status of this certificate, now
+    pub fn validity_status(&self) -> Result<ValidityStatus, CertificateError> {
+        self.validity_status_at(OffsetDateTime::now_utc())
+    }
+
+    /// The validity status of this certificate at the given time
+    pub fn validity_status_at(
+        &self,
+        now: OffsetDateTime,
+    ) -> Result<ValidityStatus, CertificateError> {
+        let x509 = PemCertificate::extract_certificate(&self.pem)?;
+        let validity = &x509.tbs_certificate.validity;
+        let now = now.unix_timestamp();
+        let not_before = validity.not_before.timestamp();
+        let not_after = validity.not_after.timestamp();
+
+        let status = if now < not_before {
+            ValidityStatus::NotValidYet {
+                valid_in: seconds_between(now, not_before),
+            }
+        } else if now > not_after {
+            ValidityStatus::Expired {
+                since: seconds_between(not_after, now),
+            }
+        } else {
+            ValidityStatus::Valid {
+                expires_in: seconds_between(now, not_after),
+            }
+        };
+        Ok(status)
+    }
+
     pub fn thumbprint(&self) -> Result<String, CertificateError> {
         let bytes = Sha1::digest(&self.pem.contents).as_slice().to_vec();
         let strs: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
@@ -85,7 +117,7 @@ impl PemCertificate {
 
     fn extract_certificate(
         pem: &x509_parser::pem::Pem,
-    ) -> Result<x509_parser::certificate::X509Certificate, CertificateError> {
+    ) -> Result<x509_parser::certificate::X509Certificate<'_>, CertificateError> {
         let x509 = pem.parse_x509().map_err(|err| {
             // The x509 error is wrapped into a `nom::Err`
             // and cannot be extracted without pattern matching on that type
@@ -103,6 +135,60 @@ impl PemCertificate {
     }
 }
 
+/// The validity status of a certificate at a given time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidityStatus {
+    /// The certificate is valid and will expire after the given duration
+    Valid { expires_in: std::time::Duration },
+
+    /// The certificate has expired since the given duration
+    Expired { since: std::time::Duration },
+
+    /// The certificate will only be valid after the given duration
+    NotValidYet { valid_in: std::time::Duration },
+}
+
+impl ValidityStatus {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, ValidityStatus::Valid { .. })
+    }
+}
+
+impl std::fmt::Display for ValidityStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidityStatus::Valid { expires_in } => {
+                write!(f, "valid, expires in {}", HumanDuration(*expires_in))
+            }
+            ValidityStatus::Expired { since } => write!(f, "expired {} ago", HumanDuration(*since)),
+            ValidityStatus::NotValidYet { valid_in } => {
+                write!(f, "not valid for another {}", HumanDuration(*valid_in))
+            }
+        }
+    }
+}
+
+/// Display a duration using its largest unit, e.g. "30 days" or "1 hour"
+struct HumanDuration(std::time::Duration);
+
+impl std::fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let secs = self.0.as_secs();
+        let (count, unit) = match secs {
+            86400.. => (secs / 86400, "day"),
+            3600.. => (secs / 3600, "hour"),
+            60.. => (secs / 60, "minute"),
+            _ => (secs, "second"),
+        };
+        let plural = if count == 1 { "" } else { "s" };
+        write!(f, "{count} {unit}{plural}")
+    }
+}
+
+fn seconds_between(from: i64, to: i64) -> std::time::Duration {
+    std::time::Duration::from_secs(to.saturating_sub(from).unsigned_abs())
+}
+
 pub enum KeyKind {
     /// Create a new key
     New,
@@ -149,7 +235,7 @@ impl KeyCertPair {
         key_kind: &KeyKind,
         not_before: OffsetDateTime,
     ) -> Result<CertificateParams, CertificateError> {
-        let mut params = Self::create_csr_parameters(config, id, key_kind)?;
+        let mut params = Self::create_common_parameters(config, id, key_kind)?;
 
         let not_after = not_before + Duration::days(config.validity_period_days.into());
         params.not_before = not_before;
@@ -158,6 +244,18 @@ impl KeyCertPair {
         // IsCa::SelfSignedOnly is rejected by C8Y with "422 Unprocessable Entity"
         params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
 
+        params.key_usages = config.key_usages.clone();
+        params.extended_key_usages = config.extended_key_usages.clone();
+
+        // Being its own issuer, a self-signed certificate with restricted key usages must be allowed to sign certificates
+        if !params.key_usages.is_empty()
+            && !params
+                .key_usages
+                .contains(&rcgen::KeyUsagePurpose::KeyCertSign)
+        {
+            params.key_usages.push(rcgen::KeyUsagePurpose::KeyCertSign);
+        }
+
         Ok(params)
     }
 
@@ -165,6 +263,28 @@ impl KeyCertPair {
         config: &NewCertificateConfig,
         id: &str,
         key_kind: &KeyKind,
+    ) -> Result<CertificateParams, CertificateError> {
+        let mut params = Self::create_common_parameters(config, id, key_kind)?;
+
+        // rcgen only supports key usages in a certificate: these are given to the CA as requested extensions
+        if !config.key_usages.is_empty() {
+            params
+                .custom_extensions
+                .push(key_usage_extension(&config.key_usages));
+        }
+        if !config.extended_key_usages.is_empty() {
+            params
+                .custom_extensions
+                .push(extended_key_usage_extension(&config.extended_key_usages));
+        }
+
+        Ok(params)
+    }
+
+    fn create_common_parameters(
+        config: &NewCertificateConfig,
+        id: &str,
+        key_kind: &KeyKind,
     ) -> Result<CertificateParams, CertificateError> {
         KeyCertPair::check_identifier(id, config.max_cn_size)?;
         let mut distinguished_name = rcgen::DistinguishedName::new();
@@ -205,6 +325,66 @@ impl KeyCertPair {
     }
 }
 
+const OID_KEY_USAGE: &[u64] = &[2, 5, 29, 15];
+const OID_EXT_KEY_USAGE: &[u64] = &[2, 5, 29, 37];
+
+/// The key usage extension, encoded as in a certificate (RFC 5280, section 4.2.1.3)
+fn key_usage_extension(key_usages: &[rcgen::KeyUsagePurpose]) -> rcgen::CustomExtension {
+    use rcgen::KeyUsagePurpose::*;
+    let mut bits: u16 = 0;
+    for key_usage in key_usages {
+        let index = match key_usage {
+            DigitalSignature => 0,
+            ContentCommitment => 1,
+            KeyEncipherment => 2,
+            DataEncipherment => 3,
+            KeyAgreement => 4,
+            KeyCertSign => 5,
+            CrlSign => 6,
+            EncipherOnly => 7,
+            DecipherOnly => 8,
+        };
+        bits |= 1 << index;
+    }
+
+    // As a DER named bit list, the trailing zero bits are removed
+    let len = (16 - bits.leading_zeros()) as usize;
+    let bytes = bits.reverse_bits().to_be_bytes();
+    let content =
+        yasna::construct_der(|writer| writer.write_bitvec_bytes(&bytes[..len.div_ceil(8)], len));
+
+    let mut extension = rcgen::CustomExtension::from_oid_content(OID_KEY_USAGE, content);
+    extension.set_criticality(true);
+    extension
+}
+
+/// The extended key usage extension, encoded as in a certificate (RFC 5280, section 4.2.1.12)
+fn extended_key_usage_extension(
+    extended_key_usages: &[rcgen::ExtendedKeyUsagePurpose],
+) -> rcgen::CustomExtension {
+    use rcgen::ExtendedKeyUsagePurpose::*;
+    let content = yasna::construct_der(|writer| {
+        writer.write_sequence(|writer| {
+            for extended_key_usage in extended_key_usages {
+                let oid: &[u64] = match extended_key_usage {
+                    Any => &[2, 5, 29, 37, 0],
+                    ServerAuth => &[1, 3, 6, 1, 5, 5, 7, 3, 1],
+                    ClientAuth => &[1, 3, 6, 1, 5, 5, 7, 3, 2],
+                    CodeSigning => &[1, 3, 6, 1, 5, 5, 7, 3, 3],
+                    EmailProtection => &[1, 3, 6, 1, 5, 5, 7, 3, 4],
+                    TimeStamping => &[1, 3, 6, 1, 5, 5, 7, 3, 8],
+                    OcspSigning => &[1, 3, 6, 1, 5, 5, 7, 3, 9],
+                };
+                writer
+                    .next()
+                    .write_oid(&yasna::models::ObjectIdentifier::from_slice(oid));
+            }
+        })
+    });
+
+    rcgen::CustomExtension::from_oid_content(OID_EXT_KEY_USAGE, content)
+}
+
 pub fn translate_rustls_error(err: &(dyn std::error::Error + 'static)) -> Option<CertificateError> {
     if let Some(rustls::Error::InvalidCertificate(inner)) = err.downcast_ref::<rustls::Error>() {
         match inner {
@@ -272,6 +452,15 @@ pub struct NewCertificateConfig {
     pub validity_period_days: u32,
     pub organization_name: String,
     pub organizational_unit_name: String,
+
+    /// The key usages of a self-signed certificate or requested by a CSR (none if empty)
+    ///
+    /// A self-signed certificate with key usages is also given the `KeyCertSign` usage,
+    /// as required for a certificate signing itself.
+    pub key_usages: Vec<rcgen::KeyUsagePurpose>,
+
+    /// The extended key usages of a self-signed certificate or requested by a CSR (none if empty)
+    pub extended_key_usages: Vec<rcgen::ExtendedKeyUsagePurpose>,
 }
 
 impl Default for NewCertificateConfig {
@@ -281,6 +470,8 @@ impl Default for NewCertificateConfig {
             validity_period_days: 365,
             organization_name: "Thin Edge".into(),
             organizational_unit_name: "Test Device".into(),
+            key_usages: vec![],
+            extended_key_usages: vec![],
         }
     }
 }
@@ -290,7 +481,9 @@ mod tests {
     use super::*;
     use std::error::Error;
     use time::macros::datetime;
+    use x509_parser::certification_request::X509CertificationRequest;
     use x509_parser::der_parser::asn1_rs::FromDer;
+    use x509_parser::extensions::ParsedExtension;
 
     impl KeyCertPair {
         fn new_selfsigned_certificate_with_new_key(
@@ -308,17 +501,21 @@ mod tests {
         PemCertificate::from_pem_string(&pem_string).expect("Fail to decode the certificate PEM")
     }
 
-    fn subject_of_csr(keypair: &KeyCertPair) -> String {
+    fn der_of_csr(keypair: &KeyCertPair) -> Vec<u8> {
         let csr = keypair
             .certificate_signing_request_string()
             .expect("Failed to read the CSR string");
 
-        let pem = x509_parser::pem::Pem::iter_from_buffer(csr.as_bytes())
+        x509_parser::pem::Pem::iter_from_buffer(csr.as_bytes())
             .next()
             .unwrap()
-            .expect("Reading PEM block failed");
+            .expect("Reading PEM block failed")
+            .contents
+    }
 
-        x509_parser::certification_request::X509CertificationRequest::from_der(&pem.contents)
+    fn subject_of_csr(keypair: &KeyCertPair) -> String {
+        let der = der_of_csr(keypair);
+        X509CertificationRequest::from_der(&der)
             .unwrap()
             .1
             .certification_request_info
@@ -444,6 +641,127 @@ mod tests {
         assert_eq!(not_after, "Sat, 10 Apr 2021 15:39:57 +0000");
     }
 
+    #[test]
+    fn self_signed_cert_validity_status() {
+        let config = NewCertificateConfig {
+            validity_period_days: 10,
+            ..Default::default()
+        };
+        let birthdate = datetime!(2021-03-31 16:39:57 +00:00);
+        let params = KeyCertPair::create_selfsigned_certificate_parameters(
+            &config,
+            "some-id",
+            &KeyKind::New,
+            birthdate,
+        )
+        .expect("Fail to get a certificate parameters");
+        let keypair = KeyCertPair {
+            certificate: Zeroizing::new(
+                Certificate::from_params(params).expect("Fail to create a certificate"),
+            ),
+        };
+        let pem = pem_of_keypair(&keypair);
+
+        let status = pem
+            .validity_status_at(birthdate - Duration::days(2))
+            .unwrap();
+        assert_eq!(status.to_string(), "not valid for another 2 days");
+        assert!(!status.is_valid());
+
+        let status = pem
+            .validity_status_at(birthdate + Duration::days(4))
+            .unwrap();
+        assert_eq!(status.to_string(), "valid, expires in 6 days");
+        assert!(status.is_valid());
+
+        let status = pem
+            .validity_status_at(birthdate + Duration::days(15))
+            .unwrap();
+        assert_eq!(status.to_string(), "expired 5 days ago");
+        assert!(!status.is_valid());
+    }
+
+    #[test]
+    fn display_valid_status() {
+        let status = ValidityStatus::Valid {
+            expires_in: std::time::Duration::from_secs(30 * 86400),
+        };
+        assert!(status.is_valid());
+        assert_eq!(status.to_string(), "valid, expires in 30 days");
+    }
+
+    #[test]
+    fn display_expired_status() {
+        let status = ValidityStatus::Expired {
+            since: std::time::Duration::from_secs(5 * 86400 + 3600),
+        };
+        assert!(!status.is_valid());
+        assert_eq!(status.to_string(), "expired 5 days ago");
+    }
+
+    #[test]
+    fn display_not_yet_valid_status() {
+        let status = ValidityStatus::NotValidYet {
+            valid_in: std::time::Duration::from_secs(2 * 86400),
+        };
+        assert!(!status.is_valid());
+        assert_eq!(status.to_string(), "not valid for another 2 days");
+    }
+
+    #[test]
+    fn display_status_with_durations_shorter_than_a_day() {
+        let status = ValidityStatus::Valid {
+            expires_in: std::time::Duration::from_secs(3600),
+        };
+        assert_eq!(status.to_string(), "valid, expires in 1 hour");
+
+        let status = ValidityStatus::Expired {
+            since: std::time::Duration::from_secs(90),
+        };
+        assert_eq!(status.to_string(), "expired 1 minute ago");
+    }
+
+    #[test]
+    fn self_signed_cert_with_client_auth_extended_key_usage() {
+        let config = NewCertificateConfig {
+            key_usages: vec![rcgen::KeyUsagePurpose::DigitalSignature],
+            extended_key_usages: vec![rcgen::ExtendedKeyUsagePurpose::ClientAuth],
+            ..Default::default()
+        };
+        let id = "some-id";
+
+        let keypair = KeyCertPair::new_selfsigned_certificate_with_new_key(&config, id)
+            .expect("Fail to create a certificate");
+
+        // Check the key usages
+        let pem = pem_of_keypair(&keypair);
+        let x509 = PemCertificate::extract_certificate(&pem.pem).unwrap();
+        let key_usage = x509.key_usage().unwrap().expect("Missing key usage");
+        assert!(key_usage.value.digital_signature());
+        assert!(key_usage.value.key_cert_sign());
+        assert!(!key_usage.value.key_encipherment());
+
+        let ext_key_usage = x509
+            .extended_key_usage()
+            .unwrap()
+            .expect("Missing extended key usage");
+        assert!(ext_key_usage.value.client_auth);
+        assert!(!ext_key_usage.value.server_auth);
+    }
+
+    #[test]
+    fn self_signed_cert_without_key_usage() {
+        let config = NewCertificateConfig::default();
+        let id = "some-id";
+
+        let keypair = KeyCertPair::new_selfsigned_certificate_with_new_key(&config, id)
+            .expect("Fail to create a certificate");
+
+        let pem = pem_of_keypair(&keypair);
+        let x509 = PemCertificate::extract_certificate(&pem.pem).unwrap();
+        assert!(x509.extended_key_usage().unwrap().is_none());
+    }
+
     #[test]
     fn create_certificate_sign_request() {
         // Create a certificate with a given birthdate.
@@ -464,6 +782,63 @@ mod tests {
         assert_eq!(subject, "CN=some-id, O=Thin Edge, OU=Test Device");
     }
 
+    #[test]
+    fn certificate_sign_request_with_key_usages() {
+        let config = NewCertificateConfig {
+            key_usages: vec![
+                rcgen::KeyUsagePurpose::DigitalSignature,
+                rcgen::KeyUsagePurpose::KeyAgreement,
+            ],
+            extended_key_usages: vec![rcgen::ExtendedKeyUsagePurpose::ClientAuth],
+            ..Default::default()
+        };
+        let id = "some-id";
+
+        let keypair = KeyCertPair::new_certificate_sign_request(&config, id, &KeyKind::New)
+            .expect("Fail to create a CSR");
+
+        let der = der_of_csr(&keypair);
+        let (_, csr) = X509CertificationRequest::from_der(&der).unwrap();
+        let extensions: Vec<_> = csr.requested_extensions().into_iter().flatten().collect();
+        let key_usage = extensions
+            .iter()
+            .find_map(|ext| match ext {
+                ParsedExtension::KeyUsage(key_usage) => Some(key_usage),
+                _ => None,
+            })
+            .expect("Missing key usage");
+        assert!(key_usage.digital_signature());
+        assert!(key_usage.key_agreement());
+        assert!(!key_usage.key_cert_sign());
+
+        let ext_key_usage = extensions
+            .iter()
+            .find_map(|ext| match ext {
+                ParsedExtension::ExtendedKeyUsage(ext_key_usage) => Some(ext_key_usage),
+                _ => None,
+            })
+            .expect("Missing extended key usage");
+        assert!(ext_key_usage.client_auth);
+        assert!(!ext_key_usage.server_auth);
+    }
+
+    #[test]
+    fn certificate_sign_request_without_key_usages() {
+        let config = NewCertificateConfig::default();
+        let id = "some-id";
+
+        let keypair = KeyCertPair::new_certificate_sign_request(&config, id, &KeyKind::New)
+            .expect("Fail to create a CSR");
+
+        let der = der_of_csr(&keypair);
+        let (_, csr) = X509CertificationRequest::from_der(&der).unwrap();
+        let extensions: Vec<_> = csr.requested_extensions().into_iter().flatten().collect();
+        assert!(!extensions.iter().any(|ext| matches!(
+            ext,
+            ParsedExtension::KeyUsage(_) | ParsedExtension::ExtendedKeyUsage(_)
+        )));
+    }
+
     #[test]
     fn check_certificate_thumbprint_b64_decode_sha1() {
         // Create a certificate key pair
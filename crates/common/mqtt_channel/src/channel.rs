@@ -1,9 +1,13 @@
 use crate::MqttError;
 use crate::MqttMessage;
+use crate::Topic;
 use async_trait::async_trait;
 use futures::channel::mpsc;
 use futures::SinkExt;
 use futures::StreamExt;
+use rumqttc::QoS;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
 
 #[async_trait]
 pub trait SubChannel: StreamExt<Item = MqttMessage> + Unpin + Send {}
@@ -14,12 +18,127 @@ pub trait ErrChannel: StreamExt<Item = MqttError> + Unpin + Send {}
 #[async_trait]
 pub trait PubChannel: SinkExt<MqttMessage> + Unpin + Send {
     /// Publish a message - unless the pub channel has been closed.
+    ///
+    /// Nothing is published till the returned future is awaited:
+    ///
+    /// ```
+    /// use mqtt_channel::MqttError;
+    /// use mqtt_channel::MqttMessage;
+    /// use mqtt_channel::PubChannel;
+    /// use mqtt_channel::Topic;
+    ///
+    /// async fn publish_temperature(channel: &mut impl PubChannel) -> Result<(), MqttError> {
+    ///     let topic = Topic::new_unchecked("te/device/main///m/");
+    ///     channel
+    ///         .publish(MqttMessage::new(&topic, r#"{"temperature": 21.3}"#))
+    ///         .await
+    /// }
+    /// ```
+    #[must_use = "the message is only published once the returned future is awaited"]
     async fn publish(&mut self, message: MqttMessage) -> Result<(), MqttError> {
         Ok(self
             .send(message)
             .await
             .map_err(|_| MqttError::SendOnClosedConnection)?)
     }
+
+    /// Publish the bytes read from a stream, chunk by chunk, without buffering the whole payload
+    ///
+    /// The stream is split into consecutive [StreamRecord]s published on the same topic:
+    /// data records, each with a payload of at most `chunk_size` bytes including the record tag,
+    /// terminated by an end record, or by an abort record if the stream cannot be read to the end.
+    /// The `chunk_size` must not exceed the [max_packet_size](crate::Config::max_packet_size) of the receivers,
+    /// which drop the messages with larger payloads.
+    ///
+    /// The receivers have to concatenate the data received till the end record,
+    /// discarding this data on an abort record.
+    /// This is only possible if the chunks are received in order and none is lost:
+    /// - the `qos` must be at least [QoS::AtLeastOnce], as with [QoS::AtMostOnce] chunks might be silently dropped,
+    /// - and there must be a single publisher streaming on that topic at a time,
+    ///   MQTT only ordering the messages published by one client on one topic with the same QoS.
+    ///
+    /// The chunks are never retained, as a retained message would only keep the last chunk.
+    ///
+    /// Return the number of payload bytes published.
+    async fn publish_stream<R>(
+        &mut self,
+        topic: &Topic,
+        qos: QoS,
+        chunk_size: usize,
+        mut stream: R,
+    ) -> Result<usize, MqttError>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let mut published = 0;
+        let mut chunk = vec![0; chunk_size.max(2)];
+        chunk[0] = StreamRecord::DATA;
+        loop {
+            let mut len = 1;
+            while len < chunk.len() {
+                match stream.read(&mut chunk[len..]).await {
+                    Ok(0) => break,
+                    Ok(n) => len += n,
+                    Err(err) => {
+                        let abort = StreamRecord::abort_payload(&err.to_string());
+                        self.publish(MqttMessage::new(topic, abort).with_qos(qos))
+                            .await?;
+                        return Err(err.into());
+                    }
+                }
+            }
+            if len == 1 {
+                break;
+            }
+            published += len - 1;
+            self.publish(MqttMessage::new(topic, &chunk[..len]).with_qos(qos))
+                .await?;
+        }
+        self.publish(MqttMessage::new(topic, [StreamRecord::END]).with_qos(qos))
+            .await?;
+        Ok(published)
+    }
+}
+
+/// A record of a stream of bytes published with [PubChannel::publish_stream]
+///
+/// The kind of record is given by the first byte of the message payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamRecord<'a> {
+    /// A chunk of the stream
+    Data(&'a [u8]),
+
+    /// The stream has been fully published
+    End,
+
+    /// The stream has been aborted, the data received so far being incomplete
+    Abort { reason: &'a str },
+}
+
+impl<'a> StreamRecord<'a> {
+    const DATA: u8 = 0;
+    const END: u8 = 1;
+    const ABORT: u8 = 2;
+
+    /// Parse the payload of a message published by [PubChannel::publish_stream]
+    ///
+    /// Return `None` if the payload is not a stream record.
+    pub fn parse(payload: &'a [u8]) -> Option<Self> {
+        match payload.split_first()? {
+            (&Self::DATA, data) => Some(StreamRecord::Data(data)),
+            (&Self::END, []) => Some(StreamRecord::End),
+            (&Self::ABORT, reason) => Some(StreamRecord::Abort {
+                reason: std::str::from_utf8(reason).ok()?,
+            }),
+            _ => None,
+        }
+    }
+
+    fn abort_payload(reason: &str) -> Vec<u8> {
+        let mut payload = vec![Self::ABORT];
+        payload.extend_from_slice(reason.as_bytes());
+        payload
+    }
 }
 
 #[async_trait]
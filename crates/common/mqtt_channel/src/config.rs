@@ -39,6 +39,15 @@ pub struct Config {
     /// Default: `false`.
     pub clean_session: bool,
 
+    /// Clean the MQTT session on the first connect only, if set to `true`.
+    ///
+    /// The session is then persisted by the broker across the subsequent reconnects.
+    /// This flag has no effect when no session name is provided,
+    /// as the session is then always clean.
+    ///
+    /// Default: `false`.
+    pub clean_session_on_first_connect_only: bool,
+
     /// Capacity of the internal message queues
     ///
     /// Default: `1024`.
@@ -50,6 +59,29 @@ pub struct Config {
     /// Default: `16777216` (16 MB).
     pub max_packet_size: usize,
 
+    /// Maximum number of QoS 0 messages held by the connection while the delivery is paused
+    ///
+    /// Once reached, the oldest QoS 0 messages are dropped to make room for the new ones.
+    /// QoS 1 and QoS 2 messages are not concerned, being held by the broker till acknowledged.
+    ///
+    /// Default: `1024`.
+    pub max_paused_qos0_messages: usize,
+
+    /// Maximum number of messages queued by the connection, waiting to be published
+    ///
+    /// Default: None, i.e. no limit.
+    pub max_queued_messages: Option<QueueLimit>,
+
+    /// Maximum number of topics for which the published messages are counted
+    ///
+    /// Default: None, i.e. no statistics are collected.
+    pub publish_stats_max_topics: Option<usize>,
+
+    /// Topics of the messages to be published before the others while the connection is congested
+    ///
+    /// Default: no topics, i.e. the messages are published in order.
+    pub priority_topics: TopicFilter,
+
     /// LastWill message for a mqtt client
     ///
     /// Default: None
@@ -59,6 +91,36 @@ pub struct Config {
     ///
     /// Default: None
     pub initial_message: Option<InitMessageFn>,
+
+    /// Messages published on connect and then each time the connection is re-established
+    ///
+    /// These are the messages initially registered in the [RepublishList](crate::RepublishList) of the connection.
+    ///
+    /// Default: no messages.
+    pub republished_messages: Vec<MqttMessage>,
+}
+
+/// Limit on the number of messages waiting to be published on a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueLimit {
+    /// Number of messages that can be queued, which must be greater than 0
+    pub capacity: usize,
+
+    /// What to do with a new message when the queue is full
+    pub overflow_policy: OverflowPolicy,
+}
+
+/// What to do with a message published while the queue of messages waiting to be published is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Make the publisher wait for room in the queue
+    Block,
+
+    /// Drop the oldest message of the queue to make room for the new one
+    DropOldest,
+
+    /// Drop the new message
+    DropNewest,
 }
 
 #[derive(Debug, Clone)]
@@ -76,6 +138,41 @@ pub struct BrokerConfig {
 
     /// Certificate authentication configuration
     pub authentication: Option<AuthenticationConfig>,
+
+    /// Username and password sent to the broker on connect
+    ///
+    /// Default: None
+    pub credentials: Option<Credentials>,
+}
+
+/// Username and password authentication
+///
+/// The password is zeroed on drop and never displayed by the `Debug` implementation.
+#[derive(Clone)]
+pub struct Credentials {
+    pub username: String,
+    password: Zeroizing<String>,
+}
+
+impl Credentials {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Credentials {
+            username: username.into(),
+            password: Zeroizing::new(password.into()),
+        }
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+impl Debug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
 }
 
 /// MQTT certificate authentication configuration.
@@ -98,6 +195,22 @@ pub struct AuthenticationConfig {
     client_auth: Option<ClientAuthConfig>,
 }
 
+impl AuthenticationConfig {
+    fn tls_config(&self) -> Result<rustls::ClientConfig, rustls::Error> {
+        let tls_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(self.cert_store.clone());
+
+        match self.client_auth.clone() {
+            Some(client_auth_config) => tls_config.with_client_auth_cert(
+                client_auth_config.cert_chain,
+                client_auth_config.key.deref().0.clone(),
+            ),
+            None => Ok(tls_config.with_no_client_auth()),
+        }
+    }
+}
+
 impl Default for AuthenticationConfig {
     fn default() -> Self {
         AuthenticationConfig {
@@ -161,14 +274,21 @@ impl Default for Config {
                 host: String::from("127.0.0.1"),
                 port: 1883,
                 authentication: None,
+                credentials: None,
             },
             session_name: None,
             subscriptions: TopicFilter::empty(),
             clean_session: false,
+            clean_session_on_first_connect_only: false,
             queue_capacity: 1024,
             max_packet_size: 16 * 1024 * 1024,
+            max_paused_qos0_messages: 1024,
+            max_queued_messages: None,
+            publish_stats_max_topics: None,
+            priority_topics: TopicFilter::empty(),
             last_will_message: None,
             initial_message: None,
+            republished_messages: vec![],
         }
     }
 }
@@ -226,6 +346,14 @@ impl Config {
         }
     }
 
+    /// Set the clean_session_on_first_connect_only flag
+    pub fn with_clean_session_on_first_connect_only(self, flag: bool) -> Self {
+        Self {
+            clean_session_on_first_connect_only: flag,
+            ..self
+        }
+    }
+
     /// Set the queue capacity
     pub fn with_queue_capacity(self, queue_capacity: usize) -> Self {
         Self {
@@ -242,6 +370,59 @@ impl Config {
         }
     }
 
+    /// Set the maximum number of QoS 0 messages held while the delivery is paused
+    ///
+    /// See [PauseHandle](crate::PauseHandle).
+    pub fn with_max_paused_qos0_messages(self, max_paused_qos0_messages: usize) -> Self {
+        Self {
+            max_paused_qos0_messages,
+            ..self
+        }
+    }
+
+    /// Bound the number of messages waiting to be published,
+    /// applying the given policy when there is no more room for a new message.
+    ///
+    /// This caps the memory used while the connection to the broker is stalled.
+    /// The capacity must be greater than 0: otherwise the connection is refused
+    /// with [MqttError::InvalidQueueConfig](crate::MqttError::InvalidQueueConfig).
+    pub fn with_max_queued_messages(
+        self,
+        capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> Self {
+        Self {
+            max_queued_messages: Some(QueueLimit {
+                capacity,
+                overflow_policy,
+            }),
+            ..self
+        }
+    }
+
+    /// Count the messages and bytes published per topic, tracking at most `max_topics` topics
+    ///
+    /// Only the messages successfully handed over to the MQTT client are accounted for.
+    /// These statistics are then available from the [PublishStats](crate::PublishStats) of the connection.
+    pub fn with_publish_stats(self, max_topics: usize) -> Self {
+        Self {
+            publish_stats_max_topics: Some(max_topics),
+            ..self
+        }
+    }
+
+    /// Publish the messages on these topics before the others, while the connection is congested
+    ///
+    /// This lets critical messages, e.g. alarms, jump ahead of bulk telemetry waiting to be published.
+    /// The messages of the same priority are still published in order.
+    /// With [OverflowPolicy::DropOldest], the low-priority messages are dropped first.
+    pub fn with_priority_topics(self, priority_topics: TopicFilter) -> Self {
+        Self {
+            priority_topics,
+            ..self
+        }
+    }
+
     /// Set the last will message, this will be published when the mqtt connection gets closed.
     pub fn with_last_will_message(self, lwm: MqttMessage) -> Self {
         Self {
@@ -261,6 +442,26 @@ impl Config {
         }
     }
 
+    /// Add a message to be published on connect and then each time the connection is re-established
+    ///
+    /// This message replaces any message previously added on the same topic.
+    pub fn with_republished_message(mut self, message: MqttMessage) -> Self {
+        self.republished_messages
+            .retain(|registered| registered.topic != message.topic);
+        self.republished_messages.push(message);
+        self
+    }
+
+    /// Set the username and password used to authenticate with the broker
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.broker.credentials = Some(Credentials::new(username, password));
+        self
+    }
+
     /// Adds all certificates present in `ca_file` file to the trust store.
     /// Enables server authentication.
     pub fn with_cafile(
@@ -316,6 +517,54 @@ impl Config {
         Ok(self)
     }
 
+    /// Set up a TLS connection authenticated with a client certificate
+    ///
+    /// The broker certificate is verified using the CA certificates of `ca_path`,
+    /// which is either a certificate file or a directory of certificate files,
+    /// while `cert_file` and `key_file` are used to authenticate this client.
+    ///
+    /// The TLS settings are checked right away, so a missing or invalid file is reported here,
+    /// rather than on connect. On error, this config is left unchanged.
+    pub fn with_tls(
+        &mut self,
+        ca_path: impl AsRef<Path>,
+        cert_file: impl AsRef<Path>,
+        key_file: impl AsRef<Path>,
+    ) -> Result<&mut Self, CertificateError> {
+        let ca_path = ca_path.as_ref();
+        let cert_file = cert_file.as_ref();
+
+        // The settings are applied to a copy, only kept once fully checked
+        let mut config = self.clone();
+        let known_ca_count = config
+            .broker
+            .authentication
+            .as_ref()
+            .map_or(0, |auth| auth.cert_store.len());
+        if ca_path.is_dir() {
+            config.with_cadir(ca_path)?;
+        } else {
+            config.with_cafile(ca_path)?;
+        }
+        config.with_client_auth(cert_file, key_file.as_ref())?;
+
+        let authentication_config = config.broker.authentication.as_ref().unwrap();
+        if authentication_config.cert_store.len() == known_ca_count {
+            return Err(no_certificate_found(ca_path));
+        }
+        if authentication_config
+            .client_auth
+            .as_ref()
+            .map_or(true, |client_auth| client_auth.cert_chain.is_empty())
+        {
+            return Err(no_certificate_found(cert_file));
+        }
+        authentication_config.tls_config()?;
+
+        *self = config;
+        Ok(self)
+    }
+
     /// Wrap this config into an internal set of options for `rumqttc`.
     pub fn rumqttc_options(&self) -> Result<rumqttc::MqttOptions, rustls::Error> {
         let id = match &self.session_name {
@@ -334,25 +583,19 @@ impl Config {
             // There is no point to have a session with a random name that will not be reused.
             mqtt_options.set_clean_session(true);
         } else {
-            mqtt_options.set_clean_session(self.clean_session);
+            mqtt_options
+                .set_clean_session(self.clean_session || self.clean_session_on_first_connect_only);
         }
 
         if let Some(authentication_config) = &broker_config.authentication {
-            let tls_config = rustls::ClientConfig::builder()
-                .with_safe_defaults()
-                .with_root_certificates(authentication_config.cert_store.clone());
-
-            let tls_config = match authentication_config.client_auth.clone() {
-                Some(client_auth_config) => tls_config.with_client_auth_cert(
-                    client_auth_config.cert_chain,
-                    client_auth_config.key.deref().0.clone(),
-                )?,
-                None => tls_config.with_no_client_auth(),
-            };
-
+            let tls_config = authentication_config.tls_config()?;
             mqtt_options.set_transport(rumqttc::Transport::tls_with_config(tls_config.into()));
         }
 
+        if let Some(credentials) = &broker_config.credentials {
+            mqtt_options.set_credentials(&credentials.username, credentials.password());
+        }
+
         mqtt_options.set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE);
 
         if let Some(lwp) = &self.last_will_message {
@@ -368,3 +611,144 @@ impl Config {
         Ok(mqtt_options)
     }
 }
+
+fn no_certificate_found(path: &Path) -> CertificateError {
+    CertificateError::CertificateParseFailed {
+        path: path.to_owned(),
+        source: std::io::Error::new(std::io::ErrorKind::InvalidData, "no certificate found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Write a self-signed certificate, used both as CA and client certificate, along its private key
+    fn write_cert_and_key(dir: &TempDir) -> (std::path::PathBuf, std::path::PathBuf) {
+        let cert = rcgen::generate_simple_self_signed(["localhost".into()]).unwrap();
+        let cert_file = dir.path().join("cert.pem");
+        let key_file = dir.path().join("key.pem");
+        std::fs::write(&cert_file, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_file, cert.serialize_private_key_pem()).unwrap();
+        (cert_file, key_file)
+    }
+
+    #[test]
+    fn with_credentials_sets_the_username_and_password() {
+        let config = Config::default().with_credentials("tedge", "secret");
+
+        let options = config.rumqttc_options().unwrap();
+        assert_eq!(
+            options.credentials(),
+            Some(("tedge".to_string(), "secret".to_string()))
+        );
+        assert!(!format!("{config:?}").contains("secret"));
+    }
+
+    #[test]
+    fn no_credentials_are_set_by_default() {
+        let options = Config::default().rumqttc_options().unwrap();
+        assert_eq!(options.credentials(), None);
+    }
+
+    #[test]
+    fn with_tls_configures_a_tls_transport() {
+        let dir = TempDir::new().unwrap();
+        let (cert_file, key_file) = write_cert_and_key(&dir);
+
+        let mut config = Config::default();
+        config.with_tls(&cert_file, &cert_file, &key_file).unwrap();
+
+        let options = config.rumqttc_options().unwrap();
+        assert!(matches!(options.transport(), rumqttc::Transport::Tls(_)));
+    }
+
+    #[test]
+    fn with_tls_accepts_a_ca_directory() {
+        let dir = TempDir::new().unwrap();
+        let (cert_file, key_file) = write_cert_and_key(&dir);
+
+        let mut config = Config::default();
+        config.with_tls(dir.path(), &cert_file, &key_file).unwrap();
+
+        let options = config.rumqttc_options().unwrap();
+        assert!(matches!(options.transport(), rumqttc::Transport::Tls(_)));
+    }
+
+    #[test]
+    fn with_tls_rejects_a_missing_key_file() {
+        let dir = TempDir::new().unwrap();
+        let (cert_file, _) = write_cert_and_key(&dir);
+        let missing_key = dir.path().join("missing.pem");
+
+        let err = Config::default()
+            .with_tls(&cert_file, &cert_file, &missing_key)
+            .unwrap_err();
+
+        assert!(matches!(err, CertificateError::IoError { path, .. } if path == missing_key));
+    }
+
+    #[test]
+    fn with_tls_rejects_an_invalid_key_file() {
+        let dir = TempDir::new().unwrap();
+        let (cert_file, key_file) = write_cert_and_key(&dir);
+        std::fs::write(&key_file, "not a key").unwrap();
+
+        let err = Config::default()
+            .with_tls(&cert_file, &cert_file, &key_file)
+            .unwrap_err();
+
+        assert!(matches!(err, CertificateError::UnknownPrivateKeyFormat));
+    }
+
+    #[test]
+    fn with_tls_leaves_the_config_unchanged_on_error() {
+        let dir = TempDir::new().unwrap();
+        let (cert_file, key_file) = write_cert_and_key(&dir);
+        let missing_key = dir.path().join("missing.pem");
+
+        let mut config = Config::default();
+        config
+            .with_tls(&cert_file, &cert_file, &missing_key)
+            .unwrap_err();
+
+        assert!(config.broker.authentication.is_none());
+        let options = config.rumqttc_options().unwrap();
+        assert!(matches!(options.transport(), rumqttc::Transport::Tcp));
+
+        // A config already set up for TLS keeps its previous settings
+        config.with_tls(&cert_file, &cert_file, &key_file).unwrap();
+        let empty_ca = dir.path().join("empty.pem");
+        std::fs::write(&empty_ca, "").unwrap();
+        config
+            .with_tls(&cert_file, &empty_ca, &key_file)
+            .unwrap_err();
+
+        let authentication = config.broker.authentication.as_ref().unwrap();
+        assert_eq!(authentication.cert_store.len(), 1);
+        assert!(authentication.client_auth.is_some());
+    }
+
+    #[test]
+    fn with_tls_rejects_a_file_without_certificates() {
+        let dir = TempDir::new().unwrap();
+        let (cert_file, key_file) = write_cert_and_key(&dir);
+        let empty_ca = dir.path().join("empty.pem");
+        std::fs::write(&empty_ca, "").unwrap();
+
+        let err = Config::default()
+            .with_tls(&empty_ca, &cert_file, &key_file)
+            .unwrap_err();
+        assert!(
+            matches!(err, CertificateError::CertificateParseFailed { path, .. } if path == empty_ca)
+        );
+
+        let err = Config::default()
+            .with_tls(&cert_file, &empty_ca, &key_file)
+            .unwrap_err();
+        assert!(
+            matches!(err, CertificateError::CertificateParseFailed { path, .. } if path == empty_ca)
+        );
+    }
+}
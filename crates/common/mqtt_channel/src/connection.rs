@@ -1,23 +1,37 @@
+use crate::publish_queue::publish_queue;
+use crate::publish_queue::PublishReceiver;
 use crate::Config;
 use crate::ErrChannel;
 use crate::MqttError;
 use crate::MqttMessage;
 use crate::PubChannel;
+use crate::PublishSender;
+use crate::PublishStats;
 use crate::SubChannel;
+use crate::Topic;
+use crate::TopicFilter;
 use futures::channel::mpsc;
 use futures::channel::oneshot;
 use futures::SinkExt;
 use futures::StreamExt;
 use log::error;
 use log::info;
+use log::warn;
 use rumqttc::AsyncClient;
 use rumqttc::Event;
 use rumqttc::EventLoop;
 use rumqttc::Incoming;
 use rumqttc::Outgoing;
 use rumqttc::Packet;
+use rumqttc::Publish;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
+use tokio::sync::watch;
 use tokio::time::sleep;
+use tokio::time::timeout;
 
 /// A connection to some MQTT server
 pub struct Connection {
@@ -25,13 +39,354 @@ pub struct Connection {
     pub received: mpsc::UnboundedReceiver<MqttMessage>,
 
     /// The channel of the output messages to be published on this connection.
-    pub published: mpsc::UnboundedSender<MqttMessage>,
+    ///
+    /// Unbounded, unless a limit is set with [Config::with_max_queued_messages].
+    ///
+    /// Breaking change: this used to be an `mpsc::UnboundedSender<MqttMessage>`.
+    /// A [PublishSender] is still a `Sink<MqttMessage>`, with the same `close_channel` and `is_closed` methods,
+    /// but has no `unbounded_send` method: messages are to be sent with `SinkExt::send` or `PubChannel::publish`.
+    pub published: PublishSender,
 
     /// The channel of the error messages received by this connection.
+    ///
+    /// This includes the messages that cannot be published, see [MqttError::PublishFailure].
     pub errors: mpsc::UnboundedReceiver<MqttError>,
 
     /// A channel to notify that all the published messages have been actually published.
     pub pub_done: oneshot::Receiver<()>,
+
+    /// A handle to pause and resume the delivery of the input messages.
+    pub pause_handle: PauseHandle,
+
+    /// A handle to observe whether this connection is established or not.
+    pub status: ConnectionStatus,
+
+    /// The per-topic statistics of the published messages, if enabled with [Config::with_publish_stats].
+    pub publish_stats: Option<PublishStats>,
+
+    /// The messages to be published again each time the connection is re-established.
+    pub republish_on_reconnect: RepublishList,
+
+    /// A handle to subscribe this connection to new topics, once established.
+    pub subscriptions: SubscriptionHandle,
+
+    /// Tells the sender loop how long to wait for the in-flight messages on close, if at all
+    close_grace_period: oneshot::Sender<Duration>,
+}
+
+/// Count of the QoS 1 and QoS 2 messages published on a connection and not acknowledged yet by the broker
+type InFlightCount = Arc<watch::Sender<usize>>;
+
+fn published_in_flight(in_flight: &InFlightCount, qos: rumqttc::QoS) {
+    if qos != rumqttc::QoS::AtMostOnce {
+        in_flight.send_modify(|count| *count += 1);
+    }
+}
+
+fn acknowledged_in_flight(in_flight: &InFlightCount) {
+    in_flight.send_modify(|count| *count = count.saturating_sub(1));
+}
+
+/// The list of messages published by an MQTT connection each time established or re-established
+///
+/// This list is initialized from [Config::with_republished_message]
+/// and can then be updated at any time by the client,
+/// notably to restore on the broker a state that might have been lost while disconnected.
+///
+/// At most one message is registered per topic:
+/// registering a message on a topic replaces any message previously registered on that topic.
+#[derive(Clone, Debug, Default)]
+pub struct RepublishList {
+    messages: Arc<Mutex<Vec<MqttMessage>>>,
+}
+
+impl RepublishList {
+    /// Register a message to be re-published on reconnect
+    ///
+    /// The message is not published by this call, but only on the next reconnects.
+    pub fn register(&self, message: MqttMessage) {
+        let mut messages = self.messages.lock().unwrap();
+        messages.retain(|registered| registered.topic != message.topic);
+        messages.push(message);
+    }
+
+    /// Stop re-publishing the message registered on that topic, if any
+    pub fn unregister(&self, topic: &Topic) {
+        let mut messages = self.messages.lock().unwrap();
+        messages.retain(|registered| &registered.topic != topic);
+    }
+
+    /// The messages currently registered, in registration order
+    pub fn messages(&self) -> Vec<MqttMessage> {
+        self.messages.lock().unwrap().clone()
+    }
+}
+
+/// A handle to pause and resume the delivery of the messages received by an MQTT connection
+///
+/// While paused, the connection is still polled, so the session is kept alive (pings are exchanged),
+/// but the received messages are neither delivered to the client nor acknowledged to the broker.
+/// This provides MQTT-level back-pressure:
+/// - QoS 1 and QoS 2 messages are held by the broker once its inflight window for this client
+///   (e.g. mosquitto `max_inflight_messages`) is full of unacknowledged messages.
+///   These pending messages are then queued by the broker and not in memory by the client.
+/// - QoS 0 messages are not flow-controlled by the broker,
+///   and are buffered by the client till the delivery is resumed.
+///   This buffer is bounded (see [Config::with_max_paused_qos0_messages]):
+///   once full, the oldest QoS 0 messages are dropped to make room for the new ones.
+///
+/// All the messages held while paused, and not dropped, are delivered and acknowledged, in order, when resumed.
+#[derive(Clone, Debug)]
+pub struct PauseHandle {
+    paused: Arc<watch::Sender<bool>>,
+}
+
+impl Default for PauseHandle {
+    fn default() -> Self {
+        PauseHandle::new()
+    }
+}
+
+impl PauseHandle {
+    pub fn new() -> Self {
+        let (paused, _) = watch::channel(false);
+        PauseHandle {
+            paused: Arc::new(paused),
+        }
+    }
+
+    /// Stop delivering the messages received from the broker
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// Resume the delivery of the messages received from the broker
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Wait till the delivery of messages is not paused
+    async fn wait_for_resume(&self) {
+        let mut paused = self.paused.subscribe();
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+}
+
+/// The messages received while the delivery is paused, in order
+///
+/// The QoS 1 and QoS 2 messages are bounded by the broker inflight window, as not acknowledged till delivered.
+/// By contrast, the QoS 0 messages are not flow-controlled by the broker:
+/// hence at most `max_qos0` QoS 0 messages are held, the oldest being dropped to make room for new ones.
+pub(crate) struct HeldMessages {
+    messages: VecDeque<Publish>,
+    qos0_count: usize,
+    max_qos0: usize,
+}
+
+impl HeldMessages {
+    pub(crate) fn new(max_qos0: usize) -> Self {
+        HeldMessages {
+            messages: VecDeque::new(),
+            qos0_count: 0,
+            max_qos0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, msg: Publish) {
+        if msg.qos == rumqttc::QoS::AtMostOnce {
+            if self.qos0_count >= self.max_qos0 {
+                // Only the QoS 1 and QoS 2 messages held ahead of the oldest QoS 0 message are scanned
+                let Some(oldest) = self
+                    .messages
+                    .iter()
+                    .position(|held| held.qos == rumqttc::QoS::AtMostOnce)
+                else {
+                    // No room at all for QoS 0 messages
+                    warn!(
+                        "Dropping QoS 0 message received on topic {} while paused",
+                        msg.topic
+                    );
+                    return;
+                };
+                if let Some(dropped) = self.messages.remove(oldest) {
+                    warn!(
+                        "Dropping QoS 0 message received on topic {} while paused",
+                        dropped.topic
+                    );
+                }
+                self.qos0_count -= 1;
+            }
+            self.qos0_count += 1;
+        }
+        self.messages.push_back(msg);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Publish> {
+        let msg = self.messages.pop_front()?;
+        if msg.qos == rumqttc::QoS::AtMostOnce {
+            self.qos0_count -= 1;
+        }
+        Some(msg)
+    }
+}
+
+/// The subscription requests sent to the broker and not acknowledged yet
+///
+/// The return codes of a `SubAck` are given in the order of the filters of the acknowledged `Subscribe`,
+/// which is identified by its packet id. This packet id being only known once the request is sent,
+/// the filters of each request are queued till the `Outgoing::Subscribe` event gives its packet id.
+///
+/// This list is shared by the event loop and the [SubscriptionHandle] of the connection.
+#[derive(Clone, Default)]
+pub(crate) struct PendingSubscriptions {
+    requests: Arc<Mutex<SubscriptionRequests>>,
+}
+
+#[derive(Default)]
+struct SubscriptionRequests {
+    requested: VecDeque<SubscriptionRequest>,
+    sent: HashMap<u16, SubscriptionRequest>,
+}
+
+struct SubscriptionRequest {
+    filters: Vec<rumqttc::SubscribeFilter>,
+
+    /// Notified with the outcome of the request, if requested by a [SubscriptionHandle]
+    on_ack: Option<oneshot::Sender<Option<MqttError>>>,
+}
+
+impl PendingSubscriptions {
+    /// Send a subscription request, queuing its filters till acknowledged
+    ///
+    /// The request is sent under the lock, so the requests are queued in the order of their packet ids.
+    fn send_request(
+        &self,
+        mqtt_client: &AsyncClient,
+        filters: Vec<rumqttc::SubscribeFilter>,
+        on_ack: Option<oneshot::Sender<Option<MqttError>>>,
+    ) -> Result<(), MqttError> {
+        let mut requests = self.requests.lock().unwrap();
+        mqtt_client.try_subscribe_many(filters.clone())?;
+        requests
+            .requested
+            .push_back(SubscriptionRequest { filters, on_ack });
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn requested(&self, filters: Vec<rumqttc::SubscribeFilter>) {
+        self.requests
+            .lock()
+            .unwrap()
+            .requested
+            .push_back(SubscriptionRequest {
+                filters,
+                on_ack: None,
+            });
+    }
+
+    pub(crate) fn sent(&self, pkid: u16) {
+        let mut requests = self.requests.lock().unwrap();
+        if let Some(request) = requests.requested.pop_front() {
+            requests.sent.insert(pkid, request);
+        }
+    }
+
+    /// Check the return codes of a `SubAck` against the filters of the acknowledged request
+    ///
+    /// An error on a request sent by a [SubscriptionHandle] is returned to the handle only.
+    pub(crate) fn acknowledged(&self, ack: &rumqttc::SubAck) -> Option<MqttError> {
+        let request = self.requests.lock().unwrap().sent.remove(&ack.pkid);
+        let (filters, on_ack) =
+            request.map_or((vec![], None), |request| (request.filters, request.on_ack));
+        let err = MqttError::maybe_subscription_error(ack, &filters);
+        match on_ack {
+            Some(on_ack) => {
+                let _ = on_ack.send(err);
+                None
+            }
+            None => err,
+        }
+    }
+}
+
+/// A handle to subscribe an established connection to new topics, and to unsubscribe
+///
+/// The messages received on these topics are delivered along those received on the subscriptions of the [Config].
+/// However, these subscriptions are not restored by the connection on reconnect, unless persisted by the session.
+#[derive(Clone)]
+pub struct SubscriptionHandle {
+    mqtt_client: AsyncClient,
+    pending_subscriptions: PendingSubscriptions,
+}
+
+impl SubscriptionHandle {
+    /// Subscribe to the given topics, returning once the subscription is acknowledged by the broker
+    ///
+    /// Note that this never returns if the connection is lost before the acknowledgement.
+    pub async fn subscribe(&self, topics: &TopicFilter) -> Result<(), MqttError> {
+        let filters = topics.filters();
+        if filters.is_empty() {
+            return Ok(());
+        }
+
+        let (on_ack, ack) = oneshot::channel();
+        self.pending_subscriptions
+            .send_request(&self.mqtt_client, filters, Some(on_ack))?;
+
+        match ack.await {
+            Ok(None) => Ok(()),
+            Ok(Some(err)) => Err(err),
+            Err(_) => Err(MqttError::SendOnClosedConnection),
+        }
+    }
+
+    /// Unsubscribe from the given topics, without waiting for the acknowledgement of the broker
+    pub fn unsubscribe(&self, topics: &TopicFilter) -> Result<(), MqttError> {
+        for pattern in topics.patterns() {
+            self.mqtt_client.try_unsubscribe(pattern)?;
+        }
+        Ok(())
+    }
+}
+
+/// A handle to observe the state of an MQTT connection
+///
+/// A connection is established when created, and then might be lost and re-established
+/// as the underlying MQTT client tries to reconnect the broker.
+/// Once the connection is closed, it is reported as disconnected for good.
+#[derive(Clone, Debug)]
+pub struct ConnectionStatus {
+    connected: watch::Receiver<bool>,
+}
+
+impl ConnectionStatus {
+    pub(crate) fn new(connected: bool) -> (watch::Sender<bool>, ConnectionStatus) {
+        let (sender, connected) = watch::channel(connected);
+        (sender, ConnectionStatus { connected })
+    }
+
+    /// Return `true` if the connection to the broker is currently established
+    ///
+    /// This is the state as last reported by the MQTT client:
+    /// a connection lost but not yet detected by the client is still reported as established.
+    pub fn is_connected(&self) -> bool {
+        *self.connected.borrow()
+    }
+
+    /// Wait till the connection to the broker is established
+    ///
+    /// Return `true` immediately if the connection is already established,
+    /// and `false` if the connection is closed, as it will then never be re-established.
+    pub async fn connected(&self) -> bool {
+        let mut connected = self.connected.clone();
+        let established = connected.wait_for(|connected| *connected).await.is_ok();
+        established
+    }
 }
 
 impl Connection {
@@ -81,26 +436,83 @@ impl Connection {
     ///     Connection::new(&config).await
     /// # }
     pub async fn new(config: &Config) -> Result<Connection, MqttError> {
+        Connection::new_with_pause_handle(config, PauseHandle::new()).await
+    }
+
+    /// Establish a connection which delivery of input messages is controlled by the given `pause_handle`.
+    pub async fn new_with_pause_handle(
+        config: &Config,
+        pause_handle: PauseHandle,
+    ) -> Result<Connection, MqttError> {
+        if config
+            .max_queued_messages
+            .is_some_and(|limit| limit.capacity == 0)
+        {
+            return Err(MqttError::InvalidQueueConfig);
+        }
+
         let (received_sender, received_receiver) = mpsc::unbounded();
-        let (published_sender, published_receiver) = mpsc::unbounded();
+        let (incoming_sender, incoming_receiver) = mpsc::unbounded();
+        let (published_sender, published_receiver) =
+            publish_queue(config.max_queued_messages, config.priority_topics.clone());
         let (error_sender, error_receiver) = mpsc::unbounded();
         let (pub_done_sender, pub_done_receiver) = oneshot::channel();
+        let (close_grace_period, close_grace_period_receiver) = oneshot::channel();
+        let in_flight: InFlightCount = Arc::new(watch::channel(0).0);
+        let publish_stats = config.publish_stats_max_topics.map(PublishStats::new);
+        let republish_on_reconnect = RepublishList::default();
+        for message in config.republished_messages.iter() {
+            republish_on_reconnect.register(message.clone());
+        }
 
-        let (mqtt_client, event_loop) =
-            Connection::open(config, received_sender.clone(), error_sender.clone()).await?;
-        tokio::spawn(Connection::receiver_loop(
+        let (mqtt_client, event_loop, pending_subscriptions) = Connection::open(
+            config,
+            incoming_sender.clone(),
+            error_sender.clone(),
+            &republish_on_reconnect,
+            &in_flight,
+        )
+        .await?;
+        let subscriptions = SubscriptionHandle {
+            mqtt_client: mqtt_client.clone(),
+            pending_subscriptions: pending_subscriptions.clone(),
+        };
+        let (connected_sender, status) = ConnectionStatus::new(true);
+        tokio::spawn(Connection::delivery_loop(
             mqtt_client.clone(),
-            config.clone(),
-            event_loop,
+            incoming_receiver,
             received_sender,
-            error_sender.clone(),
+            pause_handle.clone(),
+            config.clone(),
         ));
+        tokio::spawn({
+            let receiver_loop = Connection::receiver_loop(
+                mqtt_client.clone(),
+                config.clone(),
+                event_loop,
+                pending_subscriptions,
+                incoming_sender,
+                error_sender.clone(),
+                connected_sender.clone(),
+                republish_on_reconnect.clone(),
+                in_flight.clone(),
+            );
+            async move {
+                let result = receiver_loop.await;
+                // Whatever the cause, the connection is closed for good once the event loop is no more polled
+                connected_sender.send_replace(false);
+                result
+            }
+        });
         tokio::spawn(Connection::sender_loop(
             mqtt_client,
             published_receiver,
             error_sender,
             config.last_will_message.clone(),
+            publish_stats.clone(),
             pub_done_sender,
+            close_grace_period_receiver,
+            in_flight,
         ));
 
         Ok(Connection {
@@ -108,19 +520,92 @@ impl Connection {
             published: published_sender,
             errors: error_receiver,
             pub_done: pub_done_receiver,
+            pause_handle,
+            status,
+            publish_stats,
+            republish_on_reconnect,
+            subscriptions,
+            close_grace_period,
         })
     }
 
-    pub async fn close(self) {
+    /// Return `true` if the connection to the broker is currently established
+    ///
+    /// See [ConnectionStatus::is_connected]
+    pub fn is_connected(&self) -> bool {
+        self.status.is_connected()
+    }
+
+    /// Wait till the connection to the broker is established
+    ///
+    /// See [ConnectionStatus::connected]
+    pub async fn connected(&self) -> bool {
+        self.status.connected().await
+    }
+
+    /// Establish a connection subscribed to the given topics, and wait for the retained messages
+    ///
+    /// Return the retained messages sent by the broker on subscription,
+    /// along the connection which then only delivers the live messages.
+    ///
+    /// The subscriptions are acknowledged by the broker before any retained message is awaited.
+    /// The retained messages are then collected till no more message is received for `settle_timeout`
+    /// or a live message is received.
+    pub async fn subscribe_and_settle(
+        config: &Config,
+        topics: TopicFilter,
+        settle_timeout: Duration,
+    ) -> Result<(Vec<MqttMessage>, Connection), MqttError> {
+        let config = config.clone().with_subscriptions(topics);
+        let mut connection = Connection::new(&config).await?;
+
+        let mut retained = vec![];
+        while let Ok(Some(message)) = timeout(settle_timeout, connection.received.next()).await {
+            if message.retain {
+                retained.push(message);
+                continue;
+            }
+
+            // A live message has to be delivered first on the live stream
+            let (mut live_sender, live_receiver) = mpsc::unbounded();
+            let mut received = std::mem::replace(&mut connection.received, live_receiver);
+            tokio::spawn(async move {
+                let _ = live_sender.send(message).await;
+                while let Some(message) = received.next().await {
+                    if live_sender.send(message).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            break;
+        }
+
+        Ok((retained, connection))
+    }
+
+    pub async fn close(mut self) {
+        self.published.close_channel();
+        let _ = self.pub_done.await;
+    }
+
+    /// Close the connection once the published messages have been acknowledged by the broker
+    ///
+    /// As with [Connection::close], all the messages published so far are first sent to the broker.
+    /// However, the connection is then kept open till the broker acknowledges the QoS 1 and QoS 2 messages,
+    /// so these messages are not lost on disconnect. The wait for these acknowledgements is bounded by `timeout`.
+    pub async fn close_gracefully(mut self, timeout: Duration) {
+        let _ = self.close_grace_period.send(timeout);
         self.published.close_channel();
         let _ = self.pub_done.await;
     }
 
     async fn open(
         config: &Config,
-        mut message_sender: mpsc::UnboundedSender<MqttMessage>,
+        mut message_sender: mpsc::UnboundedSender<Publish>,
         mut error_sender: mpsc::UnboundedSender<MqttError>,
-    ) -> Result<(AsyncClient, EventLoop), MqttError> {
+        republish_on_connect: &RepublishList,
+        in_flight: &InFlightCount,
+    ) -> Result<(AsyncClient, EventLoop, PendingSubscriptions), MqttError> {
         const INSECURE_MQTT_PORT: u16 = 1883;
         const SECURE_MQTT_PORT: u16 = 8883;
 
@@ -131,13 +616,16 @@ impl Connection {
             eprintln!("WARNING: Connecting on port 8883 for secure MQTT without a CA file");
         }
 
-        let mqtt_options = config.rumqttc_options()?;
+        let mut mqtt_options = config.rumqttc_options()?;
+        // The received messages are acknowledged only when delivered to the client (see `delivery_loop`)
+        mqtt_options.set_manual_acks(true);
         let (mqtt_client, mut event_loop) = AsyncClient::new(mqtt_options, config.queue_capacity);
 
         info!(
             "MQTT connecting to broker: host={}:{}, session_name={:?}",
             config.broker.host, config.broker.port, config.session_name
         );
+        let pending_subscriptions = PendingSubscriptions::default();
 
         loop {
             match event_loop.poll().await {
@@ -147,6 +635,16 @@ impl Connection {
                     };
                     info!("MQTT connection established");
 
+                    if config.clean_session_on_first_connect_only
+                        && config.session_name.is_some()
+                        && !config.clean_session
+                    {
+                        // The session has been cleaned: it has now to be persisted across reconnects
+                        event_loop.mqtt_options.set_clean_session(false);
+                    }
+
+                    Connection::republish(&mqtt_client, republish_on_connect.messages(), in_flight);
+
                     let subscriptions = config.subscriptions.filters();
 
                     // Need check here otherwise it will hang waiting for a SubAck, and none will come when there is no subscription.
@@ -154,11 +652,19 @@ impl Connection {
                         break;
                     }
 
-                    Connection::subscribe_to_topics(&mqtt_client, subscriptions).await?
+                    Connection::subscribe_to_topics(
+                        &mqtt_client,
+                        &pending_subscriptions,
+                        subscriptions,
+                    )?
+                }
+
+                Ok(Event::Outgoing(Outgoing::Subscribe(pkid))) => {
+                    pending_subscriptions.sent(pkid);
                 }
 
                 Ok(Event::Incoming(Packet::SubAck(ack))) => {
-                    if let Some(err) = MqttError::maybe_subscription_error(&ack) {
+                    if let Some(err) = pending_subscriptions.acknowledged(&ack) {
                         return Err(err);
                     };
                     break;
@@ -167,12 +673,7 @@ impl Connection {
                 Ok(Event::Incoming(Packet::Publish(msg))) => {
                     // Messages can be received before a sub ack
                     // Errors on send are ignored: it just means the client has closed the receiving channel.
-                    if msg.payload.len() > config.max_packet_size {
-                        error!("Dropping message received on topic {} with payload size {} that exceeds the maximum packet size of {}", 
-                            msg.topic, msg.payload.len(), config.max_packet_size);
-                        continue;
-                    }
-                    let _ = message_sender.send(msg.into()).await;
+                    let _ = message_sender.send(msg).await;
                 }
 
                 Err(err) => {
@@ -191,27 +692,27 @@ impl Connection {
             }
         }
 
-        Ok((mqtt_client, event_loop))
+        Ok((mqtt_client, event_loop, pending_subscriptions))
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn receiver_loop(
         mqtt_client: AsyncClient,
         config: Config,
         mut event_loop: EventLoop,
-        mut message_sender: mpsc::UnboundedSender<MqttMessage>,
+        pending_subscriptions: PendingSubscriptions,
+        mut message_sender: mpsc::UnboundedSender<Publish>,
         mut error_sender: mpsc::UnboundedSender<MqttError>,
+        connected: watch::Sender<bool>,
+        republish_on_reconnect: RepublishList,
+        in_flight: InFlightCount,
     ) -> Result<(), MqttError> {
         loop {
             match event_loop.poll().await {
                 Ok(Event::Incoming(Packet::Publish(msg))) => {
-                    if msg.payload.len() > config.max_packet_size {
-                        error!("Dropping message received on topic {} with payload size {} that exceeds the maximum packet size of {}", 
-                            msg.topic, msg.payload.len(), config.max_packet_size);
-                        continue;
-                    }
                     // Errors on send are ignored: it just means the client has closed the receiving channel.
                     // One has to continue the loop though, because rumqttc relies on this polling.
-                    let _ = message_sender.send(msg.into()).await;
+                    let _ = message_sender.send(msg).await;
                 }
 
                 Ok(Event::Incoming(Packet::ConnAck(ack))) => {
@@ -219,9 +720,11 @@ impl Connection {
                         error!("MQTT connection Error {err}");
                     } else {
                         info!("MQTT connection re-established");
+                        connected.send_replace(true);
                         if let Some(ref imsg_fn) = config.initial_message {
                             // publish the initial message on connect
                             let message = imsg_fn.new_init_message();
+                            published_in_flight(&in_flight, message.qos);
                             mqtt_client
                                 .publish(
                                     message.topic.name.clone(),
@@ -232,6 +735,12 @@ impl Connection {
                                 .await?;
                         }
 
+                        Connection::republish(
+                            &mqtt_client,
+                            republish_on_reconnect.messages(),
+                            &in_flight,
+                        );
+
                         if config.session_name.is_none() {
                             // Workaround for  https://github.com/bytebeamio/rumqtt/issues/250
                             // If session_name is not provided, then re-subscribe
@@ -241,19 +750,44 @@ impl Connection {
                             if subscriptions.is_empty() {
                                 break;
                             }
-                            Connection::subscribe_to_topics(&mqtt_client, subscriptions).await?;
+                            Connection::subscribe_to_topics(
+                                &mqtt_client,
+                                &pending_subscriptions,
+                                subscriptions,
+                            )?;
                         }
                     }
                 }
 
+                Ok(Event::Outgoing(Outgoing::Subscribe(pkid))) => {
+                    pending_subscriptions.sent(pkid);
+                }
+
+                Ok(Event::Incoming(Packet::SubAck(ack))) => {
+                    // On re-subscription, the broker might refuse filters accepted so far
+                    if let Some(err) = pending_subscriptions.acknowledged(&ack) {
+                        error!("MQTT subscription error: {err}");
+
+                        // Errors on send are ignored: it just means the client has closed the receiving channel.
+                        let _ = error_sender.send(err).await;
+                    }
+                }
+
+                Ok(Event::Incoming(Packet::PubAck(_)))
+                | Ok(Event::Incoming(Packet::PubComp(_))) => {
+                    acknowledged_in_flight(&in_flight);
+                }
+
                 Ok(Event::Incoming(Incoming::Disconnect))
                 | Ok(Event::Outgoing(Outgoing::Disconnect)) => {
                     info!("MQTT connection closed");
+                    connected.send_replace(false);
                     break;
                 }
 
                 Err(err) => {
                     error!("MQTT connection error: {err}");
+                    connected.send_replace(false);
 
                     // Errors on send are ignored: it just means the client has closed the receiving channel.
                     let _ = error_sender.send(err.into()).await;
@@ -269,12 +803,73 @@ impl Connection {
         Ok(())
     }
 
+    /// Deliver the received messages to the client, unless paused, acknowledging them once delivered
+    ///
+    /// The messages received on topics excluded by the subscriptions are acknowledged but not delivered,
+    /// and so are the messages which payload exceeds the maximum packet size.
+    /// Acknowledging the dropped messages is required, as otherwise the broker would never
+    /// release their inflight slots and would eventually stop sending messages.
+    ///
+    /// This is done in a task distinct from the `receiver_loop`,
+    /// so the event loop is still polled while the delivery is paused.
+    async fn delivery_loop(
+        mqtt_client: AsyncClient,
+        mut incoming_receiver: mpsc::UnboundedReceiver<Publish>,
+        mut message_sender: mpsc::UnboundedSender<MqttMessage>,
+        pause_handle: PauseHandle,
+        config: Config,
+    ) {
+        let mut held = HeldMessages::new(config.max_paused_qos0_messages);
+        loop {
+            if !pause_handle.is_paused() {
+                if let Some(msg) = held.pop() {
+                    Connection::deliver(&mqtt_client, &mut message_sender, &config, msg).await;
+                    continue;
+                }
+            }
+
+            // While paused, the received messages are held till resumed
+            tokio::select! {
+                _ = pause_handle.wait_for_resume(), if pause_handle.is_paused() => {}
+                msg = incoming_receiver.next() => match msg {
+                    Some(msg) => held.push(msg),
+                    None => break,
+                }
+            }
+        }
+
+        // No more messages will be forwarded to the client
+        let _ = message_sender.close().await;
+    }
+
+    async fn deliver(
+        mqtt_client: &AsyncClient,
+        message_sender: &mut mpsc::UnboundedSender<MqttMessage>,
+        config: &Config,
+        msg: Publish,
+    ) {
+        if msg.payload.len() > config.max_packet_size {
+            error!("Dropping message received on topic {} with payload size {} that exceeds the maximum packet size of {}",
+                msg.topic, msg.payload.len(), config.max_packet_size);
+        } else if !config.subscriptions.is_excluded(&msg.topic) {
+            // Errors on send are ignored: it just means the client has closed the receiving channel.
+            let _ = message_sender.send(msg.clone().into()).await;
+        }
+
+        // Errors on ack are ignored: it just means the connection has been closed.
+        let _ = mqtt_client.ack(&msg).await;
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn sender_loop(
         mqtt_client: AsyncClient,
-        mut messages_receiver: mpsc::UnboundedReceiver<MqttMessage>,
+        mut messages_receiver: PublishReceiver,
         mut error_sender: mpsc::UnboundedSender<MqttError>,
         last_will: Option<MqttMessage>,
+        publish_stats: Option<PublishStats>,
         done: oneshot::Sender<()>,
+        mut close_grace_period: oneshot::Receiver<Duration>,
+        in_flight: InFlightCount,
     ) {
         loop {
             match messages_receiver.next().await {
@@ -285,16 +880,42 @@ impl Connection {
                 }
                 Some(message) => {
                     let payload = Vec::from(message.payload_bytes());
-                    if let Err(err) = mqtt_client
-                        .publish(message.topic, message.qos, message.retain, payload)
+                    published_in_flight(&in_flight, message.qos);
+                    if let Err(source) = mqtt_client
+                        .publish(message.topic.clone(), message.qos, message.retain, payload)
                         .await
                     {
-                        let _ = error_sender.send(err.into()).await;
+                        acknowledged_in_flight(&in_flight);
+                        error!(
+                            "MQTT: failed to publish on {}: {source}",
+                            message.topic.name
+                        );
+                        let failure = MqttError::PublishFailure {
+                            message: Box::new(message),
+                            source,
+                        };
+                        let _ = error_sender.send(failure).await;
+                    } else if let Some(stats) = &publish_stats {
+                        stats.record(&message);
                     }
                 }
             }
         }
 
+        // On a graceful close, the in-flight messages have to be acknowledged before disconnecting
+        if let Ok(Some(grace_period)) = close_grace_period.try_recv() {
+            let mut in_flight = in_flight.subscribe();
+            if timeout(grace_period, in_flight.wait_for(|count| *count == 0))
+                .await
+                .is_err()
+            {
+                error!(
+                    "MQTT connection closed with {} message(s) still waiting for an acknowledgement",
+                    *in_flight.borrow()
+                );
+            }
+        }
+
         // As the broker doesn't send the last will when the client disconnects gracefully
         // one has first to explicitly send the last will message.
         if let Some(last_will) = last_will {
@@ -307,17 +928,49 @@ impl Connection {
         let _ = done.send(());
     }
 
+    /// Publish the registered messages from a dedicated task
+    ///
+    /// These messages cannot be published from the task polling the event loop:
+    /// this would dead-lock as soon as the request channel of the MQTT client is full.
+    fn republish(mqtt_client: &AsyncClient, messages: Vec<MqttMessage>, in_flight: &InFlightCount) {
+        if messages.is_empty() {
+            return;
+        }
+        let mqtt_client = mqtt_client.clone();
+        let in_flight = in_flight.clone();
+        tokio::spawn(async move {
+            for message in messages {
+                let payload = Vec::from(message.payload_bytes());
+                published_in_flight(&in_flight, message.qos);
+                if let Err(err) = mqtt_client
+                    .publish(
+                        message.topic.name.clone(),
+                        message.qos,
+                        message.retain,
+                        payload,
+                    )
+                    .await
+                {
+                    acknowledged_in_flight(&in_flight);
+                    error!("MQTT: failed to republish on {}: {err}", message.topic.name);
+                    break;
+                }
+            }
+        });
+    }
+
     pub(crate) async fn do_pause() {
         sleep(Duration::from_secs(1)).await;
     }
 
-    pub(crate) async fn subscribe_to_topics(
+    /// Request the subscriptions, without waiting for the acknowledgement of the broker
+    ///
+    /// The request is not awaited, as this is called by the event loop that processes the requests.
+    pub(crate) fn subscribe_to_topics(
         mqtt_client: &AsyncClient,
+        pending_subscriptions: &PendingSubscriptions,
         subscriptions: Vec<rumqttc::SubscribeFilter>,
     ) -> Result<(), MqttError> {
-        mqtt_client
-            .subscribe_many(subscriptions)
-            .await
-            .map_err(MqttError::ClientError)
+        pending_subscriptions.send_request(mqtt_client, subscriptions, None)
     }
 }
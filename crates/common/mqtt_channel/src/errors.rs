@@ -13,6 +13,9 @@ pub enum MqttError {
     #[error("Invalid session: a session name must be provided")]
     InvalidSessionConfig,
 
+    #[error("Invalid publish queue: the maximum number of queued messages must be greater than 0")]
+    InvalidQueueConfig,
+
     #[error(transparent)]
     InvalidPrivateKey(#[from] rustls::Error),
 
@@ -22,12 +25,18 @@ pub enum MqttError {
     #[error("MQTT connection error: {0}")]
     ConnectionError(#[from] rumqttc::ConnectionError),
 
+    #[error("Failed to publish a message on {}: {source}", message.topic.name)]
+    PublishFailure {
+        message: Box<crate::MqttMessage>,
+        source: rumqttc::ClientError,
+    },
+
     #[error("MQTT connection rejected: {0:?}")]
     ConnectionRejected(rumqttc::ConnectReturnCode),
 
-    #[error("MQTT subscription failure")]
-    // The MQTT specs are mysterious on the possible cause of such a failure
-    SubscriptionFailure,
+    #[error("MQTT subscription failure: the broker refused {}", patterns.join(", "))]
+    // The MQTT specs are mysterious on the possible cause of such a failure (e.g. ACLs)
+    SubscriptionFailure { patterns: Vec<String> },
 
     #[error("Invalid UTF8 payload: {from}: {input_excerpt}...")]
     InvalidUtf8Payload {
@@ -35,6 +44,12 @@ pub enum MqttError {
         from: std::str::Utf8Error,
     },
 
+    #[error("Invalid JSON payload: {from}: {input_excerpt}...")]
+    InvalidJsonPayload {
+        input_excerpt: String,
+        from: serde_json::Error,
+    },
+
     #[error(
         "The read channel of the connection has been closed and no more messages can be received"
     )]
@@ -45,6 +60,9 @@ pub enum MqttError {
     )]
     SendOnClosedConnection,
 
+    #[error("Failed to read the stream of bytes to publish: {0}")]
+    StreamReadError(#[from] std::io::Error),
+
     #[error("Failed to create a TLS config")]
     TlsConfig(#[from] certificate::CertificateError),
 
@@ -60,13 +78,30 @@ impl MqttError {
         }
     }
 
-    pub fn maybe_subscription_error(ack: &rumqttc::SubAck) -> Option<MqttError> {
-        for code in ack.return_codes.iter() {
-            if let rumqttc::SubscribeReasonCode::Failure = code {
-                return Some(MqttError::SubscriptionFailure);
-            }
+    /// Check the return codes of a `SubAck` against the filters of the acknowledged subscription
+    ///
+    /// The return codes are given by the broker in the order of the subscription filters.
+    pub fn maybe_subscription_error(
+        ack: &rumqttc::SubAck,
+        subscriptions: &[rumqttc::SubscribeFilter],
+    ) -> Option<MqttError> {
+        let patterns: Vec<String> = ack
+            .return_codes
+            .iter()
+            .enumerate()
+            .filter(|(_, code)| matches!(code, rumqttc::SubscribeReasonCode::Failure))
+            .map(|(i, _)| {
+                subscriptions
+                    .get(i)
+                    .map_or_else(|| format!("filter #{i}"), |filter| filter.path.clone())
+            })
+            .collect();
+
+        if patterns.is_empty() {
+            None
+        } else {
+            Some(MqttError::SubscriptionFailure { patterns })
         }
-        None
     }
 
     pub fn new_invalid_utf8_payload(bytes: &[u8], from: std::str::Utf8Error) -> MqttError {
@@ -80,6 +115,16 @@ impl MqttError {
         }
     }
 
+    pub fn new_invalid_json_payload(bytes: &[u8], from: serde_json::Error) -> MqttError {
+        const EXCERPT_LEN: usize = 80;
+        let input = String::from_utf8_lossy(bytes);
+
+        MqttError::InvalidJsonPayload {
+            input_excerpt: MqttError::input_prefix(&input, EXCERPT_LEN),
+            from,
+        }
+    }
+
     fn input_prefix(input: &str, len: usize) -> String {
         input
             .chars()
@@ -34,6 +34,8 @@ mod config;
 mod connection;
 mod errors;
 mod messages;
+mod publish_queue;
+mod publish_stats;
 mod session;
 mod topics;
 
@@ -45,6 +47,8 @@ pub use config::*;
 pub use connection::*;
 pub use errors::*;
 pub use messages::*;
+pub use publish_queue::PublishSender;
+pub use publish_stats::*;
 pub use session::*;
 pub use topics::*;
 
@@ -1,7 +1,9 @@
 use crate::errors::MqttError;
 use crate::topics::Topic;
+use crate::topics::TopicFilter;
 use rumqttc::Publish;
 use rumqttc::QoS;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Deserializer;
 use serde::Serialize;
@@ -198,6 +200,26 @@ impl MqttMessage {
     pub fn payload_bytes(&self) -> &[u8] {
         self.payload.as_bytes()
     }
+
+    /// Check if the payload of this message is empty
+    ///
+    /// By MQTT convention, a retained message with an empty payload clears the message retained on its topic.
+    /// Only a truly empty payload is empty: a payload made of a single null char is not,
+    /// even if [MqttMessage::payload_bytes] strips that char, as the broker doesn't clear a topic on such a message.
+    pub fn is_empty(&self) -> bool {
+        self.payload.0.is_empty()
+    }
+
+    /// Check if the topic of this message matches the given filter
+    pub fn matches(&self, filter: &TopicFilter) -> bool {
+        filter.accept(self)
+    }
+
+    /// The payload deserialized from JSON (except any trailing null char)
+    pub fn payload_json<T: DeserializeOwned>(&self) -> Result<T, MqttError> {
+        let bytes = self.payload_bytes();
+        serde_json::from_slice(bytes).map_err(|err| MqttError::new_invalid_json_payload(bytes, err))
+    }
 }
 
 impl From<MqttMessage> for Publish {
@@ -266,6 +288,16 @@ mod tests {
 
         assert_eq!(message.payload_bytes(), b"");
     }
+
+    #[test]
+    fn only_a_message_without_payload_is_empty() {
+        let topic = Topic::new("trimmed").unwrap();
+
+        assert!(MqttMessage::new(&topic, &b""[..]).is_empty());
+        assert!(!MqttMessage::new(&topic, &b"\0"[..]).is_empty());
+        assert!(!MqttMessage::new(&topic, &b"123"[..]).is_empty());
+    }
+
     #[test]
     fn check_non_null_terminated_messages() {
         let topic = Topic::new("trimmed").unwrap();
@@ -292,6 +324,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn message_matches_topic_filter() {
+        let message = MqttMessage::new(&Topic::new_unchecked("te/device/main///m/temp"), "{}");
+
+        assert!(message.matches(&TopicFilter::new_unchecked("te/device/main///m/temp")));
+        assert!(message.matches(&TopicFilter::new_unchecked("te/+/+/+/+/m/+")));
+        assert!(message.matches(&TopicFilter::new_unchecked("te/#")));
+        assert!(message.matches(&TopicFilter::new_unchecked("#")));
+
+        assert!(!message.matches(&TopicFilter::new_unchecked("te/device/main///m")));
+        assert!(!message.matches(&TopicFilter::new_unchecked("te/+/+/+/+/e/+")));
+        assert!(!message.matches(&TopicFilter::new_unchecked("c8y/#")));
+        assert!(!message.matches(&TopicFilter::empty()));
+    }
+
+    #[test]
+    fn message_matches_any_pattern_of_a_topic_filter() {
+        let message = MqttMessage::new(&Topic::new_unchecked("c8y/s/ds"), "");
+        let mut filter = TopicFilter::new_unchecked("te/#");
+        assert!(!message.matches(&filter));
+
+        filter.add_unchecked("c8y/s/+");
+        assert!(message.matches(&filter));
+    }
+
+    #[test]
+    fn payload_json_with_valid_json() {
+        let topic = Topic::new("trimmed").unwrap();
+        let message = MqttMessage::new(&topic, r#"{"temperature": 23.5}"#);
+        let payload: serde_json::Value = message.payload_json().unwrap();
+        assert_eq!(payload, json!({"temperature": 23.5}));
+    }
+
+    #[test]
+    fn payload_json_with_null_terminated_json() {
+        let topic = Topic::new("trimmed").unwrap();
+        let message = MqttMessage::new(&topic, &b"{\"temperature\": 23.5}\0"[..]);
+        let payload: serde_json::Value = message.payload_json().unwrap();
+        assert_eq!(payload, json!({"temperature": 23.5}));
+    }
+
+    #[test]
+    fn payload_json_with_invalid_json() {
+        let topic = Topic::new("trimmed").unwrap();
+        let message = MqttMessage::new(&topic, r#"{"temperature": }"#);
+        assert_eq!(
+            message
+                .payload_json::<serde_json::Value>()
+                .unwrap_err()
+                .to_string(),
+            r#"Invalid JSON payload: expected value at line 1 column 17: {"temperature":}..."#
+        );
+    }
+
     #[test]
     fn message_serialize_deserialize() {
         let message = MqttMessage {
@@ -0,0 +1,296 @@
+use crate::MqttMessage;
+use crate::OverflowPolicy;
+use crate::PubChannel;
+use crate::QueueLimit;
+use crate::TopicFilter;
+use futures::channel::mpsc;
+use futures::future::poll_fn;
+use futures::Sink;
+use futures::SinkExt;
+use futures::Stream;
+use futures::StreamExt;
+use log::warn;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+
+/// The stream of messages waiting to be published, as consumed by the connection
+pub(crate) type PublishReceiver = Pin<Box<dyn Stream<Item = MqttMessage> + Send>>;
+
+/// The channel used by a client to publish messages on an MQTT connection
+///
+/// Depending on the [QueueLimit] of the connection, this channel is either:
+/// - unbounded (the default),
+/// - bounded, publishers waiting for room when the queue is full ([OverflowPolicy::Block]),
+/// - or never blocking, dropping messages when the queue is full
+///   ([OverflowPolicy::DropOldest] and [OverflowPolicy::DropNewest]).
+#[derive(Clone, Debug)]
+pub struct PublishSender {
+    sender: Sender,
+}
+
+#[derive(Clone, Debug)]
+enum Sender {
+    Unbounded(mpsc::UnboundedSender<MqttMessage>),
+    Bounded(mpsc::Sender<MqttMessage>),
+}
+
+impl PublishSender {
+    /// Close the channel: no more messages will be accepted
+    /// and the connection will be closed once all the queued messages published.
+    pub fn close_channel(&mut self) {
+        match &mut self.sender {
+            Sender::Unbounded(sender) => sender.close_channel(),
+            Sender::Bounded(sender) => sender.close_channel(),
+        }
+    }
+
+    /// Return `true` if the channel has been closed
+    pub fn is_closed(&self) -> bool {
+        match &self.sender {
+            Sender::Unbounded(sender) => sender.is_closed(),
+            Sender::Bounded(sender) => sender.is_closed(),
+        }
+    }
+}
+
+impl Sink<MqttMessage> for PublishSender {
+    type Error = mpsc::SendError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.get_mut().sender {
+            Sender::Unbounded(sender) => sender.poll_ready_unpin(cx),
+            Sender::Bounded(sender) => sender.poll_ready_unpin(cx),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: MqttMessage) -> Result<(), Self::Error> {
+        match &mut self.get_mut().sender {
+            Sender::Unbounded(sender) => sender.start_send_unpin(message),
+            Sender::Bounded(sender) => sender.start_send_unpin(message),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.get_mut().sender {
+            Sender::Unbounded(sender) => sender.poll_flush_unpin(cx),
+            Sender::Bounded(sender) => sender.poll_flush_unpin(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.get_mut().sender {
+            Sender::Unbounded(sender) => sender.poll_close_unpin(cx),
+            Sender::Bounded(sender) => sender.poll_close_unpin(cx),
+        }
+    }
+}
+
+impl PubChannel for PublishSender {}
+
+/// Create the queue of the messages waiting to be published, applying the given limit if any
+///
+/// For the drop policies, a task is spawned to eagerly consume the published messages,
+/// so the publishers are never blocked.
+///
+/// The messages published on the `priority_topics`, if any, are sent before the others.
+/// A task is then spawned to eagerly consume the published messages
+/// and to reorder them while the connection is congested.
+/// Within each priority class, the messages are sent in the order they have been published.
+pub(crate) fn publish_queue(
+    limit: Option<QueueLimit>,
+    priority_topics: TopicFilter,
+) -> (PublishSender, PublishReceiver) {
+    if !priority_topics.patterns().is_empty() {
+        let (capacity, overflow_policy) = match limit {
+            None => (usize::MAX, OverflowPolicy::DropNewest),
+            Some(limit) => (limit.capacity, limit.overflow_policy),
+        };
+        let (output, receiver) = mpsc::channel(0);
+        let sender = if overflow_policy == OverflowPolicy::Block {
+            let (sender, input) = mpsc::channel(0);
+            tokio::spawn(queue_loop(
+                input,
+                output,
+                capacity,
+                overflow_policy,
+                priority_topics,
+            ));
+            Sender::Bounded(sender)
+        } else {
+            let (sender, input) = mpsc::unbounded();
+            tokio::spawn(queue_loop(
+                input,
+                output,
+                capacity,
+                overflow_policy,
+                priority_topics,
+            ));
+            Sender::Unbounded(sender)
+        };
+        return (PublishSender { sender }, Box::pin(receiver));
+    }
+
+    match limit {
+        None => {
+            let (sender, receiver) = mpsc::unbounded();
+            let sender = Sender::Unbounded(sender);
+            (PublishSender { sender }, Box::pin(receiver))
+        }
+        Some(QueueLimit {
+            capacity,
+            overflow_policy: OverflowPolicy::Block,
+        }) => {
+            let (sender, receiver) = mpsc::channel(capacity);
+            let sender = Sender::Bounded(sender);
+            (PublishSender { sender }, Box::pin(receiver))
+        }
+        Some(QueueLimit {
+            capacity,
+            overflow_policy,
+        }) => {
+            let (sender, input) = mpsc::unbounded();
+            let (output, receiver) = mpsc::channel(0);
+            tokio::spawn(queue_loop(
+                input,
+                output,
+                capacity,
+                overflow_policy,
+                TopicFilter::empty(),
+            ));
+            let sender = Sender::Unbounded(sender);
+            (PublishSender { sender }, Box::pin(receiver))
+        }
+    }
+}
+
+/// Forward the input messages to the output as fast as accepted by the output,
+/// queueing at most `capacity` messages and applying the overflow policy when the queue is full.
+///
+/// With [OverflowPolicy::Block], the input is no longer consumed while the queue is full.
+async fn queue_loop(
+    mut input: impl Stream<Item = MqttMessage> + Unpin,
+    mut output: impl Sink<MqttMessage> + Unpin,
+    capacity: usize,
+    overflow_policy: OverflowPolicy,
+    priority_topics: TopicFilter,
+) {
+    let mut queue = PriorityQueue::new(priority_topics, capacity, overflow_policy);
+    let mut dropped_count: usize = 0;
+    loop {
+        let blocked = overflow_policy == OverflowPolicy::Block && queue.is_full();
+        tokio::select! {
+            biased;
+
+            ready = poll_fn(|cx| output.poll_ready_unpin(cx)), if !queue.is_empty() => {
+                if ready.is_err() {
+                    // The connection has been closed
+                    return;
+                }
+                if let Some(message) = queue.pop_front() {
+                    if output.start_send_unpin(message).is_err() {
+                        return;
+                    }
+                }
+            }
+
+            message = input.next(), if !blocked => {
+                let Some(message) = message else {
+                    // The client has closed the channel
+                    break;
+                };
+                if let Some(dropped) = queue.push(message) {
+                    dropped_count += 1;
+                    warn!(
+                        "MQTT: publish queue full ({capacity} messages), dropping message on topic {} (dropped_messages_total={dropped_count})",
+                        dropped.topic.name
+                    );
+                }
+            }
+        }
+    }
+
+    // Publish the remaining messages, before closing the output
+    while let Some(message) = queue.pop_front() {
+        if output.send(message).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// A queue of messages, the messages on the priority topics being popped before the others
+///
+/// Within each priority class, the messages are popped in the order they have been pushed.
+/// At most `capacity` messages are queued, the overflow policy being applied when the queue is full.
+pub(crate) struct PriorityQueue {
+    priority_topics: TopicFilter,
+    capacity: usize,
+    overflow_policy: OverflowPolicy,
+    high: VecDeque<MqttMessage>,
+    low: VecDeque<MqttMessage>,
+}
+
+impl PriorityQueue {
+    pub(crate) fn new(
+        priority_topics: TopicFilter,
+        capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> Self {
+        PriorityQueue {
+            priority_topics,
+            capacity,
+            overflow_policy,
+            high: VecDeque::new(),
+            low: VecDeque::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.high.len() + self.low.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.high.is_empty() && self.low.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    /// Push a message, returning the message dropped by the overflow policy if the queue is full
+    ///
+    /// With [OverflowPolicy::Block], the caller is expected to wait for room in the queue:
+    /// the new message is dropped if the queue is full.
+    pub(crate) fn push(&mut self, message: MqttMessage) -> Option<MqttMessage> {
+        if !self.is_full() {
+            self.push_back(message);
+            return None;
+        }
+
+        match self.overflow_policy {
+            OverflowPolicy::DropOldest => {
+                self.push_back(message);
+                self.pop_oldest()
+            }
+            OverflowPolicy::DropNewest | OverflowPolicy::Block => Some(message),
+        }
+    }
+
+    fn push_back(&mut self, message: MqttMessage) {
+        if self.priority_topics.accept(&message) {
+            self.high.push_back(message)
+        } else {
+            self.low.push_back(message)
+        }
+    }
+
+    pub(crate) fn pop_front(&mut self) -> Option<MqttMessage> {
+        self.high.pop_front().or_else(|| self.low.pop_front())
+    }
+
+    /// Pop the message to be dropped first, i.e. the oldest low-priority message if any
+    fn pop_oldest(&mut self) -> Option<MqttMessage> {
+        self.low.pop_front().or_else(|| self.high.pop_front())
+    }
+}
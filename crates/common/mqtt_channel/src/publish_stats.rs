@@ -0,0 +1,146 @@
+use crate::MqttMessage;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// The number of messages and payload bytes published on a topic
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TopicStats {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// A handle to the per-topic statistics of the messages published on a connection
+///
+/// Enabled with [Config::with_publish_stats](crate::Config::with_publish_stats).
+///
+/// The number of topics tracked is capped, so high-cardinality topics don't make the memory grow unbounded:
+/// when a message is published on a new topic while the table is full,
+/// the statistics of the least recently published topic are evicted.
+#[derive(Clone, Debug)]
+pub struct PublishStats {
+    table: Arc<Mutex<StatsTable>>,
+}
+
+#[derive(Debug)]
+struct StatsTable {
+    max_topics: usize,
+    tick: u64,
+    topics: HashMap<String, TopicEntry>,
+    /// The tracked topics, indexed by the tick of their last publication
+    recency: BTreeMap<u64, String>,
+}
+
+#[derive(Debug)]
+struct TopicEntry {
+    stats: TopicStats,
+    last_published: u64,
+}
+
+impl PublishStats {
+    /// Create an empty table, tracking at most `max_topics` topics
+    pub fn new(max_topics: usize) -> Self {
+        let table = StatsTable {
+            max_topics: max_topics.max(1),
+            tick: 0,
+            topics: HashMap::new(),
+            recency: BTreeMap::new(),
+        };
+        PublishStats {
+            table: Arc::new(Mutex::new(table)),
+        }
+    }
+
+    /// A snapshot of the statistics of the topics currently tracked
+    pub fn snapshot(&self) -> HashMap<String, TopicStats> {
+        let table = self.table.lock().unwrap();
+        table
+            .topics
+            .iter()
+            .map(|(topic, entry)| (topic.clone(), entry.stats))
+            .collect()
+    }
+
+    /// Account for a message published on the connection
+    pub(crate) fn record(&self, message: &MqttMessage) {
+        let mut table = self.table.lock().unwrap();
+        table.tick += 1;
+        let tick = table.tick;
+
+        let topic = &message.topic.name;
+        if !table.topics.contains_key(topic) && table.topics.len() >= table.max_topics {
+            table.evict_least_recently_published();
+        }
+
+        let table = &mut *table;
+        let entry = table
+            .topics
+            .entry(topic.clone())
+            .or_insert_with(|| TopicEntry {
+                stats: TopicStats::default(),
+                last_published: tick,
+            });
+        entry.stats.messages += 1;
+        entry.stats.bytes += message.payload_bytes().len() as u64;
+        table.recency.remove(&entry.last_published);
+        table.recency.insert(tick, topic.clone());
+        entry.last_published = tick;
+    }
+}
+
+impl StatsTable {
+    fn evict_least_recently_published(&mut self) {
+        if let Some((_, topic)) = self.recency.pop_first() {
+            self.topics.remove(&topic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Topic;
+
+    fn message(topic: &str, payload: &str) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload)
+    }
+
+    #[test]
+    fn stats_are_aggregated_per_topic() {
+        let stats = PublishStats::new(10);
+        stats.record(&message("a/b", "123"));
+        stats.record(&message("c/d", "12345"));
+        stats.record(&message("a/b", "1234567"));
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(
+            snapshot.get("a/b"),
+            Some(&TopicStats {
+                messages: 2,
+                bytes: 10
+            })
+        );
+        assert_eq!(
+            snapshot.get("c/d"),
+            Some(&TopicStats {
+                messages: 1,
+                bytes: 5
+            })
+        );
+    }
+
+    #[test]
+    fn the_least_recently_published_topic_is_evicted() {
+        let stats = PublishStats::new(2);
+        stats.record(&message("a", "x"));
+        stats.record(&message("b", "x"));
+        stats.record(&message("a", "x"));
+        stats.record(&message("c", "x"));
+
+        let mut topics: Vec<_> = stats.snapshot().into_keys().collect();
+        topics.sort();
+        assert_eq!(topics, vec!["a", "c"]);
+    }
+}
@@ -1,9 +1,12 @@
+use crate::connection::PendingSubscriptions;
 use crate::Config;
+use crate::Connection;
 use crate::MqttError;
 use log::warn;
 use rumqttc::AsyncClient;
 use rumqttc::ConnectReturnCode;
 use rumqttc::Event;
+use rumqttc::Outgoing;
 use rumqttc::Packet;
 
 /// Create a persistent session on the MQTT server `config.host`.
@@ -23,6 +26,7 @@ pub async fn init_session(config: &Config) -> Result<(), MqttError> {
 
     let mqtt_options = config.rumqttc_options()?;
     let (mqtt_client, mut event_loop) = AsyncClient::new(mqtt_options, config.queue_capacity);
+    let pending_subscriptions = PendingSubscriptions::default();
 
     loop {
         match event_loop.poll().await {
@@ -34,10 +38,21 @@ pub async fn init_session(config: &Config) -> Result<(), MqttError> {
                 if subscriptions.is_empty() {
                     break;
                 }
-                mqtt_client.subscribe_many(subscriptions).await?;
+                Connection::subscribe_to_topics(
+                    &mqtt_client,
+                    &pending_subscriptions,
+                    subscriptions,
+                )?;
             }
 
-            Ok(Event::Incoming(Packet::SubAck(_))) => {
+            Ok(Event::Outgoing(Outgoing::Subscribe(pkid))) => {
+                pending_subscriptions.sent(pkid);
+            }
+
+            Ok(Event::Incoming(Packet::SubAck(ack))) => {
+                if let Some(err) = pending_subscriptions.acknowledged(&ack) {
+                    return Err(err);
+                };
                 break;
             }
 
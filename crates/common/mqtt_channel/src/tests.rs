@@ -1,3 +1,4 @@
+use crate::publish_queue::PriorityQueue;
 use crate::*;
 use futures::SinkExt;
 use futures::StreamExt;
@@ -119,6 +120,37 @@ async fn subscribing_to_many_topics() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn messages_on_excluded_topics_are_not_delivered() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = Config::default().with_port(broker.port);
+
+    // A client can subscribe to all the topics but some
+    let mut topics = TopicFilter::new_unchecked("te/#");
+    topics.exclude("te/+/+/+/+/cmd/#")?;
+
+    let mqtt_config = mqtt_config
+        .with_session_name("client_excluding_topics")
+        .with_subscriptions(topics);
+    let con = Connection::new(&mqtt_config).await?;
+    let mut messages = con.received;
+
+    // The messages published on excluded topics are dropped by the client
+    broker
+        .publish("te/device/main///cmd/restart/123", "excluded")
+        .await?;
+    broker.publish("te/device/main///m/", "included").await?;
+    assert_eq!(
+        MaybeMessage::Next(message("te/device/main///m/", "included")),
+        next_message(&mut messages).await
+    );
+    assert_eq!(MaybeMessage::Timeout, next_message(&mut messages).await);
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn publishing_messages() -> Result<(), anyhow::Error> {
@@ -154,6 +186,163 @@ async fn publishing_messages() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn streaming_a_large_payload() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker
+    let broker = mqtt_tests::test_mqtt_broker();
+    let chunk_size = 4096;
+    let mqtt_config = Config::default()
+        .with_port(broker.port)
+        .with_max_packet_size(chunk_size);
+
+    // A client subscribed to a topic
+    let topic = "a/streamed/topic";
+    let sub_config = mqtt_config
+        .clone()
+        .with_session_name("stream_receiver")
+        .with_subscriptions(topic.try_into()?);
+    let mut receiver = Connection::new(&sub_config).await?;
+
+    // A payload, too large for a single message, streamed on that topic
+    // (the test broker keeps only 100 KB of messages for slow subscribers)
+    let payload: Vec<u8> = (0..60_000u32).map(|i| (i % 251) as u8).collect();
+    let pub_config = mqtt_config.with_session_name("stream_publisher");
+    let mut publisher = Connection::new(&pub_config).await?;
+    let published = publisher
+        .published
+        .publish_stream(
+            &Topic::new_unchecked(topic),
+            QoS::AtLeastOnce,
+            chunk_size,
+            payload.as_slice(),
+        )
+        .await?;
+    assert_eq!(published, payload.len());
+
+    // Is received chunk by chunk, till an end record
+    let mut received = vec![];
+    let mut chunks = 0;
+    loop {
+        let MaybeMessage::Next(chunk) = next_message(&mut receiver.received).await else {
+            panic!("The stream has not been fully received");
+        };
+        assert!(chunk.payload_bytes().len() <= chunk_size);
+        match StreamRecord::parse(chunk.payload_bytes()) {
+            Some(StreamRecord::Data(data)) => received.extend_from_slice(data),
+            Some(StreamRecord::End) => break,
+            record => panic!("Unexpected stream record: {record:?}"),
+        }
+        chunks += 1;
+    }
+    assert_eq!(chunks, payload.len().div_ceil(chunk_size - 1));
+    assert_eq!(received, payload);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn streaming_a_payload_that_cannot_be_read_to_the_end() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = Config::default().with_port(broker.port);
+
+    // A client subscribed to a topic
+    let topic = "a/streamed/topic";
+    let sub_config = mqtt_config
+        .clone()
+        .with_session_name("aborted_stream_receiver")
+        .with_subscriptions(topic.try_into()?);
+    let mut receiver = Connection::new(&sub_config).await?;
+
+    // A stream failing after a first chunk
+    let pub_config = mqtt_config.with_session_name("aborted_stream_publisher");
+    let mut publisher = Connection::new(&pub_config).await?;
+    let stream = tokio::io::AsyncReadExt::chain(&b"first chunk"[..], FailingReader);
+    let result = publisher
+        .published
+        .publish_stream(&Topic::new_unchecked(topic), QoS::AtLeastOnce, 12, stream)
+        .await;
+    assert!(matches!(result, Err(MqttError::StreamReadError(_))));
+
+    // Is received up to the failure, which is marked by an abort record
+    let MaybeMessage::Next(chunk) = next_message(&mut receiver.received).await else {
+        panic!("Missing chunk");
+    };
+    assert_eq!(
+        StreamRecord::parse(chunk.payload_bytes()),
+        Some(StreamRecord::Data(b"first chunk"))
+    );
+    let MaybeMessage::Next(chunk) = next_message(&mut receiver.received).await else {
+        panic!("Missing abort record");
+    };
+    assert_eq!(
+        StreamRecord::parse(chunk.payload_bytes()),
+        Some(StreamRecord::Abort {
+            reason: "broken stream"
+        })
+    );
+
+    Ok(())
+}
+
+/// A stream of bytes that always fails to be read
+struct FailingReader;
+
+impl tokio::io::AsyncRead for FailingReader {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        _buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Err(std::io::Error::other("broken stream")))
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn counting_published_bytes_per_topic() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = Config::default().with_port(broker.port);
+
+    // A client collecting statistics on the published messages
+    let mqtt_config = mqtt_config
+        .with_session_name("counting_published_bytes_per_topic")
+        .with_publish_stats(10);
+    let mut con = Connection::new(&mqtt_config).await?;
+    let stats = con
+        .publish_stats
+        .clone()
+        .expect("publish stats are enabled");
+
+    // When messages are published on two topics
+    con.published.send(message("foo/topic", "12345")).await?;
+    con.published.send(message("foo/topic", "123")).await?;
+    con.published.send(message("bar/topic", "1234567")).await?;
+    con.close().await;
+
+    // Then the bytes are accounted per topic
+    let snapshot = stats.snapshot();
+    assert_eq!(
+        snapshot.get("foo/topic"),
+        Some(&TopicStats {
+            messages: 2,
+            bytes: 8
+        })
+    );
+    assert_eq!(
+        snapshot.get("bar/topic"),
+        Some(&TopicStats {
+            messages: 1,
+            bytes: 7
+        })
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn implementing_a_message_mapper() -> Result<(), anyhow::Error> {
@@ -484,6 +673,41 @@ async fn ensure_that_all_messages_are_sent_before_disconnect() -> Result<(), any
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn graceful_close_waits_for_the_messages_to_be_acknowledged() -> Result<(), anyhow::Error> {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let topic = "data/topic";
+    let mut messages = broker.messages_published_on(topic).await;
+
+    let mqtt_config = Config::default().with_port(broker.port);
+    let topic = Topic::new_unchecked(topic);
+    let mut con = Connection::new(&mqtt_config).await.expect("a connection");
+    for payload in ["datum 1", "datum 2", "datum 3"] {
+        con.published
+            .send(MqttMessage::new(&topic, payload).with_qos(QoS::AtLeastOnce))
+            .await
+            .expect("message sent");
+    }
+
+    // The close only waits for the grace period if some messages are not acknowledged
+    let grace_period = Duration::from_secs(10);
+    let closed = tokio::time::timeout(grace_period, con.close_gracefully(grace_period)).await;
+    assert!(
+        closed.is_ok(),
+        "The messages should have been acknowledged before the end of the grace period"
+    );
+
+    mqtt_tests::assert_received(
+        &mut messages,
+        TIMEOUT,
+        vec!["datum 1", "datum 2", "datum 3"],
+    )
+    .await;
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn ensure_that_last_will_message_is_delivered() -> Result<(), anyhow::Error> {
@@ -592,6 +816,106 @@ async fn test_retain_message_delivery() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[tokio::test]
+#[serial]
+async fn subscribe_and_settle_returns_the_retained_messages() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker with pre-existing retained messages
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = Config::default().with_port(broker.port);
+    broker
+        .publish_with_opts("settle/topic/1", "retained 1", QoS::AtLeastOnce, true)
+        .await?;
+    broker
+        .publish_with_opts("settle/topic/2", "retained 2", QoS::AtLeastOnce, true)
+        .await?;
+
+    // A client that subscribes and settles receives the retained messages at once
+    let (retained, mut con) = Connection::subscribe_and_settle(
+        &mqtt_config,
+        "settle/topic/+".try_into()?,
+        Duration::from_millis(200),
+    )
+    .await?;
+    let mut retained: Vec<_> = retained
+        .into_iter()
+        .map(|msg| {
+            (
+                msg.topic.name.clone(),
+                msg.payload_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    retained.sort();
+    assert_eq!(
+        retained,
+        vec![
+            ("settle/topic/1".to_string(), "retained 1".to_string()),
+            ("settle/topic/2".to_string(), "retained 2".to_string()),
+        ]
+    );
+
+    // Then only the live messages are received
+    broker.publish("settle/topic/1", "live").await?;
+    assert_eq!(
+        MaybeMessage::Next(message("settle/topic/1", "live")),
+        next_message(&mut con.received).await
+    );
+
+    // Clear the retained messages
+    for topic in ["settle/topic/1", "settle/topic/2"] {
+        broker
+            .publish_with_opts(topic, "", QoS::AtLeastOnce, true)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn an_established_connection_can_subscribe_to_new_topics() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker with a retained message
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = Config::default()
+        .with_port(broker.port)
+        .with_subscriptions("dynamic/initial".try_into()?);
+    broker
+        .publish_with_opts("dynamic/later", "retained", QoS::AtLeastOnce, true)
+        .await?;
+
+    // A connection subscribing to a new topic receives the retained message, and then the live ones
+    let mut con = Connection::new(&mqtt_config).await?;
+    let topics: TopicFilter = "dynamic/later".try_into()?;
+    con.subscriptions.subscribe(&topics).await?;
+    assert_eq!(
+        MaybeMessage::Next(message("dynamic/later", "retained").with_retain()),
+        next_message(&mut con.received).await
+    );
+    broker.publish("dynamic/later", "live").await?;
+    assert_eq!(
+        MaybeMessage::Next(message("dynamic/later", "live")),
+        next_message(&mut con.received).await
+    );
+
+    // Once unsubscribed, only the messages of the initial subscriptions are received
+    // The unsubscription being not acknowledged, give the broker some time to process it
+    con.subscriptions.unsubscribe(&topics)?;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    broker.publish("dynamic/later", "ignored").await?;
+    broker.publish("dynamic/initial", "received").await?;
+    assert_eq!(
+        MaybeMessage::Next(message("dynamic/initial", "received")),
+        next_message(&mut con.received).await
+    );
+
+    // Clear the retained message
+    broker
+        .publish_with_opts("dynamic/later", "", QoS::AtLeastOnce, true)
+        .await?;
+
+    Ok(())
+}
+
 #[tokio::test]
 #[serial]
 async fn test_max_packet_size_validation() -> Result<(), anyhow::Error> {
@@ -625,3 +949,631 @@ async fn test_max_packet_size_validation() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[serial]
+async fn oversized_messages_are_acknowledged_even_if_dropped() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker, which sends at most 200 unacknowledged messages to a client
+    let broker = mqtt_tests::test_mqtt_broker();
+    let topic = "a/large/topic";
+    let mqtt_config = Config::default()
+        .with_port(broker.port)
+        .with_max_packet_size(4)
+        .with_session_name("oversized_client")
+        .with_subscriptions(topic.try_into()?);
+    let mut con = Connection::new(&mqtt_config).await?;
+
+    // More oversized messages than the broker inflight window are dropped by the client
+    let mut publisher = Connection::new(&Config::default().with_port(broker.port)).await?;
+    for _ in 0..250 {
+        publisher
+            .published
+            .send(message(topic, "aaaaa").with_qos(QoS::AtLeastOnce))
+            .await?;
+    }
+    publisher
+        .published
+        .send(message(topic, "aaa").with_qos(QoS::AtLeastOnce))
+        .await?;
+    publisher.close().await;
+
+    // But acknowledged, so the broker keeps sending messages
+    assert_eq!(
+        MaybeMessage::Next(message(topic, "aaa").with_qos(QoS::AtLeastOnce)),
+        next_message(&mut con.received).await
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn pausing_and_resuming_message_delivery() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = Config::default().with_port(broker.port);
+
+    // A client subscribes to a topic on connect
+    let topic = "a/paused/topic";
+    let mqtt_config = mqtt_config
+        .with_session_name("pausing_client")
+        .with_subscriptions(topic.try_into()?);
+    let mut con = Connection::new(&mqtt_config).await?;
+
+    // While paused
+    con.pause_handle.pause();
+    assert!(con.pause_handle.is_paused());
+
+    // Any messages published on that topic ...
+    broker.publish(topic, "msg 1").await?;
+    broker.publish(topic, "msg 2").await?;
+
+    // ... are not delivered to the client
+    assert_eq!(MaybeMessage::Timeout, next_message(&mut con.received).await);
+
+    // Till resumed
+    con.pause_handle.resume();
+    assert_eq!(
+        MaybeMessage::Next(message(topic, "msg 1")),
+        next_message(&mut con.received).await
+    );
+    assert_eq!(
+        MaybeMessage::Next(message(topic, "msg 2")),
+        next_message(&mut con.received).await
+    );
+
+    // Then messages are delivered as usual
+    broker.publish(topic, "msg 3").await?;
+    assert_eq!(
+        MaybeMessage::Next(message(topic, "msg 3")),
+        next_message(&mut con.received).await
+    );
+
+    Ok(())
+}
+
+#[test]
+fn oldest_qos0_messages_are_dropped_while_paused() {
+    use rumqttc::Publish;
+
+    let mut held = crate::connection::HeldMessages::new(2);
+    for (payload, qos) in [
+        ("qos0 1", QoS::AtMostOnce),
+        ("qos1 1", QoS::AtLeastOnce),
+        ("qos0 2", QoS::AtMostOnce),
+        ("qos0 3", QoS::AtMostOnce),
+        ("qos2 1", QoS::ExactlyOnce),
+        ("qos0 4", QoS::AtMostOnce),
+    ] {
+        held.push(Publish::new("a/chatty/topic", qos, payload));
+    }
+
+    // At most 2 QoS 0 messages are held, the oldest being dropped,
+    // while all the QoS 1 and QoS 2 messages are kept, in order
+    let delivered: Vec<_> = std::iter::from_fn(|| held.pop())
+        .map(|msg| String::from_utf8(msg.payload.to_vec()).unwrap())
+        .collect();
+    assert_eq!(delivered, vec!["qos1 1", "qos0 3", "qos2 1", "qos0 4"]);
+
+    // Once delivered, room is made for new QoS 0 messages
+    held.push(Publish::new("a/chatty/topic", QoS::AtMostOnce, "qos0 5"));
+    held.push(Publish::new("a/chatty/topic", QoS::AtMostOnce, "qos0 6"));
+    assert_eq!(
+        held.pop().map(|msg| msg.payload.to_vec()),
+        Some(b"qos0 5".to_vec())
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn a_new_connection_is_connected() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = Config::default().with_port(broker.port);
+
+    // A connection is established when created
+    let con = Connection::new(&mqtt_config).await?;
+    assert!(con.is_connected());
+    assert!(tokio::time::timeout(TIMEOUT, con.connected())
+        .await
+        .expect("the connection to be established"));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[serial]
+async fn a_closed_connection_is_disconnected() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = Config::default().with_port(broker.port);
+
+    // Given a connection which status is observed
+    let con = Connection::new(&mqtt_config).await?;
+    let status = con.status.clone();
+    assert!(status.is_connected());
+
+    // Once closed, the connection is no more reported as established
+    con.close().await;
+    assert!(!tokio::time::timeout(TIMEOUT, status.connected())
+        .await
+        .expect("the wait to be aborted as the connection is closed"));
+    assert!(!status.is_connected());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn waiting_for_a_connection() {
+    let (connected, status) = ConnectionStatus::new(false);
+    assert!(!status.is_connected());
+
+    // While disconnected, waiting for a connection blocks
+    assert!(tokio::time::timeout(TIMEOUT, status.connected())
+        .await
+        .is_err());
+
+    // Till the connection is established
+    let waiting = tokio::spawn({
+        let status = status.clone();
+        async move { status.connected().await }
+    });
+    connected.send_replace(true);
+    assert!(tokio::time::timeout(TIMEOUT, waiting)
+        .await
+        .expect("the connection to be established")
+        .unwrap());
+    assert!(status.is_connected());
+}
+
+#[test]
+fn subscription_failures_report_the_refused_filters() {
+    use rumqttc::SubAck;
+    use rumqttc::SubscribeReasonCode;
+
+    let subscriptions: TopicFilter = vec!["a/b", "c/#", "d/+"].try_into().unwrap();
+    let subscriptions = subscriptions.filters();
+
+    let ack = SubAck::new(
+        1,
+        vec![
+            SubscribeReasonCode::Success(QoS::AtLeastOnce),
+            SubscribeReasonCode::Success(QoS::AtLeastOnce),
+            SubscribeReasonCode::Success(QoS::AtLeastOnce),
+        ],
+    );
+    assert!(MqttError::maybe_subscription_error(&ack, &subscriptions).is_none());
+
+    let ack = SubAck::new(
+        1,
+        vec![
+            SubscribeReasonCode::Success(QoS::AtLeastOnce),
+            SubscribeReasonCode::Failure,
+            SubscribeReasonCode::Success(QoS::AtLeastOnce),
+        ],
+    );
+    let err = MqttError::maybe_subscription_error(&ack, &subscriptions).unwrap();
+    assert!(
+        matches!(&err, MqttError::SubscriptionFailure { patterns } if patterns == &vec!["c/#"])
+    );
+    assert_eq!(
+        err.to_string(),
+        "MQTT subscription failure: the broker refused c/#"
+    );
+}
+
+#[test]
+fn subscription_failures_are_reported_against_the_acknowledged_request() {
+    use crate::connection::PendingSubscriptions;
+    use rumqttc::SubAck;
+    use rumqttc::SubscribeReasonCode;
+
+    let first: TopicFilter = vec!["a/b", "c/#"].try_into().unwrap();
+    let second: TopicFilter = vec!["d/+", "e/f"].try_into().unwrap();
+    let pending = PendingSubscriptions::default();
+    pending.requested(first.filters());
+    pending.requested(second.filters());
+    pending.sent(7);
+    pending.sent(8);
+
+    // The acknowledgements are matched by packet id, whatever their order
+    let second_ack = SubAck::new(
+        8,
+        vec![
+            SubscribeReasonCode::Failure,
+            SubscribeReasonCode::Success(QoS::AtLeastOnce),
+        ],
+    );
+    let err = pending.acknowledged(&second_ack).unwrap();
+    assert!(
+        matches!(&err, MqttError::SubscriptionFailure { patterns } if patterns == &vec!["d/+"])
+    );
+
+    let first_ack = SubAck::new(
+        7,
+        vec![
+            SubscribeReasonCode::Success(QoS::AtLeastOnce),
+            SubscribeReasonCode::Failure,
+        ],
+    );
+    let err = pending.acknowledged(&first_ack).unwrap();
+    assert!(
+        matches!(&err, MqttError::SubscriptionFailure { patterns } if patterns == &vec!["c/#"])
+    );
+}
+
+#[tokio::test]
+async fn block_policy_makes_publishers_wait_when_the_queue_is_full() {
+    let limit = QueueLimit {
+        capacity: 2,
+        overflow_policy: OverflowPolicy::Block,
+    };
+    let (mut published, mut queued) =
+        crate::publish_queue::publish_queue(Some(limit), TopicFilter::empty());
+    let topic = "a/test/topic";
+
+    // While the connection is stalled, the queue accepts up to `capacity` messages
+    for i in 1..=2 {
+        tokio::time::timeout(
+            TIMEOUT,
+            published.publish(message(topic, &format!("msg {i}"))),
+        )
+        .await
+        .expect("the queue is not full")
+        .unwrap();
+    }
+
+    // Then the publisher is blocked
+    {
+        let mut blocked_publisher = published.clone();
+        let mut blocked = std::pin::pin!(blocked_publisher.publish(message(topic, "msg 3")));
+        assert!(
+            futures::poll!(&mut blocked).is_pending(),
+            "the publisher should be blocked"
+        );
+
+        // Till there is room in the queue
+        assert_eq!(queued.next().await, Some(message(topic, "msg 1")));
+        tokio::time::timeout(TIMEOUT, blocked)
+            .await
+            .expect("the publisher should be unblocked")
+            .unwrap();
+    }
+
+    // No messages are lost
+    published.close_channel();
+    let remaining: Vec<_> = queued.collect().await;
+    assert_eq!(
+        remaining,
+        vec![message(topic, "msg 2"), message(topic, "msg 3")]
+    );
+}
+
+#[test]
+fn drop_newest_policy_drops_new_messages_when_the_queue_is_full() {
+    let published = publish_while_stalled(OverflowPolicy::DropNewest, 5);
+
+    // Only the first two messages are kept in the queue, all the subsequent messages being dropped
+    assert_eq!(published, vec!["msg 1", "msg 2"]);
+}
+
+#[test]
+fn drop_oldest_policy_drops_old_messages_when_the_queue_is_full() {
+    let published = publish_while_stalled(OverflowPolicy::DropOldest, 5);
+
+    // Only the last two messages are kept in the queue
+    assert_eq!(published, vec!["msg 4", "msg 5"]);
+}
+
+#[tokio::test]
+async fn drop_policies_never_block_publishers() {
+    for overflow_policy in [OverflowPolicy::DropNewest, OverflowPolicy::DropOldest] {
+        let limit = QueueLimit {
+            capacity: 2,
+            overflow_policy,
+        };
+        let (mut published, _queued) =
+            crate::publish_queue::publish_queue(Some(limit), TopicFilter::empty());
+
+        // While nothing is consumed, publishing never blocks
+        for i in 1..=5 {
+            let publish = published.publish(message("a/test/topic", &format!("msg {i}")));
+            assert!(
+                futures::FutureExt::now_or_never(publish).is_some(),
+                "publishers should not be blocked"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn a_publish_queue_with_no_capacity_is_rejected() {
+    let mqtt_config = Config::default().with_max_queued_messages(0, OverflowPolicy::Block);
+
+    let err = Connection::new(&mqtt_config).await.err();
+    assert!(matches!(err, Some(MqttError::InvalidQueueConfig)));
+
+    // The check applies to the queue prioritizing messages too
+    let mqtt_config = mqtt_config.with_priority_topics(TopicFilter::new_unchecked("alarms/#"));
+    let err = Connection::new(&mqtt_config).await.err();
+    assert!(matches!(err, Some(MqttError::InvalidQueueConfig)));
+}
+
+/// Push `count` messages on a queue of capacity 2, while nothing is consumed,
+/// and return the messages that are kept in the queue
+fn publish_while_stalled(overflow_policy: OverflowPolicy, count: usize) -> Vec<String> {
+    let mut queue = PriorityQueue::new(TopicFilter::empty(), 2, overflow_policy);
+    for i in 1..=count {
+        queue.push(message("a/test/topic", &format!("msg {i}")));
+    }
+
+    std::iter::from_fn(|| queue.pop_front())
+        .map(|message| message.payload_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn priority_messages_are_sent_first_while_congested() {
+    let priority_topics = TopicFilter::new_unchecked("alarms/#");
+    let mut queue = PriorityQueue::new(priority_topics, usize::MAX, OverflowPolicy::DropNewest);
+
+    // A first message is handed over to the connection, which is then stalled
+    queue.push(message("telemetry", "low 1"));
+    let mut sent = vec![queue.pop_front().unwrap()];
+
+    // While the connection is stalled, low-priority messages are queued
+    for i in 2..=3 {
+        assert_eq!(queue.push(message("telemetry", &format!("low {i}"))), None);
+    }
+    // and then high-priority messages
+    for i in 1..=2 {
+        assert_eq!(
+            queue.push(message("alarms/critical", &format!("high {i}"))),
+            None
+        );
+    }
+
+    // The high-priority messages jump ahead of the other queued messages, preserving FIFO within a class
+    sent.extend(std::iter::from_fn(|| queue.pop_front()));
+    let sent: Vec<_> = sent
+        .iter()
+        .map(|message| message.payload_str().unwrap())
+        .collect();
+    assert_eq!(sent, vec!["low 1", "high 1", "high 2", "low 2", "low 3"]);
+}
+
+#[test]
+fn low_priority_messages_are_dropped_first() {
+    let priority_topics = TopicFilter::new_unchecked("alarms/#");
+    let mut queue = PriorityQueue::new(priority_topics, 2, OverflowPolicy::DropOldest);
+
+    // A first message is handed over to the connection, which is then stalled
+    queue.push(message("telemetry", "low 1"));
+    let mut sent = vec![queue.pop_front().unwrap()];
+
+    // The low-priority messages are dropped to make room for the high-priority ones
+    let mut dropped = vec![];
+    for (topic, payload) in [
+        ("telemetry", "low 2"),
+        ("alarms/critical", "high 1"),
+        ("telemetry", "low 3"),
+        ("alarms/critical", "high 2"),
+    ] {
+        dropped.extend(queue.push(message(topic, payload)));
+    }
+    assert_eq!(
+        dropped,
+        vec![message("telemetry", "low 2"), message("telemetry", "low 3")]
+    );
+
+    sent.extend(std::iter::from_fn(|| queue.pop_front()));
+    let sent: Vec<_> = sent
+        .iter()
+        .map(|message| message.payload_str().unwrap())
+        .collect();
+    assert_eq!(sent, vec!["low 1", "high 1", "high 2"]);
+}
+
+#[tokio::test]
+async fn a_session_can_be_cleaned_on_first_connect_only() {
+    // Given a broker that closes the first connection right after the CONNACK
+    let (port, mut received) = broker_closing_the_first_connection().await;
+
+    // When a client connects with a session that has to be cleaned on first connect only
+    let mqtt_config = Config::default()
+        .with_port(port)
+        .with_session_name("a-session-cleaned-once")
+        .with_clean_session_on_first_connect_only(true);
+    let _connection = Connection::new(&mqtt_config).await.unwrap();
+
+    // Then the session is cleaned on the first connect
+    // But persisted on reconnect, which happens after a pause
+    assert_eq!(
+        clean_session_flags(&mut received, 2).await,
+        vec![true, false]
+    );
+}
+
+#[tokio::test]
+async fn a_clean_session_is_cleaned_on_reconnect_too() {
+    // Given a broker that closes the first connection right after the CONNACK
+    let (port, mut received) = broker_closing_the_first_connection().await;
+
+    // When a client connects with a clean session, even if cleaned on first connect only
+    let mqtt_config = Config::default()
+        .with_port(port)
+        .with_session_name("a-session-always-cleaned")
+        .with_clean_session(true)
+        .with_clean_session_on_first_connect_only(true);
+    let _connection = Connection::new(&mqtt_config).await.unwrap();
+
+    // Then the session is cleaned on the first connect and on reconnect
+    assert_eq!(
+        clean_session_flags(&mut received, 2).await,
+        vec![true, true]
+    );
+}
+
+#[tokio::test]
+async fn an_unnamed_session_is_cleaned_on_reconnect_too() {
+    // Given a broker that closes the first connection right after the CONNACK
+    let (port, mut received) = broker_closing_the_first_connection().await;
+
+    // When a client connects with no session name, a session cleaned on first connect only being ignored
+    let mqtt_config = Config::default()
+        .with_port(port)
+        .with_clean_session_on_first_connect_only(true);
+    let _connection = Connection::new(&mqtt_config).await.unwrap();
+
+    // Then the session is cleaned on the first connect and on reconnect
+    assert_eq!(
+        clean_session_flags(&mut received, 2).await,
+        vec![true, true]
+    );
+}
+
+/// Return the clean session flags of the first `count` connections received by a fake broker
+async fn clean_session_flags(
+    received: &mut tokio::sync::mpsc::UnboundedReceiver<(usize, rumqttc::Packet)>,
+    count: usize,
+) -> Vec<bool> {
+    let mut flags = vec![];
+    while flags.len() < count {
+        // The reconnection happens after a pause
+        match tokio::time::timeout(5 * TIMEOUT, received.recv()).await {
+            Ok(Some((_, rumqttc::Packet::Connect(connect)))) => flags.push(connect.clean_session),
+            Ok(Some(_)) => continue,
+            Ok(None) | Err(_) => break,
+        }
+    }
+    flags
+}
+
+/// Launch a fake MQTT broker that closes the first connection right after the CONNACK
+///
+/// Return the port of the broker and the packets it receives,
+/// each packet being tagged with the number of the connection on which it has been received.
+async fn broker_closing_the_first_connection() -> (
+    u16,
+    tokio::sync::mpsc::UnboundedReceiver<(usize, rumqttc::Packet)>,
+) {
+    use bytes::BytesMut;
+    use rumqttc::mqttbytes;
+    use rumqttc::ConnAck;
+    use rumqttc::ConnectReturnCode;
+    use rumqttc::Packet;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (received_sender, received) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for connection_count in 1.. {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                break;
+            };
+            let mut buffer = BytesMut::new();
+            loop {
+                match mqttbytes::v4::read(&mut buffer, 1024) {
+                    Ok(Packet::Connect(connect)) => {
+                        let _ = received_sender.send((connection_count, Packet::Connect(connect)));
+                        let mut connack = BytesMut::new();
+                        ConnAck::new(ConnectReturnCode::Success, false)
+                            .write(&mut connack)
+                            .unwrap();
+                        socket.write_all(&connack).await.unwrap();
+                        if connection_count == 1 {
+                            break;
+                        }
+                    }
+                    Ok(packet) => {
+                        let _ = received_sender.send((connection_count, packet));
+                    }
+                    Err(mqttbytes::Error::InsufficientBytes(_)) => {
+                        // Keep the connection open, reading till the client disconnects
+                        if socket.read_buf(&mut buffer).await.unwrap_or(0) == 0 {
+                            break;
+                        }
+                    }
+                    Err(err) => panic!("Invalid MQTT packet: {err}"),
+                }
+            }
+        }
+    });
+
+    (port, received)
+}
+
+#[tokio::test]
+async fn registered_messages_are_republished_on_reconnect() {
+    // Given a fake broker that closes the first connection right after the CONNACK
+    let (port, mut received) = broker_closing_the_first_connection().await;
+
+    // When a client registers messages to be republished on reconnect
+    let mqtt_config = Config::default()
+        .with_port(port)
+        .with_session_name("republishing-on-reconnect");
+    let connection = Connection::new(&mqtt_config).await.unwrap();
+    let republished = connection.republish_on_reconnect.clone();
+    republished.register(message("a/topic", "old state"));
+    republished.register(message("b/topic", "b state"));
+    republished.register(message("a/topic", "new state"));
+    republished.register(message("c/topic", "c state"));
+    republished.unregister(&Topic::new_unchecked("c/topic"));
+
+    // Then these messages are published on reconnect, which happens after a pause
+    for expected in ["b/topic", "a/topic"] {
+        let publish = tokio::time::timeout(5 * TIMEOUT, next_publish_topic(&mut received)).await;
+        assert_eq!(publish, Ok(Some((2, expected.to_string()))));
+    }
+    assert_eq!(
+        republished.messages(),
+        vec![
+            message("b/topic", "b state"),
+            message("a/topic", "new state")
+        ]
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn registered_messages_are_published_on_connect() -> Result<(), anyhow::Error> {
+    // Given an MQTT broker
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mut all_messages = broker.messages_published_on("#").await;
+
+    // When a connection is created with messages to be republished
+    let mqtt_config = Config::default()
+        .with_port(broker.port)
+        .with_session_name("publishing_registered_messages_on_connect")
+        .with_republished_message(message("a/topic", "old state"))
+        .with_republished_message(message("b/topic", "b state"))
+        .with_republished_message(message("a/topic", "new state"));
+    let con = Connection::new(&mqtt_config).await?;
+
+    // Then these messages are published as soon as connected
+    mqtt_tests::assert_received(&mut all_messages, TIMEOUT, vec!["b state", "new state"]).await;
+    assert_eq!(
+        con.republish_on_reconnect.messages(),
+        vec![
+            message("b/topic", "b state"),
+            message("a/topic", "new state")
+        ]
+    );
+
+    Ok(())
+}
+
+/// The topic of the next message published to a fake broker, tagged with the connection count
+async fn next_publish_topic(
+    received: &mut tokio::sync::mpsc::UnboundedReceiver<(usize, rumqttc::Packet)>,
+) -> Option<(usize, String)> {
+    while let Some((connection_count, packet)) = received.recv().await {
+        if let rumqttc::Packet::Publish(publish) = packet {
+            return Some((connection_count, publish.topic));
+        }
+    }
+    None
+}
@@ -25,7 +25,10 @@ impl Topic {
     }
 
     /// Build a new topic, assuming the name is valid
+    ///
+    /// In debug builds, this panics if the name is actually invalid.
     pub fn new_unchecked(name: &str) -> Topic {
+        debug_assert!(rumqttc::valid_topic(name), "Invalid MQTT topic: {name:?}");
         let name = String::from(name);
         Topic { name }
     }
@@ -34,15 +37,24 @@ impl Topic {
     pub fn filter(&self) -> TopicFilter {
         TopicFilter {
             patterns: vec![self.name.clone()],
+            exclusions: vec![],
             qos: QoS::AtLeastOnce,
         }
     }
 }
 
 /// An MQTT topic filter
+///
+/// A filter is made of patterns, the topics to subscribe to,
+/// and of exclusion patterns, the topics to ignore even if matched by a pattern.
+///
+/// MQTT brokers having no support for exclusions, these are applied client-side:
+/// the broker is only given the patterns, and the messages received on an excluded topic
+/// are acknowledged but not delivered by the [Connection](crate::Connection).
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct TopicFilter {
     patterns: Vec<String>,
+    exclusions: Vec<String>,
     qos: QoS,
 }
 
@@ -60,6 +72,7 @@ impl TopicFilter {
         if rumqttc::valid_filter(&pattern) {
             Ok(TopicFilter {
                 patterns: vec![pattern],
+                exclusions: vec![],
                 qos,
             })
         } else {
@@ -71,15 +84,23 @@ impl TopicFilter {
     pub fn empty() -> TopicFilter {
         TopicFilter {
             patterns: vec![],
+            exclusions: vec![],
             qos: QoS::AtLeastOnce,
         }
     }
 
     /// Build a new topic filter, assuming the pattern is valid.
+    ///
+    /// In debug builds, this panics if the pattern is actually invalid.
     pub fn new_unchecked(pattern: &str) -> TopicFilter {
+        debug_assert!(
+            rumqttc::valid_filter(pattern),
+            "Invalid MQTT topic filter: {pattern:?}"
+        );
         let patterns = vec![String::from(pattern)];
         TopicFilter {
             patterns,
+            exclusions: vec![],
             qos: QoS::AtLeastOnce,
         }
     }
@@ -95,17 +116,56 @@ impl TopicFilter {
         }
     }
 
+    /// Check if the pattern is valid and exclude the matching topics from this topic filter.
+    ///
+    /// A topic matching an exclusion pattern is rejected, even if matching one of the patterns.
+    pub fn exclude(&mut self, pattern: &str) -> Result<(), MqttError> {
+        let pattern = String::from(pattern);
+        if rumqttc::valid_filter(&pattern) {
+            self.exclusions.push(pattern);
+            Ok(())
+        } else {
+            Err(MqttError::InvalidFilter { pattern })
+        }
+    }
+
+    /// Check all the patterns are valid and build a topic filter combining them.
+    ///
+    /// Return an `MqttError::InvalidFilter` error for the first invalid pattern, if any.
+    pub fn try_from_iter<I, P>(patterns: I) -> Result<TopicFilter, MqttError>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        let mut filter = TopicFilter::empty();
+        for pattern in patterns {
+            filter.add(pattern.as_ref())?
+        }
+        Ok(filter)
+    }
+
     /// Assuming the pattern is valid and add it to this topic filter.
+    ///
+    /// In debug builds, this panics if the pattern is actually invalid.
     pub fn add_unchecked(&mut self, pattern: &str) {
+        debug_assert!(
+            rumqttc::valid_filter(pattern),
+            "Invalid MQTT topic filter: {pattern:?}"
+        );
         let pattern = String::from(pattern);
         self.patterns.push(pattern);
     }
 
     /// Add all the other topics to this one.
+    ///
+    /// The exclusions of both filters apply to the combined filter.
     pub fn add_all(&mut self, other: TopicFilter) {
         for pattern in other.patterns {
             self.patterns.push(pattern)
         }
+        for pattern in other.exclusions {
+            self.exclusions.push(pattern)
+        }
     }
 
     /// Check if the given topic matches this filter pattern.
@@ -113,6 +173,14 @@ impl TopicFilter {
         self.patterns
             .iter()
             .any(|pattern| rumqttc::matches(&topic.name, pattern))
+            && !self.is_excluded(&topic.name)
+    }
+
+    /// Check if the given topic name matches one of the exclusion patterns.
+    pub(crate) fn is_excluded(&self, topic: &str) -> bool {
+        self.exclusions
+            .iter()
+            .any(|pattern| rumqttc::matches(topic, pattern))
     }
 
     /// Check if the given message matches this filter pattern.
@@ -175,6 +243,10 @@ impl TopicFilter {
     pub fn patterns(&self) -> &Vec<String> {
         &self.patterns
     }
+
+    pub fn exclusions(&self) -> &Vec<String> {
+        &self.exclusions
+    }
 }
 
 impl TryInto<Topic> for &str {
@@ -213,11 +285,7 @@ impl TryInto<TopicFilter> for Vec<&str> {
     type Error = MqttError;
 
     fn try_into(self) -> Result<TopicFilter, Self::Error> {
-        let mut filter = TopicFilter::empty();
-        for pattern in self.into_iter() {
-            filter.add(pattern)?
-        }
-        Ok(filter)
+        TopicFilter::try_from_iter(self)
     }
 }
 
@@ -231,11 +299,7 @@ impl TryInto<TopicFilter> for Vec<String> {
     type Error = MqttError;
 
     fn try_into(self) -> Result<TopicFilter, Self::Error> {
-        let mut filter = TopicFilter::empty();
-        for pattern in self.into_iter() {
-            filter.add(pattern.as_str())?
-        }
-        Ok(filter)
+        TopicFilter::try_from_iter(self)
     }
 }
 
@@ -249,11 +313,7 @@ impl TryInto<TopicFilter> for HashSet<String> {
     type Error = MqttError;
 
     fn try_into(self) -> Result<TopicFilter, Self::Error> {
-        let mut filter = TopicFilter::empty();
-        for pattern in self.into_iter() {
-            filter.add(pattern.as_str())?
-        }
-        Ok(filter)
+        TopicFilter::try_from_iter(self)
     }
 }
 
@@ -273,6 +333,20 @@ mod tests {
         assert!(Topic::new("/temp/#").is_err());
     }
 
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Invalid MQTT topic: \"temp/+\"")]
+    fn unchecked_invalid_topic_panics_in_debug_builds() {
+        let _ = Topic::new_unchecked("temp/+");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Invalid MQTT topic filter: \"a/#/b\"")]
+    fn unchecked_invalid_topic_filter_panics_in_debug_builds() {
+        let _ = TopicFilter::new_unchecked("a/#/b");
+    }
+
     #[test]
     fn check_valid_topic_filter() {
         assert!(TopicFilter::new("a/b/c").is_ok());
@@ -288,6 +362,65 @@ mod tests {
         assert!(TopicFilter::new("/a/#/+").is_err());
     }
 
+    #[test]
+    fn build_topic_filter_from_valid_patterns() {
+        let filter = TopicFilter::try_from_iter(["a/b/c", "a/b/#", "a/+/b"]).unwrap();
+        assert_eq!(filter.patterns(), &vec!["a/b/c", "a/b/#", "a/+/b"]);
+
+        let filter = TopicFilter::try_from_iter(Vec::<String>::new()).unwrap();
+        assert_eq!(filter, TopicFilter::empty());
+    }
+
+    #[test]
+    fn build_topic_filter_reports_the_first_invalid_pattern() {
+        let error = TopicFilter::try_from_iter(["a/b/c", "/a/#/b", "a/+/b", "/a/#/+"]).unwrap_err();
+        assert!(matches!(error, MqttError::InvalidFilter { pattern } if pattern == "/a/#/b"));
+    }
+
+    #[test]
+    fn excluded_topics_are_rejected() {
+        let mut filter = TopicFilter::new_unchecked("te/#");
+        filter.exclude("te/+/+/+/+/cmd/#").unwrap();
+
+        assert!(filter.accept_topic(&Topic::new_unchecked("te/device/main///m/temp")));
+        assert!(filter.accept_topic(&Topic::new_unchecked("te/device/main///e/cmd")));
+        assert!(!filter.accept_topic(&Topic::new_unchecked("te/device/main///cmd/restart")));
+        assert!(!filter.accept_topic(&Topic::new_unchecked(
+            "te/device/child01///cmd/restart/c8y-mapper-1"
+        )));
+        assert!(!filter.accept_topic(&Topic::new_unchecked("c8y/s/ds")));
+
+        // The exclusions are not part of the subscriptions
+        assert_eq!(filter.patterns(), &vec!["te/#"]);
+        assert_eq!(filter.exclusions(), &vec!["te/+/+/+/+/cmd/#"]);
+    }
+
+    #[test]
+    fn exclusions_prevail_over_overlapping_patterns() {
+        let mut filter = TopicFilter::try_from_iter(["te/#", "te/device/main///cmd/+"]).unwrap();
+        filter.exclude("te/device/+///cmd/restart").unwrap();
+        filter.exclude("te/device/main///cmd/#").unwrap();
+
+        assert!(!filter.accept_topic(&Topic::new_unchecked("te/device/main///cmd/restart")));
+        assert!(!filter.accept_topic(&Topic::new_unchecked("te/device/main///cmd/log_upload")));
+        assert!(!filter.accept_topic(&Topic::new_unchecked("te/device/child01///cmd/restart")));
+        assert!(filter.accept_topic(&Topic::new_unchecked("te/device/child01///cmd/log_upload")));
+
+        // The exclusions are kept when combined with other filters
+        let mut combined = TopicFilter::new_unchecked("c8y/#");
+        combined.add_all(filter);
+        assert!(combined.accept_topic(&Topic::new_unchecked("c8y/s/ds")));
+        assert!(!combined.accept_topic(&Topic::new_unchecked("te/device/main///cmd/restart")));
+    }
+
+    #[test]
+    fn invalid_exclusions_are_rejected() {
+        let mut filter = TopicFilter::new_unchecked("te/#");
+        let error = filter.exclude("te/#/cmd").unwrap_err();
+        assert!(matches!(error, MqttError::InvalidFilter { pattern } if pattern == "te/#/cmd"));
+        assert!(filter.exclusions().is_empty());
+    }
+
     #[test]
     fn check_removing_overlapping_patterns() {
         let mut topics = TopicFilter::empty();
@@ -932,6 +932,40 @@ define_tedge_config! {
                 #[tedge_config(example = "5m", default(from_str = "5m"))]
                 reset_window: SecondsOrHumanTime,
             },
+
+            local_reconnect_policy: {
+                /// The minimum time the built-in bridge will wait before reconnecting to the local broker
+                #[tedge_config(note = "Defaults to `mqtt.bridge.reconnect_policy.initial_interval` when not set")]
+                #[tedge_config(example = "1s")]
+                initial_interval: SecondsOrHumanTime,
+
+                /// The maximum time the built-in bridge will wait before reconnecting to the local broker
+                #[tedge_config(note = "Defaults to `mqtt.bridge.reconnect_policy.maximum_interval` when not set")]
+                #[tedge_config(example = "30s")]
+                maximum_interval: SecondsOrHumanTime,
+
+                /// How long to wait after successful reconnection to the local broker before resetting the reconnect timeout
+                #[tedge_config(note = "Defaults to `mqtt.bridge.reconnect_policy.reset_window` when not set")]
+                #[tedge_config(example = "5m")]
+                reset_window: SecondsOrHumanTime,
+            },
+
+            cloud_reconnect_policy: {
+                /// The minimum time the built-in bridge will wait before reconnecting to the cloud broker
+                #[tedge_config(note = "Defaults to `mqtt.bridge.reconnect_policy.initial_interval` when not set")]
+                #[tedge_config(example = "30s")]
+                initial_interval: SecondsOrHumanTime,
+
+                /// The maximum time the built-in bridge will wait before reconnecting to the cloud broker
+                #[tedge_config(note = "Defaults to `mqtt.bridge.reconnect_policy.maximum_interval` when not set")]
+                #[tedge_config(example = "10m")]
+                maximum_interval: SecondsOrHumanTime,
+
+                /// How long to wait after successful reconnection to the cloud broker before resetting the reconnect timeout
+                #[tedge_config(note = "Defaults to `mqtt.bridge.reconnect_policy.reset_window` when not set")]
+                #[tedge_config(example = "5m")]
+                reset_window: SecondsOrHumanTime,
+            },
         },
     },
 
@@ -1019,6 +1053,10 @@ define_tedge_config! {
             /// On a clean start, the whole state of the device, services and child-devices is resent to the cloud
             #[tedge_config(example = "true", default(value = true), deprecated_key = "c8y.entity_store.clean_start")]
             clean_start: bool,
+
+            /// Compress the persisted state of the device, services and child-devices, to save disk space
+            #[tedge_config(example = "false", default(value = false))]
+            compress: bool,
         },
 
 
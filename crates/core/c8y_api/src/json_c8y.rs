@@ -140,6 +140,9 @@ impl From<ThinEdgeEvent> for C8yCreateEvent {
             },
             Some(event_data) => {
                 extras.extend(event_data.extras);
+                if let Some(severity) = event_data.severity {
+                    extras.insert("severity".into(), severity.to_string().into());
+                }
 
                 // If payload contains type, use the value as the event type unless it's empty
                 let event_type = match extras.remove("type") {
@@ -496,6 +499,7 @@ mod tests {
         ThinEdgeEvent {
             name: "click_event".into(),
             data: Some(ThinEdgeEventData {
+                severity: None,
                 text: Some("Someone clicked".into()),
                 time: Some(datetime!(2021-04-23 19:00:00 +05:00)),
                 extras: HashMap::new(),
@@ -515,6 +519,7 @@ mod tests {
         ThinEdgeEvent {
             name: "click_event".into(),
             data: Some(ThinEdgeEventData {
+                severity: None,
                 text: None,
                 time: Some(datetime!(2021-04-23 19:00:00 +05:00)),
                 extras: HashMap::new(),
@@ -534,6 +539,7 @@ mod tests {
         ThinEdgeEvent {
             name: "click_event".into(),
             data: Some(ThinEdgeEventData {
+                severity: None,
                 text: Some("Someone, clicked, it".into()),
                 time: Some(datetime!(2021-04-23 19:00:00 +05:00)),
                 extras: HashMap::new(),
@@ -565,6 +571,7 @@ mod tests {
         let tedge_event = ThinEdgeEvent {
             name: "empty_event".into(),
             data: Some(ThinEdgeEventData {
+                severity: None,
                 text: None,
                 time: None,
                 extras: HashMap::new(),
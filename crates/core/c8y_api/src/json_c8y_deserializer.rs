@@ -234,6 +234,7 @@ impl TryFrom<C8ySoftwareUpdate> for SoftwarePayload {
                     C8ySoftwareUpdateAction::Delete => Some(SoftwareModuleAction::Remove),
                 },
                 reason: None,
+                duration_ms: None,
             };
 
             if let Some(list) = software_info
@@ -1000,6 +1001,7 @@ mod tests {
 
         let mut thin_edge_json = DeviceProfileCmdPayload {
             status: CommandStatus::Init,
+            status_message: None,
             name,
             operations: Vec::new(),
         };
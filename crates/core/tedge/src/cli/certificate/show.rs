@@ -36,6 +36,7 @@ impl ShowCertCmd {
         println!("Issuer: {}", pem.issuer()?);
         println!("Valid from: {}", pem.not_before()?);
         println!("Valid up to: {}", pem.not_after()?);
+        println!("Status: {}", pem.validity_status()?);
         println!("Thumbprint: {}", pem.thumbprint()?);
         Ok(())
     }
@@ -40,6 +40,7 @@ use tedge_actors::Sequential;
 use tedge_actors::ServerActorBuilder;
 use tedge_actors::ServerConfig;
 use tedge_api::entity_store::EntityRegistrationMessage;
+use tedge_api::entity_store::EntityStoreLogConfig;
 use tedge_api::mqtt_topics::DeviceTopicId;
 use tedge_api::mqtt_topics::EntityTopicId;
 use tedge_api::mqtt_topics::MqttSchema;
@@ -96,6 +97,7 @@ pub(crate) struct AgentConfig {
     pub capabilities: Capabilities,
     entity_auto_register: bool,
     entity_store_clean_start: bool,
+    entity_store_compress: bool,
 }
 
 impl AgentConfig {
@@ -184,6 +186,7 @@ impl AgentConfig {
 
         let entity_auto_register = tedge_config.agent.entity_store.auto_register;
         let entity_store_clean_start = tedge_config.agent.entity_store.clean_start;
+        let entity_store_compress = tedge_config.agent.entity_store.compress;
 
         Ok(Self {
             mqtt_config,
@@ -211,6 +214,7 @@ impl AgentConfig {
             capabilities,
             entity_auto_register,
             entity_store_clean_start,
+            entity_store_compress,
         })
     }
 }
@@ -373,7 +377,10 @@ impl Agent {
             runtime.spawn(tedge_to_te_converter).await?;
 
             let state_dir = agent_state_dir(self.config.state_dir, self.config.config_dir);
-            let clean_start = self.config.entity_store_clean_start;
+            let log_config = EntityStoreLogConfig {
+                clean_start: self.config.entity_store_clean_start,
+                compress: self.config.entity_store_compress,
+            };
             let telemetry_cache_size = 0; // Agent need not cache any data messages, the mapper would
 
             let main_device = EntityRegistrationMessage::main_device(None);
@@ -383,7 +390,7 @@ impl Agent {
                 self.config.service.ty.clone(),
                 telemetry_cache_size,
                 state_dir,
-                clean_start,
+                log_config,
             )?;
             let entity_store_server = EntityStoreServer::new(
                 entity_store,
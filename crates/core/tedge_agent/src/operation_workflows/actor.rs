@@ -166,6 +166,9 @@ impl WorkflowActor {
             Err(WorkflowExecutionError::UnknownOperation { operation }) => {
                 info!("Ignoring {operation} operation which is not registered");
             }
+            Err(WorkflowExecutionError::DuplicateCommand(duplicate)) => {
+                info!("Ignoring {operation} operation request: {duplicate}");
+            }
             Err(err) => {
                 error!("{operation} operation request cannot be processed: {err}");
                 log_file.log_step(&step, &format!("Error: {err}\n")).await;
@@ -13,7 +13,9 @@ use tedge_api::workflow::IllFormedOperationWorkflow;
 use tedge_api::workflow::OperationAction;
 use tedge_api::workflow::OperationName;
 use tedge_api::workflow::OperationWorkflow;
+use tedge_api::workflow::OperationWorkflowDefinition;
 use tedge_api::workflow::WorkflowExecutionError;
+use tedge_api::workflow::WorkflowFragment;
 use tedge_api::workflow::WorkflowSupervisor;
 use tedge_api::workflow::WorkflowVersion;
 use tedge_file_system_ext::FsWatchEvent;
@@ -102,7 +104,7 @@ impl WorkflowRepository {
         for entry in dir_path.read_dir_utf8()?.flatten() {
             let file = entry.path();
             if file.extension() == Some("toml") {
-                match read_operation_workflow(file)
+                match read_operation_workflow(file, &self.custom_workflows_dir)
                     .await
                     .and_then(|(workflow, version)| {
                         let file_source = source.set_inner(file.into());
@@ -214,7 +216,7 @@ impl WorkflowRepository {
     ///
     /// Return the operation name if this is a new operation or workflow version.
     async fn reload_operation_workflow(&mut self, path: &Utf8PathBuf) -> Option<OperationName> {
-        match read_operation_workflow(path).await {
+        match read_operation_workflow(path, &self.custom_workflows_dir).await {
             Ok((workflow, version)) => {
                 if let Ok(cmd) = self.load_operation_workflow(
                     WorkflowSource::UserDefined(path.clone()),
@@ -321,7 +323,9 @@ impl WorkflowRepository {
         operation: &OperationName,
     ) -> Option<(Utf8PathBuf, WorkflowVersion, OperationWorkflow)> {
         if let Some((version, path)) = self.definitions.get(operation) {
-            if let Ok((workflow, latest)) = read_operation_workflow(path).await {
+            if let Ok((workflow, latest)) =
+                read_operation_workflow(path, &self.custom_workflows_dir).await
+            {
                 if version != &latest {
                     return Some((path.to_owned(), latest, workflow));
                 };
@@ -331,7 +335,9 @@ impl WorkflowRepository {
                 .custom_workflows_dir
                 .join(operation)
                 .with_extension("toml");
-            if let Ok((workflow, new)) = read_operation_workflow(&path).await {
+            if let Ok((workflow, new)) =
+                read_operation_workflow(&path, &self.custom_workflows_dir).await
+            {
                 return Some((path, new, workflow));
             };
         }
@@ -474,15 +480,20 @@ impl WorkflowRepository {
     }
 }
 
+/// Read an operation workflow definition, resolving the fragments it includes
+///
+/// The included fragments are read from the workflow directory,
+/// their names being paths relative to this directory.
 async fn read_operation_workflow(
     path: &Utf8Path,
+    workflows_dir: &Utf8Path,
 ) -> Result<(OperationWorkflow, WorkflowVersion), anyhow::Error> {
     let bytes = tokio::fs::read(path).await.context("Fail to read file")?;
     let input = std::str::from_utf8(&bytes).context("Fail to extract UTF8 content")?;
     let version = sha256::digest(input);
 
-    toml::from_str::<OperationWorkflow>(input)
-        .context("Fail to parse TOML")
+    parse_operation_workflow(input, workflows_dir)
+        .await
         .or_else(|err| {
             error!("Ill-formed operation workflow definition from {path:?}: {err:?}");
             let workflow = toml::from_str::<IllFormedOperationWorkflow>(input)
@@ -493,3 +504,106 @@ async fn read_operation_workflow(
         })
         .map(|workflow| (workflow, version))
 }
+
+async fn parse_operation_workflow(
+    input: &str,
+    workflows_dir: &Utf8Path,
+) -> Result<OperationWorkflow, anyhow::Error> {
+    let definition =
+        toml::from_str::<OperationWorkflowDefinition>(input).context("Fail to parse TOML")?;
+    let fragments = read_workflow_fragments(workflows_dir, definition.includes()).await?;
+    Ok(definition.resolve(&fragments)?)
+}
+
+/// Read the given fragments as well as those they include, recursively
+///
+/// A fragment that cannot be read is simply omitted, to be reported as a missing include on resolution.
+async fn read_workflow_fragments(
+    workflows_dir: &Utf8Path,
+    mut includes: Vec<String>,
+) -> Result<HashMap<String, WorkflowFragment>, anyhow::Error> {
+    let mut fragments = HashMap::new();
+    while let Some(name) = includes.pop() {
+        if fragments.contains_key(&name) {
+            continue;
+        }
+        let path = workflows_dir.join(&name);
+        let Ok(input) = tokio::fs::read_to_string(&path).await else {
+            continue;
+        };
+        let fragment = toml::from_str::<WorkflowFragment>(&input)
+            .with_context(|| format!("Fail to parse the workflow fragment {path}"))?;
+        includes.extend(fragment.includes());
+        fragments.insert(name, fragment);
+    }
+    Ok(fragments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tedge_mqtt_ext::Topic;
+    use tedge_test_utils::fs::TempTedgeDir;
+
+    #[tokio::test]
+    async fn included_fragments_are_read_from_the_workflow_directory() {
+        let tmp_dir = TempTedgeDir::new();
+        let workflows_dir = tmp_dir.dir("operations");
+        workflows_dir
+            .file("custom_operation.toml")
+            .with_raw_content(
+                r#"
+operation = "custom_operation"
+
+[init]
+action = "proceed"
+on_success = "download"
+
+[download]
+include = "fragments/download.toml"
+on_success = "successful"
+on_error = "failed"
+"#,
+            );
+        workflows_dir
+            .dir("fragments")
+            .file("download.toml")
+            .with_raw_content(
+                r#"
+[init]
+script = "/usr/bin/download.sh"
+on_success = "check"
+
+[check]
+script = "/usr/bin/check.sh"
+on_success = "successful"
+"#,
+            );
+
+        let mut repository = WorkflowRepository::new(
+            vec![],
+            workflows_dir.utf8_path_buf(),
+            tmp_dir.utf8_path_buf(),
+        );
+        repository.load().await;
+
+        let operation = OperationType::Custom("custom_operation".to_string());
+        let command = GenericCommandState::new(
+            Topic::new_unchecked("te/device/main///cmd/custom_operation/123"),
+            "init".to_string(),
+            json!({}),
+        );
+        let command = repository
+            .apply_external_update(&operation, command)
+            .await
+            .unwrap()
+            .expect("The custom operation should be registered");
+
+        let command = command.move_to("download.check".into());
+        let OperationAction::Script(script, _) = repository.get_action(&command).unwrap() else {
+            panic!("Expected a script action")
+        };
+        assert_eq!(script.command, "/usr/bin/check.sh");
+    }
+}
@@ -71,6 +71,7 @@ async fn convert_incoming_software_list_request() -> Result<(), DynError> {
             cmd_id: "some-cmd-id".to_string(),
             payload: SoftwareListCommandPayload {
                 status: CommandStatus::Scheduled,
+                status_message: None,
                 current_software_list: Vec::default(),
                 log_path: Some(
                     tmp_dir
@@ -109,6 +110,7 @@ async fn convert_incoming_software_update_request() -> Result<(), DynError> {
         action: Some(SoftwareModuleAction::Install),
         url: None,
         reason: None,
+        duration_ms: None,
     };
     let debian_list = SoftwareRequestResponseSoftwareList {
         plugin_type: "debian".into(),
@@ -123,6 +125,7 @@ async fn convert_incoming_software_update_request() -> Result<(), DynError> {
             cmd_id: "1234".to_string(),
             payload: SoftwareUpdateCommandPayload {
                 status: CommandStatus::Scheduled,
+                status_message: None,
                 update_list: vec![debian_list],
                 failures: vec![],
                 log_path: Some(
@@ -165,6 +168,7 @@ async fn convert_incoming_restart_request() -> Result<(), DynError> {
             cmd_id: "random".to_string(),
             payload: RestartCommandPayload {
                 status: CommandStatus::Scheduled,
+                status_message: None,
                 log_path: Some(
                     tmp_dir
                         .path()
@@ -84,6 +84,7 @@ async fn test_new_software_update_operation() -> Result<(), DynError> {
         action: Some(SoftwareModuleAction::Install),
         url: None,
         reason: None,
+        duration_ms: None,
     };
     let debian_list = SoftwareRequestResponseSoftwareList {
         plugin_type: "debian".into(),
@@ -96,6 +97,7 @@ async fn test_new_software_update_operation() -> Result<(), DynError> {
         cmd_id: "random".to_string(),
         payload: SoftwareUpdateCommandPayload {
             status: CommandStatus::Scheduled,
+            status_message: None,
             update_list: vec![debian_list],
             failures: vec![],
             log_path: None,
@@ -92,6 +92,70 @@ where
             .with_qos(QoS::AtLeastOnce)
     }
 
+    /// Return the MQTT message to register support for this types of command,
+    /// advertising the given metadata (e.g. the supported software types)
+    pub fn capability_message_with_metadata(
+        schema: &MqttSchema,
+        target: &EntityTopicId,
+        metadata: &Payload::Metadata,
+    ) -> MqttMessage {
+        let meta_topic = schema.capability_topic_for(target, Payload::operation_type());
+        MqttMessage::new(&meta_topic, metadata.to_bytes())
+            .with_retain()
+            .with_qos(QoS::AtLeastOnce)
+    }
+
+    /// Parse a capability message received from MQTT, returning the target and its metadata
+    ///
+    /// An empty payload is parsed as the default metadata.
+    pub fn parse_capability_message(
+        schema: &MqttSchema,
+        message: &MqttMessage,
+    ) -> Result<(EntityTopicId, Payload::Metadata), CommandParsingError> {
+        let (target, channel) = schema.entity_channel_of(message.topic.as_ref())?;
+        match channel {
+            Channel::CommandMetadata { operation } if operation == Payload::operation_type() => {}
+            Channel::CommandMetadata { operation } => {
+                return Err(CommandParsingError::InvalidCommandType {
+                    actual: operation.to_string(),
+                    expected: Payload::operation_type().to_string(),
+                })
+            }
+            _ => {
+                return Err(CommandParsingError::InvalidCommandTopic {
+                    topic: message.topic.name.clone(),
+                })
+            }
+        };
+
+        let bytes = message.payload();
+        let metadata = if bytes.is_empty() {
+            Payload::Metadata::default()
+        } else {
+            Payload::Metadata::from_slice(bytes)?
+        };
+        Ok((target, metadata))
+    }
+
+    /// Return the human-readable progress message of the command, if any
+    pub fn status_message(&self) -> Option<&str> {
+        self.payload.status_message()
+    }
+
+    /// Update the human-readable progress message of the command, e.g. `downloading 45%`
+    ///
+    /// The status of the command is left unchanged,
+    /// so a command can report its progress repeatedly while `executing`.
+    /// The message is cleared when the status of the command is updated.
+    pub fn set_status_message(&mut self, message: impl Into<String>) {
+        self.payload.set_status_message(Some(message.into()));
+    }
+
+    /// Remove the progress message of the command
+    pub fn clear_status_message(&mut self) {
+        self.payload.set_status_message(None);
+    }
+
     /// Mark the command as executing
     pub fn executing(&mut self) {
         self.payload.executing();
@@ -132,6 +196,9 @@ where
     Payload: Jsonify + DeserializeOwned + Serialize + CommandPayload,
 {
     /// Parse a command received from MQTT
+    ///
+    /// The command id and the operation, if given by the payload as `id` and `operation`,
+    /// must be those given by the topic. Otherwise the message is rejected as misrouted.
     pub fn parse(
         schema: &MqttSchema,
         message: MqttMessage,
@@ -154,6 +221,12 @@ where
             }
         };
 
+        if !message.payload_bytes().is_empty() {
+            let identity: PayloadIdentity = serde_json::from_slice(message.payload_bytes())?;
+            if let Some(err) = identity.mismatch(&Payload::operation_type(), &cmd_id) {
+                return Err(err);
+            }
+        }
         Ok(Self::try_from_bytes(target, cmd_id, message.payload())?)
     }
 
@@ -244,6 +317,36 @@ where
     }
 }
 
+/// The command id and operation, as possibly given by a command payload
+#[derive(Deserialize)]
+struct PayloadIdentity {
+    id: Option<Value>,
+    operation: Option<Value>,
+}
+
+impl PayloadIdentity {
+    /// Check that the command id and operation given by the payload, if any, are those given by the topic
+    fn mismatch(self, operation: &OperationType, cmd_id: &str) -> Option<CommandParsingError> {
+        if let Some(Value::String(payload_operation)) = self.operation {
+            if payload_operation != operation.name() {
+                return Some(CommandParsingError::OperationMismatch {
+                    topic: operation.to_string(),
+                    payload: payload_operation,
+                });
+            }
+        }
+        if let Some(Value::String(payload_cmd_id)) = self.id {
+            if payload_cmd_id != cmd_id {
+                return Some(CommandParsingError::CommandIdMismatch {
+                    topic: cmd_id.to_string(),
+                    payload: payload_cmd_id,
+                });
+            }
+        }
+        None
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum CommandParsingError {
     #[error(transparent)]
@@ -255,6 +358,12 @@ pub enum CommandParsingError {
     #[error("Not the expected command type: {actual} instead of {expected}")]
     InvalidCommandType { actual: String, expected: String },
 
+    #[error("The payload is for a {payload} command, while received on a {topic} command topic")]
+    OperationMismatch { topic: String, payload: String },
+
+    #[error("The payload is for the command {payload:?}, while received on the topic of the command {topic:?}")]
+    CommandIdMismatch { topic: String, payload: String },
+
     #[error(transparent)]
     InvalidPayload(#[from] MqttError),
 
@@ -264,6 +373,9 @@ pub enum CommandParsingError {
 
 /// A command payload describing the current state of a command
 pub trait CommandPayload {
+    /// The metadata published along the capability message of these commands
+    type Metadata: Jsonify + Default + Serialize + DeserializeOwned;
+
     /// Return the operation type shared by all these commands
     fn operation_type() -> OperationType;
 
@@ -273,25 +385,41 @@ pub trait CommandPayload {
     /// Set the status of the command
     fn set_status(&mut self, status: CommandStatus);
 
-    /// Set the failure reason of the command
+    /// Return the progress message of the command, if any
+    ///
+    /// By default, a command has no progress message.
+    fn status_message(&self) -> Option<&str> {
+        None
+    }
+
+    /// Set the progress message of the command, leaving its status unchanged
+    ///
+    /// By default, the progress message is ignored.
+    fn set_status_message(&mut self, _message: Option<String>) {}
+
+    /// Set the failure reason of the command, clearing its progress message
     fn set_error(&mut self, reason: impl Into<String>) {
+        self.set_status_message(None);
         self.set_status(CommandStatus::Failed {
             reason: reason.into(),
         });
     }
 
-    /// Mark the command as executing
+    /// Mark the command as executing, clearing its progress message
     fn executing(&mut self) {
+        self.set_status_message(None);
         self.set_status(CommandStatus::Executing);
     }
 
-    /// Mark the command as successful
+    /// Mark the command as successful, clearing its progress message
     fn successful(&mut self) {
+        self.set_status_message(None);
         self.set_status(CommandStatus::Successful);
     }
 
-    /// Mark the command as failed
+    /// Mark the command as failed, clearing its progress message
     fn failed(&mut self, reason: impl Into<String>) {
+        self.set_status_message(None);
         self.set_status(CommandStatus::Failed {
             reason: reason.into(),
         });
@@ -334,6 +462,34 @@ pub trait Jsonify {
     {
         serde_json::to_vec(self).unwrap() // all thin-edge data can be serialized to json
     }
+
+    /// Serialize to json using a canonical form, with no whitespace and the object keys sorted
+    ///
+    /// Two equal values are serialized byte-identically,
+    /// whatever the order of the fields of their types or of their free-form json parts.
+    fn to_canonical_json(&self) -> String
+    where
+        Self: Serialize,
+    {
+        let canonical = sort_object_keys(self.to_value());
+        serde_json::to_string(&canonical).unwrap() // all thin-edge data can be serialized to json
+    }
+}
+
+/// Recursively sort the keys of the json objects
+fn sort_object_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut fields: Vec<_> = map.into_iter().collect();
+            fields.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+            fields
+                .into_iter()
+                .map(|(k, v)| (k, sort_object_keys(v)))
+                .collect()
+        }
+        Value::Array(values) => values.into_iter().map(sort_object_keys).collect(),
+        value => value,
+    }
 }
 
 /// Command to request the list of software packages that are installed on a device
@@ -346,6 +502,9 @@ pub struct SoftwareListCommandPayload {
     #[serde(flatten)]
     pub status: CommandStatus,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
+
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub current_software_list: Vec<SoftwareList>,
 
@@ -356,6 +515,8 @@ pub struct SoftwareListCommandPayload {
 impl Jsonify for SoftwareListCommandPayload {}
 
 impl CommandPayload for SoftwareListCommandPayload {
+    type Metadata = SoftwareCommandMetadata;
+
     fn operation_type() -> OperationType {
         OperationType::SoftwareList
     }
@@ -367,6 +528,14 @@ impl CommandPayload for SoftwareListCommandPayload {
     fn set_status(&mut self, status: CommandStatus) {
         self.status = status
     }
+
+    fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    fn set_status_message(&mut self, message: Option<String>) {
+        self.status_message = message
+    }
 }
 
 /// Sub list of modules grouped by plugin type.
@@ -408,6 +577,56 @@ impl SoftwareListCommand {
             })
             .collect()
     }
+
+    /// List the packages of a given type
+    pub fn modules_of_type(&self, plugin_type: &str) -> Vec<SoftwareModule> {
+        self.modules()
+            .into_iter()
+            .filter(|module| module.module_type.as_deref() == Some(plugin_type))
+            .collect()
+    }
+
+    /// Count all the packages, whatever their type
+    pub fn module_count(&self) -> usize {
+        self.payload
+            .current_software_list
+            .iter()
+            .map(|list| list.modules.len())
+            .sum()
+    }
+
+    /// Return `true` if there is no package in the list
+    pub fn is_empty(&self) -> bool {
+        self.module_count() == 0
+    }
+
+    /// Merge the response of another software list command, e.g. as reported by another plugin
+    ///
+    /// The modules of the other response are added to the modules of the same type, if any.
+    /// The merged response is failed if any of the two is failed, the failure reasons being joined.
+    pub fn merge(&mut self, other: SoftwareListCommand) {
+        for list in other.payload.current_software_list {
+            match self
+                .payload
+                .current_software_list
+                .iter_mut()
+                .find(|known| known.plugin_type == list.plugin_type)
+            {
+                Some(known) => known.modules.extend(list.modules),
+                None => self.payload.current_software_list.push(list),
+            }
+        }
+
+        match (&mut self.payload.status, other.payload.status) {
+            (CommandStatus::Failed { reason }, CommandStatus::Failed { reason: other }) => {
+                reason.push_str("; ");
+                reason.push_str(&other);
+            }
+            (CommandStatus::Failed { .. }, _) => {}
+            (status, failed @ CommandStatus::Failed { .. }) => *status = failed,
+            _ => {}
+        }
+    }
 }
 
 /// Command to install/remove software packages on a device
@@ -420,6 +639,9 @@ pub struct SoftwareUpdateCommandPayload {
     #[serde(flatten)]
     pub status: CommandStatus,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
+
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub update_list: Vec<SoftwareRequestResponseSoftwareList>,
 
@@ -433,6 +655,8 @@ pub struct SoftwareUpdateCommandPayload {
 impl Jsonify for SoftwareUpdateCommandPayload {}
 
 impl CommandPayload for SoftwareUpdateCommandPayload {
+    type Metadata = SoftwareCommandMetadata;
+
     fn operation_type() -> OperationType {
         OperationType::SoftwareUpdate
     }
@@ -444,6 +668,14 @@ impl CommandPayload for SoftwareUpdateCommandPayload {
     fn set_status(&mut self, status: CommandStatus) {
         self.status = status
     }
+
+    fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    fn set_status_message(&mut self, message: Option<String>) {
+        self.status_message = message
+    }
 }
 
 impl SoftwareUpdateCommand {
@@ -496,6 +728,20 @@ impl SoftwareUpdateCommand {
         modules_types
     }
 
+    /// Count all the packages to be installed or removed, whatever their type
+    pub fn module_count(&self) -> usize {
+        self.payload
+            .update_list
+            .iter()
+            .map(|list| list.modules.len())
+            .sum()
+    }
+
+    /// Return `true` if there is no package to be installed or removed
+    pub fn is_empty(&self) -> bool {
+        self.module_count() == 0
+    }
+
     pub fn updates_for(&self, module_type: &str) -> Vec<SoftwareModuleUpdate> {
         let mut updates = vec![];
 
@@ -529,6 +775,56 @@ impl SoftwareUpdateCommand {
         updates
     }
 
+    /// Split this command into one self-contained command per module type
+    ///
+    /// Each sub-command only contains the updates and the failures for its module type,
+    /// while sharing the target, the command id, the status and the log path of this command.
+    /// The sub-commands are given in the order of the module types in the update list.
+    pub fn split_per_module_type(&self) -> Vec<SoftwareUpdateCommand> {
+        let mut module_types: Vec<SoftwareType> = vec![];
+        for module_type in self.modules_types() {
+            if !module_types.contains(&module_type) {
+                module_types.push(module_type);
+            }
+        }
+
+        module_types
+            .into_iter()
+            .map(|module_type| {
+                let modules = self
+                    .payload
+                    .update_list
+                    .iter()
+                    .filter(|list| list.plugin_type == module_type)
+                    .flat_map(|list| list.modules.iter().cloned())
+                    .collect();
+                let failures = self
+                    .payload
+                    .failures
+                    .iter()
+                    .filter(|list| list.plugin_type == module_type)
+                    .cloned()
+                    .collect();
+
+                SoftwareUpdateCommand {
+                    target: self.target.clone(),
+                    cmd_id: self.cmd_id.clone(),
+                    payload: SoftwareUpdateCommandPayload {
+                        status: self.payload.status.clone(),
+                        status_message: self.payload.status_message.clone(),
+                        update_list: vec![SoftwareRequestResponseSoftwareList {
+                            plugin_type: module_type,
+                            modules,
+                            errors: vec![],
+                        }],
+                        failures,
+                        log_path: self.payload.log_path.clone(),
+                    },
+                }
+            })
+            .collect()
+    }
+
     pub fn add_errors(&mut self, plugin_type: &str, errors: Vec<SoftwareError>) {
         self.payload
             .failures
@@ -584,6 +880,17 @@ pub struct SoftwareModuleItem {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reason: Option<String>,
+
+    /// How long the install or remove action took, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+impl SoftwareModuleItem {
+    /// Record how long the install or remove action took for this module
+    pub fn set_duration(&mut self, duration: std::time::Duration) {
+        self.duration_ms = Some(duration.as_millis().try_into().unwrap_or(u64::MAX));
+    }
 }
 
 impl From<SoftwareModule> for SoftwareModuleItem {
@@ -594,6 +901,7 @@ impl From<SoftwareModule> for SoftwareModuleItem {
             url: module.url,
             action: None,
             reason: None,
+            duration_ms: None,
         }
     }
 }
@@ -607,6 +915,7 @@ impl From<SoftwareModuleUpdate> for SoftwareModuleItem {
                 url: module.url,
                 action: Some(SoftwareModuleAction::Install),
                 reason: None,
+                duration_ms: None,
             },
             SoftwareModuleUpdate::Remove { module } => SoftwareModuleItem {
                 name: module.name,
@@ -614,6 +923,7 @@ impl From<SoftwareModuleUpdate> for SoftwareModuleItem {
                 url: module.url,
                 action: Some(SoftwareModuleAction::Remove),
                 reason: None,
+                duration_ms: None,
             },
         }
     }
@@ -628,6 +938,7 @@ impl From<SoftwareError> for Vec<SoftwareModuleItem> {
                 url: module.url,
                 action: Some(SoftwareModuleAction::Install),
                 reason: Some(reason),
+                duration_ms: None,
             }],
             SoftwareError::Remove { module, reason } => vec![SoftwareModuleItem {
                 name: module.name,
@@ -635,6 +946,7 @@ impl From<SoftwareError> for Vec<SoftwareModuleItem> {
                 url: module.url,
                 action: Some(SoftwareModuleAction::Remove),
                 reason: Some(reason),
+                duration_ms: None,
             }],
             SoftwareError::UnknownSoftwareType {
                 updates,
@@ -652,6 +964,7 @@ impl From<SoftwareError> for Vec<SoftwareModuleItem> {
                             url: module.url,
                             action: Some(action),
                             reason: Some(reason.clone()),
+                            duration_ms: None,
                         }
                     })
                     .collect()
@@ -671,6 +984,14 @@ pub struct SoftwareCommandMetadata {
 
 impl Jsonify for SoftwareCommandMetadata {}
 
+/// Metadata of the commands that have no specific capabilities to advertise
+///
+/// Serialized as an empty JSON object, any field being ignored when parsed.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct EmptyMetadata {}
+
+impl Jsonify for EmptyMetadata {}
+
 /// Command to restart a device
 pub type RestartCommand = Command<RestartCommandPayload>;
 
@@ -681,6 +1002,9 @@ pub struct RestartCommandPayload {
     #[serde(flatten)]
     pub status: CommandStatus,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
+
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub log_path: Option<Utf8PathBuf>,
 }
@@ -689,6 +1013,7 @@ impl RestartCommandPayload {
     pub fn new(status: CommandStatus) -> Self {
         RestartCommandPayload {
             status,
+            status_message: None,
             log_path: None,
         }
     }
@@ -697,6 +1022,8 @@ impl RestartCommandPayload {
 impl Jsonify for RestartCommandPayload {}
 
 impl CommandPayload for RestartCommandPayload {
+    type Metadata = EmptyMetadata;
+
     fn operation_type() -> OperationType {
         OperationType::Restart
     }
@@ -708,6 +1035,14 @@ impl CommandPayload for RestartCommandPayload {
     fn set_status(&mut self, status: CommandStatus) {
         self.status = status
     }
+
+    fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    fn set_status_message(&mut self, message: Option<String>) {
+        self.status_message = message
+    }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
@@ -771,6 +1106,9 @@ impl Jsonify for LogMetadata {}
 pub struct LogUploadCmdPayload {
     #[serde(flatten)]
     pub status: CommandStatus,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
     pub tedge_url: String,
     #[serde(rename = "type")]
     pub log_type: String,
@@ -788,6 +1126,8 @@ pub struct LogUploadCmdPayload {
 impl Jsonify for LogUploadCmdPayload {}
 
 impl CommandPayload for LogUploadCmdPayload {
+    type Metadata = LogUploadCmdMetadata;
+
     fn operation_type() -> OperationType {
         OperationType::LogUpload
     }
@@ -799,6 +1139,14 @@ impl CommandPayload for LogUploadCmdPayload {
     fn set_status(&mut self, status: CommandStatus) {
         self.status = status
     }
+
+    fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    fn set_status_message(&mut self, message: Option<String>) {
+        self.status_message = message
+    }
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize, Eq, PartialEq)]
@@ -813,9 +1161,10 @@ impl Jsonify for LogUploadCmdMetadata {}
 /// Command to request a configuration snapshot to be uploaded
 pub type ConfigSnapshotCmd = Command<ConfigSnapshotCmdPayload>;
 
-#[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ConfigMetadata {
+    #[serde(default)]
     pub types: Vec<String>,
 }
 
@@ -826,6 +1175,9 @@ impl Jsonify for ConfigMetadata {}
 pub struct ConfigSnapshotCmdPayload {
     #[serde(flatten)]
     pub status: CommandStatus,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tedge_url: Option<String>,
     #[serde(rename = "type")]
@@ -839,6 +1191,8 @@ pub struct ConfigSnapshotCmdPayload {
 impl Jsonify for ConfigSnapshotCmdPayload {}
 
 impl CommandPayload for ConfigSnapshotCmdPayload {
+    type Metadata = ConfigMetadata;
+
     fn operation_type() -> OperationType {
         OperationType::ConfigSnapshot
     }
@@ -850,6 +1204,14 @@ impl CommandPayload for ConfigSnapshotCmdPayload {
     fn set_status(&mut self, status: CommandStatus) {
         self.status = status
     }
+
+    fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    fn set_status_message(&mut self, message: Option<String>) {
+        self.status_message = message
+    }
 }
 
 impl ConfigSnapshotCmdPayload {
@@ -874,6 +1236,9 @@ pub type ConfigUpdateCmd = Command<ConfigUpdateCmdPayload>;
 pub struct ConfigUpdateCmdPayload {
     #[serde(flatten)]
     pub status: CommandStatus,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tedge_url: Option<String>,
     pub remote_url: String,
@@ -888,6 +1253,8 @@ pub struct ConfigUpdateCmdPayload {
 impl Jsonify for ConfigUpdateCmdPayload {}
 
 impl CommandPayload for ConfigUpdateCmdPayload {
+    type Metadata = ConfigMetadata;
+
     fn operation_type() -> OperationType {
         OperationType::ConfigUpdate
     }
@@ -899,6 +1266,14 @@ impl CommandPayload for ConfigUpdateCmdPayload {
     fn set_status(&mut self, status: CommandStatus) {
         self.status = status
     }
+
+    fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    fn set_status_message(&mut self, message: Option<String>) {
+        self.status_message = message
+    }
 }
 
 impl ConfigUpdateCmdPayload {
@@ -927,11 +1302,17 @@ impl Jsonify for FirmwareInfo {}
 pub struct FirmwareUpdateCmdPayload {
     #[serde(flatten)]
     pub status: CommandStatus,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tedge_url: Option<String>,
     pub remote_url: String,
     pub name: String,
     pub version: String,
+    /// The SHA-256 checksum of the firmware file, to check the integrity of the downloaded file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub log_path: Option<Utf8PathBuf>,
 }
@@ -939,6 +1320,8 @@ pub struct FirmwareUpdateCmdPayload {
 impl Jsonify for FirmwareUpdateCmdPayload {}
 
 impl CommandPayload for FirmwareUpdateCmdPayload {
+    type Metadata = EmptyMetadata;
+
     fn operation_type() -> OperationType {
         OperationType::FirmwareUpdate
     }
@@ -950,16 +1333,49 @@ impl CommandPayload for FirmwareUpdateCmdPayload {
     fn set_status(&mut self, status: CommandStatus) {
         self.status = status
     }
+
+    fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    fn set_status_message(&mut self, message: Option<String>) {
+        self.status_message = message
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn equal_commands_have_the_same_canonical_json() {
+        let json_1 = r#"{
+            "status": "init",
+            "updateList": [{
+                "type": "debian",
+                "modules": [{"name": "nodered", "version": "1.0.0", "action": "install"}]
+            }],
+            "logPath": "/tmp/software_update.log"
+        }"#;
+        let json_2 = r#"{"logPath":"/tmp/software_update.log","updateList":[{"modules":[{"action":"install","version":"1.0.0","name":"nodered"}],"type":"debian"}],"status":"init"}"#;
+
+        let payload_1 = SoftwareUpdateCommandPayload::from_json(json_1).unwrap();
+        let payload_2 = SoftwareUpdateCommandPayload::from_json(json_2).unwrap();
+        assert_eq!(payload_1, payload_2);
+
+        let canonical_json = payload_1.to_canonical_json();
+        assert_eq!(canonical_json, payload_2.to_canonical_json());
+        assert_eq!(
+            canonical_json,
+            r#"{"logPath":"/tmp/software_update.log","status":"init","updateList":[{"modules":[{"action":"install","name":"nodered","version":"1.0.0"}],"type":"debian"}]}"#
+        );
+    }
+
     #[test]
     fn serde_software_request_list() {
         let request = SoftwareListCommandPayload {
             status: CommandStatus::Init,
+            status_message: None,
             current_software_list: vec![],
             log_path: None,
         };
@@ -982,6 +1398,7 @@ mod tests {
             action: Some(SoftwareModuleAction::Install),
             url: None,
             reason: None,
+            duration_ms: None,
         };
 
         let debian_module2 = SoftwareModuleItem {
@@ -990,6 +1407,7 @@ mod tests {
             action: Some(SoftwareModuleAction::Install),
             url: None,
             reason: None,
+            duration_ms: None,
         };
 
         let debian_list = SoftwareRequestResponseSoftwareList {
@@ -1004,6 +1422,7 @@ mod tests {
             action: Some(SoftwareModuleAction::Remove),
             url: Some("test.com".into()),
             reason: None,
+            duration_ms: None,
         };
 
         let docker_list = SoftwareRequestResponseSoftwareList {
@@ -1014,6 +1433,7 @@ mod tests {
 
         let request = SoftwareUpdateCommandPayload {
             status: CommandStatus::Init,
+            status_message: None,
             update_list: vec![debian_list, docker_list],
             failures: vec![],
             log_path: None,
@@ -1029,10 +1449,58 @@ mod tests {
         assert_eq!(parsed_request, request);
     }
 
+    #[test]
+    fn serde_software_update_response_with_module_durations() {
+        let mut debian_module = SoftwareModuleItem {
+            name: "debian1".into(),
+            version: None,
+            url: None,
+            action: Some(SoftwareModuleAction::Install),
+            reason: None,
+            duration_ms: None,
+        };
+        debian_module.set_duration(std::time::Duration::from_millis(1234));
+        let debian_list = SoftwareRequestResponseSoftwareList {
+            plugin_type: "debian".into(),
+            modules: vec![debian_module],
+            errors: vec![],
+        };
+        let response = SoftwareUpdateCommandPayload {
+            status: CommandStatus::Successful,
+            status_message: None,
+            update_list: vec![debian_list],
+            failures: vec![],
+            log_path: None,
+        };
+
+        let expected_json = r#"{"status":"successful","updateList":[{"type":"debian","modules":[{"name":"debian1","action":"install","durationMs":1234}]}]}"#;
+        let actual_json = response.to_json();
+        assert_eq!(actual_json, expected_json);
+
+        let parsed_response = SoftwareUpdateCommandPayload::from_json(&actual_json)
+            .expect("Fail to parse the json response");
+        assert_eq!(parsed_response, response);
+        assert_eq!(
+            parsed_response.update_list[0].modules[0].duration_ms,
+            Some(1234)
+        );
+    }
+
+    #[test]
+    fn serde_software_update_response_without_module_durations() {
+        let json = r#"{"status":"successful","updateList":[{"type":"debian","modules":[{"name":"debian1","action":"install"}]}]}"#;
+
+        let parsed_response =
+            SoftwareUpdateCommandPayload::from_json(json).expect("Fail to parse the json response");
+        assert_eq!(parsed_response.update_list[0].modules[0].duration_ms, None);
+        assert_eq!(parsed_response.to_json(), json);
+    }
+
     #[test]
     fn serde_custom_command_status() {
         let request = SoftwareListCommandPayload {
             status: CommandStatus::Unknown,
+            status_message: None,
             current_software_list: vec![],
             log_path: None,
         };
@@ -1047,4 +1515,473 @@ mod tests {
         // However, if serialized again the custom status is lost
         assert_eq!(request.to_json(), r#"{"status":"unknown"}"#);
     }
+
+    #[test]
+    fn serde_firmware_update_command() {
+        let request = FirmwareUpdateCmdPayload {
+            status: CommandStatus::Init,
+            status_message: None,
+            tedge_url: None,
+            remote_url: "https://example.com/firmware.bin".into(),
+            name: "OpenWRT".into(),
+            version: "22.03".into(),
+            sha256: Some("c036cbb7553a909f8b8877d4461924307f27ecb66cff928eeeafd569c3887e29".into()),
+            log_path: None,
+        };
+        let expected_json = r#"{"status":"init","remoteUrl":"https://example.com/firmware.bin","name":"OpenWRT","version":"22.03","sha256":"c036cbb7553a909f8b8877d4461924307f27ecb66cff928eeeafd569c3887e29"}"#;
+
+        let actual_json = request.to_json();
+        assert_eq!(actual_json, expected_json);
+
+        let parsed_request = FirmwareUpdateCmdPayload::from_json(&actual_json)
+            .expect("Fail to parse the json request");
+        assert_eq!(parsed_request, request);
+
+        // The checksum is optional
+        let parsed_request = FirmwareUpdateCmdPayload::from_json(
+            r#"{"status":"init","remoteUrl":"https://example.com/firmware.bin","name":"OpenWRT","version":"22.03"}"#,
+        )
+        .expect("Fail to parse the json request");
+        assert_eq!(parsed_request.sha256, None);
+        assert_eq!(
+            parsed_request.to_json(),
+            r#"{"status":"init","remoteUrl":"https://example.com/firmware.bin","name":"OpenWRT","version":"22.03"}"#
+        );
+    }
+
+    #[test]
+    fn capability_messages_with_metadata() {
+        let schema = MqttSchema::default();
+        let target = EntityTopicId::default_main_device();
+
+        let metadata = SoftwareCommandMetadata {
+            types: vec!["apt".into(), "docker".into()],
+        };
+        let capability =
+            SoftwareListCommand::capability_message_with_metadata(&schema, &target, &metadata);
+        assert_eq!(capability.topic.name, "te/device/main///cmd/software_list");
+        assert_eq!(
+            capability.payload_str().unwrap(),
+            r#"{"types":["apt","docker"]}"#
+        );
+        assert!(capability.retain);
+
+        let (parsed_target, parsed_metadata) =
+            SoftwareListCommand::parse_capability_message(&schema, &capability).unwrap();
+        assert_eq!(parsed_target, target);
+        assert_eq!(parsed_metadata, metadata);
+
+        let metadata = LogUploadCmdMetadata {
+            types: vec!["syslog".into()],
+        };
+        let capability =
+            LogUploadCmd::capability_message_with_metadata(&schema, &target, &metadata);
+        assert_eq!(capability.payload_str().unwrap(), r#"{"types":["syslog"]}"#);
+
+        let capability =
+            RestartCommand::capability_message_with_metadata(&schema, &target, &EmptyMetadata {});
+        assert_eq!(capability.payload_str().unwrap(), "{}");
+    }
+
+    #[test]
+    fn capability_metadata_defaults_to_empty() {
+        let schema = MqttSchema::default();
+        let target = EntityTopicId::default_main_device();
+
+        // The capability message without metadata is parsed as the default metadata
+        let capability = SoftwareUpdateCommand::capability_message(&schema, &target);
+        assert_eq!(capability.payload_str().unwrap(), "{}");
+        let (_, metadata) =
+            SoftwareUpdateCommand::parse_capability_message(&schema, &capability).unwrap();
+        assert_eq!(metadata, SoftwareCommandMetadata::default());
+
+        let capability = ConfigSnapshotCmd::capability_message(&schema, &target);
+        let (_, metadata) =
+            ConfigSnapshotCmd::parse_capability_message(&schema, &capability).unwrap();
+        assert_eq!(metadata, ConfigMetadata::default());
+
+        let empty = MqttMessage::new(&capability.topic, "");
+        let (_, metadata) = ConfigSnapshotCmd::parse_capability_message(&schema, &empty).unwrap();
+        assert!(metadata.types.is_empty());
+    }
+
+    #[test]
+    fn parsing_capability_messages_checks_the_operation() {
+        let schema = MqttSchema::default();
+        let target = EntityTopicId::default_main_device();
+
+        let capability = RestartCommand::capability_message(&schema, &target);
+        let error =
+            SoftwareListCommand::parse_capability_message(&schema, &capability).unwrap_err();
+        assert!(matches!(
+            error,
+            CommandParsingError::InvalidCommandType { actual, expected }
+            if actual == "restart" && expected == "software_list"
+        ));
+
+        let command = RestartCommand::new(&target, "123".to_string()).command_message(&schema);
+        let error = RestartCommand::parse_capability_message(&schema, &command).unwrap_err();
+        assert!(matches!(
+            error,
+            CommandParsingError::InvalidCommandTopic { .. }
+        ));
+    }
+
+    #[test]
+    fn firmware_update_command_messages() {
+        let schema = MqttSchema::default();
+        let target = EntityTopicId::default_child_device("child01").unwrap();
+
+        let capability = FirmwareUpdateCmd::capability_message(&schema, &target);
+        assert_eq!(
+            capability.topic.name,
+            "te/device/child01///cmd/firmware_update"
+        );
+
+        let command = FirmwareUpdateCmd {
+            target: target.clone(),
+            cmd_id: "123".to_string(),
+            payload: FirmwareUpdateCmdPayload {
+                status: CommandStatus::Init,
+                status_message: None,
+                tedge_url: None,
+                remote_url: "https://example.com/firmware.bin".into(),
+                name: "OpenWRT".into(),
+                version: "22.03".into(),
+                sha256: None,
+                log_path: None,
+            },
+        };
+        let message = command.command_message(&schema);
+        assert_eq!(
+            message.topic.name,
+            "te/device/child01///cmd/firmware_update/123"
+        );
+        assert_eq!(
+            FirmwareUpdateCmd::parse(&schema, message).unwrap(),
+            Some(command.clone())
+        );
+
+        // A cleared command is parsed as no command
+        let clearing_message = command.clearing_message(&schema);
+        assert!(clearing_message.payload_bytes().is_empty());
+        assert_eq!(
+            FirmwareUpdateCmd::parse(&schema, clearing_message).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn progress_messages_are_updated_while_executing() {
+        let schema = MqttSchema::default();
+        let target = EntityTopicId::default_main_device();
+        let mut command = RestartCommand::new(&target, "123".to_string());
+        command.executing();
+        assert_eq!(command.status_message(), None);
+        assert!(!command
+            .command_message(&schema)
+            .payload_str()
+            .unwrap()
+            .contains("statusMessage"));
+
+        for progress in ["downloading 45%", "downloading 100%", "installing collectd"] {
+            command.set_status_message(progress);
+            let message = command.command_message(&schema);
+            let parsed = RestartCommand::parse(&schema, message).unwrap().unwrap();
+            assert_eq!(parsed.status(), CommandStatus::Executing);
+            assert_eq!(parsed.status_message(), Some(progress));
+            assert_eq!(parsed, command);
+        }
+
+        // The progress message is cleared when the status changes
+        command.successful();
+        let message = command.command_message(&schema);
+        assert_eq!(
+            serde_json::from_slice::<Value>(message.payload_bytes()).unwrap(),
+            serde_json::json!({"status": "successful"})
+        );
+    }
+
+    #[test]
+    fn parse_a_progress_message() {
+        let schema = MqttSchema::default();
+        let message = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_update/c8y-mapper-1"),
+            r#"{"status":"executing","statusMessage":"installing collectd","updateList":[
+                {"type":"apt","modules":[{"name":"collectd","action":"install"}]},
+                {"type":"docker","modules":[{"name":"nodered","action":"install"}]}
+            ]}"#,
+        );
+        let command = SoftwareUpdateCommand::parse(&schema, message)
+            .unwrap()
+            .unwrap();
+        assert_eq!(command.status(), CommandStatus::Executing);
+        assert_eq!(command.status_message(), Some("installing collectd"));
+
+        // The progress message is kept when the command is split per module type
+        let split = command.split_per_module_type();
+        assert_eq!(split.len(), 2);
+        assert!(split
+            .iter()
+            .all(|command| command.status_message() == Some("installing collectd")));
+    }
+
+    #[test]
+    fn parse_a_command_whose_payload_matches_its_topic() {
+        let schema = MqttSchema::default();
+        let message = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_update/c8y-mapper-1"),
+            r#"{"status":"init","id":"c8y-mapper-1","operation":"software_update","updateList":[]}"#,
+        );
+        let command = SoftwareUpdateCommand::parse(&schema, message)
+            .unwrap()
+            .unwrap();
+        assert_eq!(command.cmd_id, "c8y-mapper-1");
+        assert_eq!(command.status(), CommandStatus::Init);
+    }
+
+    #[test]
+    fn reject_a_command_whose_payload_disagrees_with_its_topic() {
+        let schema = MqttSchema::default();
+        let misrouted = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_update/c8y-mapper-1"),
+            r#"{"status":"init","id":"c8y-mapper-2","updateList":[]}"#,
+        );
+        assert!(matches!(
+            SoftwareUpdateCommand::parse(&schema, misrouted),
+            Err(CommandParsingError::CommandIdMismatch { topic, payload })
+            if topic == "c8y-mapper-1" && payload == "c8y-mapper-2"
+        ));
+
+        let misrouted = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/software_update/c8y-mapper-1"),
+            r#"{"status":"init","operation":"software_list"}"#,
+        );
+        assert!(matches!(
+            SoftwareUpdateCommand::parse(&schema, misrouted),
+            Err(CommandParsingError::OperationMismatch { topic, payload })
+            if topic == "software_update" && payload == "software_list"
+        ));
+    }
+
+    #[test]
+    fn split_software_update_command_per_module_type() {
+        let mut command =
+            SoftwareUpdateCommand::new(&EntityTopicId::default_main_device(), "c-123".to_string())
+                .with_status(CommandStatus::Executing);
+        command.add_update(SoftwareModuleUpdate::install(SoftwareModule::new(
+            Some("apt".into()),
+            "nodered".into(),
+            Some("1.0.0".into()),
+            None,
+            None,
+        )));
+        command.add_update(SoftwareModuleUpdate::install(SoftwareModule::new(
+            Some("docker".into()),
+            "nginx".into(),
+            Some("1.21.0".into()),
+            None,
+            None,
+        )));
+        command.add_update(SoftwareModuleUpdate::remove(SoftwareModule::new(
+            Some("apt".into()),
+            "collectd".into(),
+            None,
+            None,
+            None,
+        )));
+
+        let sub_commands = command.split_per_module_type();
+        assert_eq!(sub_commands.len(), 2);
+
+        for sub_command in sub_commands.iter() {
+            assert_eq!(sub_command.target, command.target);
+            assert_eq!(sub_command.cmd_id, "c-123");
+            assert_eq!(sub_command.status(), CommandStatus::Executing);
+        }
+
+        let apt = &sub_commands[0];
+        assert_eq!(apt.modules_types(), vec!["apt".to_string()]);
+        assert_eq!(apt.updates_for("apt"), command.updates_for("apt"));
+        assert_eq!(apt.updates_for("apt").len(), 2);
+        assert!(apt.updates_for("docker").is_empty());
+
+        let docker = &sub_commands[1];
+        assert_eq!(docker.modules_types(), vec!["docker".to_string()]);
+        assert_eq!(docker.updates_for("docker"), command.updates_for("docker"));
+        assert_eq!(docker.updates_for("docker").len(), 1);
+        assert!(docker.updates_for("apt").is_empty());
+    }
+
+    #[test]
+    fn count_the_modules_of_an_empty_software_list() {
+        let mut command =
+            SoftwareListCommand::new(&EntityTopicId::default_main_device(), "c-123".to_string());
+        assert!(command.is_empty());
+        assert_eq!(command.module_count(), 0);
+
+        command.add_modules("apt".into(), vec![]);
+        assert!(command.is_empty());
+        assert_eq!(command.module_count(), 0);
+        assert!(command.modules_of_type("apt").is_empty());
+    }
+
+    #[test]
+    fn count_and_filter_the_modules_of_a_software_list() {
+        let mut command =
+            SoftwareListCommand::new(&EntityTopicId::default_main_device(), "c-123".to_string());
+        command.add_modules(
+            "apt".into(),
+            vec![
+                SoftwareModule::new(None, "collectd".into(), Some("5.7".into()), None, None),
+                SoftwareModule::new(None, "nodered".into(), Some("1.0.0".into()), None, None),
+            ],
+        );
+        command.add_modules(
+            "docker".into(),
+            vec![SoftwareModule::new(
+                None,
+                "nginx".into(),
+                Some("1.21.0".into()),
+                None,
+                None,
+            )],
+        );
+
+        assert!(!command.is_empty());
+        assert_eq!(command.module_count(), 3);
+
+        let apt_modules = command.modules_of_type("apt");
+        assert_eq!(
+            apt_modules
+                .iter()
+                .map(|module| module.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["collectd", "nodered"]
+        );
+        assert_eq!(command.modules_of_type("docker").len(), 1);
+        assert!(command.modules_of_type("snap").is_empty());
+    }
+
+    #[test]
+    fn merge_two_successful_software_lists() {
+        let target = EntityTopicId::default_main_device();
+        let mut apt = SoftwareListCommand::new(&target, "c-123".to_string())
+            .with_status(CommandStatus::Successful);
+        apt.add_modules(
+            "apt".into(),
+            vec![SoftwareModule::new(
+                None,
+                "collectd".into(),
+                Some("5.7".into()),
+                None,
+                None,
+            )],
+        );
+        let mut others = SoftwareListCommand::new(&target, "c-123".to_string())
+            .with_status(CommandStatus::Successful);
+        others.add_modules(
+            "apt".into(),
+            vec![SoftwareModule::new(
+                None,
+                "nodered".into(),
+                Some("1.0.0".into()),
+                None,
+                None,
+            )],
+        );
+        others.add_modules(
+            "docker".into(),
+            vec![SoftwareModule::new(
+                None,
+                "nginx".into(),
+                Some("1.21.0".into()),
+                None,
+                None,
+            )],
+        );
+
+        apt.merge(others);
+
+        assert_eq!(apt.status(), CommandStatus::Successful);
+        assert_eq!(apt.module_count(), 3);
+        assert_eq!(apt.payload.current_software_list.len(), 2);
+        assert_eq!(
+            apt.modules_of_type("apt")
+                .iter()
+                .map(|module| module.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["collectd", "nodered"]
+        );
+        assert_eq!(apt.modules_of_type("docker").len(), 1);
+    }
+
+    #[test]
+    fn merge_a_successful_and_a_failed_software_list() {
+        let target = EntityTopicId::default_main_device();
+        let mut apt = SoftwareListCommand::new(&target, "c-123".to_string())
+            .with_status(CommandStatus::Successful);
+        apt.add_modules(
+            "apt".into(),
+            vec![SoftwareModule::new(
+                None,
+                "collectd".into(),
+                Some("5.7".into()),
+                None,
+                None,
+            )],
+        );
+        let docker = SoftwareListCommand::new(&target, "c-123".to_string()).with_status(
+            CommandStatus::Failed {
+                reason: "docker is not running".to_string(),
+            },
+        );
+        let snap = SoftwareListCommand::new(&target, "c-123".to_string()).with_status(
+            CommandStatus::Failed {
+                reason: "snap is not installed".to_string(),
+            },
+        );
+
+        apt.merge(docker);
+        assert_eq!(
+            apt.status(),
+            CommandStatus::Failed {
+                reason: "docker is not running".to_string()
+            }
+        );
+        assert_eq!(apt.module_count(), 1);
+
+        apt.merge(snap);
+        assert_eq!(
+            apt.status(),
+            CommandStatus::Failed {
+                reason: "docker is not running; snap is not installed".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn count_the_modules_of_a_software_update() {
+        let mut command =
+            SoftwareUpdateCommand::new(&EntityTopicId::default_main_device(), "c-123".to_string());
+        assert!(command.is_empty());
+        assert_eq!(command.module_count(), 0);
+
+        command.add_update(SoftwareModuleUpdate::install(SoftwareModule::new(
+            Some("apt".into()),
+            "nodered".into(),
+            None,
+            None,
+            None,
+        )));
+        command.add_update(SoftwareModuleUpdate::remove(SoftwareModule::new(
+            Some("docker".into()),
+            "nginx".into(),
+            None,
+            None,
+            None,
+        )));
+        assert!(!command.is_empty());
+        assert_eq!(command.module_count(), 2);
+    }
 }
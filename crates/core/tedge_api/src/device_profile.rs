@@ -1,5 +1,6 @@
 use crate::commands::Command;
 use crate::commands::CommandPayload;
+use crate::commands::EmptyMetadata;
 use crate::commands::SoftwareRequestResponseSoftwareList;
 use crate::mqtt_topics::OperationType;
 use crate::CommandStatus;
@@ -16,6 +17,9 @@ pub type DeviceProfileCmd = Command<DeviceProfileCmdPayload>;
 pub struct DeviceProfileCmdPayload {
     #[serde(flatten)]
     pub status: CommandStatus,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_message: Option<String>,
     pub name: String,
     pub operations: Vec<DeviceProfileOperation>,
 }
@@ -23,6 +27,8 @@ pub struct DeviceProfileCmdPayload {
 impl Jsonify for DeviceProfileCmdPayload {}
 
 impl CommandPayload for DeviceProfileCmdPayload {
+    type Metadata = EmptyMetadata;
+
     fn operation_type() -> OperationType {
         OperationType::DeviceProfile
     }
@@ -34,6 +40,14 @@ impl CommandPayload for DeviceProfileCmdPayload {
     fn set_status(&mut self, status: CommandStatus) {
         self.status = status
     }
+
+    fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    fn set_status_message(&mut self, message: Option<String>) {
+        self.status_message = message
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone)]
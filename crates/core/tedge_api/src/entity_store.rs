@@ -20,6 +20,7 @@ use crate::store::message_log::MessageLogReader;
 use crate::store::message_log::MessageLogWriter;
 use crate::store::pending_entity_store::PendingEntityData;
 use crate::store::pending_entity_store::PendingEntityStore;
+use futures::channel::mpsc;
 use log::debug;
 use log::error;
 use log::info;
@@ -33,11 +34,26 @@ use serde_json::Value as JsonValue;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::path::Path;
 
 // In the future, root will be read from config
 const MQTT_ROOT: &str = "te";
 
+/// Validates, and possibly normalizes, the external id provided on entity registration.
+pub type ExternalIdValidatorFn =
+    Box<dyn Fn(&str) -> Result<EntityExternalId, InvalidExternalIdError> + Send + Sync + 'static>;
+
+/// How the entity store log is to be opened
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntityStoreLogConfig {
+    /// Discard the entities persisted by a previous run
+    pub clean_start: bool,
+
+    /// Compress the log, when a new log is created
+    pub compress: bool,
+}
+
 /// A store for topic-based entity metadata lookup.
 ///
 /// This object is a hashmap from MQTT identifiers to entities (devices or
@@ -58,7 +74,7 @@ const MQTT_ROOT: &str = "te";
 /// ```
 /// # use mqtt_channel::{MqttMessage, Topic};
 /// # use tedge_api::mqtt_topics::MqttSchema;
-/// # use tedge_api::entity_store::{EntityStore, EntityRegistrationMessage};
+/// # use tedge_api::entity_store::{EntityStore, EntityStoreLogConfig, EntityRegistrationMessage};
 /// let mqtt_message = MqttMessage::new(
 ///     &Topic::new("te/device/main//").unwrap(),
 ///     r#"{"@type": "device"}"#.to_string(),
@@ -71,7 +87,10 @@ const MQTT_ROOT: &str = "te";
 ///     "service".into(),
 ///     0,
 ///     "/tmp",
-///     true
+///     EntityStoreLogConfig {
+///         clean_start: true,
+///         ..Default::default()
+///     },
 /// );
 /// ```
 pub struct EntityStore {
@@ -83,17 +102,47 @@ pub struct EntityStore {
     pending_entity_store: PendingEntityStore,
     // The persistent message log to persist entity registrations and twin data messages
     message_log: MessageLogWriter,
+    external_id_validator_fn: ExternalIdValidatorFn,
+    // The subscribers to the entity changes, see `EntityStore::subscribe_to_changes`
+    change_subscribers: Vec<mpsc::UnboundedSender<EntityChange>>,
+}
+
+/// A change notified to the subscribers of an [EntityStore]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntityChange {
+    /// An entity has been registered, or its registration updated
+    Registered(EntityTopicId),
+
+    /// An entity has been deregistered
+    Deregistered(EntityTopicId),
+
+    /// A twin fragment of an entity has been added, updated or removed
+    TwinUpdated {
+        topic_id: EntityTopicId,
+        diff: TwinFragmentDiff,
+    },
+}
+
+/// The change of a twin fragment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwinFragmentDiff {
+    pub fragment_key: String,
+
+    /// The previous value, `None` if the fragment has been added
+    pub old_value: Option<JsonValue>,
+
+    /// The new value, `None` if the fragment has been removed
+    pub new_value: Option<JsonValue>,
 }
 
 impl EntityStore {
-    #[allow(clippy::too_many_arguments)]
     pub fn with_main_device_and_default_service_type<P>(
         mqtt_schema: MqttSchema,
         main_device: EntityRegistrationMessage,
         default_service_type: String,
         telemetry_cache_size: usize,
         log_dir: P,
-        clean_start: bool,
+        log_config: EntityStoreLogConfig,
     ) -> Result<Self, InitError>
     where
         P: AsRef<Path>,
@@ -113,14 +162,16 @@ impl EntityStore {
             twin_data: Map::new(),
         };
 
-        let message_log = if clean_start {
-            MessageLogWriter::new_truncated(log_dir.as_ref()).map_err(|err| {
-                InitError::Custom(format!(
-                    "Loading the entity store log for writes failed with {err}",
-                ))
-            })?
+        let message_log = if log_config.clean_start {
+            MessageLogWriter::new_truncated(log_dir.as_ref(), log_config.compress).map_err(
+                |err| {
+                    InitError::Custom(format!(
+                        "Loading the entity store log for writes failed with {err}",
+                    ))
+                },
+            )?
         } else {
-            MessageLogWriter::new(log_dir.as_ref()).map_err(|err| {
+            MessageLogWriter::new(log_dir.as_ref(), log_config.compress).map_err(|err| {
                 InitError::Custom(format!(
                     "Loading the entity store log for writes failed with {err}",
                 ))
@@ -134,6 +185,8 @@ impl EntityStore {
             default_service_type,
             pending_entity_store: PendingEntityStore::new(mqtt_schema, telemetry_cache_size),
             message_log,
+            external_id_validator_fn: Box::new(|id| Ok(id.into())),
+            change_subscribers: vec![],
         };
 
         entity_store.load_from_message_log(log_dir.as_ref());
@@ -141,6 +194,22 @@ impl EntityStore {
         Ok(entity_store)
     }
 
+    /// Set the function used to validate and normalize the external ids of registered entities.
+    ///
+    /// The validator is applied to the `@id` of each new registration, be it explicit or automatic,
+    /// and the registration is rejected with [Error::InvalidExternalId] if the validation fails.
+    /// The entities restored from the persistent message log are not validated again.
+    ///
+    /// By default, any external id is accepted as is.
+    pub fn with_external_id_validator<F>(mut self, external_id_validator_fn: F) -> Self
+    where
+        F: Fn(&str) -> Result<EntityExternalId, InvalidExternalIdError>,
+        F: 'static + Send + Sync,
+    {
+        self.external_id_validator_fn = Box::new(external_id_validator_fn);
+        self
+    }
+
     pub fn load_from_message_log<P>(&mut self, log_dir: P)
     where
         P: AsRef<Path>,
@@ -255,6 +324,22 @@ impl EntityStore {
             .ok_or_else(|| Error::UnknownEntity(entity_topic_id.to_string()))
     }
 
+    /// Subscribe to the changes of this store: entity registrations, deregistrations and twin updates
+    ///
+    /// Only the changes made after the subscription are notified.
+    /// The subscription ends when the returned stream is dropped.
+    pub fn subscribe_to_changes(&mut self) -> mpsc::UnboundedReceiver<EntityChange> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.change_subscribers.push(sender);
+        receiver
+    }
+
+    fn notify_change(&mut self, change: EntityChange) {
+        // Errors on send just mean that the subscriber has dropped its stream
+        self.change_subscribers
+            .retain(|subscriber| subscriber.unbounded_send(change.clone()).is_ok());
+    }
+
     /// Returns the MQTT identifier of the main device.
     ///
     /// The main device is an entity with `@type: "device"`.
@@ -282,6 +367,69 @@ impl EntityStore {
             .collect()
     }
 
+    /// Returns the metadata of all the descendants of a given entity, excluding the entity itself.
+    ///
+    /// The child devices and services are listed breadth-first, level by level,
+    /// the siblings being listed in their registration order.
+    /// An entity reached twice, as when the parent links form a cycle, is listed only once.
+    pub fn descendants(&self, entity_topic: &EntityTopicId) -> Vec<&EntityMetadata> {
+        let mut descendants = vec![];
+        let mut visited = HashSet::from([entity_topic]);
+        let mut next_entities = VecDeque::from([entity_topic]);
+        while let Some(topic_id) = next_entities.pop_front() {
+            for (child, metadata) in self.entities.children(topic_id) {
+                if visited.insert(child) {
+                    descendants.push(metadata);
+                    next_entities.push_back(child);
+                }
+            }
+        }
+        descendants
+    }
+
+    /// Export the registered entities as the retained messages that would recreate them
+    ///
+    /// Each registration message is followed by the twin data messages of the entity.
+    /// The entities are ordered from the main device down to the leaves of the entity tree,
+    /// hence the registration of any entity comes after that of its parent.
+    /// The siblings are sorted by topic id, so the export is deterministic.
+    pub fn to_registration_messages(&self) -> Vec<MqttMessage> {
+        let mut messages = vec![];
+        let mut next_entities = VecDeque::from([&self.main_device]);
+        while let Some(topic_id) = next_entities.pop_front() {
+            let Some(entity) = self.get(topic_id) else {
+                continue;
+            };
+
+            let registration = EntityRegistrationMessage {
+                topic_id: entity.topic_id.clone(),
+                external_id: entity.external_id.clone(),
+                r#type: entity.r#type.clone(),
+                parent: entity.parent.clone(),
+                other: entity.other.clone(),
+            };
+            messages.push(registration.to_mqtt_message(&self.mqtt_schema));
+            for (fragment_key, fragment_value) in entity.twin_data.iter() {
+                let twin_data = EntityTwinMessage::new(
+                    topic_id.clone(),
+                    fragment_key.clone(),
+                    fragment_value.clone(),
+                );
+                messages.push(twin_data.to_mqtt_message(&self.mqtt_schema));
+            }
+
+            let mut children: Vec<_> = self
+                .entities
+                .children(topic_id)
+                .into_iter()
+                .map(|(child, _)| child)
+                .collect();
+            children.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            next_entities.extend(children);
+        }
+        messages
+    }
+
     /// Updates entity store state based on the content of the entity
     /// registration message.
     ///
@@ -289,7 +437,18 @@ impl EntityStore {
     /// entity, returning a list of all entities affected by the update, e.g.:
     ///
     /// - when adding/removing a child device or service, the parent is affected
+    ///
+    /// The external id of the entity is validated, and possibly normalized, before anything else:
+    /// so an entity registered before its parent is cached with its normalized id.
     pub fn update(
+        &mut self,
+        mut message: EntityRegistrationMessage,
+    ) -> Result<(Vec<EntityTopicId>, Vec<PendingEntityData>), Error> {
+        self.validate_external_id(&mut message)?;
+        self.update_validated(message)
+    }
+
+    fn update_validated(
         &mut self,
         message: EntityRegistrationMessage,
     ) -> Result<(Vec<EntityTopicId>, Vec<PendingEntityData>), Error> {
@@ -376,14 +535,26 @@ impl EntityStore {
 
         match self.entities.insert(topic_id.clone(), entity_metadata) {
             InsertOutcome::Unchanged => Ok(vec![]),
-            InsertOutcome::Inserted => Ok(affected_entities),
+            InsertOutcome::Inserted => {
+                self.notify_change(EntityChange::Registered(topic_id));
+                Ok(affected_entities)
+            }
             InsertOutcome::Updated => {
+                self.notify_change(EntityChange::Registered(topic_id.clone()));
                 affected_entities.push(topic_id);
                 Ok(affected_entities)
             }
         }
     }
 
+    /// Apply the external id validator to the given registration message
+    fn validate_external_id(&self, message: &mut EntityRegistrationMessage) -> Result<(), Error> {
+        if let Some(external_id) = message.external_id.take() {
+            message.external_id = Some((self.external_id_validator_fn)(external_id.as_ref())?);
+        }
+        Ok(())
+    }
+
     fn register_and_persist_entity(
         &mut self,
         message: EntityRegistrationMessage,
@@ -427,8 +598,9 @@ impl EntityStore {
                         .insert("type".to_string(), self.default_service_type.clone().into());
                 }
 
+                self.validate_external_id(&mut auto_entity)?;
                 register_messages.push(auto_entity.clone());
-                self.update(auto_entity)?;
+                self.update_validated(auto_entity)?;
             }
         }
 
@@ -439,6 +611,9 @@ impl EntityStore {
     pub fn deregister_entity(&mut self, topic_id: &EntityTopicId) -> Vec<EntityTopicId> {
         let mut removed_entities = vec![];
         self.entities.remove(topic_id, &mut removed_entities);
+        for removed_entity in removed_entities.iter() {
+            self.notify_change(EntityChange::Deregistered(removed_entity.clone()));
+        }
         removed_entities
     }
 
@@ -479,22 +654,47 @@ impl EntityStore {
         twin_message: EntityTwinMessage,
     ) -> Result<bool, entity_store::Error> {
         let fragment_key = twin_message.fragment_key.clone();
-        let fragment_value = twin_message.fragment_value.clone();
         let entity = self.try_get_mut(&twin_message.topic_id)?;
-        if fragment_value.is_null() {
+        let fragment_value = match twin_message.update_mode {
+            TwinUpdateMode::Merge if !twin_message.fragment_value.is_null() => {
+                let mut merged = entity
+                    .twin_data
+                    .get(&fragment_key)
+                    .cloned()
+                    .unwrap_or(JsonValue::Null);
+                merge_json(&mut merged, twin_message.fragment_value.clone());
+                merged
+            }
+            _ => twin_message.fragment_value.clone(),
+        };
+        let diff = if fragment_value.is_null() {
             let existing = entity.twin_data.remove(&fragment_key);
             if existing.is_none() {
                 return Ok(false);
             }
+            TwinFragmentDiff {
+                fragment_key,
+                old_value: existing,
+                new_value: None,
+            }
         } else {
             let existing = entity
                 .twin_data
-                .insert(fragment_key, fragment_value.clone());
-            if existing.is_some_and(|v| v.eq(&fragment_value)) {
+                .insert(fragment_key.clone(), fragment_value.clone());
+            if existing.as_ref().is_some_and(|v| v.eq(&fragment_value)) {
                 return Ok(false);
             }
-        }
+            TwinFragmentDiff {
+                fragment_key,
+                old_value: existing,
+                new_value: Some(fragment_value),
+            }
+        };
 
+        self.notify_change(EntityChange::TwinUpdated {
+            topic_id: twin_message.topic_id,
+            diff,
+        });
         Ok(true)
     }
 
@@ -504,8 +704,20 @@ impl EntityStore {
     ) -> Result<bool, entity_store::Error> {
         let updated = self.register_twin_data(twin_message.clone())?;
         if updated {
+            // Persist the resulting fragment value, which for a merge update differs from the message
+            let fragment_value = self
+                .try_get(&twin_message.topic_id)?
+                .twin_data
+                .get(&twin_message.fragment_key)
+                .cloned()
+                .unwrap_or(JsonValue::Null);
+            let persisted_message = EntityTwinMessage::new(
+                twin_message.topic_id,
+                twin_message.fragment_key,
+                fragment_value,
+            );
             self.message_log
-                .append_message(&twin_message.to_mqtt_message(&self.mqtt_schema))?;
+                .append_message(&persisted_message.to_mqtt_message(&self.mqtt_schema))?;
         }
 
         Ok(updated)
@@ -524,14 +736,25 @@ struct EntityTree {
 #[derive(Debug)]
 struct EntityNode {
     metadata: EntityMetadata,
-    children: HashSet<EntityTopicId>,
+    /// The children of this entity, in registration order
+    children: Vec<EntityTopicId>,
+    /// The same children, to check in constant time if already registered
+    known_children: HashSet<EntityTopicId>,
 }
 
 impl EntityNode {
     pub fn new(metadata: EntityMetadata) -> Self {
         EntityNode {
             metadata,
-            children: HashSet::new(),
+            children: Vec::new(),
+            known_children: HashSet::new(),
+        }
+    }
+
+    /// Add a child to this entity, unless already registered
+    fn add_child(&mut self, topic_id: EntityTopicId) {
+        if self.known_children.insert(topic_id.clone()) {
+            self.children.push(topic_id);
         }
     }
 
@@ -596,7 +819,6 @@ impl EntityTree {
             Entry::Occupied(mut occupied) => {
                 // if there is no change, no entities were affected
                 let existing_entity = occupied.get().metadata.clone();
-                let existing_children = occupied.get().children.clone();
 
                 let mut merged_other = existing_entity.other.clone();
                 merged_other.extend(entity_metadata.other.clone());
@@ -609,11 +831,7 @@ impl EntityTree {
                 if existing_entity == merged_entity {
                     InsertOutcome::Unchanged
                 } else {
-                    let updated_entity = EntityNode {
-                        metadata: merged_entity,
-                        children: existing_children,
-                    };
-                    occupied.insert(updated_entity);
+                    occupied.get_mut().metadata = merged_entity;
                     InsertOutcome::Updated
                 }
             }
@@ -625,7 +843,7 @@ impl EntityTree {
 
         if let Some(parent) = maybe_parent {
             if let Some(parent_entry) = self.entities.get_mut(&parent) {
-                parent_entry.children.insert(topic_id);
+                parent_entry.add_child(topic_id);
             }
         }
 
@@ -652,6 +870,13 @@ impl EntityTree {
     }
 }
 
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid external id {external_id:?}: {reason}")]
+pub struct InvalidExternalIdError {
+    pub external_id: String,
+    pub reason: String,
+}
+
 /// Represents an error encountered while updating the store.
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -670,6 +895,9 @@ pub enum Error {
     #[error("The specified entity {0} does not exist in the store")]
     UnknownEntity(String),
 
+    #[error(transparent)]
+    InvalidExternalId(#[from] InvalidExternalIdError),
+
     #[error("Auto registration of the entity with topic id {0} failed as it does not match the default topic scheme: 'device/<device-id>/service/<service-id>'. Try explicit registration instead.")]
     NonDefaultTopicScheme(EntityTopicId),
 
@@ -884,6 +1112,23 @@ pub struct EntityTwinMessage {
     pub topic_id: EntityTopicId,
     pub fragment_key: String,
     pub fragment_value: JsonValue,
+    pub update_mode: TwinUpdateMode,
+}
+
+/// How a twin fragment value is applied to the value already stored for that fragment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwinUpdateMode {
+    /// The new value replaces the stored value
+    #[default]
+    Replace,
+
+    /// The new value is deep-merged into the stored value
+    ///
+    /// As for a JSON merge patch ([RFC 7386](https://www.rfc-editor.org/rfc/rfc7386)),
+    /// objects are merged key by key, a `null` removing the key,
+    /// and any other value replaces the stored one.
+    Merge,
 }
 
 impl EntityTwinMessage {
@@ -892,6 +1137,15 @@ impl EntityTwinMessage {
             topic_id,
             fragment_key,
             fragment_value,
+            update_mode: TwinUpdateMode::Replace,
+        }
+    }
+
+    /// A twin message to be deep-merged into the stored fragment value
+    pub fn merge(topic_id: EntityTopicId, fragment_key: String, fragment_value: JsonValue) -> Self {
+        EntityTwinMessage {
+            update_mode: TwinUpdateMode::Merge,
+            ..EntityTwinMessage::new(topic_id, fragment_key, fragment_value)
         }
     }
 
@@ -906,6 +1160,27 @@ impl EntityTwinMessage {
     }
 }
 
+/// Apply a JSON merge patch to a value
+fn merge_json(target: &mut JsonValue, patch: JsonValue) {
+    let JsonValue::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = JsonValue::Object(Map::new());
+    }
+    let JsonValue::Object(target) = target else {
+        return;
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge_json(target.entry(key).or_insert(JsonValue::Null), value);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1266,6 +1541,88 @@ mod tests {
         assert!(entity_metadata.twin_data.get("foo").is_none());
     }
 
+    #[test]
+    fn merge_twin_data_into_existing_fragment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir, true);
+
+        let topic_id = EntityTopicId::default_main_device();
+        store
+            .update_twin_data(EntityTwinMessage::new(
+                topic_id.clone(),
+                "hardware".into(),
+                json!({
+                    "model": "raspberry-pi",
+                    "serialNo": "123-456",
+                    "firmware": { "name": "rpi-firmware", "version": "1.0" }
+                }),
+            ))
+            .unwrap();
+
+        let updated = store
+            .update_twin_data(EntityTwinMessage::merge(
+                topic_id.clone(),
+                "hardware".into(),
+                json!({
+                    "serialNo": null,
+                    "firmware": { "version": "2.0" }
+                }),
+            ))
+            .unwrap();
+        assert!(updated);
+
+        let expected_fragment = json!({
+            "model": "raspberry-pi",
+            "firmware": { "name": "rpi-firmware", "version": "2.0" }
+        });
+        let entity_metadata = store.get(&topic_id).unwrap();
+        assert_eq!(
+            entity_metadata.twin_data.get("hardware"),
+            Some(&expected_fragment)
+        );
+
+        // The merged value is persisted
+        let store = new_entity_store(&temp_dir, false);
+        let entity_metadata = store.get(&topic_id).unwrap();
+        assert_eq!(
+            entity_metadata.twin_data.get("hardware"),
+            Some(&expected_fragment)
+        );
+    }
+
+    #[test]
+    fn replace_existing_twin_fragment() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir, true);
+
+        let topic_id = EntityTopicId::default_main_device();
+        store
+            .update_twin_data(EntityTwinMessage::new(
+                topic_id.clone(),
+                "hardware".into(),
+                json!({
+                    "model": "raspberry-pi",
+                    "firmware": { "name": "rpi-firmware", "version": "1.0" }
+                }),
+            ))
+            .unwrap();
+
+        let updated = store
+            .update_twin_data(EntityTwinMessage::new(
+                topic_id.clone(),
+                "hardware".into(),
+                json!({ "firmware": { "version": "2.0" } }),
+            ))
+            .unwrap();
+        assert!(updated);
+
+        let entity_metadata = store.get(&topic_id).unwrap();
+        assert_eq!(
+            entity_metadata.twin_data.get("hardware"),
+            Some(&json!({ "firmware": { "version": "2.0" } }))
+        );
+    }
+
     #[test]
     fn updated_registration_message_after_twin_updates() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -1286,7 +1643,10 @@ mod tests {
             "service".into(),
             0,
             &temp_dir,
-            true,
+            EntityStoreLogConfig {
+                clean_start: true,
+                ..Default::default()
+            },
         )
         .unwrap();
 
@@ -1500,6 +1860,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn compressed_entities_persisted_and_restored() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let child_topic_id = EntityTopicId::default_child_device("child1").unwrap();
+
+        {
+            let mut store = new_entity_store_with_log_compression(&temp_dir, true, true);
+            store
+                .update(
+                    EntityRegistrationMessage::new_custom(
+                        child_topic_id.clone(),
+                        EntityType::ChildDevice,
+                    )
+                    .with_external_id("child1".into()),
+                )
+                .unwrap();
+        }
+
+        let log = std::fs::read(temp_dir.path().join("entity_store.jsonl")).unwrap();
+        assert!(log.starts_with(&[0x1f, 0x8b]), "the log is gzip-compressed");
+
+        // The compressed log is detected on load, whatever the compression setting
+        let store = new_entity_store_with_log_compression(&temp_dir, false, false);
+        assert_eq!(
+            store.get(&child_topic_id).unwrap(),
+            &EntityMetadata::child_device("child1".into()).unwrap()
+        );
+    }
+
     #[test]
     fn deregister_entities() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -1553,7 +1942,353 @@ mod tests {
         assert!(store.get(&entity("device/00E//")).is_some());
     }
 
+    #[test]
+    fn external_ids_are_validated_on_registration() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir, true).with_external_id_validator(|id| {
+            if id.len() > 16 {
+                return Err(InvalidExternalIdError {
+                    external_id: id.to_string(),
+                    reason: "longer than 16 characters".to_string(),
+                });
+            }
+            Ok(id.replace(' ', "-").into())
+        });
+
+        // A valid id is registered as is
+        let child1 = EntityRegistrationMessage::new_custom(
+            entity("device/child1//"),
+            EntityType::ChildDevice,
+        )
+        .with_external_id("child1".into());
+        store.update(child1).unwrap();
+        assert_eq!(
+            store.get(&entity("device/child1//")).unwrap().external_id,
+            Some("child1".into())
+        );
+
+        // An id is normalized by the validator
+        let child2 = EntityRegistrationMessage::new_custom(
+            entity("device/child2//"),
+            EntityType::ChildDevice,
+        )
+        .with_external_id("child 2".into());
+        store.update(child2).unwrap();
+        assert_eq!(
+            store.get(&entity("device/child2//")).unwrap().external_id,
+            Some("child-2".into())
+        );
+
+        // An invalid id is rejected, and the entity is not registered
+        let child3 = EntityRegistrationMessage::new_custom(
+            entity("device/child3//"),
+            EntityType::ChildDevice,
+        )
+        .with_external_id("a-far-too-long-child-id".into());
+        let error = store.update(child3).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InvalidExternalId(InvalidExternalIdError { external_id, .. }) if external_id == "a-far-too-long-child-id"
+        ));
+        assert!(store.get(&entity("device/child3//")).is_none());
+
+        // The normalized id is persisted
+        let store = new_entity_store(&temp_dir, false);
+        assert_eq!(
+            store.get(&entity("device/child2//")).unwrap().external_id,
+            Some("child-2".into())
+        );
+    }
+
+    #[test]
+    fn entity_changes_are_notified_to_subscribers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir, true);
+        let mut changes = store.subscribe_to_changes();
+
+        register_child(&mut store, "device/main//", "device/child1//");
+        assert_eq!(
+            changes.try_next().unwrap(),
+            Some(EntityChange::Registered(entity("device/child1//")))
+        );
+
+        store
+            .update_twin_data(EntityTwinMessage::new(
+                entity("device/child1//"),
+                "hardware".into(),
+                json!({ "serialNo": "123-456" }),
+            ))
+            .unwrap();
+        assert_eq!(
+            changes.try_next().unwrap(),
+            Some(EntityChange::TwinUpdated {
+                topic_id: entity("device/child1//"),
+                diff: TwinFragmentDiff {
+                    fragment_key: "hardware".into(),
+                    old_value: None,
+                    new_value: Some(json!({ "serialNo": "123-456" })),
+                }
+            })
+        );
+
+        store.deregister_entity(&entity("device/child1//"));
+        assert_eq!(
+            changes.try_next().unwrap(),
+            Some(EntityChange::Deregistered(entity("device/child1//")))
+        );
+
+        // No more changes
+        assert!(changes.try_next().is_err());
+    }
+
+    #[test]
+    fn exported_registration_messages_recreate_the_entities() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir, true);
+        register_service(&mut store, "device/main//", "device/main/service/collectd");
+        register_child(&mut store, "device/main//", "device/child2//");
+        register_child(&mut store, "device/main//", "device/child1//");
+        register_child(&mut store, "device/child1//", "device/child11//");
+        register_service(&mut store, "device/child1//", "device/child1/service/app");
+        store
+            .update_twin_data(EntityTwinMessage::new(
+                entity("device/child1//"),
+                "hardware".into(),
+                json!({ "version": 5 }),
+            ))
+            .unwrap();
+
+        let messages = store.to_registration_messages();
+        let topics: Vec<_> = messages.iter().map(|m| m.topic.name.as_str()).collect();
+        assert_eq!(
+            topics,
+            vec![
+                "te/device/main//",
+                "te/device/child1//",
+                "te/device/child1///twin/hardware",
+                "te/device/child2//",
+                "te/device/main/service/collectd",
+                "te/device/child1/service/app",
+                "te/device/child11//",
+            ]
+        );
+        assert!(messages.iter().all(|m| m.retain));
+
+        // Replay the messages into a fresh store
+        let other_dir = tempfile::tempdir().unwrap();
+        let mut restored = new_entity_store(&other_dir, true);
+        for message in messages.iter() {
+            let (topic_id, channel) = restored
+                .mqtt_schema
+                .entity_channel_of(&message.topic)
+                .unwrap();
+            match channel {
+                Channel::EntityMetadata => {
+                    let registration = EntityRegistrationMessage::try_from(message).unwrap();
+                    restored.update(registration).unwrap();
+                }
+                Channel::EntityTwinData { fragment_key } => {
+                    let fragment_value = serde_json::from_slice(message.payload_bytes()).unwrap();
+                    restored
+                        .update_twin_data(EntityTwinMessage::new(
+                            topic_id,
+                            fragment_key,
+                            fragment_value,
+                        ))
+                        .unwrap();
+                }
+                channel => panic!("unexpected channel: {channel:?}"),
+            }
+        }
+
+        for topic_id in [
+            "device/main//",
+            "device/child1//",
+            "device/child2//",
+            "device/child11//",
+            "device/main/service/collectd",
+            "device/child1/service/app",
+        ] {
+            let topic_id = entity(topic_id);
+            assert_eq!(restored.get(&topic_id), store.get(&topic_id));
+        }
+        assert_eq!(restored.to_registration_messages(), messages);
+    }
+
+    #[test]
+    fn lists_descendants_breadth_first() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir, true);
+
+        // device/main//
+        // ├── device/main/service/mapper
+        // ├── device/child1//
+        // │   ├── device/child1/service/agent
+        // │   └── device/child11//
+        // │       └── device/child11/service/agent
+        // └── device/child2//
+        for (topic, payload) in [
+            ("te/device/child2//", json!({"@type": "child-device"})),
+            ("te/device/child1//", json!({"@type": "child-device"})),
+            (
+                "te/device/child11//",
+                json!({"@type": "child-device", "@parent": "device/child1//"}),
+            ),
+            (
+                "te/device/child1/service/agent",
+                json!({"@type": "service"}),
+            ),
+            ("te/device/main/service/mapper", json!({"@type": "service"})),
+            (
+                "te/device/child11/service/agent",
+                json!({"@type": "service"}),
+            ),
+        ] {
+            store
+                .update(
+                    EntityRegistrationMessage::new(&MqttMessage::new(
+                        &Topic::new(topic).unwrap(),
+                        payload.to_string(),
+                    ))
+                    .unwrap(),
+                )
+                .unwrap();
+        }
+
+        let descendants = |topic_id: &str| -> Vec<String> {
+            store
+                .descendants(&EntityTopicId::from_str(topic_id).unwrap())
+                .into_iter()
+                .map(|entity| entity.topic_id.to_string())
+                .collect()
+        };
+        assert_eq!(
+            descendants("device/main//"),
+            [
+                "device/child2//",
+                "device/child1//",
+                "device/main/service/mapper",
+                "device/child11//",
+                "device/child1/service/agent",
+                "device/child11/service/agent",
+            ]
+        );
+        assert_eq!(
+            descendants("device/child1//"),
+            [
+                "device/child11//",
+                "device/child1/service/agent",
+                "device/child11/service/agent",
+            ]
+        );
+        assert_eq!(
+            descendants("device/child11//"),
+            ["device/child11/service/agent"]
+        );
+        assert!(descendants("device/child2//").is_empty());
+        assert!(descendants("device/unknown//").is_empty());
+    }
+
+    #[test]
+    fn descendants_are_listed_once_even_with_a_cycle() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir, true);
+        for (topic, payload) in [
+            ("te/device/child1//", json!({"@type": "child-device"})),
+            (
+                "te/device/child2//",
+                json!({"@type": "child-device", "@parent": "device/child1//"}),
+            ),
+            // child1 is re-parented to its own child
+            (
+                "te/device/child1//",
+                json!({"@type": "child-device", "@parent": "device/child2//"}),
+            ),
+        ] {
+            store
+                .update(
+                    EntityRegistrationMessage::new(&MqttMessage::new(
+                        &Topic::new(topic).unwrap(),
+                        payload.to_string(),
+                    ))
+                    .unwrap(),
+                )
+                .unwrap();
+        }
+
+        let descendants: Vec<_> = store
+            .descendants(&EntityTopicId::from_str("device/child1//").unwrap())
+            .into_iter()
+            .map(|entity| entity.topic_id.to_string())
+            .collect();
+        assert_eq!(descendants, ["device/child2//"]);
+    }
+
+    #[test]
+    fn external_ids_of_entities_registered_before_their_parent_are_validated() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut store = new_entity_store(&temp_dir, true).with_external_id_validator(|id| {
+            if id.len() > 16 {
+                return Err(InvalidExternalIdError {
+                    external_id: id.to_string(),
+                    reason: "longer than 16 characters".to_string(),
+                });
+            }
+            Ok(id.replace(' ', "-").into())
+        });
+
+        // An early registration with an invalid id is rejected, and not cached
+        let invalid_child = EntityRegistrationMessage::new_custom(
+            entity("device/child01//"),
+            EntityType::ChildDevice,
+        )
+        .with_external_id("a-far-too-long-child-id".into())
+        .with_parent(entity("device/child0//"));
+        assert!(matches!(
+            store.update(invalid_child),
+            Err(Error::InvalidExternalId(_))
+        ));
+
+        // An early registration with a valid id is cached with the normalized id
+        let valid_child = EntityRegistrationMessage::new_custom(
+            entity("device/child02//"),
+            EntityType::ChildDevice,
+        )
+        .with_external_id("child 02".into())
+        .with_parent(entity("device/child0//"));
+        assert_eq!(store.update(valid_child).unwrap(), (vec![], vec![]));
+
+        // The registration of the parent is not failing because of the invalid child
+        let parent = EntityRegistrationMessage::new_custom(
+            entity("device/child0//"),
+            EntityType::ChildDevice,
+        )
+        .with_external_id("child 0".into());
+        let (_, pending_entities) = store.update(parent).unwrap();
+        let registered_ids: Vec<_> = pending_entities
+            .iter()
+            .map(|pending| pending.reg_message.external_id.clone())
+            .collect();
+        assert_eq!(
+            registered_ids,
+            vec![Some("child-0".into()), Some("child-02".into())]
+        );
+        assert!(store.get(&entity("device/child01//")).is_none());
+        assert_eq!(
+            store.get(&entity("device/child02//")).unwrap().external_id,
+            Some("child-02".into())
+        );
+    }
+
     fn new_entity_store(temp_dir: &TempDir, clean_start: bool) -> EntityStore {
+        new_entity_store_with_log_compression(temp_dir, clean_start, false)
+    }
+
+    fn new_entity_store_with_log_compression(
+        temp_dir: &TempDir,
+        clean_start: bool,
+        compress_log: bool,
+    ) -> EntityStore {
         EntityStore::with_main_device_and_default_service_type(
             MqttSchema::default(),
             EntityRegistrationMessage {
@@ -1566,7 +2301,10 @@ mod tests {
             "service".into(),
             0,
             temp_dir,
-            clean_start,
+            EntityStoreLogConfig {
+                clean_start,
+                compress: compress_log,
+            },
         )
         .unwrap()
     }
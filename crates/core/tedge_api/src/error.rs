@@ -29,7 +29,7 @@ pub enum SoftwareError {
         reason: String,
     },
 
-    #[error("Failed to install {module:?}")]
+    #[error("Failed to install {}", .module.display_name())]
     Install {
         module: Box<SoftwareModule>,
         reason: String,
@@ -56,7 +56,7 @@ pub enum SoftwareError {
         reason: String,
     },
 
-    #[error("Failed to uninstall {module:?}")]
+    #[error("Failed to uninstall {}", .module.display_name())]
     Remove {
         module: Box<SoftwareModule>,
         reason: String,
@@ -112,7 +112,7 @@ pub enum SoftwareError {
 fn module_names(updates: &[SoftwareModuleUpdate]) -> Vec<String> {
     updates
         .iter()
-        .map(|update| update.module().name.to_owned())
+        .map(|update| update.module().display_name())
         .collect()
 }
 
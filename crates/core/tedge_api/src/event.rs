@@ -2,7 +2,10 @@ use self::error::ThinEdgeJsonDeserializerError;
 use crate::entity::EntityExternalId;
 use crate::entity::EntityType;
 use clock::Timestamp;
+use log::warn;
 use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
 use serde_json::Value;
 use std::collections::HashMap;
 use tedge_utils::timestamp::deserialize_optional_string_or_unix_timestamp;
@@ -18,18 +21,71 @@ pub struct ThinEdgeEvent {
 }
 
 /// In-memory representation of ThinEdge JSON event payload
-#[derive(Debug, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub struct ThinEdgeEventData {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub text: Option<String>,
 
+    /// The severity as given by the payload, if any (see [ThinEdgeEventData::severity])
+    ///
+    /// An unknown severity is ignored, as events were accepted with any severity before being categorized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_optional_severity")]
+    pub severity: Option<EventSeverity>,
+
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_optional_string_or_unix_timestamp")]
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub time: Option<Timestamp>,
 
     #[serde(flatten)]
     pub extras: HashMap<String, Value>,
 }
 
+impl ThinEdgeEventData {
+    /// The severity of the event, `info` if not given by the payload
+    pub fn severity(&self) -> EventSeverity {
+        self.severity.unwrap_or_default()
+    }
+}
+
+/// The category of an event
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSeverity {
+    #[default]
+    Info,
+    Warning,
+    Error,
+}
+
+fn deserialize_optional_severity<'de, D>(deserializer: D) -> Result<Option<EventSeverity>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<Value>::deserialize(deserializer)?;
+    Ok(
+        value.and_then(|value| match EventSeverity::deserialize(value.clone()) {
+            Ok(severity) => Some(severity),
+            Err(_) => {
+                warn!("Ignoring unknown event severity {value}, defaulting to info");
+                None
+            }
+        }),
+    )
+}
+
+impl std::fmt::Display for EventSeverity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventSeverity::Info => f.write_str("info"),
+            EventSeverity::Warning => f.write_str("warning"),
+            EventSeverity::Error => f.write_str("error"),
+        }
+    }
+}
+
 pub mod error {
 
     #[derive(thiserror::Error, Debug)]
@@ -94,6 +150,7 @@ mod tests {
             name: "click_event".into(),
             data: Some(ThinEdgeEventData {
                 text: Some("Someone clicked".into()),
+                severity: None,
                 time: Some(datetime!(2021-04-23 19:00:00 +05:00)),
                 extras: HashMap::new(),
             }),
@@ -109,6 +166,7 @@ mod tests {
             name: "click_event".into(),
             data: Some(ThinEdgeEventData {
                 text: Some("Someone clicked".into()),
+                severity: None,
                 time: None,
                 extras: HashMap::new(),
             }),
@@ -124,6 +182,7 @@ mod tests {
             name: "click_event".into(),
             data: Some(ThinEdgeEventData {
                 text: None,
+                severity: None,
                 time: Some(datetime!(2021-04-23 19:00:00 +05:00)),
                 extras: HashMap::new(),
             }),
@@ -137,6 +196,7 @@ mod tests {
             name: "click_event".into(),
             data: Some(ThinEdgeEventData {
                 text: None,
+                severity: None,
                 time: None,
                 extras: HashMap::new(),
             }),
@@ -167,6 +227,7 @@ mod tests {
             name: "click_event".into(),
             data: Some(ThinEdgeEventData {
                 text: Some("Someone clicked".into()),
+                severity: None,
                 time: Some(datetime!(2021-04-23 19:00:00 +05:00)),
                 extras: HashMap::new(),
             }),
@@ -180,6 +241,7 @@ mod tests {
             name: "click_event".into(),
             data: Some(ThinEdgeEventData {
                 text: None,
+                severity: None,
                 time: None,
                 extras: HashMap::new(),
             }),
@@ -249,4 +311,76 @@ mod tests {
         );
         assert_matches!(event_data.extras.get("complex"), Some(Value::Object(_)));
     }
+
+    #[test]
+    fn event_severity_defaults_to_info() {
+        let entity = "main-device".into();
+        let event = ThinEdgeEvent::try_from(
+            "click_event",
+            &EntityType::MainDevice,
+            &entity,
+            r#"{"text": "Someone clicked"}"#,
+        )
+        .unwrap();
+
+        let event_data = event.data.unwrap();
+        assert_eq!(event_data.severity, None);
+        assert_eq!(event_data.severity(), EventSeverity::Info);
+    }
+
+    #[test]
+    fn event_severity_is_parsed() {
+        let entity = "main-device".into();
+        let event = ThinEdgeEvent::try_from(
+            "click_event",
+            &EntityType::MainDevice,
+            &entity,
+            r#"{"text": "Someone clicked", "severity": "warning"}"#,
+        )
+        .unwrap();
+
+        let event_data = event.data.unwrap();
+        assert_eq!(event_data.severity(), EventSeverity::Warning);
+        assert!(event_data.extras.is_empty());
+    }
+
+    #[test_case(r#""catastrophic""#; "unknown severity")]
+    #[test_case(r#""WARNING""#; "severity with another case")]
+    #[test_case("3"; "severity that is not a string")]
+    fn unknown_event_severity_defaults_to_info(severity: &str) {
+        let entity = "main-device".into();
+        let event = ThinEdgeEvent::try_from(
+            "click_event",
+            &EntityType::MainDevice,
+            &entity,
+            &format!(r#"{{"text": "Someone clicked", "severity": {severity}}}"#),
+        )
+        .unwrap();
+
+        let event_data = event.data.unwrap();
+        assert_eq!(event_data.severity, None);
+        assert_eq!(event_data.severity(), EventSeverity::Info);
+    }
+
+    #[test_case(
+        json!({"text": "Someone clicked", "severity": "error", "time": "2021-04-23T19:00:00+05:00"});
+        "with severity"
+    )]
+    #[test_case(
+        json!({"text": "Someone clicked", "time": "2021-04-23T19:00:00+05:00"});
+        "without severity"
+    )]
+    #[test_case(
+        json!({"text": "Someone clicked", "severity": "info", "extra": "field"});
+        "with extra fields"
+    )]
+    fn event_data_serialization_round_trip(payload: Value) {
+        let event_data: ThinEdgeEventData = serde_json::from_value(payload.clone()).unwrap();
+
+        let serialized = serde_json::to_value(&event_data).unwrap();
+        assert_eq!(serialized, payload);
+
+        let deserialized: ThinEdgeEventData = serde_json::from_value(serialized).unwrap();
+        assert_eq!(deserialized, event_data);
+    }
 }
@@ -3,16 +3,19 @@ use crate::mqtt_topics::EntityTopicId;
 use crate::mqtt_topics::MqttSchema;
 use crate::mqtt_topics::ServiceTopicId;
 use clock::Clock;
+use clock::Timestamp;
 use clock::WallClock;
 use log::error;
 use mqtt_channel::MqttMessage;
 use mqtt_channel::Topic;
 use serde::Deserialize;
+use serde::Deserializer;
 use serde::Serialize;
 use serde_json::json;
 use std::fmt::Display;
 use std::process;
 use std::sync::Arc;
+use tedge_utils::timestamp::IsoOrUnix;
 use tedge_utils::timestamp::TimeFormat;
 
 pub fn service_health_topic(
@@ -72,19 +75,10 @@ impl ServiceHealthTopic {
     }
 
     pub fn up_message(&self) -> MqttMessage {
-        let now = WallClock.now();
-        let time_format = self.time_format;
-        let timestamp = time_format.to_json(now).unwrap_or_else(|err| {
-            error!(
-                "Health message: Failed to convert timestamp to {time_format} format due to: {err}"
-            );
-            now.to_string().into()
-        });
-
         let health_status = json!({
             "status": "up",
             "pid": process::id(),
-            "time": timestamp
+            "time": timestamp(self.time_format)
         })
         .to_string();
 
@@ -94,6 +88,41 @@ impl ServiceHealthTopic {
             .with_qos(mqtt_channel::QoS::AtLeastOnce)
             .with_retain()
     }
+
+    /// Build a health message, giving the reason of the status if any
+    ///
+    /// With no reason, the payload is the same as the [Self::up_message] or [Self::down_message] one.
+    /// With a reason, the payload is extended with this reason and an RFC3339 `time`,
+    /// whatever the time format of the topic, e.g.
+    /// `{"status":"down","pid":1234,"reason":"connection refused","time":"2024-01-01T00:00:00Z"}`
+    pub fn status_message(&self, status: Status, reason: Option<String>) -> MqttMessage {
+        let health_status = match reason {
+            None if status == Status::Up => return self.up_message(),
+            None => json!({
+                "status": status,
+                "pid": process::id()
+            }),
+            Some(reason) => json!({
+                "status": status,
+                "pid": process::id(),
+                "reason": reason,
+                "time": timestamp(TimeFormat::Rfc3339)
+            }),
+        }
+        .to_string();
+
+        MqttMessage::new(&Topic::new_unchecked(self.as_str()), health_status)
+            .with_qos(mqtt_channel::QoS::AtLeastOnce)
+            .with_retain()
+    }
+}
+
+fn timestamp(time_format: TimeFormat) -> serde_json::Value {
+    let now = WallClock.now();
+    time_format.to_json(now).unwrap_or_else(|err| {
+        error!("Health message: Failed to convert timestamp to {time_format} format due to: {err}");
+        now.to_string().into()
+    })
 }
 
 /// Payload of the health status message.
@@ -106,6 +135,17 @@ impl ServiceHealthTopic {
 pub struct HealthStatus {
     /// Current status of the service, synced by the mapper to the cloud
     pub status: Status,
+
+    /// Why the service is in this status, if given
+    #[serde(default, deserialize_with = "ignore_invalid_reason")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+
+    /// When the status has been published, if given either as an RFC3339 string or a unix timestamp
+    #[serde(default, deserialize_with = "ignore_invalid_time")]
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<Timestamp>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -150,7 +190,10 @@ impl HealthStatus {
                     Ok("0") => Status::Down,
                     _ => Status::default(),
                 };
-                HealthStatus { status }
+                HealthStatus {
+                    status,
+                    ..Default::default()
+                }
             } else {
                 serde_json::from_slice(message.payload()).unwrap_or_default()
             };
@@ -165,6 +208,21 @@ impl HealthStatus {
     }
 }
 
+// An invalid optional field must not prevent the status to be read
+fn ignore_invalid_reason<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(value.as_str().map(str::to_owned))
+}
+
+fn ignore_invalid_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Timestamp>, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(IsoOrUnix::try_from(&value).ok().map(IsoOrUnix::into_inner))
+}
+
 pub fn entity_is_mosquitto_bridge_service(entity_topic_id: &EntityTopicId) -> bool {
     entity_topic_id
         .default_service_name()
@@ -255,6 +313,93 @@ mod tests {
         assert_eq!(health_status.unwrap().status, expected_status);
     }
 
+    #[test_case(
+        r#"{"status":"up","pid":1234,"time":1702029646}"#,
+        Status::Up,
+        None;
+        "minimal-form-with-unix-time"
+    )]
+    #[test_case(
+        r#"{"status":"down","pid":1234}"#,
+        Status::Down,
+        None;
+        "minimal-form-with-no-time"
+    )]
+    #[test_case(
+        r#"{"status":"down","pid":1234,"reason":"connection refused","time":"2023-12-08T10:00:46Z"}"#,
+        Status::Down,
+        Some("connection refused");
+        "extended-form"
+    )]
+    #[test_case(
+        r#"{"status":"down","reason":42,"time":"yesterday"}"#,
+        Status::Down,
+        None;
+        "extended-form-with-invalid-fields"
+    )]
+    fn parse_health_status_reason(
+        health_payload: &str,
+        expected_status: Status,
+        reason: Option<&str>,
+    ) {
+        let mqtt_schema = MqttSchema::new();
+        let topic = Topic::new_unchecked("te/device/main/service/tedge-agent/status/health");
+        let health_message = MqttMessage::new(&topic, health_payload);
+
+        let health_status =
+            HealthStatus::try_from_health_status_message(&health_message, &mqtt_schema).unwrap();
+        assert_eq!(health_status.status, expected_status);
+        assert_eq!(health_status.reason.as_deref(), reason);
+        assert!(health_status.is_valid());
+    }
+
+    #[test]
+    fn minimal_payload_is_kept_when_no_reason_is_given() {
+        let health_topic = ServiceHealthTopic {
+            topic: "te/device/main/service/test_daemon/status/health".into(),
+            time_format: TimeFormat::Unix,
+        };
+
+        let down: Value = serde_json::from_slice(
+            health_topic
+                .status_message(Status::Down, None)
+                .payload_bytes(),
+        )
+        .unwrap();
+        assert_eq!(down, json!({"status": "down", "pid": process::id()}));
+
+        let up: Value = serde_json::from_slice(
+            health_topic
+                .status_message(Status::Up, None)
+                .payload_bytes(),
+        )
+        .unwrap();
+        assert_eq!(up.get("reason"), None);
+        assert_matches!(up.get("time"), Some(Value::Number(..)));
+    }
+
+    #[test]
+    fn reason_is_given_with_an_rfc3339_timestamp() {
+        let mqtt_schema = MqttSchema::new();
+        let health_topic = ServiceHealthTopic {
+            topic: "te/device/main/service/test_daemon/status/health".into(),
+            time_format: TimeFormat::Unix,
+        };
+        let msg = health_topic.status_message(Status::Down, Some("connection refused".into()));
+        assert!(msg.retain);
+
+        let payload: Value = serde_json::from_slice(msg.payload_bytes()).unwrap();
+        assert_eq!(payload.get("status"), Some(&json!("down")));
+        assert_eq!(payload.get("reason"), Some(&json!("connection refused")));
+        assert_matches!(payload.get("time"), Some(Value::String(..)));
+
+        let health_status =
+            HealthStatus::try_from_health_status_message(&msg, &mqtt_schema).unwrap();
+        assert_eq!(health_status.status, Status::Down);
+        assert_eq!(health_status.reason.as_deref(), Some("connection refused"));
+        assert!(health_status.time.is_some());
+    }
+
     #[test]
     fn is_rfc3339_timestamp() {
         let health_topic = ServiceHealthTopic {
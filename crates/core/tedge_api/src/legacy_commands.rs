@@ -0,0 +1,379 @@
+//! Conversion of the commands between the legacy `tedge/commands/#` topics and the `te` topics
+//!
+//! Older versions of thin-edge exchange the software and restart commands of the main device
+//! on `tedge/commands/req/<cmd-type>` and `tedge/commands/res/<cmd-type>`, e.g. `tedge/commands/req/software/list`,
+//! with the command id given by the `id` property of the payload.
+//! The same commands are now exchanged on `te/device/main///cmd/<operation>/<cmd-id>`,
+//! with the command status given by the `status` property of the payload.
+//!
+//! The payloads are otherwise identical, so these helpers only move the command id
+//! between the payload and the topic, and set the status as expected on each side:
+//! - a legacy request has no status, while the equivalent command has an `init` status,
+//! - a legacy response and the equivalent command state have the same status.
+//!
+//! These helpers are the building blocks of a compatibility shim between old and new components.
+use crate::mqtt_topics::Channel;
+use crate::mqtt_topics::EntityTopicId;
+use crate::mqtt_topics::MqttSchema;
+use crate::mqtt_topics::OperationType;
+use mqtt_channel::MqttMessage;
+use mqtt_channel::QoS;
+use mqtt_channel::Topic;
+use serde_json::Map;
+use serde_json::Value;
+
+const LEGACY_REQUEST_PREFIX: &str = "tedge/commands/req/";
+const LEGACY_RESPONSE_PREFIX: &str = "tedge/commands/res/";
+const ID: &str = "id";
+const STATUS: &str = "status";
+const INIT: &str = "init";
+
+#[derive(thiserror::Error, Debug)]
+pub enum LegacyCommandError {
+    #[error("Invalid JSON payload received on {topic}: {reason}")]
+    InvalidPayload { topic: String, reason: String },
+
+    #[error("No command id in the payload received on {topic}")]
+    MissingCommandId { topic: String },
+
+    #[error("No command status in the payload received on {topic}")]
+    MissingStatus { topic: String },
+}
+
+/// Return the legacy command type of an operation, e.g. `software/list`, if supported by the legacy topics
+fn legacy_command_type(operation: &OperationType) -> Option<&'static str> {
+    match operation {
+        OperationType::Restart => Some("control/restart"),
+        OperationType::SoftwareList => Some("software/list"),
+        OperationType::SoftwareUpdate => Some("software/update"),
+        _ => None,
+    }
+}
+
+/// Return the operation of a legacy command type, e.g. `software/list`
+fn operation_of(legacy_command_type: &str) -> Option<OperationType> {
+    match legacy_command_type {
+        "control/restart" => Some(OperationType::Restart),
+        "software/list" => Some(OperationType::SoftwareList),
+        "software/update" => Some(OperationType::SoftwareUpdate),
+        _ => None,
+    }
+}
+
+/// Convert a legacy request, e.g. received on `tedge/commands/req/software/list`, into an `init` command
+///
+/// Return `None` if the message is not a legacy request.
+pub fn command_from_legacy_request(
+    schema: &MqttSchema,
+    request: &MqttMessage,
+) -> Result<Option<MqttMessage>, LegacyCommandError> {
+    let Some(operation) = request
+        .topic
+        .name
+        .strip_prefix(LEGACY_REQUEST_PREFIX)
+        .and_then(operation_of)
+    else {
+        return Ok(None);
+    };
+    let mut payload = json_object(request)?;
+    let cmd_id = take_command_id(request, &mut payload)?;
+    payload.insert(STATUS.to_string(), INIT.into());
+    Ok(Some(command_message(schema, operation, cmd_id, payload)))
+}
+
+/// Convert a legacy response, e.g. received on `tedge/commands/res/software/list`, into a command state
+///
+/// Return `None` if the message is not a legacy response.
+pub fn command_from_legacy_response(
+    schema: &MqttSchema,
+    response: &MqttMessage,
+) -> Result<Option<MqttMessage>, LegacyCommandError> {
+    let Some(operation) = response
+        .topic
+        .name
+        .strip_prefix(LEGACY_RESPONSE_PREFIX)
+        .and_then(operation_of)
+    else {
+        return Ok(None);
+    };
+    let mut payload = json_object(response)?;
+    let cmd_id = take_command_id(response, &mut payload)?;
+    if !payload.contains_key(STATUS) {
+        return Err(LegacyCommandError::MissingStatus {
+            topic: response.topic.name.clone(),
+        });
+    }
+    Ok(Some(command_message(schema, operation, cmd_id, payload)))
+}
+
+/// Convert an `init` command of the main device into a legacy request, e.g. on `tedge/commands/req/software/list`
+///
+/// Return `None` if the message is not a command supported by the legacy topics,
+/// if the command is not in its `init` state or if the command is cleared.
+pub fn legacy_request_from_command(
+    schema: &MqttSchema,
+    command: &MqttMessage,
+) -> Result<Option<MqttMessage>, LegacyCommandError> {
+    let Some((cmd_type, cmd_id)) = legacy_command_of(schema, command) else {
+        return Ok(None);
+    };
+    if command.payload_bytes().is_empty() {
+        return Ok(None);
+    }
+    let mut payload = json_object(command)?;
+    if payload.get(STATUS).and_then(Value::as_str) != Some(INIT) {
+        return Ok(None);
+    }
+    payload.remove(STATUS);
+    payload.insert(ID.to_string(), cmd_id.into());
+    let topic = Topic::new_unchecked(&format!("{LEGACY_REQUEST_PREFIX}{cmd_type}"));
+    Ok(Some(legacy_message(&topic, payload)))
+}
+
+/// Convert the state of a command of the main device into a legacy response, e.g. on `tedge/commands/res/software/list`
+///
+/// Return `None` if the message is not a command supported by the legacy topics,
+/// if the command is still in its `init` state or if the command is cleared.
+pub fn legacy_response_from_command(
+    schema: &MqttSchema,
+    command: &MqttMessage,
+) -> Result<Option<MqttMessage>, LegacyCommandError> {
+    let Some((cmd_type, cmd_id)) = legacy_command_of(schema, command) else {
+        return Ok(None);
+    };
+    if command.payload_bytes().is_empty() {
+        return Ok(None);
+    }
+    let mut payload = json_object(command)?;
+    match payload.get(STATUS).and_then(Value::as_str) {
+        None => {
+            return Err(LegacyCommandError::MissingStatus {
+                topic: command.topic.name.clone(),
+            })
+        }
+        Some(INIT) => return Ok(None),
+        Some(_) => {}
+    }
+    payload.insert(ID.to_string(), cmd_id.into());
+    let topic = Topic::new_unchecked(&format!("{LEGACY_RESPONSE_PREFIX}{cmd_type}"));
+    Ok(Some(legacy_message(&topic, payload)))
+}
+
+/// Return the legacy command type and the command id of a command of the main device
+fn legacy_command_of(schema: &MqttSchema, command: &MqttMessage) -> Option<(&'static str, String)> {
+    match schema.entity_channel_of(&command.topic) {
+        Ok((entity, Channel::Command { operation, cmd_id })) if entity.is_default_main_device() => {
+            legacy_command_type(&operation).map(|cmd_type| (cmd_type, cmd_id))
+        }
+        _ => None,
+    }
+}
+
+fn json_object(message: &MqttMessage) -> Result<Map<String, Value>, LegacyCommandError> {
+    match serde_json::from_slice(message.payload_bytes()) {
+        Ok(Value::Object(payload)) => Ok(payload),
+        Ok(_) => Err(LegacyCommandError::InvalidPayload {
+            topic: message.topic.name.clone(),
+            reason: "not a JSON object".to_string(),
+        }),
+        Err(err) => Err(LegacyCommandError::InvalidPayload {
+            topic: message.topic.name.clone(),
+            reason: err.to_string(),
+        }),
+    }
+}
+
+fn take_command_id(
+    message: &MqttMessage,
+    payload: &mut Map<String, Value>,
+) -> Result<String, LegacyCommandError> {
+    match payload.remove(ID) {
+        Some(Value::String(cmd_id)) if !cmd_id.is_empty() => Ok(cmd_id),
+        _ => Err(LegacyCommandError::MissingCommandId {
+            topic: message.topic.name.clone(),
+        }),
+    }
+}
+
+/// Build a command message, retained as all the `te` command messages
+fn command_message(
+    schema: &MqttSchema,
+    operation: OperationType,
+    cmd_id: String,
+    payload: Map<String, Value>,
+) -> MqttMessage {
+    let topic = schema.topic_for(
+        &EntityTopicId::default_main_device(),
+        &Channel::Command { operation, cmd_id },
+    );
+    MqttMessage::new(&topic, Value::Object(payload).to_string())
+        .with_retain()
+        .with_qos(QoS::AtLeastOnce)
+}
+
+/// Build a legacy message, not retained as all the `tedge/commands` messages
+fn legacy_message(topic: &Topic, payload: Map<String, Value>) -> MqttMessage {
+    MqttMessage::new(topic, Value::Object(payload).to_string()).with_qos(QoS::AtLeastOnce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use test_case::test_case;
+
+    fn message(topic: &str, payload: Value) -> MqttMessage {
+        MqttMessage::new(&Topic::new_unchecked(topic), payload.to_string())
+    }
+
+    fn json_payload(message: &MqttMessage) -> Value {
+        serde_json::from_slice(message.payload_bytes()).unwrap()
+    }
+
+    #[test_case(
+        "tedge/commands/req/software/list",
+        json!({"id": "1234"}),
+        "te/device/main///cmd/software_list/1234",
+        json!({"status": "init"})
+        ; "software list"
+    )]
+    #[test_case(
+        "tedge/commands/req/software/update",
+        json!({"id": "1234", "updateList": [{"type": "apt", "modules": [{"name": "vim", "action": "install"}]}]}),
+        "te/device/main///cmd/software_update/1234",
+        json!({"status": "init", "updateList": [{"type": "apt", "modules": [{"name": "vim", "action": "install"}]}]})
+        ; "software update"
+    )]
+    fn request_round_trip(
+        legacy_topic: &str,
+        legacy_payload: Value,
+        command_topic: &str,
+        command_payload: Value,
+    ) {
+        let schema = MqttSchema::default();
+        let legacy = message(legacy_topic, legacy_payload.clone());
+
+        let command = command_from_legacy_request(&schema, &legacy)
+            .unwrap()
+            .unwrap();
+        assert_eq!(command.topic.name, command_topic);
+        assert!(command.retain);
+        assert_eq!(json_payload(&command), command_payload);
+
+        let back = legacy_request_from_command(&schema, &command)
+            .unwrap()
+            .unwrap();
+        assert_eq!(back.topic.name, legacy_topic);
+        assert!(!back.retain);
+        assert_eq!(json_payload(&back), legacy_payload);
+    }
+
+    #[test_case(
+        "tedge/commands/res/software/list",
+        json!({"id": "1234", "status": "successful", "currentSoftwareList": [{"type": "apt", "modules": [{"name": "vim", "version": "9.0"}]}]}),
+        "te/device/main///cmd/software_list/1234",
+        json!({"status": "successful", "currentSoftwareList": [{"type": "apt", "modules": [{"name": "vim", "version": "9.0"}]}]})
+        ; "software list"
+    )]
+    #[test_case(
+        "tedge/commands/res/software/update",
+        json!({"id": "1234", "status": "failed", "reason": "Partial failure"}),
+        "te/device/main///cmd/software_update/1234",
+        json!({"status": "failed", "reason": "Partial failure"})
+        ; "software update"
+    )]
+    fn response_round_trip(
+        legacy_topic: &str,
+        legacy_payload: Value,
+        command_topic: &str,
+        command_payload: Value,
+    ) {
+        let schema = MqttSchema::default();
+        let legacy = message(legacy_topic, legacy_payload.clone());
+
+        let command = command_from_legacy_response(&schema, &legacy)
+            .unwrap()
+            .unwrap();
+        assert_eq!(command.topic.name, command_topic);
+        assert!(command.retain);
+        assert_eq!(json_payload(&command), command_payload);
+
+        let back = legacy_response_from_command(&schema, &command)
+            .unwrap()
+            .unwrap();
+        assert_eq!(back.topic.name, legacy_topic);
+        assert!(!back.retain);
+        assert_eq!(json_payload(&back), legacy_payload);
+    }
+
+    #[test]
+    fn only_the_commands_supported_by_the_legacy_topics_are_converted() {
+        let schema = MqttSchema::default();
+        for command in [
+            message(
+                "te/device/main///cmd/log_upload/1234",
+                json!({"status": "init"}),
+            ),
+            message(
+                "te/device/child01///cmd/software_list/1234",
+                json!({"status": "init"}),
+            ),
+            message("te/device/main///cmd/software_list", json!({})),
+            MqttMessage::new(
+                &Topic::new_unchecked("te/device/main///cmd/software_list/1234"),
+                "",
+            ),
+        ] {
+            assert!(legacy_request_from_command(&schema, &command)
+                .unwrap()
+                .is_none());
+            assert!(legacy_response_from_command(&schema, &command)
+                .unwrap()
+                .is_none());
+        }
+
+        let unknown = message("tedge/commands/req/config/update", json!({"id": "1234"}));
+        assert!(command_from_legacy_request(&schema, &unknown)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn the_init_state_is_a_request_not_a_response() {
+        let schema = MqttSchema::default();
+        let init = message(
+            "te/device/main///cmd/software_list/1234",
+            json!({"status": "init"}),
+        );
+        let executing = message(
+            "te/device/main///cmd/software_list/1234",
+            json!({"status": "executing"}),
+        );
+
+        assert!(legacy_response_from_command(&schema, &init)
+            .unwrap()
+            .is_none());
+        assert!(legacy_request_from_command(&schema, &executing)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn a_legacy_message_without_command_id_is_rejected() {
+        let schema = MqttSchema::default();
+        let request = message("tedge/commands/req/software/list", json!({}));
+        assert!(matches!(
+            command_from_legacy_request(&schema, &request),
+            Err(LegacyCommandError::MissingCommandId { .. })
+        ));
+
+        let response = message(
+            "tedge/commands/res/software/list",
+            json!({"status": "successful"}),
+        );
+        assert!(matches!(
+            command_from_legacy_response(&schema, &response),
+            Err(LegacyCommandError::MissingCommandId { .. })
+        ));
+    }
+}
@@ -6,13 +6,17 @@ pub mod entity_store;
 pub mod error;
 pub mod event;
 pub mod health;
+pub mod legacy_commands;
 pub mod measurement;
+pub mod message_kind;
+pub mod metadata;
 pub mod mqtt_topics;
 pub mod path;
 pub mod script;
 mod software;
 pub mod store;
 pub mod substitution;
+pub mod supported_operations;
 pub mod workflow;
 
 pub use commands::CommandStatus;
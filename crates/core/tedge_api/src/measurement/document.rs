@@ -0,0 +1,352 @@
+use crate::measurement::MeasurementVisitor;
+use crate::measurement::ThinEdgeJsonSerializationError;
+use crate::measurement::ThinEdgeJsonSerializer;
+use std::collections::HashSet;
+use time::OffsetDateTime;
+
+/// Top-level keys having a specific meaning in a thin-edge JSON measurement,
+/// hence that cannot be used as series names.
+pub(crate) const RESERVED_KEYS: [&str; 2] = ["time", "type"];
+
+/// Builds a thin-edge JSON measurement document made of flat and grouped series.
+///
+/// ```
+/// # use tedge_api::measurement::MeasurementBuilder;
+/// let json = MeasurementBuilder::new()
+///     .with_series("temperature", 23.5)
+///     .with_grouped_series("three_phase_current", [("L1", 1.0), ("L2", 2.0)])
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(
+///     json,
+///     r#"{"temperature":23.5,"three_phase_current":{"L1":1.0,"L2":2.0}}"#
+/// );
+/// ```
+///
+/// Key names and values are only checked by [`MeasurementBuilder::build`],
+/// which fails if the document would not be accepted by the thin-edge JSON parser.
+#[derive(Debug, Clone, Default)]
+pub struct MeasurementBuilder {
+    timestamp: Option<OffsetDateTime>,
+    series: Vec<(String, Series)>,
+}
+
+#[derive(Debug, Clone)]
+enum Series {
+    Single(f64),
+    Group(Vec<(String, f64)>),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MeasurementBuilderError {
+    #[error("Empty measurement: it must contain at least one series")]
+    EmptyMeasurement,
+
+    #[error("Empty group: {0:?} must contain at least one series")]
+    EmptyGroup(String),
+
+    #[error("Invalid series name: a series name cannot be empty")]
+    EmptyName,
+
+    #[error("Invalid series name: {0:?} is a reserved key")]
+    ReservedName(String),
+
+    #[error("Invalid series name: {0:?} is used more than once")]
+    DuplicateName(String),
+
+    #[error("Invalid value for {name:?}: {value} cannot be represented in thin-edge JSON")]
+    InvalidValue { name: String, value: f64 },
+
+    #[error(transparent)]
+    SerializationError(#[from] ThinEdgeJsonSerializationError),
+}
+
+impl MeasurementBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the timestamp shared by all the series of this measurement.
+    pub fn with_timestamp(mut self, timestamp: OffsetDateTime) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Add a single-value series, e.g. `"temperature": 23.5`.
+    pub fn with_series(mut self, name: impl Into<String>, value: f64) -> Self {
+        self.series.push((name.into(), Series::Single(value)));
+        self
+    }
+
+    /// Add a group of series, e.g. `"three_phase_current": {"L1": 1, "L2": 2}`.
+    pub fn with_grouped_series<K>(
+        mut self,
+        group: impl Into<String>,
+        values: impl IntoIterator<Item = (K, f64)>,
+    ) -> Self
+    where
+        K: Into<String>,
+    {
+        let values = values
+            .into_iter()
+            .map(|(name, value)| (name.into(), value))
+            .collect();
+        self.series.push((group.into(), Series::Group(values)));
+        self
+    }
+
+    /// Check the series and produce the thin-edge JSON document.
+    pub fn build(self) -> Result<String, MeasurementBuilderError> {
+        self.validate()?;
+
+        let mut serializer = ThinEdgeJsonSerializer::new();
+        self.accept(&mut serializer)?;
+        Ok(serializer.into_string()?)
+    }
+
+    /// Visit the series of this measurement, without any prior validation.
+    fn accept<V>(&self, visitor: &mut V) -> Result<(), V::Error>
+    where
+        V: MeasurementVisitor,
+    {
+        if let Some(timestamp) = self.timestamp {
+            visitor.visit_timestamp(timestamp)?;
+        }
+
+        for (name, series) in self.series.iter() {
+            match series {
+                Series::Single(value) => visitor.visit_measurement(name, *value)?,
+                Series::Group(values) => {
+                    visitor.visit_start_group(name)?;
+                    for (name, value) in values.iter() {
+                        visitor.visit_measurement(name, *value)?;
+                    }
+                    visitor.visit_end_group()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), MeasurementBuilderError> {
+        if self.series.is_empty() {
+            return Err(MeasurementBuilderError::EmptyMeasurement);
+        }
+
+        let mut names = HashSet::new();
+        for (name, series) in self.series.iter() {
+            validate_name(name, &mut names)?;
+            if RESERVED_KEYS.contains(&name.as_str()) {
+                return Err(MeasurementBuilderError::ReservedName(name.clone()));
+            }
+
+            match series {
+                Series::Single(value) => validate_value(name, *value)?,
+                Series::Group(values) => {
+                    if values.is_empty() {
+                        return Err(MeasurementBuilderError::EmptyGroup(name.clone()));
+                    }
+
+                    let mut group_names = HashSet::new();
+                    for (name, value) in values.iter() {
+                        validate_name(name, &mut group_names)?;
+                        validate_value(name, *value)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_name<'a>(
+    name: &'a str,
+    known_names: &mut HashSet<&'a str>,
+) -> Result<(), MeasurementBuilderError> {
+    if name.is_empty() {
+        return Err(MeasurementBuilderError::EmptyName);
+    }
+    if !known_names.insert(name) {
+        return Err(MeasurementBuilderError::DuplicateName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Only accept the values accepted by the thin-edge JSON parser
+fn validate_value(name: &str, value: f64) -> Result<(), MeasurementBuilderError> {
+    if value != 0.0 && !value.is_normal() {
+        return Err(MeasurementBuilderError::InvalidValue {
+            name: name.to_string(),
+            value,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measurement::parse_str;
+    use crate::measurement::MeasurementGrouper;
+    use time::macros::datetime;
+
+    #[test]
+    fn build_flat_measurement() {
+        let json = MeasurementBuilder::new()
+            .with_timestamp(datetime!(2021-04-30 17:03:14.123 +02:00))
+            .with_series("temperature", 23.0)
+            .with_series("pressure", 123.4)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"time":"2021-04-30T17:03:14.123+02:00","temperature":23.0,"pressure":123.4}"#
+        );
+
+        let group = parse(&json);
+        assert_eq!(
+            group.timestamp(),
+            Some(datetime!(2021-04-30 17:03:14.123 +02:00))
+        );
+        assert_eq!(group.get_measurement_value(None, "temperature"), Some(23.0));
+        assert_eq!(group.get_measurement_value(None, "pressure"), Some(123.4));
+    }
+
+    #[test]
+    fn build_grouped_measurement() {
+        let json = MeasurementBuilder::new()
+            .with_series("temperature", 23.0)
+            .with_grouped_series(
+                "three_phase_current",
+                [("L1", 1.0), ("L2", 2.0), ("L3", -3.5)],
+            )
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"temperature":23.0,"three_phase_current":{"L1":1.0,"L2":2.0,"L3":-3.5}}"#
+        );
+
+        let group = parse(&json);
+        assert_eq!(group.timestamp(), None);
+        assert_eq!(group.get_measurement_value(None, "temperature"), Some(23.0));
+        assert_eq!(
+            group.get_measurement_value(Some("three_phase_current"), "L1"),
+            Some(1.0)
+        );
+        assert_eq!(
+            group.get_measurement_value(Some("three_phase_current"), "L2"),
+            Some(2.0)
+        );
+        assert_eq!(
+            group.get_measurement_value(Some("three_phase_current"), "L3"),
+            Some(-3.5)
+        );
+    }
+
+    #[test]
+    fn the_same_name_can_be_used_in_distinct_groups() {
+        let json = MeasurementBuilder::new()
+            .with_grouped_series("current", [("L1", 1.0)])
+            .with_grouped_series("voltage", [("L1", 230.0)])
+            .build()
+            .unwrap();
+
+        let group = parse(&json);
+        assert_eq!(
+            group.get_measurement_value(Some("current"), "L1"),
+            Some(1.0)
+        );
+        assert_eq!(
+            group.get_measurement_value(Some("voltage"), "L1"),
+            Some(230.0)
+        );
+    }
+
+    #[test]
+    fn reject_empty_measurement() {
+        let error = MeasurementBuilder::new().build().unwrap_err();
+        assert!(matches!(error, MeasurementBuilderError::EmptyMeasurement));
+
+        let error = MeasurementBuilder::new()
+            .with_grouped_series::<&str>("current", [])
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, MeasurementBuilderError::EmptyGroup(group) if group == "current"));
+    }
+
+    #[test]
+    fn reject_invalid_names() {
+        let error = MeasurementBuilder::new()
+            .with_series("", 1.0)
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, MeasurementBuilderError::EmptyName));
+
+        let error = MeasurementBuilder::new()
+            .with_grouped_series("current", [("", 1.0)])
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, MeasurementBuilderError::EmptyName));
+
+        let error = MeasurementBuilder::new()
+            .with_series("time", 1.0)
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, MeasurementBuilderError::ReservedName(name) if name == "time"));
+
+        let error = MeasurementBuilder::new()
+            .with_series("temperature", 1.0)
+            .with_grouped_series("temperature", [("inside", 1.0)])
+            .build()
+            .unwrap_err();
+        assert!(
+            matches!(error, MeasurementBuilderError::DuplicateName(name) if name == "temperature")
+        );
+
+        let error = MeasurementBuilder::new()
+            .with_grouped_series("current", [("L1", 1.0), ("L1", 2.0)])
+            .build()
+            .unwrap_err();
+        assert!(matches!(error, MeasurementBuilderError::DuplicateName(name) if name == "L1"));
+    }
+
+    #[test]
+    fn reject_invalid_values() {
+        for value in [
+            f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::MIN_POSITIVE / 2.0,
+        ] {
+            let error = MeasurementBuilder::new()
+                .with_series("temperature", value)
+                .build()
+                .unwrap_err();
+            assert!(
+                matches!(error, MeasurementBuilderError::InvalidValue { ref name, .. } if name == "temperature"),
+                "{value} should be rejected"
+            );
+
+            let error = MeasurementBuilder::new()
+                .with_grouped_series("current", [("L1", value)])
+                .build()
+                .unwrap_err();
+            assert!(
+                matches!(error, MeasurementBuilderError::InvalidValue { ref name, .. } if name == "L1"),
+                "{value} should be rejected"
+            );
+        }
+    }
+
+    fn parse(json: &str) -> crate::measurement::MeasurementGroup {
+        let mut grouper = MeasurementGrouper::new();
+        parse_str(json, &mut grouper).expect("valid thin-edge JSON");
+        grouper.end().expect("complete measurement")
+    }
+}
@@ -4,11 +4,13 @@ use time::OffsetDateTime;
 pub mod builder;
 #[cfg(test)]
 mod data;
+mod document;
 mod group;
 mod parser;
 mod serialize;
 pub(crate) mod utils;
 
+pub use document::*;
 pub use group::*;
 pub use parser::*;
 pub use serialize::*;
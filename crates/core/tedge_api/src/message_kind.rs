@@ -0,0 +1,206 @@
+//! Classification of the thin-edge messages published on `te/...` topics.
+//!
+//! The kind of a message is derived from its topic,
+//! and the payload is then parsed according to this kind.
+
+use crate::alarm::ThinEdgeAlarm;
+use crate::event::ThinEdgeEventData;
+use crate::health::HealthStatus;
+use crate::measurement::parse_str;
+use crate::measurement::MeasurementGroup;
+use crate::measurement::MeasurementGrouper;
+use crate::mqtt_topics::Channel;
+use crate::mqtt_topics::EntityTopicId;
+use crate::mqtt_topics::MqttSchema;
+use crate::mqtt_topics::OperationType;
+use crate::workflow::GenericCommandState;
+use mqtt_channel::MqttMessage;
+
+/// A thin-edge message, along its source entity and parsed payload
+#[derive(Debug)]
+pub enum TeMessageKind {
+    Measurement {
+        source: EntityTopicId,
+        measurement_type: String,
+        measurement: MeasurementGroup,
+    },
+    Event {
+        source: EntityTopicId,
+        event_type: String,
+        /// None for an event with an empty payload
+        event: Option<ThinEdgeEventData>,
+    },
+    Alarm {
+        source: EntityTopicId,
+        alarm: ThinEdgeAlarm,
+    },
+    Command {
+        source: EntityTopicId,
+        operation: OperationType,
+        cmd_id: String,
+        state: GenericCommandState,
+    },
+    Health {
+        source: EntityTopicId,
+        health: HealthStatus,
+    },
+}
+
+/// Classify a message as a measurement, an event, an alarm, a command or a health status message
+///
+/// Return `None` if the message is not published on one of these channels
+/// or if the payload cannot be parsed accordingly.
+pub fn classify(message: &MqttMessage, mqtt_schema: &MqttSchema) -> Option<TeMessageKind> {
+    let (source, channel) = mqtt_schema.entity_channel_of(&message.topic).ok()?;
+    match channel {
+        Channel::Measurement { measurement_type } => {
+            let mut grouper = MeasurementGrouper::new();
+            parse_str(message.payload_str().ok()?, &mut grouper).ok()?;
+            let measurement = grouper.end().ok()?;
+            Some(TeMessageKind::Measurement {
+                source,
+                measurement_type,
+                measurement,
+            })
+        }
+
+        Channel::Event { event_type } => {
+            let payload = message.payload_str().ok()?;
+            let event = if payload.is_empty() {
+                None
+            } else {
+                Some(serde_json::from_str(payload).ok()?)
+            };
+            Some(TeMessageKind::Event {
+                source,
+                event_type,
+                event,
+            })
+        }
+
+        Channel::Alarm { alarm_type } => {
+            let alarm =
+                ThinEdgeAlarm::try_from(&alarm_type, &source, message.payload_str().ok()?).ok()?;
+            Some(TeMessageKind::Alarm { source, alarm })
+        }
+
+        Channel::Command { operation, cmd_id } => {
+            let state = GenericCommandState::from_command_message(message).ok()?;
+            Some(TeMessageKind::Command {
+                source,
+                operation,
+                cmd_id,
+                state,
+            })
+        }
+
+        Channel::Health => {
+            let health = HealthStatus::try_from_health_status_message(message, mqtt_schema).ok()?;
+            Some(TeMessageKind::Health { source, health })
+        }
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::Status;
+    use mqtt_channel::Topic;
+
+    fn classify_message(topic: &str, payload: &str) -> Option<TeMessageKind> {
+        let message = MqttMessage::new(&Topic::new_unchecked(topic), payload);
+        classify(&message, &MqttSchema::default())
+    }
+
+    #[test]
+    fn classify_measurement() {
+        let kind = classify_message(
+            "te/device/child1///m/environment",
+            r#"{"temperature": 23.5}"#,
+        );
+        let Some(TeMessageKind::Measurement {
+            source,
+            measurement_type,
+            measurement,
+        }) = kind
+        else {
+            panic!("expecting a measurement, got {kind:?}");
+        };
+        assert_eq!(source.as_str(), "device/child1//");
+        assert_eq!(measurement_type, "environment");
+        assert_eq!(
+            measurement.get_measurement_value(None, "temperature"),
+            Some(23.5)
+        );
+    }
+
+    #[test]
+    fn classify_event() {
+        let kind = classify_message("te/device/main///e/login", r#"{"text": "user logged in"}"#);
+        let Some(TeMessageKind::Event {
+            source,
+            event_type,
+            event: Some(event),
+        }) = kind
+        else {
+            panic!("expecting an event, got {kind:?}");
+        };
+        assert_eq!(source.as_str(), "device/main//");
+        assert_eq!(event_type, "login");
+        assert_eq!(event.text.as_deref(), Some("user logged in"));
+    }
+
+    #[test]
+    fn classify_alarm() {
+        let kind = classify_message(
+            "te/device/main///a/temperature_high",
+            r#"{"severity": "major", "text": "too hot"}"#,
+        );
+        let Some(TeMessageKind::Alarm { source, alarm }) = kind else {
+            panic!("expecting an alarm, got {kind:?}");
+        };
+        assert_eq!(source.as_str(), "device/main//");
+        assert_eq!(alarm.alarm_type, "temperature_high");
+        assert_eq!(alarm.data.unwrap().severity.as_deref(), Some("major"));
+    }
+
+    #[test]
+    fn classify_command() {
+        let kind = classify_message("te/device/main///cmd/restart/123", r#"{"status": "init"}"#);
+        let Some(TeMessageKind::Command {
+            source,
+            operation,
+            cmd_id,
+            state,
+        }) = kind
+        else {
+            panic!("expecting a command, got {kind:?}");
+        };
+        assert_eq!(source.as_str(), "device/main//");
+        assert_eq!(operation, OperationType::Restart);
+        assert_eq!(cmd_id, "123");
+        assert_eq!(state.status, "init");
+    }
+
+    #[test]
+    fn classify_health_status() {
+        let kind = classify_message(
+            "te/device/main/service/tedge-agent/status/health",
+            r#"{"status": "up"}"#,
+        );
+        let Some(TeMessageKind::Health { source, health }) = kind else {
+            panic!("expecting a health status, got {kind:?}");
+        };
+        assert_eq!(source.as_str(), "device/main/service/tedge-agent");
+        assert_eq!(health.status, Status::Up);
+    }
+
+    #[test]
+    fn messages_of_other_kinds_are_not_classified() {
+        assert!(classify_message("te/device/main//", r#"{"@type": "device"}"#).is_none());
+        assert!(classify_message("te/device/main///m/", "not json").is_none());
+        assert!(classify_message("c8y/s/us", "100").is_none());
+    }
+}
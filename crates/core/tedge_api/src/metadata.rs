@@ -0,0 +1,269 @@
+//! Metadata fragments describing the measurements and events of a given type
+//!
+//! The metadata of a measurement or event type, such as units and display hints,
+//! travel alongside the values as a retained message published on the `meta` channel of that type,
+//! e.g. on `te/device/main///m/environment/meta` for the measurements published on `te/device/main///m/environment`
+//! (see [Channel::MeasurementMetadata] and [Channel::EventMetadata]).
+//!
+//! The payload maps each series, or event property, to its metadata fragment.
+//! A series of a group is named after the group and the series, separated by a dot:
+//!
+//! ```json
+//! {
+//!   "temperature": {"unit": "°C", "precision": 1},
+//!   "location.altitude": {"unit": "m", "displayName": "Altitude"}
+//! }
+//! ```
+//!
+//! The measurement and event payloads themselves are left unchanged, with or without metadata.
+//!
+//! [Channel::MeasurementMetadata]: crate::mqtt_topics::Channel::MeasurementMetadata
+//! [Channel::EventMetadata]: crate::mqtt_topics::Channel::EventMetadata
+use crate::measurement::RESERVED_KEYS;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The maximum number of decimals of a series, f64 values having at most 15 significant decimal digits
+pub const MAX_PRECISION: u8 = 15;
+
+/// The metadata of the series of a measurement type, or of the properties of an event type
+///
+/// ```
+/// # use tedge_api::metadata::MetadataFragment;
+/// # use tedge_api::metadata::ThinEdgeMetadata;
+/// let metadata = ThinEdgeMetadata::new()
+///     .with_fragment("temperature", MetadataFragment::with_unit("°C"))
+///     .with_fragment("pressure", MetadataFragment::with_unit("bar"));
+///
+/// let json = metadata.to_json();
+/// assert_eq!(
+///     json,
+///     r#"{"pressure":{"unit":"bar"},"temperature":{"unit":"°C"}}"#
+/// );
+/// assert_eq!(ThinEdgeMetadata::from_json(&json).unwrap(), Some(metadata));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ThinEdgeMetadata {
+    pub fragments: BTreeMap<String, MetadataFragment>,
+}
+
+/// The metadata of a single series or event property
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataFragment {
+    /// The unit of the values, e.g. `°C`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+
+    /// The number of decimals to be displayed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precision: Option<u8>,
+
+    /// The name to be displayed in place of the series name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+
+    /// Any other display hints, forwarded as is
+    #[serde(flatten)]
+    pub extras: BTreeMap<String, Value>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MetadataError {
+    #[error("Invalid metadata: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+
+    #[error("Invalid metadata: a series name cannot be empty")]
+    EmptySeriesName,
+
+    #[error("Invalid metadata: {0:?} is a reserved name, not a series")]
+    ReservedName(String),
+
+    #[error("Invalid metadata for {series:?}: the unit cannot be empty")]
+    EmptyUnit { series: String },
+
+    #[error("Invalid metadata for {series:?}: the precision {precision} exceeds {MAX_PRECISION} decimals")]
+    InvalidPrecision { series: String, precision: u8 },
+}
+
+impl ThinEdgeMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the metadata fragment of a series, replacing any previous fragment for that series
+    pub fn with_fragment(mut self, series: impl Into<String>, fragment: MetadataFragment) -> Self {
+        self.fragments.insert(series.into(), fragment);
+        self
+    }
+
+    /// The metadata fragment of a series, if any
+    pub fn get(&self, series: &str) -> Option<&MetadataFragment> {
+        self.fragments.get(series)
+    }
+
+    /// The metadata fragment of a series of a group, if any
+    pub fn get_grouped(&self, group: &str, series: &str) -> Option<&MetadataFragment> {
+        self.fragments.get(&format!("{group}.{series}"))
+    }
+
+    /// Parse and validate the payload of a metadata message
+    ///
+    /// Return `None` for an empty payload, which clears the metadata previously published.
+    pub fn from_json(payload: &str) -> Result<Option<Self>, MetadataError> {
+        if payload.is_empty() {
+            return Ok(None);
+        }
+        let metadata: ThinEdgeMetadata = serde_json::from_str(payload)?;
+        metadata.validate()?;
+        Ok(Some(metadata))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Metadata can always be serialized as JSON")
+    }
+
+    fn validate(&self) -> Result<(), MetadataError> {
+        for (series, fragment) in &self.fragments {
+            if series.is_empty() {
+                return Err(MetadataError::EmptySeriesName);
+            }
+            if RESERVED_KEYS.contains(&series.as_str()) {
+                return Err(MetadataError::ReservedName(series.clone()));
+            }
+            if fragment.unit.as_deref() == Some("") {
+                return Err(MetadataError::EmptyUnit {
+                    series: series.clone(),
+                });
+            }
+            if let Some(precision) = fragment.precision.filter(|p| *p > MAX_PRECISION) {
+                return Err(MetadataError::InvalidPrecision {
+                    series: series.clone(),
+                    precision,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MetadataFragment {
+    pub fn with_unit(unit: impl Into<String>) -> Self {
+        MetadataFragment {
+            unit: Some(unit.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_precision(self, precision: u8) -> Self {
+        MetadataFragment {
+            precision: Some(precision),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measurement::builder::ThinEdgeJsonBuilder;
+    use crate::measurement::parse_str;
+    use assert_matches::assert_matches;
+    use serde_json::json;
+    use test_case::test_case;
+
+    #[test]
+    fn measurement_with_unit_metadata_round_trip() {
+        let measurement = r#"{"temperature":23.5,"location":{"altitude":1023.0}}"#;
+        let metadata = ThinEdgeMetadata::new()
+            .with_fragment(
+                "temperature",
+                MetadataFragment::with_unit("°C").with_precision(1),
+            )
+            .with_fragment("location.altitude", MetadataFragment::with_unit("m"));
+
+        let json = metadata.to_json();
+        assert_eq!(
+            serde_json::from_str::<Value>(&json).unwrap(),
+            json!({
+                "location.altitude": {"unit": "m"},
+                "temperature": {"unit": "°C", "precision": 1},
+            })
+        );
+        let parsed = ThinEdgeMetadata::from_json(&json).unwrap().unwrap();
+        assert_eq!(parsed, metadata);
+        assert_eq!(
+            parsed.get("temperature").unwrap().unit.as_deref(),
+            Some("°C")
+        );
+        assert_eq!(
+            parsed
+                .get_grouped("location", "altitude")
+                .unwrap()
+                .unit
+                .as_deref(),
+            Some("m")
+        );
+
+        // The measurement itself is parsed unchanged
+        let mut builder = ThinEdgeJsonBuilder::default();
+        parse_str(measurement, &mut builder).unwrap();
+        assert_eq!(builder.done().unwrap().values.len(), 2);
+    }
+
+    #[test]
+    fn display_hints_are_kept_as_is() {
+        let payload = json!({
+            "text": {"displayName": "Message", "color": "red", "hidden": false}
+        })
+        .to_string();
+
+        let metadata = ThinEdgeMetadata::from_json(&payload).unwrap().unwrap();
+        let fragment = metadata.get("text").unwrap();
+        assert_eq!(fragment.display_name.as_deref(), Some("Message"));
+        assert_eq!(fragment.extras.get("color"), Some(&json!("red")));
+        assert_eq!(
+            serde_json::from_str::<Value>(&metadata.to_json()).unwrap(),
+            serde_json::from_str::<Value>(&payload).unwrap()
+        );
+    }
+
+    #[test]
+    fn an_empty_payload_clears_the_metadata() {
+        assert_eq!(ThinEdgeMetadata::from_json("").unwrap(), None);
+    }
+
+    #[test_case(r#"{"temperature":23.5}"#; "a measurement")]
+    #[test_case(r#"[{"unit":"°C"}]"#; "not an object")]
+    #[test_case(r#"{"temperature":{"unit":23}}"#; "a unit that is not a string")]
+    #[test_case(r#"{"temperature":{"precision":-1}}"#; "a negative precision")]
+    fn invalid_json_is_rejected(payload: &str) {
+        assert_matches!(
+            ThinEdgeMetadata::from_json(payload),
+            Err(MetadataError::InvalidJson(_))
+        );
+    }
+
+    #[test]
+    fn invalid_fragments_are_rejected() {
+        assert_matches!(
+            ThinEdgeMetadata::from_json(r#"{"":{"unit":"°C"}}"#),
+            Err(MetadataError::EmptySeriesName)
+        );
+        assert_matches!(
+            ThinEdgeMetadata::from_json(r#"{"time":{"unit":"s"}}"#),
+            Err(MetadataError::ReservedName(name)) if name == "time"
+        );
+        assert_matches!(
+            ThinEdgeMetadata::from_json(r#"{"temperature":{"unit":""}}"#),
+            Err(MetadataError::EmptyUnit { series }) if series == "temperature"
+        );
+        assert_matches!(
+            ThinEdgeMetadata::from_json(r#"{"temperature":{"precision":16}}"#),
+            Err(MetadataError::InvalidPrecision { precision: 16, .. })
+        );
+    }
+}
@@ -190,6 +190,30 @@ impl MqttSchema {
         self.topic_for(target, &Channel::CommandMetadata { operation })
     }
 
+    /// Return the entity and operation of a capability topic, as built by [MqttSchema::capability_topic_for]
+    ///
+    /// Return `None` for any other topic, including the command instance topics.
+    ///
+    /// ```
+    /// # use tedge_api::mqtt_topics::{MqttSchema, OperationType};
+    /// # use mqtt_channel::Topic;
+    /// let te = MqttSchema::default();
+    ///
+    /// let topic = Topic::new_unchecked("te/device/main///cmd/restart");
+    /// let (entity, operation) = te.capability_of(&topic).unwrap();
+    /// assert_eq!(entity, "device/main//");
+    /// assert_eq!(operation, OperationType::Restart);
+    ///
+    /// let topic = Topic::new_unchecked("te/device/main///cmd/restart/c8y-mapper-123");
+    /// assert!(te.capability_of(&topic).is_none());
+    /// ```
+    pub fn capability_of(&self, topic: &Topic) -> Option<(EntityTopicId, OperationType)> {
+        match self.entity_channel_of(topic) {
+            Ok((entity, Channel::CommandMetadata { operation })) => Some((entity, operation)),
+            _ => None,
+        }
+    }
+
     /// Build a new error topic using the given schema for the root prefix.
     /// ```
     /// use mqtt_channel::Topic;
@@ -759,6 +783,32 @@ impl OperationType {
     pub fn name(&self) -> String {
         format!("{self}")
     }
+
+    /// Return the operation type of an operation directory, given the directory name
+    ///
+    /// The built-in operations are named after their command topic, e.g. `software_update`,
+    /// any other name being the name of a custom operation.
+    pub fn from_dir_name(dir_name: &str) -> OperationType {
+        dir_name.into()
+    }
+
+    /// Return the name of the directory for this operation
+    ///
+    /// This is the inverse of [OperationType::from_dir_name].
+    pub fn as_dir_name(&self) -> &str {
+        match self {
+            OperationType::Restart => "restart",
+            OperationType::SoftwareList => "software_list",
+            OperationType::SoftwareUpdate => "software_update",
+            OperationType::LogUpload => "log_upload",
+            OperationType::ConfigSnapshot => "config_snapshot",
+            OperationType::ConfigUpdate => "config_update",
+            OperationType::FirmwareUpdate => "firmware_update",
+            OperationType::Health => "health",
+            OperationType::DeviceProfile => "device_profile",
+            OperationType::Custom(operation) => operation,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error, PartialEq, Eq, Clone)]
@@ -867,6 +917,40 @@ mod tests {
 
     const MQTT_ROOT: &str = "test_te";
 
+    #[test]
+    fn capability_of_the_main_and_child_devices() {
+        let schema = MqttSchema::default();
+        let main = EntityTopicId::default_main_device();
+        let child = EntityTopicId::default_child_device("child01").unwrap();
+
+        for (entity, operation) in [
+            (main.clone(), OperationType::SoftwareUpdate),
+            (child.clone(), OperationType::Restart),
+            (child, OperationType::Custom("c8y_Command".to_string())),
+        ] {
+            let topic = schema.capability_topic_for(&entity, operation.clone());
+            assert_eq!(schema.capability_of(&topic), Some((entity, operation)));
+        }
+    }
+
+    #[test]
+    fn capability_of_non_capability_topics() {
+        let schema = MqttSchema::default();
+        for topic in [
+            "te/device/main///cmd/software_update/c8y-mapper-123",
+            "te/device/child01///cmd/restart/456",
+            "te/device/main///m/temperature",
+            "te/device/main//",
+            "not-te/device/main///cmd/restart",
+        ] {
+            assert_eq!(
+                schema.capability_of(&Topic::new_unchecked(topic)),
+                None,
+                "{topic}"
+            );
+        }
+    }
+
     #[test]
     fn parses_full_correct_topic() {
         let schema = MqttSchema::with_root(MQTT_ROOT.to_string());
@@ -1071,6 +1155,22 @@ mod tests {
         );
     }
 
+    #[test_case("restart", OperationType::Restart)]
+    #[test_case("software_list", OperationType::SoftwareList)]
+    #[test_case("software_update", OperationType::SoftwareUpdate)]
+    #[test_case("log_upload", OperationType::LogUpload)]
+    #[test_case("config_snapshot", OperationType::ConfigSnapshot)]
+    #[test_case("config_update", OperationType::ConfigUpdate)]
+    #[test_case("firmware_update", OperationType::FirmwareUpdate)]
+    #[test_case("device_profile", OperationType::DeviceProfile)]
+    #[test_case("my_operation", OperationType::Custom("my_operation".to_string()))]
+    fn operation_type_from_dir_name(dir_name: &str, expected: OperationType) {
+        let operation = OperationType::from_dir_name(dir_name);
+        assert_eq!(operation, expected);
+        assert_eq!(operation.as_dir_name(), dir_name);
+        assert_eq!(operation.as_dir_name(), operation.to_string());
+    }
+
     #[test_case("abc-1234", Some("1234"))]
     #[test_case("abc-", Some(""))]
     #[test_case("abc", None)]
@@ -49,6 +49,25 @@ impl SoftwareModule {
         module
     }
 
+    /// A human-readable representation of this module, as used in user-facing messages
+    ///
+    /// The name is followed by the version and the type, when known, e.g. `collectd 5.7 (debian)`.
+    pub fn display_name(&self) -> String {
+        let mut display_name = self.name.clone();
+        if let Some(version) = self.version.as_ref().filter(|v| !v.is_empty()) {
+            display_name.push(' ');
+            display_name.push_str(version);
+        }
+        if let Some(module_type) = self
+            .module_type
+            .as_ref()
+            .filter(|t| !SoftwareModule::is_default_type(t))
+        {
+            display_name.push_str(&format!(" ({module_type})"));
+        }
+        display_name
+    }
+
     pub fn normalize(&mut self) {
         match &self.module_type {
             Some(module_type) if SoftwareModule::is_default_type(module_type) => {
@@ -116,3 +135,47 @@ impl SoftwareModuleUpdate {
         module.normalize();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(module_type: Option<&str>, name: &str, version: Option<&str>) -> SoftwareModule {
+        SoftwareModule {
+            module_type: module_type.map(str::to_string),
+            name: name.to_string(),
+            version: version.map(str::to_string),
+            url: None,
+            file_path: None,
+        }
+    }
+
+    #[test]
+    fn display_name_includes_the_version_and_type_when_known() {
+        assert_eq!(
+            module(Some("debian"), "collectd", Some("5.7")).display_name(),
+            "collectd 5.7 (debian)"
+        );
+        assert_eq!(
+            module(Some("debian"), "collectd", None).display_name(),
+            "collectd (debian)"
+        );
+        assert_eq!(
+            module(None, "collectd", Some("5.7")).display_name(),
+            "collectd 5.7"
+        );
+        assert_eq!(module(None, "collectd", None).display_name(), "collectd");
+    }
+
+    #[test]
+    fn display_name_ignores_empty_versions_and_default_types() {
+        assert_eq!(
+            module(Some("default"), "collectd", Some("")).display_name(),
+            "collectd"
+        );
+        assert_eq!(
+            module(Some(""), "collectd", Some("5.7")).display_name(),
+            "collectd 5.7"
+        );
+    }
+}
@@ -1,6 +1,16 @@
 //! The message log is a persistent append-only log of MQTT messages.
 //! Each line is the JSON representation of that MQTT message.
 //! The underlying file is a JSON lines file.
+//!
+//! The log can be compressed as a gzip stream, which is flushed after each appended line,
+//! so the log is readable up to the last line appended, even if the stream is not finished.
+//! When a compressed log is re-opened for appends, the lines readable so far are rewritten
+//! in a new gzip stream, discarding any incomplete line left by a crash.
+//!
+//! Compressed and plain logs are detected on read, so both can be loaded whatever the settings.
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use mqtt_channel::MqttMessage;
 use serde_json::json;
 use std::fs::File;
@@ -8,11 +18,13 @@ use std::fs::OpenOptions;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::BufWriter;
+use std::io::ErrorKind;
 use std::io::Write;
 use std::path::Path;
 
 const LOG_FILE_NAME: &str = "entity_store.jsonl";
 const LOG_FORMAT_VERSION: &str = "1.0";
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
 
 #[derive(thiserror::Error, Debug)]
 pub enum LogEntryError {
@@ -25,7 +37,8 @@ pub enum LogEntryError {
 
 /// A reader to read the log file entries line by line
 pub(crate) struct MessageLogReader {
-    reader: BufReader<File>,
+    reader: Box<dyn BufRead>,
+    compressed: bool,
 }
 
 impl MessageLogReader {
@@ -33,65 +46,127 @@ impl MessageLogReader {
     where
         P: AsRef<Path>,
     {
-        let file = OpenOptions::new()
-            .read(true)
-            .open(log_dir.as_ref().join(LOG_FILE_NAME))?;
-        let mut reader = BufReader::new(file);
+        let mut reader = MessageLogReader::open(log_dir.as_ref())?;
 
-        let mut version_info = String::new();
-        reader.read_line(&mut version_info)?;
         // TODO: Validate if the read version is supported
+        let _version_info = reader.next_line()?;
+
+        Ok(reader)
+    }
+
+    fn open(log_dir: &Path) -> Result<MessageLogReader, std::io::Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .open(log_dir.join(LOG_FILE_NAME))?;
+        let mut file = BufReader::new(file);
+        let compressed = file.fill_buf()?.starts_with(&GZIP_MAGIC_BYTES);
+        let reader: Box<dyn BufRead> = if compressed {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(file)
+        };
 
-        Ok(MessageLogReader { reader })
+        Ok(MessageLogReader { reader, compressed })
     }
 
     /// Return the next MQTT message from the log
     /// The reads start from the beginning of the file
     /// and each read advances the file pointer to the next line
     pub fn next_message(&mut self) -> Result<Option<MqttMessage>, LogEntryError> {
-        let mut buffer = String::new();
-        match self.reader.read_line(&mut buffer) {
-            Ok(bytes_read) if bytes_read > 0 => {
-                let message: MqttMessage = serde_json::from_str(&buffer)
-                    .map_err(|err| LogEntryError::FromSerdeJson(err, buffer))?;
+        match self.next_line() {
+            Ok(Some(line)) => {
+                let message: MqttMessage = serde_json::from_str(&line)
+                    .map_err(|err| LogEntryError::FromSerdeJson(err, line))?;
                 Ok(Some(message))
             }
-            Ok(_) => Ok(None), // EOF
+            Ok(None) => Ok(None), // EOF
             Err(err) => Err(LogEntryError::FromStdIo(err)),
         }
     }
+
+    /// Return the next line of the log, if any
+    ///
+    /// A compressed log ends with the last complete line:
+    /// the gzip stream being either still written or truncated by a crash.
+    fn next_line(&mut self) -> Result<Option<String>, std::io::Error> {
+        let mut buffer = String::new();
+        match self.reader.read_line(&mut buffer) {
+            Ok(0) => Ok(None),
+            Ok(_) if self.compressed && !buffer.ends_with('\n') => Ok(None),
+            Ok(_) => Ok(Some(buffer)),
+            Err(err) if self.compressed && err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
 }
 
 /// A writer to append new MQTT messages to the end of the log
 pub(crate) struct MessageLogWriter {
-    writer: BufWriter<File>,
+    writer: LogWriter,
+}
+
+enum LogWriter {
+    Plain(BufWriter<File>),
+    Compressed(GzEncoder<File>),
 }
 
 impl MessageLogWriter {
-    pub fn new<P>(log_dir: P) -> Result<MessageLogWriter, std::io::Error>
+    /// Open the log for appends, creating the log if not existing yet
+    ///
+    /// The `compress` flag is only used to create a new log:
+    /// an existing log is extended using its current format, compressed or plain.
+    pub fn new<P>(log_dir: P, compress: bool) -> Result<MessageLogWriter, std::io::Error>
     where
         P: AsRef<Path>,
     {
+        let log_dir = log_dir.as_ref();
         let file = OpenOptions::new()
             .create(true)
             .append(true)
-            .open(log_dir.as_ref().join(LOG_FILE_NAME))?;
+            .open(log_dir.join(LOG_FILE_NAME))?;
 
         // If the file is empty append the version information as a header
         let metadata = file.metadata()?;
         let file_is_empty = metadata.len() == 0;
 
-        let mut writer = BufWriter::new(file);
-
         if file_is_empty {
+            let mut log_writer = MessageLogWriter::with_format(file, compress);
             let version_info = json!({ "version": LOG_FORMAT_VERSION }).to_string();
-            writeln!(writer, "{}", version_info)?;
+            log_writer.append_line(&version_info)?;
+            return Ok(log_writer);
+        }
+
+        let mut reader = MessageLogReader::open(log_dir)?;
+        if !reader.compressed {
+            return Ok(MessageLogWriter::with_format(file, false));
+        }
+
+        // A gzip stream cannot be extended once finished or truncated:
+        // the readable lines are copied into a new stream, which then replaces the log
+        let tmp_path = log_dir.join(format!("{LOG_FILE_NAME}.tmp"));
+        let tmp_file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&tmp_path)?;
+        let mut log_writer = MessageLogWriter::with_format(tmp_file, true);
+        while let Some(line) = reader.next_line()? {
+            log_writer.append_line(line.trim_end_matches('\n'))?;
         }
+        std::fs::rename(&tmp_path, log_dir.join(LOG_FILE_NAME))?;
+        Ok(log_writer)
+    }
 
-        Ok(MessageLogWriter { writer })
+    fn with_format(file: File, compressed: bool) -> MessageLogWriter {
+        let writer = if compressed {
+            LogWriter::Compressed(GzEncoder::new(file, Compression::default()))
+        } else {
+            LogWriter::Plain(BufWriter::new(file))
+        };
+        MessageLogWriter { writer }
     }
 
-    pub fn new_truncated<P>(log_dir: P) -> Result<MessageLogWriter, std::io::Error>
+    pub fn new_truncated<P>(log_dir: P, compress: bool) -> Result<MessageLogWriter, std::io::Error>
     where
         P: AsRef<Path>,
     {
@@ -101,17 +176,32 @@ impl MessageLogWriter {
             .truncate(true)
             .open(log_dir.as_ref().join(LOG_FILE_NAME))?;
 
-        MessageLogWriter::new(log_dir)
+        MessageLogWriter::new(log_dir, compress)
     }
 
     /// Append the JSON representation of the given message to the log.
     /// Each message is appended on a new line.
     pub fn append_message(&mut self, message: &MqttMessage) -> Result<(), std::io::Error> {
         let json_line = serde_json::to_string(message)?;
-        writeln!(self.writer, "{}", json_line)?;
-        self.writer.flush()?;
-        self.writer.get_ref().sync_all()?;
-        Ok(())
+        self.append_line(&json_line)?;
+        match &self.writer {
+            LogWriter::Plain(writer) => writer.get_ref().sync_all(),
+            LogWriter::Compressed(encoder) => encoder.get_ref().sync_all(),
+        }
+    }
+
+    fn append_line(&mut self, line: &str) -> Result<(), std::io::Error> {
+        match &mut self.writer {
+            LogWriter::Plain(writer) => {
+                writeln!(writer, "{}", line)?;
+                writer.flush()
+            }
+            LogWriter::Compressed(encoder) => {
+                // A sync flush, so all the lines written so far can be decompressed
+                writeln!(encoder, "{}", line)?;
+                encoder.flush()
+            }
+        }
     }
 }
 
@@ -119,12 +209,113 @@ impl MessageLogWriter {
 mod tests {
     use super::MessageLogReader;
     use super::MessageLogWriter;
+    use super::GZIP_MAGIC_BYTES;
+    use super::LOG_FILE_NAME;
     use mqtt_channel::MqttMessage;
     use mqtt_channel::Topic;
     use tempfile::tempdir;
 
     #[test]
     fn test_append_and_retrieve() {
+        append_and_retrieve(false)
+    }
+
+    #[test]
+    fn test_append_and_retrieve_compressed() {
+        append_and_retrieve(true)
+    }
+
+    #[test]
+    fn an_existing_log_is_extended_in_its_own_format() {
+        let temp_dir = tempdir().unwrap();
+        let topic = Topic::new("topic").unwrap();
+        let first = MqttMessage::new(&topic, "first");
+        let second = MqttMessage::new(&topic, "second");
+
+        MessageLogWriter::new(&temp_dir, false)
+            .unwrap()
+            .append_message(&first)
+            .unwrap();
+        MessageLogWriter::new(&temp_dir, true)
+            .unwrap()
+            .append_message(&second)
+            .unwrap();
+
+        let content = std::fs::read_to_string(temp_dir.path().join(LOG_FILE_NAME)).unwrap();
+        assert_eq!(content.lines().count(), 3);
+
+        let mut message_log_reader = MessageLogReader::new(&temp_dir).unwrap();
+        assert_eq!(message_log_reader.next_message().unwrap(), Some(first));
+        assert_eq!(message_log_reader.next_message().unwrap(), Some(second));
+        assert_eq!(message_log_reader.next_message().unwrap(), None);
+    }
+
+    #[test]
+    fn a_compressed_log_can_be_extended_after_a_restart() {
+        let temp_dir = tempdir().unwrap();
+        let topic = Topic::new("topic").unwrap();
+        let messages: Vec<_> = (1..5)
+            .map(|i| MqttMessage::new(&topic, format!("payload{i}")))
+            .collect();
+
+        for message in messages.iter() {
+            MessageLogWriter::new(&temp_dir, true)
+                .unwrap()
+                .append_message(message)
+                .unwrap();
+        }
+
+        let mut message_log_reader = MessageLogReader::new(&temp_dir).unwrap();
+        for message in messages {
+            assert_eq!(message_log_reader.next_message().unwrap(), Some(message));
+        }
+        assert_eq!(message_log_reader.next_message().unwrap(), None);
+    }
+
+    #[test]
+    fn a_truncated_compressed_log_is_recovered_up_to_the_last_complete_line() {
+        let temp_dir = tempdir().unwrap();
+        let topic = Topic::new("topic").unwrap();
+        let first = MqttMessage::new(&topic, "first");
+        let second = MqttMessage::new(&topic, "second");
+        let third = MqttMessage::new(&topic, "third");
+
+        let log_file = temp_dir.path().join(LOG_FILE_NAME);
+        let mut message_log = MessageLogWriter::new(&temp_dir, true).unwrap();
+        message_log.append_message(&first).unwrap();
+        let complete_len = std::fs::metadata(&log_file).unwrap().len();
+        message_log.append_message(&second).unwrap();
+        let full_len = std::fs::metadata(&log_file).unwrap().len();
+
+        // Simulate a crash in the middle of the second line, before the gzip stream is finished
+        std::mem::forget(message_log);
+        let truncated_len = complete_len + (full_len - complete_len) / 2;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&log_file)
+            .unwrap()
+            .set_len(truncated_len)
+            .unwrap();
+
+        let mut message_log_reader = MessageLogReader::new(&temp_dir).unwrap();
+        assert_eq!(
+            message_log_reader.next_message().unwrap(),
+            Some(first.clone())
+        );
+        assert_eq!(message_log_reader.next_message().unwrap(), None);
+
+        // The log can still be extended
+        MessageLogWriter::new(&temp_dir, true)
+            .unwrap()
+            .append_message(&third)
+            .unwrap();
+        let mut message_log_reader = MessageLogReader::new(&temp_dir).unwrap();
+        assert_eq!(message_log_reader.next_message().unwrap(), Some(first));
+        assert_eq!(message_log_reader.next_message().unwrap(), Some(third));
+        assert_eq!(message_log_reader.next_message().unwrap(), None);
+    }
+
+    fn append_and_retrieve(compress: bool) {
         let temp_dir = tempdir().unwrap();
 
         // Prepare some dummy messages
@@ -139,7 +330,7 @@ mod tests {
 
         // Populate the log
         {
-            let mut message_log = MessageLogWriter::new(&temp_dir).unwrap();
+            let mut message_log = MessageLogWriter::new(&temp_dir, compress).unwrap();
             let mut message_log_reader = MessageLogReader::new(&temp_dir).unwrap();
 
             assert_eq!(message_log_reader.next_message().unwrap(), None);
@@ -160,5 +351,8 @@ mod tests {
             // EOF -> None
             assert_eq!(message_log_reader.next_message().unwrap(), None);
         }
+
+        let content = std::fs::read(temp_dir.path().join(LOG_FILE_NAME)).unwrap();
+        assert_eq!(content.starts_with(&GZIP_MAGIC_BYTES), compress);
     }
 }
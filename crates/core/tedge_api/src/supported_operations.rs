@@ -0,0 +1,170 @@
+//! Discovery of the operations supported by an entity
+use crate::mqtt_topics::EntityTopicId;
+use crate::mqtt_topics::MqttSchema;
+use crate::mqtt_topics::OperationType;
+use mqtt_channel::MqttMessage;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Return the operations supported by the `target` entity, given the capability messages received so far
+///
+/// The messages are processed in order, an empty message removing the capability registered by a previous one.
+/// The messages for other entities or on other channels are ignored.
+///
+/// The operations are sorted by name, without duplicates.
+pub fn from_capability_messages<'a>(
+    schema: &MqttSchema,
+    target: &EntityTopicId,
+    messages: impl IntoIterator<Item = &'a MqttMessage>,
+) -> Vec<OperationType> {
+    let mut operations = BTreeMap::new();
+    for message in messages {
+        let Some((entity, operation)) = schema.capability_of(&message.topic) else {
+            continue;
+        };
+        if &entity != target {
+            continue;
+        }
+        if message.payload_bytes().is_empty() {
+            operations.remove(&operation.name());
+        } else {
+            operations.insert(operation.name(), operation);
+        }
+    }
+    operations.into_values().collect()
+}
+
+/// Return the operations supported by a device, given an operations directory
+///
+/// The operations of the main device are the files of `ops_dir`,
+/// while the operations of a child device are the files of its own `ops_dir/<child-id>` sub-directory.
+/// Each file is named after an operation, as given by [OperationType::as_dir_name].
+/// Hidden files are ignored, and a child device without its own directory supports no operations.
+///
+/// The operations are sorted by name.
+pub fn from_operations_dir(
+    ops_dir: &Path,
+    child_id: Option<&str>,
+) -> Result<Vec<OperationType>, std::io::Error> {
+    let dir = match child_id {
+        None => ops_dir.to_path_buf(),
+        Some(child_id) => ops_dir.join(child_id),
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound && child_id.is_some() => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    let mut operations = BTreeMap::new();
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let operation = OperationType::from_dir_name(&name);
+        operations.insert(name, operation);
+    }
+    Ok(operations.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::RestartCommand;
+    use crate::commands::SoftwareListCommand;
+    use crate::commands::SoftwareUpdateCommand;
+    use mqtt_channel::Topic;
+
+    #[test]
+    fn operations_of_the_main_and_child_devices_from_capability_messages() {
+        let schema = MqttSchema::default();
+        let main = EntityTopicId::default_main_device();
+        let child = EntityTopicId::default_child_device("child01").unwrap();
+        let messages = vec![
+            SoftwareUpdateCommand::capability_message(&schema, &main),
+            RestartCommand::capability_message(&schema, &main),
+            SoftwareListCommand::capability_message(&schema, &main),
+            MqttMessage::new(
+                &Topic::new_unchecked("te/device/main///cmd/c8y_Command"),
+                "{}",
+            ),
+            RestartCommand::capability_message(&schema, &child),
+            MqttMessage::new(
+                &Topic::new_unchecked("te/device/child01///cmd/c8y_Command"),
+                "{}",
+            ),
+            MqttMessage::new(&Topic::new_unchecked("te/device/main///m/"), "{}"),
+        ];
+
+        assert_eq!(
+            from_capability_messages(&schema, &main, &messages),
+            vec![
+                OperationType::Custom("c8y_Command".to_string()),
+                OperationType::Restart,
+                OperationType::SoftwareList,
+                OperationType::SoftwareUpdate,
+            ]
+        );
+        assert_eq!(
+            from_capability_messages(&schema, &child, &messages),
+            vec![
+                OperationType::Custom("c8y_Command".to_string()),
+                OperationType::Restart,
+            ]
+        );
+    }
+
+    #[test]
+    fn an_empty_capability_message_removes_the_operation() {
+        let schema = MqttSchema::default();
+        let main = EntityTopicId::default_main_device();
+        let messages = vec![
+            RestartCommand::capability_message(&schema, &main),
+            SoftwareListCommand::capability_message(&schema, &main),
+            MqttMessage::new(&Topic::new_unchecked("te/device/main///cmd/restart"), ""),
+        ];
+
+        assert_eq!(
+            from_capability_messages(&schema, &main, &messages),
+            vec![OperationType::SoftwareList]
+        );
+    }
+
+    #[test]
+    fn operations_of_the_main_and_child_devices_from_operations_dir() {
+        let ops_dir = tempfile::tempdir().unwrap();
+        for op in ["software_update", "restart", "c8y_Command", ".hidden"] {
+            std::fs::write(ops_dir.path().join(op), "").unwrap();
+        }
+        let child_dir = ops_dir.path().join("child01");
+        std::fs::create_dir(&child_dir).unwrap();
+        for op in ["log_upload", "config_snapshot"] {
+            std::fs::write(child_dir.join(op), "").unwrap();
+        }
+
+        assert_eq!(
+            from_operations_dir(ops_dir.path(), None).unwrap(),
+            vec![
+                OperationType::Custom("c8y_Command".to_string()),
+                OperationType::Restart,
+                OperationType::SoftwareUpdate,
+            ]
+        );
+        assert_eq!(
+            from_operations_dir(ops_dir.path(), Some("child01")).unwrap(),
+            vec![OperationType::ConfigSnapshot, OperationType::LogUpload]
+        );
+        assert_eq!(
+            from_operations_dir(ops_dir.path(), Some("child02")).unwrap(),
+            vec![]
+        );
+    }
+}
@@ -26,6 +26,18 @@ pub enum WorkflowDefinitionError {
         main_operation: String,
         builtin_operation: String,
     },
+
+    #[error("Missing workflow fragment: {fragment}")]
+    MissingInclude { fragment: String },
+
+    #[error("Cyclic inclusion of workflow fragments: {cycle}")]
+    IncludeCycle { cycle: String },
+
+    #[error("Missing init state in workflow fragment: {fragment}")]
+    MissingFragmentInit { fragment: String },
+
+    #[error("Duplicated state: {state}")]
+    DuplicatedState { state: String },
 }
 
 /// Error related to a script definition
@@ -94,13 +106,26 @@ pub enum WorkflowExecutionError {
     #[error("No command has been initiated on the command topic: {topic}")]
     UnknownRequest { topic: String },
 
-    #[error("Two concurrent requests are under execution on the same topic: {topic}")]
-    DuplicatedRequest { topic: String },
+    #[error(transparent)]
+    DuplicateCommand(#[from] DuplicateCommand),
 
     #[error("No such step is defined for {operation}: {step}")]
     UnknownStep { operation: String, step: String },
 }
 
+/// A new command request re-using the command id of a command still known by the agent
+///
+/// This is typically the case when a request is retried by the cloud mapper or by a user.
+/// The caller can either ignore the new request, or clear the previous command to restart it.
+#[derive(thiserror::Error, Debug, Clone, Eq, PartialEq)]
+pub enum DuplicateCommand {
+    #[error("A command is already in progress on the same topic: {topic} (status: {status})")]
+    InProgress { topic: String, status: String },
+
+    #[error("A command has already been executed on the same topic: {topic} (status: {status})")]
+    Terminated { topic: String, status: String },
+}
+
 /// Struct used to recover the bare minimum information from an ill-formed workflow TOML file.
 #[derive(Deserialize)]
 pub struct IllFormedOperationWorkflow {
@@ -0,0 +1,209 @@
+use crate::mqtt_topics::EntityTopicId;
+use crate::mqtt_topics::OperationType;
+use crate::workflow::CommandId;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+/// Tracks the command in progress for each entity and operation, to prevent concurrent executions
+///
+/// Two commands of the same operation executed concurrently on the same entity,
+/// e.g. two `software_update` commands, would corrupt the state of that entity.
+/// Before starting a command, the caller asks the guard with [OperationInProgressGuard::try_start]:
+/// - if no other command of that operation is in progress on that entity, the command becomes the active one,
+/// - otherwise, the command is either rejected, e.g. with [GenericCommandState::conflict_message],
+///   or queued with [OperationInProgressGuard::enqueue] to be started once the active command completes.
+///
+/// [GenericCommandState::conflict_message]: crate::workflow::GenericCommandState::conflict_message
+#[derive(Debug, Default)]
+pub struct OperationInProgressGuard {
+    commands: HashMap<(EntityTopicId, OperationType), InProgress>,
+}
+
+#[derive(Debug)]
+struct InProgress {
+    active: CommandId,
+    queued: VecDeque<CommandId>,
+}
+
+/// The command that prevents another command of the same operation to start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationConflict {
+    pub active_cmd_id: CommandId,
+}
+
+impl OperationInProgressGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark a command as the active command of its operation for the target entity
+    ///
+    /// Fail if another command of the same operation is already in progress on that entity.
+    /// Starting again the active command is not a conflict.
+    pub fn try_start(
+        &mut self,
+        target: &EntityTopicId,
+        operation: &OperationType,
+        cmd_id: impl Into<CommandId>,
+    ) -> Result<(), OperationConflict> {
+        let cmd_id = cmd_id.into();
+        match self.commands.get(&(target.clone(), operation.clone())) {
+            Some(in_progress) if in_progress.active != cmd_id => Err(OperationConflict {
+                active_cmd_id: in_progress.active.clone(),
+            }),
+            Some(_) => Ok(()),
+            None => {
+                self.commands.insert(
+                    (target.clone(), operation.clone()),
+                    InProgress {
+                        active: cmd_id,
+                        queued: VecDeque::new(),
+                    },
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Queue a command to be started once the commands in progress for the same operation complete
+    ///
+    /// If no command of that operation is in progress on the target entity,
+    /// the command is immediately made active and `true` is returned.
+    pub fn enqueue(
+        &mut self,
+        target: &EntityTopicId,
+        operation: &OperationType,
+        cmd_id: impl Into<CommandId>,
+    ) -> bool {
+        let cmd_id = cmd_id.into();
+        match self.commands.get_mut(&(target.clone(), operation.clone())) {
+            Some(in_progress) => {
+                if in_progress.active != cmd_id && !in_progress.queued.contains(&cmd_id) {
+                    in_progress.queued.push_back(cmd_id);
+                }
+                false
+            }
+            None => self.try_start(target, operation, cmd_id).is_ok(),
+        }
+    }
+
+    /// Mark a command as completed, returning the queued command to be started next, if any
+    ///
+    /// The returned command is now the active command of that operation.
+    /// A queued command that completes, e.g. because cancelled, is simply removed from the queue.
+    pub fn complete(
+        &mut self,
+        target: &EntityTopicId,
+        operation: &OperationType,
+        cmd_id: &str,
+    ) -> Option<CommandId> {
+        let key = (target.clone(), operation.clone());
+        let in_progress = self.commands.get_mut(&key)?;
+        if in_progress.active != cmd_id {
+            in_progress.queued.retain(|queued| queued != cmd_id);
+            return None;
+        }
+        match in_progress.queued.pop_front() {
+            Some(next) => {
+                in_progress.active = next.clone();
+                Some(next)
+            }
+            None => {
+                self.commands.remove(&key);
+                None
+            }
+        }
+    }
+
+    /// The command of that operation in progress on the target entity, if any
+    pub fn active_command(
+        &self,
+        target: &EntityTopicId,
+        operation: &OperationType,
+    ) -> Option<&CommandId> {
+        self.commands
+            .get(&(target.clone(), operation.clone()))
+            .map(|in_progress| &in_progress.active)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_concurrent_command_is_rejected() {
+        let main = EntityTopicId::default_main_device();
+        let mut guard = OperationInProgressGuard::new();
+
+        assert!(guard
+            .try_start(&main, &OperationType::SoftwareUpdate, "c8y-mapper-1")
+            .is_ok());
+        assert_eq!(
+            guard.try_start(&main, &OperationType::SoftwareUpdate, "c8y-mapper-2"),
+            Err(OperationConflict {
+                active_cmd_id: "c8y-mapper-1".to_string()
+            })
+        );
+
+        // Other operations and other entities are not blocked
+        let child = EntityTopicId::default_child_device("child01").unwrap();
+        assert!(guard
+            .try_start(&main, &OperationType::Restart, "c8y-mapper-3")
+            .is_ok());
+        assert!(guard
+            .try_start(&child, &OperationType::SoftwareUpdate, "c8y-mapper-4")
+            .is_ok());
+    }
+
+    #[test]
+    fn a_command_is_allowed_after_completion_of_the_previous_one() {
+        let main = EntityTopicId::default_main_device();
+        let mut guard = OperationInProgressGuard::new();
+
+        guard
+            .try_start(&main, &OperationType::SoftwareUpdate, "c8y-mapper-1")
+            .unwrap();
+        assert_eq!(
+            guard.complete(&main, &OperationType::SoftwareUpdate, "c8y-mapper-1"),
+            None
+        );
+        assert_eq!(
+            guard.active_command(&main, &OperationType::SoftwareUpdate),
+            None
+        );
+        assert!(guard
+            .try_start(&main, &OperationType::SoftwareUpdate, "c8y-mapper-2")
+            .is_ok());
+    }
+
+    #[test]
+    fn queued_commands_are_started_in_order() {
+        let main = EntityTopicId::default_main_device();
+        let op = OperationType::SoftwareUpdate;
+        let mut guard = OperationInProgressGuard::new();
+
+        assert!(guard.enqueue(&main, &op, "c8y-mapper-1"));
+        assert!(!guard.enqueue(&main, &op, "c8y-mapper-2"));
+        assert!(!guard.enqueue(&main, &op, "c8y-mapper-3"));
+        assert!(!guard.enqueue(&main, &op, "c8y-mapper-4"));
+
+        // A queued command can complete before being started
+        assert_eq!(guard.complete(&main, &op, "c8y-mapper-3"), None);
+
+        assert_eq!(
+            guard.complete(&main, &op, "c8y-mapper-1"),
+            Some("c8y-mapper-2".to_string())
+        );
+        assert_eq!(
+            guard.active_command(&main, &op),
+            Some(&"c8y-mapper-2".to_string())
+        );
+        assert_eq!(
+            guard.complete(&main, &op, "c8y-mapper-2"),
+            Some("c8y-mapper-4".to_string())
+        );
+        assert_eq!(guard.complete(&main, &op, "c8y-mapper-4"), None);
+        assert_eq!(guard.active_command(&main, &op), None);
+    }
+}
@@ -1,9 +1,11 @@
 pub mod error;
 pub mod handlers;
+pub mod in_progress;
 pub(crate) mod log;
 mod on_disk;
 pub mod state;
 pub mod supervisor;
+pub mod timeout_watcher;
 mod toml_config;
 
 use crate::mqtt_topics::EntityTopicId;
@@ -14,6 +16,7 @@ use crate::substitution::Record;
 use ::log::info;
 pub use error::*;
 pub use handlers::*;
+pub use in_progress::*;
 use mqtt_channel::MqttMessage;
 use mqtt_channel::QoS;
 use serde::Deserialize;
@@ -23,6 +26,7 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::fmt::Formatter;
 pub use supervisor::*;
+pub use timeout_watcher::*;
 
 pub type OperationName = String;
 pub type StateName = String;
@@ -50,6 +54,66 @@ pub struct OperationWorkflow {
     pub states: HashMap<StateName, OperationAction>,
 }
 
+/// An operation workflow definition, which included fragments are not resolved yet
+///
+/// A state can include the states of a reusable [WorkflowFragment]:
+///
+/// ```toml
+/// [download]
+/// include = "<fragment-name>"
+/// on_success = "<state>"
+/// on_error = "<state>"
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+pub struct OperationWorkflowDefinition {
+    definition: toml_config::TomlOperationWorkflow,
+}
+
+/// A set of states that can be included by operation workflows
+///
+/// A fragment must have an `init` state, used as the entry point,
+/// and can move to the `successful` and `failed` states,
+/// which are redirected to the `on_success` and `on_error` handlers of the including state.
+/// These next states must be given explicitly, default handlers being those of the including workflow.
+///
+/// A fragment can include other fragments.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+pub struct WorkflowFragment {
+    fragment: toml_config::TomlWorkflowFragment,
+}
+
+impl WorkflowFragment {
+    /// The names of the fragments directly included by this fragment
+    pub fn includes(&self) -> Vec<String> {
+        toml_config::included_fragments(&self.fragment.states)
+    }
+}
+
+impl OperationWorkflowDefinition {
+    /// The operation to which this workflow applies
+    pub fn operation(&self) -> &OperationType {
+        &self.definition.operation
+    }
+
+    /// The names of the fragments directly included by this workflow
+    pub fn includes(&self) -> Vec<String> {
+        toml_config::included_fragments(&self.definition.states)
+    }
+
+    /// Build the operation workflow, flattening the included fragments
+    ///
+    /// The states of a fragment included by a state named `step` are namespaced as `step.<state>`,
+    /// the `init` state of the fragment being renamed `step`.
+    pub fn resolve(
+        self,
+        fragments: &HashMap<String, WorkflowFragment>,
+    ) -> Result<OperationWorkflow, WorkflowDefinitionError> {
+        self.definition.resolve_includes(fragments)?.try_into()
+    }
+}
+
 /// What needs to be done to advance an operation request in some state
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 #[serde(try_from = "toml_config::TomlOperationState")]
@@ -72,6 +72,9 @@ const EXECUTING: &str = "executing";
 const SUCCESSFUL: &str = "successful";
 const FAILED: &str = "failed";
 const REASON: &str = "reason";
+const REASON_CODE: &str = "reasonCode";
+const UNSUPPORTED: &str = "unsupported";
+const CONFLICT: &str = "conflict";
 
 impl GenericCommandState {
     pub fn new(topic: Topic, status: String, mut payload: Value) -> Self {
@@ -138,6 +141,66 @@ impl GenericCommandState {
         })
     }
 
+    /// Build the message acknowledging that a command request is being executed
+    ///
+    /// This works for any operation, builtin or custom, with no need to know the actual command type:
+    /// the message is published on the same command topic, hence with the same command id,
+    /// and its payload is the request payload with the status set to `executing`.
+    pub fn executing_message(request: &MqttMessage) -> Result<MqttMessage, WorkflowExecutionError> {
+        let command = GenericCommandState::from_command_request(request)?;
+        Ok(command
+            .update(GenericStateUpdate::executing())
+            .into_message())
+    }
+
+    /// Build the message rejecting a command request for an operation that is not supported
+    ///
+    /// As for [GenericCommandState::executing_message], this works for any operation.
+    /// The command is marked as `failed`, with a `reasonCode` set to `unsupported`,
+    /// so the requester can tell this failure from a failure of the operation itself.
+    pub fn unsupported_message(
+        request: &MqttMessage,
+    ) -> Result<MqttMessage, WorkflowExecutionError> {
+        let command = GenericCommandState::from_command_request(request)?;
+        let operation = MqttSchema::get_operation_name(request.topic.as_ref()).unwrap_or_default();
+        let mut command = command.update(GenericStateUpdate::failed(format!(
+            "Unsupported operation: {operation}"
+        )));
+        GenericCommandState::inject_text_property(&mut command.payload, REASON_CODE, UNSUPPORTED);
+        Ok(command.into_message())
+    }
+
+    /// Build the message rejecting a command request because another command of the same operation is in progress
+    ///
+    /// As for [GenericCommandState::unsupported_message], this works for any operation.
+    /// The command is marked as `failed`, with a `reasonCode` set to `conflict`.
+    pub fn conflict_message(
+        request: &MqttMessage,
+        active_cmd_id: &str,
+    ) -> Result<MqttMessage, WorkflowExecutionError> {
+        let command = GenericCommandState::from_command_request(request)?;
+        let operation = MqttSchema::get_operation_name(request.topic.as_ref()).unwrap_or_default();
+        let mut command = command.update(GenericStateUpdate::failed(format!(
+            "Another {operation} command is in progress: {active_cmd_id}"
+        )));
+        GenericCommandState::inject_text_property(&mut command.payload, REASON_CODE, CONFLICT);
+        Ok(command.into_message())
+    }
+
+    /// Extract the state of a command request, rejecting non-command topics and cleared commands
+    fn from_command_request(request: &MqttMessage) -> Result<Self, WorkflowExecutionError> {
+        if MqttSchema::get_command_id(request.topic.as_ref()).is_none() {
+            return Err(WorkflowExecutionError::InvalidCmdTopic {
+                topic: request.topic.name.clone(),
+            });
+        }
+        let command = GenericCommandState::from_command_message(request)?;
+        if command.is_cleared() {
+            return Err(WorkflowExecutionError::MissingStatus);
+        }
+        Ok(command)
+    }
+
     /// Build an MQTT message to publish the command state
     pub fn into_message(mut self) -> MqttMessage {
         if self.is_cleared() {
@@ -716,6 +779,109 @@ mod tests {
         );
     }
 
+    #[test]
+    fn executing_message_for_a_builtin_operation() {
+        let topic = Topic::new_unchecked("te/device/main///cmd/software_update/c8y-mapper-1234");
+        let request = MqttMessage::new(
+            &topic,
+            r#"{"status":"init","updateList":[{"type":"apt","modules":[{"name":"vim","action":"install"}]}]}"#,
+        );
+
+        let executing = GenericCommandState::executing_message(&request).unwrap();
+        assert_eq!(executing.topic, topic);
+        assert!(executing.retain);
+        assert_eq!(
+            serde_json::from_slice::<Value>(executing.payload_bytes()).unwrap(),
+            json!({
+                "status": "executing",
+                "updateList": [{"type":"apt","modules":[{"name":"vim","action":"install"}]}]
+            })
+        );
+    }
+
+    #[test]
+    fn executing_message_for_a_custom_operation() {
+        let topic = Topic::new_unchecked("te/device/child01///cmd/my_operation/id-42");
+        let request = MqttMessage::new(&topic, r#"{"status":"init","foo":42}"#);
+
+        let executing = GenericCommandState::executing_message(&request).unwrap();
+        assert_eq!(executing.topic, topic);
+        assert_eq!(
+            serde_json::from_slice::<Value>(executing.payload_bytes()).unwrap(),
+            json!({"status": "executing", "foo": 42})
+        );
+    }
+
+    #[test]
+    fn no_executing_message_for_a_non_command() {
+        let not_a_command = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/my_operation"),
+            r#"{"status":"init"}"#,
+        );
+        assert!(matches!(
+            GenericCommandState::executing_message(&not_a_command),
+            Err(WorkflowExecutionError::InvalidCmdTopic { .. })
+        ));
+
+        let cleared = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/my_operation/id-42"),
+            "",
+        );
+        assert!(matches!(
+            GenericCommandState::executing_message(&cleared),
+            Err(WorkflowExecutionError::MissingStatus)
+        ));
+    }
+
+    #[test]
+    fn unsupported_message_for_any_operation() {
+        let topic = Topic::new_unchecked("te/device/child01///cmd/my_operation/c8y-mapper-42");
+        let request = MqttMessage::new(&topic, r#"{"status":"init","foo":42}"#);
+
+        let unsupported = GenericCommandState::unsupported_message(&request).unwrap();
+        assert_eq!(unsupported.topic, topic);
+        assert!(unsupported.retain);
+        assert_eq!(
+            serde_json::from_slice::<Value>(unsupported.payload_bytes()).unwrap(),
+            json!({
+                "status": "failed",
+                "reason": "Unsupported operation: my_operation",
+                "reasonCode": "unsupported",
+                "foo": 42
+            })
+        );
+    }
+
+    #[test]
+    fn conflict_message_for_any_operation() {
+        let topic = Topic::new_unchecked("te/device/main///cmd/software_update/c8y-mapper-2");
+        let request = MqttMessage::new(&topic, r#"{"status":"init","updateList":[]}"#);
+
+        let conflict = GenericCommandState::conflict_message(&request, "c8y-mapper-1").unwrap();
+        assert_eq!(conflict.topic, topic);
+        assert_eq!(
+            serde_json::from_slice::<Value>(conflict.payload_bytes()).unwrap(),
+            json!({
+                "status": "failed",
+                "reason": "Another software_update command is in progress: c8y-mapper-1",
+                "reasonCode": "conflict",
+                "updateList": []
+            })
+        );
+    }
+
+    #[test]
+    fn no_unsupported_message_for_a_non_command() {
+        let not_a_command = MqttMessage::new(
+            &Topic::new_unchecked("te/device/main///cmd/my_operation"),
+            r#"{"status":"init"}"#,
+        );
+        assert!(matches!(
+            GenericCommandState::unsupported_message(&not_a_command),
+            Err(WorkflowExecutionError::InvalidCmdTopic { .. })
+        ));
+    }
+
     #[test]
     fn retrieve_invoking_command() {
         let topic = Topic::new_unchecked("te/device/main///cmd/do_it/sub:make_it:456");
@@ -481,6 +481,29 @@ impl CommandBoard {
         self.commands.values_mut()
     }
 
+    /// Check that a new operation request doesn't re-use the id of a command already on the [CommandBoard]
+    ///
+    /// A request identical to the current state of the command is accepted, as being simply re-delivered.
+    /// Otherwise, the request is rejected as a [DuplicateCommand],
+    /// telling if the previous command is still in progress or already terminated.
+    pub fn check_new_request(
+        &self,
+        new_command: &GenericCommandState,
+    ) -> Result<(), DuplicateCommand> {
+        match self.commands.get(&new_command.topic.name) {
+            Some((_, command)) if command == new_command => Ok(()),
+            Some((_, command)) if command.is_finished() => Err(DuplicateCommand::Terminated {
+                topic: new_command.topic.name.clone(),
+                status: command.status.clone(),
+            }),
+            Some((_, command)) => Err(DuplicateCommand::InProgress {
+                topic: new_command.topic.name.clone(),
+                status: command.status.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
     /// Insert a new operation request into the [CommandBoard]
     ///
     /// Reject the request if there is already an entry with the same command id, but in a different state
@@ -488,18 +511,13 @@ impl CommandBoard {
         &mut self,
         new_command: GenericCommandState,
     ) -> Result<(), WorkflowExecutionError> {
-        match self.commands.get(&new_command.topic.name) {
-            Some((_, command)) if command == &new_command => Ok(()),
-            Some(_) => Err(WorkflowExecutionError::DuplicatedRequest {
-                topic: new_command.topic.name,
-            }),
-            None => {
-                let timestamp = time::OffsetDateTime::now_utc();
-                self.commands
-                    .insert(new_command.topic.name.clone(), (timestamp, new_command));
-                Ok(())
-            }
+        self.check_new_request(&new_command)?;
+        if !self.commands.contains_key(&new_command.topic.name) {
+            let timestamp = time::OffsetDateTime::now_utc();
+            self.commands
+                .insert(new_command.topic.name.clone(), (timestamp, new_command));
         }
+        Ok(())
     }
 
     /// Update the current state of an operation request
@@ -608,4 +626,105 @@ mod tests {
             Some(&level_1_cmd)
         );
     }
+
+    fn command_state(topic: &str, status: &str) -> GenericCommandState {
+        GenericCommandState::from_command_message(&MqttMessage::new(
+            &Topic::new_unchecked(topic),
+            format!(r#"{{ "@version": "builtin", "status":"{status}" }}"#),
+        ))
+        .unwrap()
+    }
+
+    fn supervisor_with(operation: &OperationType) -> WorkflowSupervisor {
+        let mut workflows = WorkflowSupervisor::default();
+        workflows
+            .register_builtin_workflow(operation.clone())
+            .unwrap();
+        workflows
+    }
+
+    #[test]
+    fn accept_a_fresh_command() {
+        let operation = OperationType::Custom("my_op".to_string());
+        let mut workflows = supervisor_with(&operation);
+        let topic = "te/device/foo///cmd/my_op/id_1";
+
+        let new_command = command_state(topic, "init");
+        assert_eq!(
+            workflows.pending_commands().check_new_request(&new_command),
+            Ok(())
+        );
+        assert_eq!(
+            workflows
+                .apply_external_update(&operation, new_command.clone())
+                .unwrap(),
+            Some(new_command.clone())
+        );
+
+        // The very same request can be re-delivered
+        assert_eq!(
+            workflows.pending_commands().check_new_request(&new_command),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn reject_a_duplicate_of_a_command_in_progress() {
+        let operation = OperationType::Custom("my_op".to_string());
+        let mut workflows = supervisor_with(&operation);
+        let topic = "te/device/foo///cmd/my_op/id_1";
+
+        workflows
+            .apply_external_update(&operation, command_state(topic, "init"))
+            .unwrap();
+        workflows
+            .apply_internal_update(command_state(topic, "executing"))
+            .unwrap();
+
+        let duplicate = command_state(topic, "init");
+        let expected = DuplicateCommand::InProgress {
+            topic: topic.to_string(),
+            status: "executing".to_string(),
+        };
+        assert_eq!(
+            workflows.pending_commands().check_new_request(&duplicate),
+            Err(expected.clone())
+        );
+        assert!(matches!(
+            workflows.apply_external_update(&operation, duplicate),
+            Err(WorkflowExecutionError::DuplicateCommand(err)) if err == expected
+        ));
+    }
+
+    #[test]
+    fn reject_a_duplicate_of_a_terminated_command_till_cleared() {
+        let operation = OperationType::Custom("my_op".to_string());
+        let mut workflows = supervisor_with(&operation);
+        let topic = "te/device/foo///cmd/my_op/id_1";
+
+        workflows
+            .apply_external_update(&operation, command_state(topic, "init"))
+            .unwrap();
+        workflows
+            .apply_internal_update(command_state(topic, "successful"))
+            .unwrap();
+
+        let duplicate = command_state(topic, "init");
+        assert!(matches!(
+            workflows.apply_external_update(&operation, duplicate.clone()),
+            Err(WorkflowExecutionError::DuplicateCommand(DuplicateCommand::Terminated { status, .. }))
+                if status == "successful"
+        ));
+
+        // Once the previous command cleared, the command id can be re-used
+        workflows
+            .apply_external_update(&operation, command_state(topic, "successful").clear())
+            .unwrap();
+        assert_eq!(
+            workflows
+                .apply_external_update(&operation, duplicate.clone())
+                .unwrap(),
+            Some(duplicate)
+        );
+    }
 }
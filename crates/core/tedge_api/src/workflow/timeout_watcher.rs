@@ -0,0 +1,124 @@
+use crate::workflow::CommandId;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Tracks the deadlines of the operations in progress, to time out those never reaching a terminal status
+///
+/// This covers whole operations, while the workflow timeouts only apply to a single step.
+/// The watcher is given the deadline of each operation when started,
+/// is told when an operation completes, and yields the operations still not completed once their deadline passed.
+/// These operations are then meant to be moved to a failed status, e.g. with [GenericStateUpdate::timeout].
+///
+/// The watcher holds no timer: the caller awaits [OperationTimeoutWatcher::next_deadline]
+/// and then calls [OperationTimeoutWatcher::take_timed_out].
+///
+/// [GenericStateUpdate::timeout]: crate::workflow::GenericStateUpdate::timeout
+#[derive(Debug, Default)]
+pub struct OperationTimeoutWatcher {
+    deadlines: HashMap<CommandId, Instant>,
+}
+
+impl OperationTimeoutWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watch an operation that has to be completed by the given deadline
+    ///
+    /// If the operation is already watched, its deadline is updated.
+    pub fn watch(&mut self, cmd_id: impl Into<CommandId>, deadline: Instant) {
+        self.deadlines.insert(cmd_id.into(), deadline);
+    }
+
+    /// Stop watching an operation, returning `true` if this operation was watched
+    pub fn complete(&mut self, cmd_id: &str) -> bool {
+        self.deadlines.remove(cmd_id).is_some()
+    }
+
+    /// Check if an operation is watched
+    pub fn is_watched(&self, cmd_id: &str) -> bool {
+        self.deadlines.contains_key(cmd_id)
+    }
+
+    /// The earliest deadline of the watched operations, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines.values().min().copied()
+    }
+
+    /// Remove and return the operations whose deadline is past `now`, the earliest deadline first
+    pub fn take_timed_out(&mut self, now: Instant) -> Vec<CommandId> {
+        let mut timed_out: Vec<(Instant, CommandId)> = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(cmd_id, deadline)| (*deadline, cmd_id.clone()))
+            .collect();
+        timed_out.sort();
+        for (_, cmd_id) in &timed_out {
+            self.deadlines.remove(cmd_id);
+        }
+        timed_out.into_iter().map(|(_, cmd_id)| cmd_id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn an_operation_not_completed_by_its_deadline_is_timed_out() {
+        let start = Instant::now();
+        let mut watcher = OperationTimeoutWatcher::new();
+        watcher.watch("c8y-mapper-1", start + Duration::from_secs(10));
+        watcher.watch("c8y-mapper-2", start + Duration::from_secs(5));
+        watcher.watch("c8y-mapper-3", start + Duration::from_secs(60));
+
+        assert_eq!(
+            watcher.next_deadline(),
+            Some(start + Duration::from_secs(5))
+        );
+        assert!(watcher.take_timed_out(start).is_empty());
+
+        assert_eq!(
+            watcher.take_timed_out(start + Duration::from_secs(30)),
+            vec!["c8y-mapper-2".to_string(), "c8y-mapper-1".to_string()]
+        );
+        assert!(!watcher.is_watched("c8y-mapper-1"));
+        assert!(watcher.is_watched("c8y-mapper-3"));
+        assert_eq!(
+            watcher.next_deadline(),
+            Some(start + Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn a_completed_operation_is_no_longer_watched() {
+        let start = Instant::now();
+        let mut watcher = OperationTimeoutWatcher::new();
+        watcher.watch("c8y-mapper-1", start + Duration::from_secs(10));
+
+        assert!(watcher.complete("c8y-mapper-1"));
+        assert!(!watcher.complete("c8y-mapper-1"));
+        assert!(watcher
+            .take_timed_out(start + Duration::from_secs(30))
+            .is_empty());
+        assert_eq!(watcher.next_deadline(), None);
+    }
+
+    #[test]
+    fn watching_an_operation_again_updates_its_deadline() {
+        let start = Instant::now();
+        let mut watcher = OperationTimeoutWatcher::new();
+        watcher.watch("c8y-mapper-1", start + Duration::from_secs(10));
+        watcher.watch("c8y-mapper-1", start + Duration::from_secs(60));
+
+        assert!(watcher
+            .take_timed_out(start + Duration::from_secs(30))
+            .is_empty());
+        assert_eq!(
+            watcher.take_timed_out(start + Duration::from_secs(60)),
+            vec!["c8y-mapper-1".to_string()]
+        );
+    }
+}
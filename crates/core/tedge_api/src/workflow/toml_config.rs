@@ -12,6 +12,7 @@ use crate::workflow::OperationAction;
 use crate::workflow::OperationWorkflow;
 use crate::workflow::ScriptDefinitionError;
 use crate::workflow::WorkflowDefinitionError;
+use crate::workflow::WorkflowFragment;
 use serde::de::Error;
 use serde::Deserialize;
 use serde::Deserializer;
@@ -43,6 +44,15 @@ pub struct TomlOperationWorkflow {
     pub states: HashMap<String, TomlOperationState>,
 }
 
+/// User-friendly representation of a [WorkflowFragment]
+///
+/// The states of a fragment, which names and targets are namespaced when included.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TomlWorkflowFragment {
+    #[serde(flatten)]
+    pub states: HashMap<String, TomlOperationState>,
+}
+
 /// User-friendly representation of an [OperationAction] and associated handlers.
 #[derive(Clone, Debug, Deserialize)]
 pub struct TomlOperationState {
@@ -76,6 +86,7 @@ pub enum TomlOperationAction {
     Action(String),
     Operation(String),
     Iterate(String),
+    Include(String),
 }
 
 impl Default for TomlOperationAction {
@@ -161,6 +172,10 @@ impl TryFrom<(TomlOperationState, DefaultHandlers)> for OperationAction {
                 };
                 Ok(OperationAction::Iterate(json_path.to_string(), handlers))
             }
+            TomlOperationAction::Include(fragment) => {
+                // Includes are expected to be resolved beforehand
+                Err(WorkflowDefinitionError::MissingInclude { fragment })
+            }
             TomlOperationAction::Action(command) => match command.as_str() {
                 "cleanup" => Ok(OperationAction::Clear),
                 "proceed" => {
@@ -215,6 +230,117 @@ impl TryFrom<TomlOperationWorkflow> for OperationWorkflow {
     }
 }
 
+impl TomlOperationWorkflow {
+    /// Replace the states including a fragment by the states of that fragment
+    pub fn resolve_includes(
+        mut self,
+        fragments: &HashMap<String, WorkflowFragment>,
+    ) -> Result<Self, WorkflowDefinitionError> {
+        self.states = resolve_includes(self.states, fragments, &mut vec![])?;
+        Ok(self)
+    }
+}
+
+/// Flatten the states including a fragment, recursively
+///
+/// The states of a fragment included by a state named `step` are renamed to avoid collisions:
+/// - the `init` state of the fragment is renamed `step`,
+/// - the `successful` and `failed` states are redirected to the `on_success` and `on_error` handlers of `step`,
+/// - any other state `x` of the fragment is renamed `step.x`.
+///
+/// The `stack` is the list of the fragments under resolution, used to detect include cycles.
+fn resolve_includes(
+    states: HashMap<String, TomlOperationState>,
+    fragments: &HashMap<String, WorkflowFragment>,
+    stack: &mut Vec<String>,
+) -> Result<HashMap<String, TomlOperationState>, WorkflowDefinitionError> {
+    let mut resolved = HashMap::new();
+    let mut included = Vec::new();
+    for (step, state) in states {
+        let TomlOperationAction::Include(fragment_name) = &state.action else {
+            insert_state(&mut resolved, step, state)?;
+            continue;
+        };
+
+        if stack.contains(fragment_name) {
+            let mut cycle = stack.clone();
+            cycle.push(fragment_name.clone());
+            return Err(WorkflowDefinitionError::IncludeCycle {
+                cycle: cycle.join(" -> "),
+            });
+        }
+        let fragment = fragments.get(fragment_name).ok_or_else(|| {
+            WorkflowDefinitionError::MissingInclude {
+                fragment: fragment_name.clone(),
+            }
+        })?;
+
+        stack.push(fragment_name.clone());
+        let fragment_states = resolve_includes(fragment.fragment.states.clone(), fragments, stack)?;
+        stack.pop();
+
+        if !fragment_states.contains_key("init") {
+            return Err(WorkflowDefinitionError::MissingFragmentInit {
+                fragment: fragment_name.clone(),
+            });
+        }
+
+        let mut targets: HashMap<String, GenericStateUpdate> = fragment_states
+            .keys()
+            .map(|name| (name.clone(), format!("{step}.{name}").into()))
+            .collect();
+        targets.insert("init".to_string(), step.as_str().into());
+        let on_success = state.handlers.on_success.clone().map(|u| u.into());
+        targets.insert(
+            "successful".to_string(),
+            on_success.unwrap_or_else(GenericStateUpdate::successful),
+        );
+        let on_error = state.handlers.on_error.clone().map(|u| u.into());
+        targets.insert(
+            "failed".to_string(),
+            on_error.unwrap_or_else(|| "failed".into()),
+        );
+        included.push((targets, fragment_states));
+    }
+
+    // The included states are inserted last, to detect any collision with the including states
+    for (targets, fragment_states) in included {
+        for (name, mut state) in fragment_states {
+            if name == "successful" || name == "failed" {
+                continue;
+            }
+            let name = targets[&name].status.clone();
+            state.handlers = state.handlers.redirect(&targets);
+            insert_state(&mut resolved, name, state)?;
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// The names of the fragments included by a set of states
+pub fn included_fragments(states: &HashMap<String, TomlOperationState>) -> Vec<String> {
+    states
+        .values()
+        .filter_map(|state| match &state.action {
+            TomlOperationAction::Include(fragment) => Some(fragment.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn insert_state(
+    states: &mut HashMap<String, TomlOperationState>,
+    name: String,
+    state: TomlOperationState,
+) -> Result<(), WorkflowDefinitionError> {
+    if states.contains_key(&name) {
+        return Err(WorkflowDefinitionError::DuplicatedState { state: name });
+    }
+    states.insert(name, state);
+    Ok(())
+}
+
 /// User-Friendly representation of an [ExitHandlers]; as used in the operation TOML definition files
 ///
 /// A user don't have to give a handler for all possible exit code.
@@ -259,6 +385,50 @@ pub struct TomlExitHandlers {
     on_next: Option<TomlStateUpdate>,
 }
 
+impl TomlExitHandlers {
+    /// Redirect the next states according to the given targets
+    ///
+    /// The states with no target are left unchanged.
+    fn redirect(self, targets: &HashMap<String, GenericStateUpdate>) -> Self {
+        let redirect = |update: TomlStateUpdate| update.redirect(targets);
+        TomlExitHandlers {
+            on_success: self.on_success.map(redirect),
+            on_error: self.on_error.map(redirect),
+            on_kill: self.on_kill.map(redirect),
+            on_exit: self
+                .on_exit
+                .into_iter()
+                .map(|(codes, update)| (codes, redirect(update)))
+                .collect(),
+            timeout_second: self.timeout_second,
+            on_timeout: self.on_timeout.map(redirect),
+            on_stdout: self
+                .on_stdout
+                .into_iter()
+                .map(|status| match targets.get(&status) {
+                    Some(target) => target.status.clone(),
+                    None => status,
+                })
+                .collect(),
+            on_exec: self.on_exec.map(redirect),
+            on_next: self.on_next.map(redirect),
+        }
+    }
+}
+
+impl TomlStateUpdate {
+    fn redirect(self, targets: &HashMap<String, GenericStateUpdate>) -> Self {
+        let update: GenericStateUpdate = self.clone().into();
+        match targets.get(&update.status) {
+            None => self,
+            Some(target) => TomlStateUpdate::Detailed(GenericStateUpdate {
+                status: target.status.clone(),
+                reason: update.reason.or_else(|| target.reason.clone()),
+            }),
+        }
+    }
+}
+
 impl TryFrom<TomlExitHandlers> for ExitHandlers {
     type Error = ScriptDefinitionError;
 
@@ -501,6 +671,7 @@ impl FromStr for ExitCodes {
 mod tests {
     use super::*;
     use crate::workflow::GenericStateUpdate;
+    use crate::workflow::OperationWorkflowDefinition;
     use assert_matches::assert_matches;
     use ExitCodes::*;
 
@@ -821,4 +992,205 @@ on_error = "failed"
         let res = OperationWorkflow::try_from(input);
         assert_matches!(res, Err(WorkflowDefinitionError::InvalidPathExpression(_)));
     }
+
+    fn fragments(fragments: &[(&str, &str)]) -> HashMap<String, WorkflowFragment> {
+        fragments
+            .iter()
+            .map(|(name, toml)| (name.to_string(), toml::from_str(toml).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn resolve_included_fragment() {
+        let file = r#"
+operation = "custom_operation"
+
+[init]
+action = "proceed"
+on_success = "download"
+
+[download]
+include = "download_fragment"
+on_success = "install"
+on_error = { status = "failed", reason = "download failed" }
+
+[install]
+script = "/usr/bin/install.sh"
+on_success = "successful"
+"#;
+        let fragments = fragments(&[(
+            "download_fragment",
+            r#"
+[init]
+script = "/usr/bin/download.sh"
+on_success = "check"
+on_error = "failed"
+
+[check]
+script = "/usr/bin/check.sh"
+on_exit.0 = "successful"
+on_exit.1 = "init"
+on_exit._ = "failed"
+"#,
+        )]);
+
+        let input: OperationWorkflowDefinition = toml::from_str(file).unwrap();
+        let workflow = input.resolve(&fragments).unwrap();
+
+        let mut states: Vec<_> = workflow.states.keys().cloned().collect();
+        states.sort();
+        assert_eq!(
+            states,
+            vec![
+                "download",
+                "download.check",
+                "failed",
+                "init",
+                "install",
+                "successful"
+            ]
+        );
+
+        // The fragment init state replaces the including state
+        let OperationAction::Script(script, handlers) = &workflow.states["download"] else {
+            panic!("Expected a script action")
+        };
+        assert_eq!(script.command, "/usr/bin/download.sh");
+        assert_eq!(handlers.state_update_on_success().status, "download.check");
+        assert_eq!(
+            handlers.state_update_on_exit("download.sh", 1).status,
+            "failed"
+        );
+
+        // The fragment states are renamed and redirected
+        let OperationAction::Script(_, handlers) = &workflow.states["download.check"] else {
+            panic!("Expected a script action")
+        };
+        assert_eq!(handlers.state_update_on_success().status, "install");
+        assert_eq!(
+            handlers.state_update_on_exit("check.sh", 1).status,
+            "download"
+        );
+        assert_eq!(
+            handlers.state_update_on_exit("check.sh", 2).status,
+            "failed"
+        );
+    }
+
+    #[test]
+    fn resolve_nested_fragments() {
+        let file = r#"
+operation = "custom_operation"
+
+[init]
+include = "outer"
+on_success = "successful"
+"#;
+        let fragments = fragments(&[
+            (
+                "outer",
+                r#"
+[init]
+include = "inner"
+on_success = "done"
+
+[done]
+action = "proceed"
+on_success = "successful"
+"#,
+            ),
+            (
+                "inner",
+                r#"
+[init]
+action = "proceed"
+on_success = "next"
+
+[next]
+action = "proceed"
+on_success = "successful"
+"#,
+            ),
+        ]);
+
+        let input: OperationWorkflowDefinition = toml::from_str(file).unwrap();
+        let workflow = input.resolve(&fragments).unwrap();
+
+        let mut states: Vec<_> = workflow.states.keys().cloned().collect();
+        states.sort();
+        assert_eq!(
+            states,
+            vec![
+                "failed",
+                "init",
+                "init.done",
+                "init.init.next",
+                "successful"
+            ]
+        );
+        assert_eq!(
+            workflow.states["init"],
+            OperationAction::MoveTo("init.init.next".into())
+        );
+        assert_eq!(
+            workflow.states["init.init.next"],
+            OperationAction::MoveTo("init.done".into())
+        );
+        assert_eq!(
+            workflow.states["init.done"],
+            OperationAction::MoveTo("successful".into())
+        );
+    }
+
+    #[test]
+    fn reject_missing_include() {
+        let file = r#"
+operation = "custom_operation"
+
+[init]
+include = "unknown"
+on_success = "successful"
+"#;
+        let input: OperationWorkflowDefinition = toml::from_str(file).unwrap();
+        let res = input.resolve(&HashMap::new());
+        assert_matches!(res, Err(WorkflowDefinitionError::MissingInclude { fragment }) if fragment == "unknown");
+
+        // An include cannot be used without being resolved
+        let input: TomlOperationWorkflow = toml::from_str(file).unwrap();
+        let res = OperationWorkflow::try_from(input);
+        assert_matches!(res, Err(WorkflowDefinitionError::MissingInclude { fragment }) if fragment == "unknown");
+    }
+
+    #[test]
+    fn reject_include_cycles() {
+        let file = r#"
+operation = "custom_operation"
+
+[init]
+include = "a"
+on_success = "successful"
+"#;
+        let fragments = fragments(&[
+            (
+                "a",
+                r#"
+[init]
+include = "b"
+on_success = "successful"
+"#,
+            ),
+            (
+                "b",
+                r#"
+[init]
+include = "a"
+on_success = "successful"
+"#,
+            ),
+        ]);
+
+        let input: OperationWorkflowDefinition = toml::from_str(file).unwrap();
+        let res = input.resolve(&fragments);
+        assert_matches!(res, Err(WorkflowDefinitionError::IncludeCycle { cycle }) if cycle == "a -> b -> a");
+    }
 }
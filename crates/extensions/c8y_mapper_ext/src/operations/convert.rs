@@ -95,6 +95,7 @@ impl CumulocityConverter {
 
         let request = ConfigSnapshotCmdPayload {
             status: CommandStatus::Init,
+            status_message: None,
             tedge_url: Some(tedge_url),
             config_type: config_upload_request.config_type,
             path: None,
@@ -136,6 +137,7 @@ impl CumulocityConverter {
 
         let request = LogUploadCmdPayload {
             status: CommandStatus::Init,
+            status_message: None,
             tedge_url,
             log_type: log_request.log_file,
             date_from: log_request.date_from,
@@ -214,10 +216,12 @@ impl CumulocityConverter {
 
         let request = FirmwareUpdateCmdPayload {
             status: CommandStatus::Init,
+            status_message: None,
             tedge_url: Some(tedge_url),
             remote_url: firmware_request.url,
             name: firmware_request.name,
             version: firmware_request.version,
+            sha256: None,
             log_path: None,
         };
 
@@ -286,6 +290,7 @@ impl CumulocityConverter {
 
         let request = ConfigUpdateCmdPayload {
             status: CommandStatus::Init,
+            status_message: None,
             tedge_url: None,
             remote_url,
             config_type: config_download_request.config_type.clone(),
@@ -346,6 +351,7 @@ impl CumulocityConverter {
 
         let mut request = DeviceProfileCmdPayload {
             status: CommandStatus::Init,
+            status_message: None,
             name: profile_name,
             operations: Vec::new(),
         };
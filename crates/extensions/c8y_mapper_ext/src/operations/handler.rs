@@ -453,6 +453,7 @@ mod tests {
             cmd_id: "c8y-mapper-1273384".to_string(),
             payload: ConfigSnapshotCmdPayload {
                 status: CommandStatus::Successful,
+                status_message: None,
                 tedge_url: Some("asdf".to_string()),
                 config_type: "typeA".to_string(),
                 path: None,
@@ -570,6 +571,7 @@ mod tests {
             cmd_id: "c8y-mapper-229394".to_string(),
             payload: ConfigSnapshotCmdPayload {
                 status: CommandStatus::Executing,
+                status_message: None,
                 tedge_url: Some("asdf".to_string()),
                 config_type: "typeA".to_string(),
                 path: None,
@@ -611,6 +613,7 @@ mod tests {
             cmd_id: "c8y-mapper-123456".to_string(),
             payload: ConfigSnapshotCmdPayload {
                 status: CommandStatus::Executing,
+                status_message: None,
                 tedge_url: Some("asdf".to_string()),
                 config_type: "typeA".to_string(),
                 path: None,
@@ -664,6 +667,7 @@ mod tests {
                 status: CommandStatus::Failed {
                     reason: "test".to_string(),
                 },
+                status_message: None,
                 tedge_url: Some("asdf".to_string()),
                 config_type: "typeA".to_string(),
                 path: None,
@@ -676,6 +680,7 @@ mod tests {
             cmd_id: "c8y-mapper-28433842".to_string(),
             payload: ConfigSnapshotCmdPayload {
                 status: CommandStatus::Successful,
+                status_message: None,
                 tedge_url: Some("asdf".to_string()),
                 config_type: "typeA".to_string(),
                 path: None,
@@ -759,6 +764,7 @@ mod tests {
             cmd_id: "config-snapshot-1".to_string(),
             payload: ConfigSnapshotCmdPayload {
                 status: CommandStatus::Successful,
+                status_message: None,
                 tedge_url: Some("asdf".to_string()),
                 config_type: "typeA".to_string(),
                 path: None,
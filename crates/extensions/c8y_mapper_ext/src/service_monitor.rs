@@ -36,7 +36,7 @@ pub(crate) fn convert_health_status_message(
         return vec![];
     }
 
-    let HealthStatus { status } =
+    let HealthStatus { status, .. } =
         HealthStatus::try_from_health_status_message(message, mqtt_schema).unwrap();
 
     let external_id = entity.external_id.as_ref();
@@ -4,6 +4,45 @@ use std::time::Instant;
 use ::backoff::backoff::Backoff;
 use ::backoff::exponential::ExponentialBackoff;
 use ::backoff::Clock;
+use tedge_config::OptionalConfig;
+use tedge_config::SecondsOrHumanTime;
+use tedge_config::TEdgeConfig;
+
+/// The policy used by a half bridge to reconnect its broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    pub initial_interval: Duration,
+    pub maximum_interval: Duration,
+    pub reset_window: Duration,
+}
+
+impl ReconnectPolicy {
+    /// The reconnect policies of the local and cloud half bridges, in that order
+    ///
+    /// Any setting not specific to a half bridge is taken from `mqtt.bridge.reconnect_policy`.
+    pub fn local_and_cloud(tedge_config: &TEdgeConfig) -> [ReconnectPolicy; 2] {
+        let shared = &tedge_config.mqtt.bridge.reconnect_policy;
+        let local = &tedge_config.mqtt.bridge.local_reconnect_policy;
+        let cloud = &tedge_config.mqtt.bridge.cloud_reconnect_policy;
+        let or_shared = |specific: &OptionalConfig<SecondsOrHumanTime>,
+                         shared: &SecondsOrHumanTime| {
+            specific.or_none().unwrap_or(shared).duration()
+        };
+
+        [
+            ReconnectPolicy {
+                initial_interval: or_shared(&local.initial_interval, &shared.initial_interval),
+                maximum_interval: or_shared(&local.maximum_interval, &shared.maximum_interval),
+                reset_window: or_shared(&local.reset_window, &shared.reset_window),
+            },
+            ReconnectPolicy {
+                initial_interval: or_shared(&cloud.initial_interval, &shared.initial_interval),
+                maximum_interval: or_shared(&cloud.maximum_interval, &shared.maximum_interval),
+                reset_window: or_shared(&cloud.reset_window, &shared.reset_window),
+            },
+        ]
+    }
+}
 
 pub struct CustomBackoff<C> {
     eb: ExponentialBackoff<C>,
@@ -71,8 +110,64 @@ mod tests {
     use std::cell::RefCell;
     use std::collections::VecDeque;
 
+    use tedge_config::TEdgeConfigLocation;
+    use tedge_test_utils::fs::TempTedgeDir;
+
     use super::*;
 
+    #[test]
+    fn half_bridges_share_the_reconnect_policy_by_default() {
+        let [local, cloud] = ReconnectPolicy::local_and_cloud(&tedge_config(|_| {}));
+
+        let shared = ReconnectPolicy {
+            initial_interval: Duration::from_secs(30),
+            maximum_interval: Duration::from_secs(600),
+            reset_window: Duration::from_secs(300),
+        };
+        assert_eq!(local, shared);
+        assert_eq!(cloud, shared);
+    }
+
+    #[test]
+    fn each_half_bridge_uses_its_configured_reconnect_policy() {
+        let [local, cloud] = ReconnectPolicy::local_and_cloud(&tedge_config(|dto| {
+            let bridge = &mut dto.mqtt.bridge;
+            bridge.reconnect_policy.reset_window = Some("1m".parse().unwrap());
+            bridge.local_reconnect_policy.initial_interval = Some("1s".parse().unwrap());
+            bridge.local_reconnect_policy.maximum_interval = Some("5s".parse().unwrap());
+            bridge.cloud_reconnect_policy.initial_interval = Some("1m".parse().unwrap());
+        }));
+
+        assert_eq!(
+            local,
+            ReconnectPolicy {
+                initial_interval: Duration::from_secs(1),
+                maximum_interval: Duration::from_secs(5),
+                reset_window: Duration::from_secs(60),
+            }
+        );
+        assert_eq!(
+            cloud,
+            ReconnectPolicy {
+                initial_interval: Duration::from_secs(60),
+                maximum_interval: Duration::from_secs(600),
+                reset_window: Duration::from_secs(60),
+            }
+        );
+    }
+
+    fn tedge_config(update: impl Fn(&mut tedge_config::TEdgeConfigDto)) -> TEdgeConfig {
+        let ttd = TempTedgeDir::new();
+        let config_loc = TEdgeConfigLocation::from_custom_root(ttd.path());
+        config_loc
+            .update_toml(&|dto, _reader| {
+                update(dto);
+                Ok(())
+            })
+            .unwrap();
+        TEdgeConfig::try_new(config_loc).unwrap()
+    }
+
     #[test]
     fn backoff_is_30_seconds_when_requested_for_first_time() {
         let now = Instant::now();
@@ -2,14 +2,24 @@ use crate::topics::matches_ignore_dollar_prefix;
 use crate::topics::TopicConverter;
 use certificate::parse_root_certificate::create_tls_config;
 use certificate::parse_root_certificate::create_tls_config_without_client_cert;
+use mqtt_channel::MqttMessage;
 use rumqttc::valid_filter;
 use rumqttc::valid_topic;
 use rumqttc::MqttOptions;
+use rumqttc::QoS;
+use rumqttc::SubscribeFilter;
 use rumqttc::Transport;
 use std::borrow::Cow;
+use std::num::NonZeroU32;
+use std::num::NonZeroUsize;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 use tedge_config::CloudConfig;
 
+/// The maximum length of an MQTT topic, in bytes, as encoded with a 16-bit length prefix
+const MAX_TOPIC_LENGTH: usize = u16::MAX as usize;
+
 pub fn use_key_and_cert(
     config: &mut MqttOptions,
     cloud_config: &dyn CloudConfig,
@@ -40,9 +50,97 @@ pub struct BridgeConfig {
     local_to_remote: Vec<BridgeRule>,
     remote_to_local: Vec<BridgeRule>,
     bidirectional_topics: Vec<(Cow<'static, str>, Cow<'static, str>)>,
+    deduplicate_retained_messages: bool,
+    subscription_chunks: Option<SubscriptionChunks>,
+    subscription_retry: SubscriptionRetry,
+    health_startup_grace_period: Duration,
+    bridge_name: Option<String>,
+    ready_topic: Option<String>,
+    local_message_transformer: Option<Arc<dyn BridgeTransformer>>,
+    remote_message_transformer: Option<Arc<dyn BridgeTransformer>>,
+    cloud_ack_timeout: Option<AckTimeout>,
+    max_pending_messages: Option<NonZeroUsize>,
+    local_subscription_qos: Option<QoS>,
+    remote_subscription_qos: Option<QoS>,
+    rules_topic: Option<String>,
+    retained_cache_capacity: Option<NonZeroUsize>,
+    rate_limits: Vec<(String, NonZeroU32)>,
+    max_topic_length: Option<NonZeroUsize>,
+    source_topic_field: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+/// A custom transformation of the messages forwarded by the bridge in one direction
+///
+/// The transformer is given each message about to be forwarded,
+/// with the target topic derived from the source topic by the bridge rules,
+/// and returns the message to be actually published on the target, if any.
+///
+/// ```
+/// use tedge_mqtt_bridge::BridgeTransformer;
+/// use tedge_mqtt_bridge::MqttMessage;
+///
+/// /// Drop the empty messages
+/// struct DropEmptyMessages;
+///
+/// impl BridgeTransformer for DropEmptyMessages {
+///     fn transform(&self, message: MqttMessage) -> Option<MqttMessage> {
+///         (!message.payload_bytes().is_empty()).then_some(message)
+///     }
+/// }
+/// ```
+pub trait BridgeTransformer: Send + Sync {
+    /// Return the message to be forwarded in place of the given message, or `None` to drop it
+    fn transform(&self, message: MqttMessage) -> Option<MqttMessage>;
+}
+
+impl std::fmt::Debug for dyn BridgeTransformer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BridgeTransformer")
+    }
+}
+
+/// Subscribe to the bridged topics in chunks of `size` filters, waiting `delay` between chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct SubscriptionChunks {
+    pub size: NonZeroUsize,
+    pub delay: Duration,
+}
+
+/// Try to subscribe at most `max_attempts` times, waiting `initial_interval` after the first failure,
+/// this interval being doubled after each subsequent failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct SubscriptionRetry {
+    pub max_attempts: NonZeroUsize,
+    pub initial_interval: Duration,
+}
+
+impl Default for SubscriptionRetry {
+    fn default() -> Self {
+        SubscriptionRetry {
+            max_attempts: NonZeroUsize::new(5).unwrap(),
+            initial_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// What to do with a message forwarded to the cloud that is not acknowledged in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckTimeoutAction {
+    /// Acknowledge the original message to the local broker anyway, on a best-effort basis
+    AckLocally,
+
+    /// Publish the message again on the cloud broker, and wait again for its acknowledgement
+    Republish,
+}
+
+/// Give up waiting for a cloud acknowledgement after `timeout`, then apply `action`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct AckTimeout {
+    pub timeout: Duration,
+    pub action: AckTimeoutAction,
+}
+
+#[derive(Clone)]
 /// A rule for forwarding MQTT messages from one broker to another
 ///
 /// A rule has three parts, a filter, a prefix to add and a prefix to remove. For instance, the rule
@@ -70,6 +168,28 @@ pub struct BridgeRule {
     topic_filter: Cow<'static, str>,
     prefix_to_remove: Cow<'static, str>,
     prefix_to_add: Cow<'static, str>,
+    ignore_retained_on_subscribe: bool,
+    fast_ack: bool,
+    loop_breaker: bool,
+}
+
+impl std::fmt::Debug for BridgeRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut rule = f.debug_struct("BridgeRule");
+        rule.field("topic_filter", &self.topic_filter)
+            .field("prefix_to_remove", &self.prefix_to_remove)
+            .field("prefix_to_add", &self.prefix_to_add);
+        if self.ignore_retained_on_subscribe {
+            rule.field("ignore_retained_on_subscribe", &true);
+        }
+        if self.fast_ack {
+            rule.field("fast_ack", &true);
+        }
+        if !self.loop_breaker {
+            rule.field("loop_breaker", &false);
+        }
+        rule.finish()
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -89,6 +209,39 @@ pub enum InvalidBridgeRule {
     InvalidTopicFilter(String),
 }
 
+/// A misconfiguration of the bridge, as reported by [BridgeConfig::validate]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BridgeConfigIssue {
+    #[error("No rule forwards messages from the {0} broker, and the bridge cannot subscribe to an empty list of topics")]
+    NoRules(BridgeDirection),
+
+    #[error("The rule forwarding {shadowed:?} from the {direction} broker is shadowed by the rule forwarding {by:?}, which maps these topics differently")]
+    ShadowedRule {
+        direction: BridgeDirection,
+        shadowed: String,
+        by: String,
+    },
+
+    #[error("The rules forwarding {local:?} from the local broker and {remote:?} from the remote broker forward messages back and forth; use forward_bidirectionally to prevent an infinite loop")]
+    ForwardingLoop { local: String, remote: String },
+}
+
+/// The broker from which the messages are forwarded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BridgeDirection {
+    FromLocal,
+    FromRemote,
+}
+
+impl std::fmt::Display for BridgeDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BridgeDirection::FromLocal => f.write_str("local"),
+            BridgeDirection::FromRemote => f.write_str("remote"),
+        }
+    }
+}
+
 fn validate_topic(topic: &str) -> Result<(), InvalidBridgeRule> {
     match valid_topic(topic) {
         true => Ok(()),
@@ -114,6 +267,9 @@ impl BridgeRule {
             topic_filter: prefix_to_remove.clone() + base_topic_filter.clone(),
             prefix_to_remove,
             prefix_to_add,
+            ignore_retained_on_subscribe: false,
+            fast_ack: false,
+            loop_breaker: true,
         };
 
         validate_topic(&r.prefix_to_add)?;
@@ -140,6 +296,83 @@ impl BridgeRule {
             self.prefix_to_add.clone() + topic.strip_prefix(&*self.prefix_to_remove).unwrap()
         })
     }
+
+    /// Do not forward the retained messages sent by the broker when the bridge subscribes
+    ///
+    /// On each (re)connection, the broker sends the retained messages matching the topic filter,
+    /// and these would be forwarded again, possibly resurrecting a stale state on the target.
+    /// These messages are told apart from live messages by their retain flag,
+    /// which is only set by the broker on messages sent as a result of a new subscription
+    /// (see the MQTT 3.1.1 specification, section 3.3.1.3).
+    /// Live messages are forwarded as usual, even if published as retained messages.
+    ///
+    /// Default: `false`
+    pub fn ignore_retained_on_subscribe(&mut self, enabled: bool) {
+        self.ignore_retained_on_subscribe = enabled;
+    }
+
+    pub(crate) fn ignores_retained_on_subscribe(&self) -> bool {
+        self.ignore_retained_on_subscribe
+    }
+
+    /// Acknowledge the messages to their source as soon as handed to the target client,
+    /// without waiting for the target broker to acknowledge them
+    ///
+    /// This reduces the latency of the acknowledgements, at the cost of the delivery guarantee:
+    /// the messages are forwarded at most once, and are lost if the target connection is
+    /// interrupted before they are received by the target broker.
+    /// This is intended for messages, such as pure telemetry, for which such a loss is acceptable.
+    ///
+    /// Default: `false`, messages being acknowledged only once acknowledged by the target broker
+    pub fn fast_ack(&mut self, enabled: bool) {
+        self.fast_ack = enabled;
+    }
+
+    pub(crate) fn is_fast_ack(&self) -> bool {
+        self.fast_ack
+    }
+
+    /// Do not check the messages received on this rule against the messages forwarded the other way
+    ///
+    /// Only the rules added with [BridgeConfig::forward_bidirectionally] are checked for loops,
+    /// the bridge keeping track of the messages forwarded on their topics to drop the echoes.
+    /// Disabling this bookkeeping on a high-volume rule saves its cost,
+    /// but is only safe if the messages forwarded back never reach this rule again,
+    /// e.g. because the remote broker doesn't echo them.
+    ///
+    /// This has no effect on the rules not forwarding messages bidirectionally, which are never checked.
+    ///
+    /// Default: `true`
+    pub fn loop_breaker(&mut self, enabled: bool) {
+        self.loop_breaker = enabled;
+    }
+
+    fn to_json(&self, bidirectional: bool) -> serde_json::Value {
+        serde_json::json!({
+            "topic_filter": self.topic_filter,
+            "prefix_to_remove": self.prefix_to_remove,
+            "prefix_to_add": self.prefix_to_add,
+            "bidirectional": bidirectional,
+            "fast_ack": self.fast_ack,
+            "ignore_retained_on_subscribe": self.ignore_retained_on_subscribe,
+        })
+    }
+
+    /// The filter matching the target topics of the messages forwarded by this rule
+    fn target_filter(&self) -> String {
+        let base_topic_filter = self
+            .topic_filter
+            .strip_prefix(&*self.prefix_to_remove)
+            .unwrap_or_default();
+        format!("{}{base_topic_filter}", self.prefix_to_add)
+    }
+}
+
+/// Return `true` if some topics are matched by both filters
+///
+/// This is an approximation, only detecting that one filter includes the other.
+fn overlap(lhs: &str, rhs: &str) -> bool {
+    matches_ignore_dollar_prefix(lhs, rhs) || matches_ignore_dollar_prefix(rhs, lhs)
 }
 
 impl BridgeConfig {
@@ -147,44 +380,46 @@ impl BridgeConfig {
         Self::default()
     }
 
+    /// Add a rule forwarding messages from the local broker to the remote one
+    ///
+    /// The rule is returned so it can be further configured.
     pub fn forward_from_local(
         &mut self,
         topic: impl Into<Cow<'static, str>>,
         local_prefix: impl Into<Cow<'static, str>>,
         remote_prefix: impl Into<Cow<'static, str>>,
-    ) -> Result<(), InvalidBridgeRule> {
-        self.local_to_remote.push(BridgeRule::try_new(
-            topic.into(),
-            local_prefix.into(),
-            remote_prefix.into(),
-        )?);
-        Ok(())
+    ) -> Result<&mut BridgeRule, InvalidBridgeRule> {
+        let rule = BridgeRule::try_new(topic.into(), local_prefix.into(), remote_prefix.into())?;
+        self.local_to_remote.push(rule);
+        Ok(self.local_to_remote.last_mut().unwrap())
     }
 
+    /// Add a rule forwarding messages from the remote broker to the local one
+    ///
+    /// The rule is returned so it can be further configured.
     pub fn forward_from_remote(
         &mut self,
         topic: impl Into<Cow<'static, str>>,
         local_prefix: impl Into<Cow<'static, str>>,
         remote_prefix: impl Into<Cow<'static, str>>,
-    ) -> Result<(), InvalidBridgeRule> {
-        self.remote_to_local.push(BridgeRule::try_new(
-            topic.into(),
-            remote_prefix.into(),
-            local_prefix.into(),
-        )?);
-        Ok(())
+    ) -> Result<&mut BridgeRule, InvalidBridgeRule> {
+        let rule = BridgeRule::try_new(topic.into(), remote_prefix.into(), local_prefix.into())?;
+        self.remote_to_local.push(rule);
+        Ok(self.remote_to_local.last_mut().unwrap())
     }
 
     /// Forwards the message in both directions, ensuring that an infinite loop is avoided
     ///
     /// Because this method keeps track of the topic so we don't create an infinite loop of messages
     /// this is not equivalent to calling [forward_from_local] and [forward_from_remote] in sequence.
+    ///
+    /// The two rules, from local to remote and from remote to local, are returned so they can be further configured.
     pub fn forward_bidirectionally(
         &mut self,
         topic: impl Into<Cow<'static, str>>,
         local_prefix: impl Into<Cow<'static, str>>,
         remote_prefix: impl Into<Cow<'static, str>>,
-    ) -> Result<(), InvalidBridgeRule> {
+    ) -> Result<[&mut BridgeRule; 2], InvalidBridgeRule> {
         let topic = topic.into();
         let local_prefix = local_prefix.into();
         let remote_prefix = remote_prefix.into();
@@ -194,7 +429,316 @@ impl BridgeConfig {
         ));
         self.forward_from_local(topic.clone(), local_prefix.clone(), remote_prefix.clone())?;
         self.forward_from_remote(topic, local_prefix, remote_prefix)?;
-        Ok(())
+        Ok([
+            self.local_to_remote.last_mut().unwrap(),
+            self.remote_to_local.last_mut().unwrap(),
+        ])
+    }
+
+    /// Skip forwarding a retained message identical to the last one forwarded on the same topic
+    ///
+    /// On reconnect, the retained messages are re-sent by the broker on re-subscription,
+    /// and would be re-forwarded even though the target already holds the very same retained values.
+    /// When enabled, the bridge remembers the last retained message forwarded on each topic
+    /// and acknowledges identical retained messages without forwarding them.
+    ///
+    /// Default: `false`
+    pub fn deduplicate_retained_messages(&mut self, enabled: bool) {
+        self.deduplicate_retained_messages = enabled;
+    }
+
+    /// Bound the number of topics for which the last forwarded retained message is remembered
+    ///
+    /// This applies to each direction, when [BridgeConfig::deduplicate_retained_messages] is enabled.
+    /// Once the bound reached, the least recently forwarded topic is forgotten,
+    /// its next retained message being then forwarded even if unchanged.
+    ///
+    /// Default: 10000 topics
+    pub fn retained_cache_capacity(&mut self, capacity: NonZeroUsize) {
+        self.retained_cache_capacity = Some(capacity);
+    }
+
+    /// Subscribe to the bridged topics in chunks of `chunk_size` filters, waiting `delay` between chunks
+    ///
+    /// On connect, the broker sends all the retained messages matching a subscription.
+    /// With a large retained tree, subscribing to all the topics at once floods the bridge.
+    /// Subscribing in chunks smooths this initial burst of messages.
+    ///
+    /// Default: subscribe to all the topics at once
+    pub fn subscribe_in_chunks(&mut self, chunk_size: NonZeroUsize, delay: Duration) {
+        self.subscription_chunks = Some(SubscriptionChunks {
+            size: chunk_size,
+            delay,
+        });
+    }
+
+    /// Retry to subscribe to the bridged topics, when the subscription request cannot be sent
+    ///
+    /// The bridge makes at most `max_attempts`, waiting `initial_interval` after the first failure,
+    /// and doubling this interval after each subsequent failure.
+    /// When subscribing in chunks, all the chunks are subscribed again on retry.
+    /// If all attempts fail, or if the broker refuses any of the subscriptions,
+    /// the bridge half is reported down till the next connection.
+    ///
+    /// Default: 5 attempts, waiting 1 second after the first failure
+    pub fn retry_subscriptions(&mut self, max_attempts: NonZeroUsize, initial_interval: Duration) {
+        self.subscription_retry = SubscriptionRetry {
+            max_attempts,
+            initial_interval,
+        };
+    }
+
+    /// Delay the report of connection failures on startup
+    ///
+    /// Till the end of this grace period or till connected, the bridge health status is left unset
+    /// rather than reported down, avoiding spurious alerts while establishing the first connection.
+    ///
+    /// Default: no grace period, a failure to connect is immediately reported as down
+    pub fn health_startup_grace_period(&mut self, grace_period: Duration) {
+        self.health_startup_grace_period = grace_period;
+    }
+
+    /// Name this bridge instance, to tell apart the bridges running on the same device
+    ///
+    /// The name prefixes the names of the two bridge halves in the logs, e.g. `c8y/cloud`,
+    /// and is added to the health status messages, e.g. `{"status":"up","bridge":"c8y"}`.
+    ///
+    /// Default: no name, the bridge halves being simply named `local` and `cloud`
+    pub fn bridge_name(&mut self, name: impl Into<String>) {
+        self.bridge_name = Some(name.into());
+    }
+
+    /// Publish a retained message on the given local topic while both bridge halves are connected
+    ///
+    /// The message, e.g. `{"status":"ready"}` or `{"bridge":"c8y","status":"ready"}` for a named bridge,
+    /// is published once the local and cloud connections are both up,
+    /// and cleared with an empty retained message as soon as either of them is down.
+    /// This gives a single topic to wait on for the components depending on the bridge.
+    ///
+    /// Default: no ready message
+    pub fn ready_topic(&mut self, topic: impl Into<String>) {
+        self.ready_topic = Some(topic.into());
+    }
+
+    /// Transform the local messages before forwarding them to the remote broker
+    ///
+    /// The original messages are acknowledged to the local broker,
+    /// once the transformed messages acknowledged by the remote broker or when dropped by the transformer.
+    ///
+    /// Default: no transformation
+    pub fn transform_local_messages(&mut self, transformer: impl BridgeTransformer + 'static) {
+        self.local_message_transformer = Some(Arc::new(transformer));
+    }
+
+    /// Transform the remote messages before forwarding them to the local broker
+    ///
+    /// The original messages are acknowledged to the remote broker,
+    /// once the transformed messages acknowledged by the local broker or when dropped by the transformer.
+    ///
+    /// Default: no transformation
+    pub fn transform_remote_messages(&mut self, transformer: impl BridgeTransformer + 'static) {
+        self.remote_message_transformer = Some(Arc::new(transformer));
+    }
+
+    /// Stop waiting for the cloud broker to acknowledge a forwarded message after `timeout`
+    ///
+    /// Some cloud brokers accept QoS 1 messages but are slow or unreliable to acknowledge them,
+    /// holding the acknowledgements of the original messages to the local broker.
+    /// When the timeout expires, the message is no more awaited and the `action` is applied:
+    /// either the original message is acknowledged to the local broker anyway,
+    /// or the message is published again on the cloud broker.
+    /// A warning is logged each time this happens.
+    ///
+    /// Default: no timeout, the bridge waiting for the cloud acknowledgements for ever
+    pub fn on_cloud_ack_timeout(&mut self, timeout: Duration, action: AckTimeoutAction) {
+        self.cloud_ack_timeout = Some(AckTimeout { timeout, action });
+    }
+
+    /// Cap the number of forwarded messages waiting for an acknowledgement, in each direction
+    ///
+    /// Once `max_pending` messages are awaiting the target broker acknowledgements,
+    /// the bridge stops forwarding the messages received from the source broker,
+    /// hence stops acknowledging them, till the target catches up with the acknowledgements.
+    /// This applies backpressure on the source rather than keeping an ever-growing set of messages.
+    /// The messages forwarded with [BridgeRule::fast_ack] or with QoS 0 are not counted.
+    ///
+    /// Default: no cap, the bridge forwarding the messages whatever the pending acknowledgements
+    pub fn max_pending_messages(&mut self, max_pending: NonZeroUsize) {
+        self.max_pending_messages = Some(max_pending);
+    }
+
+    /// Forward at most `max_per_second` messages per second on the target topics starting with `prefix`
+    ///
+    /// The limit applies to the topics on which the messages are published by the bridge,
+    /// i.e. after the bridge rules and the transformers applied, and to both directions.
+    /// When several prefixes match a topic, the longest prefix applies.
+    /// Each prefix is given a token bucket, allowing bursts of `max_per_second` messages.
+    ///
+    /// The messages exceeding the limit are delayed, not dropped, hence forwarded and acknowledged in order.
+    /// Meanwhile, as with [BridgeConfig::max_pending_messages], the delayed messages are not acknowledged to the source,
+    /// which stops sending more once its own limit of messages in-flight is reached.
+    /// Setting again the limit of a prefix replaces the previous limit.
+    ///
+    /// Default: no rate limit
+    pub fn with_rate_limit(&mut self, prefix: &str, max_per_second: NonZeroU32) {
+        self.rate_limits.retain(|(limited, _)| limited != prefix);
+        self.rate_limits.push((prefix.to_owned(), max_per_second));
+    }
+
+    /// Drop the messages whose target topic, once rewritten, is longer than `max_length` bytes
+    ///
+    /// The bridge rules and transformers can produce target topics longer than accepted by the target broker.
+    /// Such messages are not forwarded, but logged and acknowledged to their source,
+    /// rather than breaking the target connection.
+    ///
+    /// Default: 65535 bytes, the maximum length of an MQTT topic
+    pub fn max_topic_length(&mut self, max_length: NonZeroUsize) {
+        self.max_topic_length = Some(max_length);
+    }
+
+    /// Add the source topic of each forwarded message as a `field` of its JSON payload
+    ///
+    /// The bridge connections being MQTT 3.1.1, the source topic cannot be attached as an MQTT 5 user property.
+    /// Hence, when the bridge rules rewrite the topics, this field tells the target or a reverse bridge
+    /// on which topic the message was originally published, e.g. `{"temperature": 23, "sourceTopic": "c8y/measurement"}`.
+    ///
+    /// Only the payloads that are JSON objects are updated, and only if the field is not already set,
+    /// so the topic of the very first source is kept when messages go through several bridges.
+    /// The other messages are forwarded unchanged.
+    /// The field is added in both directions, before any [BridgeTransformer] is applied.
+    /// The loop breaker compares the messages as actually forwarded, hence including this field.
+    ///
+    /// Default: the payloads are forwarded unchanged
+    pub fn forward_source_topic_as(&mut self, field: impl Into<String>) {
+        self.source_topic_field = Some(field.into());
+    }
+
+    /// Set the QoS used to subscribe to the local topics forwarded to the remote broker
+    ///
+    /// This is the maximum QoS at which the local broker delivers the messages to the bridge,
+    /// independently of the QoS of the forwarded messages.
+    ///
+    /// Default: [QoS::AtLeastOnce]
+    pub fn local_subscription_qos(&mut self, qos: QoS) {
+        self.local_subscription_qos = Some(qos);
+    }
+
+    /// Set the QoS used to subscribe to the remote topics forwarded to the local broker
+    ///
+    /// This is the maximum QoS at which the remote broker delivers the messages to the bridge,
+    /// e.g. [QoS::AtMostOnce] to reduce the cloud traffic at the cost of delivery guarantees.
+    ///
+    /// Default: [QoS::AtLeastOnce]
+    pub fn remote_subscription_qos(&mut self, qos: QoS) {
+        self.remote_subscription_qos = Some(qos);
+    }
+
+    /// Publish the forwarding rules of this bridge as a retained message on the given local topic
+    ///
+    /// The rules are published on startup, as given by [BridgeConfig::rules_json],
+    /// so the effective bridge configuration can be audited by tools subscribed to this topic.
+    ///
+    /// Default: the rules are not published
+    pub fn publish_rules_on(&mut self, topic: impl Into<String>) {
+        self.rules_topic = Some(topic.into());
+    }
+
+    /// The forwarding rules of this bridge, in each direction, as JSON
+    ///
+    /// Only the topics and the rule flags are described.
+    /// The message transformers and the connection settings are not.
+    ///
+    /// ```
+    /// use tedge_mqtt_bridge::BridgeConfig;
+    ///
+    /// let mut rules = BridgeConfig::new();
+    /// rules.forward_from_local("s/us", "c8y/", "").unwrap();
+    /// assert_eq!(rules.rules_json()["local_to_remote"][0]["topic_filter"], "c8y/s/us");
+    /// ```
+    pub fn rules_json(&self) -> serde_json::Value {
+        let local_to_remote: Vec<_> = self
+            .local_to_remote
+            .iter()
+            .map(|rule| {
+                let bidirectional = self
+                    .bidirectional_topics
+                    .iter()
+                    .any(|(local, _)| *local == rule.topic_filter);
+                rule.to_json(bidirectional)
+            })
+            .collect();
+        let remote_to_local: Vec<_> = self
+            .remote_to_local
+            .iter()
+            .map(|rule| {
+                let bidirectional = self
+                    .bidirectional_topics
+                    .iter()
+                    .any(|(_, remote)| *remote == rule.topic_filter);
+                rule.to_json(bidirectional)
+            })
+            .collect();
+        let mut json = serde_json::json!({
+            "local_to_remote": local_to_remote,
+            "remote_to_local": remote_to_local,
+        });
+        if let Some(name) = &self.bridge_name {
+            json["bridge"] = name.as_str().into();
+        }
+        json
+    }
+
+    /// Check the rules as a whole, returning the issues found, if any
+    ///
+    /// Each rule is checked on its own when added, but some misconfigurations only show up at runtime:
+    /// - a direction without any rule, the bridge then failing to subscribe on the source connection,
+    /// - a rule never applied, because an earlier rule of the same direction matches all its topics
+    ///   and forwards them on different target topics,
+    /// - rules forwarding messages from local to remote and back, not declared with [BridgeConfig::forward_bidirectionally].
+    pub fn validate(&self) -> Vec<BridgeConfigIssue> {
+        let mut issues = vec![];
+
+        for (direction, rules) in [
+            (BridgeDirection::FromLocal, &self.local_to_remote),
+            (BridgeDirection::FromRemote, &self.remote_to_local),
+        ] {
+            if rules.is_empty() {
+                issues.push(BridgeConfigIssue::NoRules(direction));
+            }
+            for (i, rule) in rules.iter().enumerate() {
+                let shadowing_rule = rules[..i].iter().find(|earlier| {
+                    matches_ignore_dollar_prefix(&rule.topic_filter, &earlier.topic_filter)
+                        && earlier.apply(&rule.topic_filter) != rule.apply(&rule.topic_filter)
+                });
+                if let Some(earlier) = shadowing_rule {
+                    issues.push(BridgeConfigIssue::ShadowedRule {
+                        direction,
+                        shadowed: rule.topic_filter.to_string(),
+                        by: earlier.topic_filter.to_string(),
+                    });
+                }
+            }
+        }
+
+        for local in &self.local_to_remote {
+            for remote in &self.remote_to_local {
+                let declared_bidirectional = self
+                    .bidirectional_topics
+                    .iter()
+                    .any(|(l, r)| *l == local.topic_filter && *r == remote.topic_filter);
+                if !declared_bidirectional
+                    && overlap(&local.target_filter(), &remote.topic_filter)
+                    && overlap(&remote.target_filter(), &local.topic_filter)
+                {
+                    issues.push(BridgeConfigIssue::ForwardingLoop {
+                        local: local.topic_filter.to_string(),
+                        remote: remote.topic_filter.to_string(),
+                    });
+                }
+            }
+        }
+
+        issues
     }
 
     pub fn local_subscriptions(&self) -> impl Iterator<Item = &str> {
@@ -207,6 +751,80 @@ impl BridgeConfig {
         self.remote_to_local.iter().map(|rule| &*rule.topic_filter)
     }
 
+    /// The subscriptions to the local and remote brokers, with the QoS configured for each direction
+    pub(super) fn subscription_filters(&self) -> [Vec<SubscribeFilter>; 2] {
+        let local_qos = self.local_subscription_qos.unwrap_or(QoS::AtLeastOnce);
+        let remote_qos = self.remote_subscription_qos.unwrap_or(QoS::AtLeastOnce);
+        [
+            self.local_subscriptions()
+                .map(|topic| SubscribeFilter::new(topic.to_owned(), local_qos))
+                .collect(),
+            self.remote_subscriptions()
+                .map(|topic| SubscribeFilter::new(topic.to_owned(), remote_qos))
+                .collect(),
+        ]
+    }
+
+    pub(super) fn deduplicates_retained_messages(&self) -> bool {
+        self.deduplicate_retained_messages
+    }
+
+    pub(super) fn retained_cache_size(&self) -> Option<NonZeroUsize> {
+        self.retained_cache_capacity
+    }
+
+    pub(super) fn subscription_chunks(&self) -> Option<SubscriptionChunks> {
+        self.subscription_chunks
+    }
+
+    pub(super) fn subscription_retry(&self) -> SubscriptionRetry {
+        self.subscription_retry
+    }
+
+    pub(super) fn startup_grace_period(&self) -> Duration {
+        self.health_startup_grace_period
+    }
+
+    pub(super) fn name(&self) -> Option<&str> {
+        self.bridge_name.as_deref()
+    }
+
+    pub(super) fn ready_topic_name(&self) -> Option<&str> {
+        self.ready_topic.as_deref()
+    }
+
+    pub(super) fn rules_topic_name(&self) -> Option<&str> {
+        self.rules_topic.as_deref()
+    }
+
+    pub(super) fn cloud_ack_timeout(&self) -> Option<AckTimeout> {
+        self.cloud_ack_timeout
+    }
+
+    pub(super) fn max_pending(&self) -> Option<NonZeroUsize> {
+        self.max_pending_messages
+    }
+
+    pub(super) fn max_topic_len(&self) -> usize {
+        self.max_topic_length
+            .map_or(MAX_TOPIC_LENGTH, NonZeroUsize::get)
+    }
+
+    pub(super) fn source_topic_field(&self) -> Option<&str> {
+        self.source_topic_field.as_deref()
+    }
+
+    pub(super) fn rate_limits(&self) -> &[(String, NonZeroU32)] {
+        &self.rate_limits
+    }
+
+    pub(super) fn message_transformers(&self) -> [Option<Arc<dyn BridgeTransformer>>; 2] {
+        [
+            self.local_message_transformer.clone(),
+            self.remote_message_transformer.clone(),
+        ]
+    }
+
     pub(super) fn converters_and_bidirectional_topic_filters(
         self,
     ) -> [(TopicConverter, Vec<Cow<'static, str>>); 2] {
@@ -214,9 +832,25 @@ impl BridgeConfig {
             local_to_remote,
             remote_to_local,
             bidirectional_topics,
+            ..
         } = self;
 
-        let (bidir_local_topics, bidir_remote_topics) = bidirectional_topics.into_iter().unzip();
+        // The messages received on a bidirectional rule are checked for loops, unless disabled on that rule
+        let checked = |rules: &[BridgeRule], filter: &Cow<'static, str>| {
+            rules
+                .iter()
+                .any(|rule| rule.topic_filter == *filter && rule.loop_breaker)
+        };
+        let bidir_local_topics = bidirectional_topics
+            .iter()
+            .map(|(local, _)| local.clone())
+            .filter(|local| checked(&local_to_remote, local))
+            .collect();
+        let bidir_remote_topics = bidirectional_topics
+            .iter()
+            .map(|(_, remote)| remote.clone())
+            .filter(|remote| checked(&remote_to_local, remote))
+            .collect();
         [
             (TopicConverter(local_to_remote), bidir_local_topics),
             (TopicConverter(remote_to_local), bidir_remote_topics),
@@ -439,6 +1073,95 @@ mod tests {
         }
     }
 
+    mod validate {
+        use super::*;
+
+        #[test]
+        fn accepts_a_valid_config() {
+            let mut config = BridgeConfig::new();
+            config.forward_from_local("s/us", "c8y/", "").unwrap();
+            config.forward_from_local("event/#", "c8y/", "").unwrap();
+            config.forward_from_remote("s/ds", "c8y/", "").unwrap();
+            config
+                .forward_bidirectionally("inventory/#", "c8y/", "")
+                .unwrap();
+
+            assert_eq!(config.validate(), vec![]);
+        }
+
+        #[test]
+        fn reports_a_direction_without_rules() {
+            let mut config = BridgeConfig::new();
+            config.forward_from_local("s/us", "c8y/", "").unwrap();
+
+            assert_eq!(
+                config.validate(),
+                vec![BridgeConfigIssue::NoRules(BridgeDirection::FromRemote)]
+            );
+        }
+
+        #[test]
+        fn reports_a_rule_shadowed_by_a_rule_mapping_topics_differently() {
+            let mut config = BridgeConfig::new();
+            config.forward_from_local("event/#", "c8y/", "").unwrap();
+            config
+                .forward_from_local("event/alarm", "c8y/", "other/")
+                .unwrap();
+            config.forward_from_remote("s/ds", "c8y/", "").unwrap();
+
+            assert_eq!(
+                config.validate(),
+                vec![BridgeConfigIssue::ShadowedRule {
+                    direction: BridgeDirection::FromLocal,
+                    shadowed: "c8y/event/alarm".to_string(),
+                    by: "c8y/event/#".to_string(),
+                }]
+            );
+        }
+
+        #[test]
+        fn accepts_a_rule_shadowed_by_an_equivalent_rule() {
+            let mut config = BridgeConfig::new();
+            config.forward_from_local("event/#", "c8y/", "").unwrap();
+            config
+                .forward_from_local("event/alarm", "c8y/", "")
+                .unwrap();
+            config.forward_from_remote("s/ds", "c8y/", "").unwrap();
+
+            assert_eq!(config.validate(), vec![]);
+        }
+
+        #[test]
+        fn reports_rules_forwarding_messages_back_and_forth() {
+            let mut config = BridgeConfig::new();
+            config.forward_from_local("a/#", "", "").unwrap();
+            config.forward_from_remote("a/b", "", "").unwrap();
+
+            assert_eq!(
+                config.validate(),
+                vec![BridgeConfigIssue::ForwardingLoop {
+                    local: "a/#".to_string(),
+                    remote: "a/b".to_string(),
+                }]
+            );
+        }
+
+        #[test]
+        fn reports_prefixed_rules_forwarding_messages_back_and_forth() {
+            let mut config = BridgeConfig::new();
+            config.forward_from_local("s/#", "c8y/", "").unwrap();
+            config.forward_from_remote("s/#", "c8y/", "").unwrap();
+
+            assert_eq!(
+                config.validate(),
+                vec![BridgeConfigIssue::ForwardingLoop {
+                    local: "c8y/s/#".to_string(),
+                    remote: "s/#".to_string(),
+                }]
+            );
+        }
+    }
+
     mod validate_filter {
         use crate::config::validate_filter;
 
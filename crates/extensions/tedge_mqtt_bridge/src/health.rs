@@ -1,5 +1,6 @@
 use crate::overall_status;
 use crate::BridgeAsyncClient;
+use crate::BridgeDiagnostics;
 use crate::BridgeMessageSender;
 use crate::Status;
 use futures::channel::mpsc;
@@ -10,7 +11,14 @@ use rumqttc::Event;
 use rumqttc::Incoming;
 use rumqttc::Publish;
 use rumqttc::QoS;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value as JsonValue;
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
 use tracing::error;
 use tracing::log::info;
 
@@ -18,41 +26,77 @@ use tracing::log::info;
 ///
 /// When [Self::monitor] runs, this will watch the status of the bridge halves, and notify the
 /// relevant MQTT topic about the overall health.
+/// The health is `unknown` till both halves have reported their status, unless either is down.
+/// If a ready topic is set, a retained message is also published on this topic
+/// while both halves are up, and cleared as soon as either of them is down.
+/// If diagnostics are set, the messages still waiting for an acknowledgement are logged
+/// each time the bridge goes down.
 pub struct BridgeHealthMonitor {
     topic: String,
-    rx_status: mpsc::Receiver<(&'static str, Status)>,
+    ready_topic: Option<String>,
+    diagnostics: Option<BridgeDiagnostics>,
+    bridge_name: Option<String>,
+    rx_status: mpsc::Receiver<(&'static str, HalfBridgeHealth)>,
     companion_bridge_half: BridgeMessageSender,
 }
 
 impl BridgeHealthMonitor {
     pub(crate) fn new(
         topic: String,
+        bridge_name: Option<String>,
         bridge_half: &BridgeAsyncClient,
-    ) -> (mpsc::Sender<(&'static str, Status)>, Self) {
+    ) -> (mpsc::Sender<(&'static str, HalfBridgeHealth)>, Self) {
         let (tx, rx_status) = mpsc::channel(10);
         (
             tx,
             BridgeHealthMonitor {
                 topic,
+                ready_topic: None,
+                diagnostics: None,
+                bridge_name,
                 rx_status,
                 companion_bridge_half: bridge_half.clone_sender(),
             },
         )
     }
 
+    /// Publish a ready message on the given topic while both bridge halves are up
+    pub(crate) fn with_ready_topic(mut self, ready_topic: Option<String>) -> Self {
+        self.ready_topic = ready_topic;
+        self
+    }
+
+    /// Log the messages waiting for an acknowledgement, each time the bridge goes down
+    pub(crate) fn with_diagnostics(mut self, diagnostics: BridgeDiagnostics) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
     pub async fn monitor(mut self) -> ! {
-        let mut statuses = HashMap::from([("local", None), ("cloud", None)]);
+        let mut healths = HashMap::from([("local", None), ("cloud", None)]);
+        let mut last_payload = None;
         let mut last_status = None;
+        let mut ready = false;
         loop {
-            let (name, status) = self.rx_status.next().await.unwrap();
-            *statuses.entry(name).or_insert(Some(status)) = Some(status);
+            let status = healths
+                .values()
+                .map(|health: &Option<HalfBridgeHealth>| {
+                    health.map_or(Status::Unknown, |health| health.status)
+                })
+                .fold(Status::Up, overall_status);
 
-            let status = statuses.values().fold(Some(Status::Up), overall_status);
-            if last_status != status {
-                last_status = status;
+            if status == Status::Down && last_status != Some(Status::Down) {
+                if let Some(diagnostics) = &self.diagnostics {
+                    diagnostics.dump();
+                }
+            }
+            last_status = Some(status);
 
-                let mut health_msg =
-                    Publish::new(&self.topic, QoS::AtLeastOnce, status.unwrap().json());
+            let payload = health_payload(status, self.bridge_name.as_deref(), &healths);
+            if last_payload.as_ref() != Some(&payload) {
+                last_payload = Some(payload.clone());
+
+                let mut health_msg = Publish::new(&self.topic, QoS::AtLeastOnce, payload);
                 health_msg.retain = true;
 
                 // Publish the health message over MQTT, but with no duplicate for the companion
@@ -61,49 +105,545 @@ impl BridgeHealthMonitor {
                     .internal_publish(health_msg)
                     .await;
             }
+
+            if ready != (status == Status::Up) {
+                ready = status == Status::Up;
+                if let Some(ready_topic) = &self.ready_topic {
+                    let payload = if ready {
+                        ready_payload(self.bridge_name.as_deref())
+                    } else {
+                        String::new()
+                    };
+                    let mut ready_msg = Publish::new(ready_topic, QoS::AtLeastOnce, payload);
+                    ready_msg.retain = true;
+                    self.companion_bridge_half.internal_publish(ready_msg).await;
+                }
+            }
+
+            let (name, health) = self.rx_status.next().await.unwrap();
+            healths.insert(name, Some(health));
         }
     }
 }
 
+/// Build the ready message payload, including the bridge name if any,
+/// e.g. `{"bridge":"c8y","status":"ready"}`
+fn ready_payload(bridge_name: Option<&str>) -> String {
+    let mut payload = json!({ "status": "ready" });
+    if let Some(bridge_name) = bridge_name {
+        payload["bridge"] = bridge_name.into();
+    }
+    payload.to_string()
+}
+
+/// Build the health message payload, including the bridge name if any and the `session_present`
+/// flag of the most recent `ConnAck` received by each bridge half, e.g.
+/// `{"bridge":"c8y","session_present":{"cloud":false,"local":true},"status":"up"}`
+fn health_payload(
+    status: Status,
+    bridge_name: Option<&str>,
+    healths: &HashMap<&str, Option<HalfBridgeHealth>>,
+) -> String {
+    let sessions: Map<String, JsonValue> = ["local", "cloud"]
+        .into_iter()
+        .filter_map(|name| {
+            let session_present = healths.get(name).copied().flatten()?.session_present?;
+            Some((name.to_string(), session_present.into()))
+        })
+        .collect();
+
+    let mut payload = json!({ "status": status.as_str() });
+    if let Some(bridge_name) = bridge_name {
+        payload["bridge"] = bridge_name.into();
+    }
+    if !sessions.is_empty() {
+        payload["session_present"] = sessions.into();
+    }
+    payload.to_string()
+}
+
+/// The health of a bridge half, as notified to the [BridgeHealthMonitor]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct HalfBridgeHealth {
+    pub status: Status,
+    /// Whether the broker resumed the session on the most recent connection, if any
+    pub session_present: Option<bool>,
+}
+
 type NotificationRes = Result<Event, ConnectionError>;
 
 /// A client for [BridgeHealthMonitor]
 ///
 /// This is used by each bridge half to log and notify the monitor of health status updates
+///
+/// Till the end of the startup grace period, or till connected for the first time,
+/// connection errors are not reported, so the health status remains unknown rather than down.
+///
+/// A failure to subscribe, as reported by a [SubscriptionHealth], marks the bridge half down
+/// till the next connection, when the bridge subscribes again.
 pub struct BridgeHealth {
     name: &'static str,
-    tx_health: mpsc::Sender<(&'static str, Status)>,
+    log_name: String,
+    tx_health: mpsc::Sender<(&'static str, HalfBridgeHealth)>,
     last_err: Option<String>,
+    last_status: Option<Status>,
+    session_present: Option<bool>,
+    startup_deadline: Option<Instant>,
+    subscription_error: Arc<Mutex<Option<String>>>,
 }
 
 impl BridgeHealth {
-    pub(crate) fn new(name: &'static str, tx_health: mpsc::Sender<(&'static str, Status)>) -> Self {
+    pub(crate) fn new(
+        name: &'static str,
+        tx_health: mpsc::Sender<(&'static str, HalfBridgeHealth)>,
+        startup_grace_period: Duration,
+    ) -> Self {
+        let startup_deadline =
+            (!startup_grace_period.is_zero()).then(|| Instant::now() + startup_grace_period);
         Self {
             name,
+            log_name: name.to_string(),
             tx_health,
             last_err: Some("dummy error".into()),
+            last_status: None,
+            session_present: None,
+            startup_deadline,
+            subscription_error: Arc::default(),
         }
     }
 
+    /// Return a handle to report the failure of the subscriptions sent on the current connection
+    pub(crate) fn subscription_health(&self) -> SubscriptionHealth {
+        SubscriptionHealth {
+            name: self.name,
+            log_name: self.log_name.clone(),
+            tx_health: self.tx_health.clone(),
+            session_present: self.session_present,
+            subscription_error: self.subscription_error.clone(),
+        }
+    }
+
+    /// Use a distinct name for this bridge half in the logs, e.g. prefixed by the bridge name
+    pub(crate) fn with_log_name(mut self, log_name: String) -> Self {
+        self.log_name = log_name;
+        self
+    }
+
+    fn within_startup_grace_period(&self) -> bool {
+        self.startup_deadline
+            .is_some_and(|deadline| Instant::now() < deadline)
+    }
+
     pub async fn update(&mut self, result: &NotificationRes) {
         let name = self.name;
+        let log_name = &self.log_name;
+        let mut session_present = self.session_present;
         let err = match result {
             Ok(event) => {
-                if let Event::Incoming(Incoming::ConnAck(_)) = event {
-                    info!("MQTT bridge connected to {name} broker")
+                if let Event::Incoming(Incoming::ConnAck(ack)) = event {
+                    info!(
+                        "MQTT bridge connected to {log_name} broker (session present: {})",
+                        ack.session_present
+                    );
+                    session_present = Some(ack.session_present);
+                    // The bridge subscribes again on each new connection
+                    self.subscription_error.lock().unwrap().take();
                 }
                 None
             }
             Err(err) => Some(err.to_string()),
         };
 
-        if self.last_err != err {
+        match &err {
+            Some(err) if self.within_startup_grace_period() => {
+                info!("MQTT bridge not connected yet to {log_name} broker: {err}");
+                return;
+            }
+            Some(_) => {}
+            None => self.startup_deadline = None,
+        }
+
+        let subscription_failed = self.subscription_error.lock().unwrap().is_some();
+        let status = if err.is_some() || subscription_failed {
+            Status::Down
+        } else {
+            Status::Up
+        };
+        if self.last_err != err
+            || self.session_present != session_present
+            || self.last_status != Some(status)
+        {
             if let Some(err) = &err {
-                error!("MQTT bridge failed to connect to {name} broker: {err}")
+                error!("MQTT bridge failed to connect to {log_name} broker: {err}")
             }
             self.last_err = err;
-            let status = self.last_err.as_ref().map_or(Status::Up, |_| Status::Down);
-            self.tx_health.send((name, status)).await.unwrap()
+            self.last_status = Some(status);
+            self.session_present = session_present;
+            let health = HalfBridgeHealth {
+                status,
+                session_present,
+            };
+            self.tx_health.send((name, health)).await.unwrap()
+        }
+    }
+}
+
+/// A handle to report the failure of a bridge half to subscribe to its topics
+///
+/// The bridge half is then marked down, till the next connection.
+pub(crate) struct SubscriptionHealth {
+    name: &'static str,
+    log_name: String,
+    tx_health: mpsc::Sender<(&'static str, HalfBridgeHealth)>,
+    session_present: Option<bool>,
+    subscription_error: Arc<Mutex<Option<String>>>,
+}
+
+impl SubscriptionHealth {
+    pub async fn failed(mut self, reason: String) {
+        let log_name = &self.log_name;
+        error!("MQTT bridge failed to subscribe on {log_name} broker: {reason}");
+        *self.subscription_error.lock().unwrap() = Some(reason);
+        let health = HalfBridgeHealth {
+            status: Status::Down,
+            session_present: self.session_present,
+        };
+        // Errors are ignored: the monitor is gone only if the bridge is stopped
+        let _ = self.tx_health.send((self.name, health)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BridgeMessage;
+    use rumqttc::ConnAck;
+    use rumqttc::ConnectReturnCode;
+
+    fn conn_ack(session_present: bool) -> Event {
+        Event::Incoming(Incoming::ConnAck(ConnAck {
+            session_present,
+            code: ConnectReturnCode::Success,
+        }))
+    }
+
+    #[tokio::test]
+    async fn reports_the_session_present_flag_of_each_conn_ack() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut health = BridgeHealth::new("cloud", tx, Duration::ZERO);
+
+        health.update(&Ok(conn_ack(true))).await;
+        assert_eq!(
+            rx.next().await.unwrap(),
+            (
+                "cloud",
+                HalfBridgeHealth {
+                    status: Status::Up,
+                    session_present: Some(true)
+                }
+            )
+        );
+
+        health.update(&Err(ConnectionError::RequestsDone)).await;
+        assert_eq!(
+            rx.next().await.unwrap(),
+            (
+                "cloud",
+                HalfBridgeHealth {
+                    status: Status::Down,
+                    session_present: Some(true)
+                }
+            )
+        );
+
+        health.update(&Ok(conn_ack(false))).await;
+        assert_eq!(
+            rx.next().await.unwrap(),
+            (
+                "cloud",
+                HalfBridgeHealth {
+                    status: Status::Up,
+                    session_present: Some(false)
+                }
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn connection_errors_are_reported_immediately_without_grace_period() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut health = BridgeHealth::new("cloud", tx, Duration::ZERO);
+
+        health.update(&Err(ConnectionError::RequestsDone)).await;
+        assert_eq!(rx.try_next().unwrap().unwrap().1.status, Status::Down);
+    }
+
+    #[tokio::test]
+    async fn connecting_within_the_startup_grace_period_reports_up() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut health = BridgeHealth::new("cloud", tx, Duration::from_secs(60));
+
+        // The health status remains unset while within the grace period
+        health.update(&Err(ConnectionError::RequestsDone)).await;
+        assert!(rx.try_next().is_err(), "no status should be reported");
+
+        health.update(&Ok(conn_ack(false))).await;
+        assert_eq!(rx.try_next().unwrap().unwrap().1.status, Status::Up);
+
+        // Once connected, the grace period is over and errors are reported
+        health.update(&Err(ConnectionError::RequestsDone)).await;
+        assert_eq!(rx.try_next().unwrap().unwrap().1.status, Status::Down);
+    }
+
+    #[tokio::test]
+    async fn failing_past_the_startup_grace_period_reports_down() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let grace_period = Duration::from_millis(100);
+        let mut health = BridgeHealth::new("cloud", tx, grace_period);
+
+        health.update(&Err(ConnectionError::RequestsDone)).await;
+        assert!(rx.try_next().is_err(), "no status should be reported");
+
+        tokio::time::sleep(grace_period).await;
+        health.update(&Err(ConnectionError::RequestsDone)).await;
+        assert_eq!(rx.try_next().unwrap().unwrap().1.status, Status::Down);
+    }
+
+    #[tokio::test]
+    async fn a_subscription_failure_is_reported_down_till_reconnected() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut health = BridgeHealth::new("cloud", tx, Duration::ZERO);
+
+        health.update(&Ok(conn_ack(false))).await;
+        assert_eq!(rx.try_next().unwrap().unwrap().1.status, Status::Up);
+
+        health
+            .subscription_health()
+            .failed("the broker refused c8y/#".to_string())
+            .await;
+        assert_eq!(rx.try_next().unwrap().unwrap().1.status, Status::Down);
+
+        // The bridge half remains down, even if the connection itself is fine
+        for _ in 0..2 {
+            health
+                .update(&Ok(Event::Outgoing(rumqttc::Outgoing::PingReq)))
+                .await;
         }
+        while let Ok(Some((_, reported))) = rx.try_next() {
+            assert_eq!(reported.status, Status::Down);
+        }
+
+        // Till reconnected
+        health.update(&Err(ConnectionError::RequestsDone)).await;
+        assert_eq!(rx.try_next().unwrap().unwrap().1.status, Status::Down);
+        health.update(&Ok(conn_ack(false))).await;
+        assert_eq!(rx.try_next().unwrap().unwrap().1.status, Status::Up);
+    }
+
+    async fn notify(
+        tx_status: &mut mpsc::Sender<(&'static str, HalfBridgeHealth)>,
+        name: &'static str,
+        status: Status,
+    ) {
+        let health = HalfBridgeHealth {
+            status,
+            session_present: None,
+        };
+        tx_status.send((name, health)).await.unwrap()
+    }
+
+    async fn next_message(published: &mut mpsc::UnboundedReceiver<BridgeMessage>) -> Publish {
+        match tokio::time::timeout(Duration::from_secs(1), published.next()).await {
+            Ok(Some(BridgeMessage::Pub { publish })) => publish,
+            Ok(_) => panic!("unexpected bridge message"),
+            Err(_elapsed) => panic!("no message published"),
+        }
+    }
+
+    #[tokio::test]
+    async fn ready_message_is_published_while_both_halves_are_up() {
+        let (mut tx_status, rx_status) = mpsc::channel(10);
+        let (unbounded_tx, mut published) = mpsc::unbounded();
+        let monitor = BridgeHealthMonitor {
+            topic: "te/device/main/service/bridge/status/health".into(),
+            ready_topic: Some("te/device/main/service/bridge/status/ready".into()),
+            diagnostics: None,
+            bridge_name: None,
+            rx_status,
+            companion_bridge_half: BridgeMessageSender { unbounded_tx },
+        };
+        tokio::spawn(monitor.monitor());
+        let health = next_message(&mut published).await;
+        assert_eq!(health.payload, r#"{"status":"unknown"}"#);
+
+        // Only the health message is published till both halves are up
+        notify(&mut tx_status, "local", Status::Up).await;
+        notify(&mut tx_status, "cloud", Status::Down).await;
+        let health = next_message(&mut published).await;
+        assert!(health.topic.ends_with("/health"));
+        assert_eq!(health.payload, r#"{"status":"down"}"#);
+
+        notify(&mut tx_status, "cloud", Status::Up).await;
+        let health = next_message(&mut published).await;
+        assert_eq!(health.payload, r#"{"status":"up"}"#);
+        let ready = next_message(&mut published).await;
+        assert_eq!(ready.topic, "te/device/main/service/bridge/status/ready");
+        assert_eq!(ready.payload, r#"{"status":"ready"}"#);
+        assert!(ready.retain);
+
+        // The ready message is cleared as soon as either half is down
+        notify(&mut tx_status, "local", Status::Down).await;
+        let health = next_message(&mut published).await;
+        assert_eq!(health.payload, r#"{"status":"down"}"#);
+        let ready = next_message(&mut published).await;
+        assert_eq!(ready.topic, "te/device/main/service/bridge/status/ready");
+        assert!(ready.payload.is_empty());
+        assert!(ready.retain);
+
+        // and republished once both are up again
+        notify(&mut tx_status, "local", Status::Up).await;
+        let health = next_message(&mut published).await;
+        assert_eq!(health.payload, r#"{"status":"up"}"#);
+        let ready = next_message(&mut published).await;
+        assert_eq!(ready.payload, r#"{"status":"ready"}"#);
+    }
+
+    #[tokio::test]
+    async fn health_is_unknown_till_both_halves_report_their_status() {
+        let (mut tx_status, rx_status) = mpsc::channel(10);
+        let (unbounded_tx, mut published) = mpsc::unbounded();
+        let monitor = BridgeHealthMonitor {
+            topic: "te/device/main/service/bridge/status/health".into(),
+            ready_topic: None,
+            diagnostics: None,
+            bridge_name: None,
+            rx_status,
+            companion_bridge_half: BridgeMessageSender { unbounded_tx },
+        };
+        tokio::spawn(monitor.monitor());
+
+        // The unknown status is published before any connection attempt completes
+        let health = next_message(&mut published).await;
+        assert_eq!(health.payload, r#"{"status":"unknown"}"#);
+        assert!(health.retain);
+
+        // and remains unknown till the second half reports
+        notify(&mut tx_status, "local", Status::Up).await;
+        notify(&mut tx_status, "cloud", Status::Up).await;
+        let health = next_message(&mut published).await;
+        assert_eq!(health.payload, r#"{"status":"up"}"#);
+
+        notify(&mut tx_status, "cloud", Status::Down).await;
+        let health = next_message(&mut published).await;
+        assert_eq!(health.payload, r#"{"status":"down"}"#);
+    }
+
+    #[tokio::test]
+    async fn a_half_down_takes_precedence_over_an_unknown_half() {
+        let (mut tx_status, rx_status) = mpsc::channel(10);
+        let (unbounded_tx, mut published) = mpsc::unbounded();
+        let monitor = BridgeHealthMonitor {
+            topic: "te/device/main/service/bridge/status/health".into(),
+            ready_topic: None,
+            diagnostics: None,
+            bridge_name: None,
+            rx_status,
+            companion_bridge_half: BridgeMessageSender { unbounded_tx },
+        };
+        tokio::spawn(monitor.monitor());
+        let health = next_message(&mut published).await;
+        assert_eq!(health.payload, r#"{"status":"unknown"}"#);
+
+        notify(&mut tx_status, "local", Status::Down).await;
+        let health = next_message(&mut published).await;
+        assert_eq!(health.payload, r#"{"status":"down"}"#);
+    }
+
+    #[test]
+    fn overall_status_is_down_if_any_is_down_else_unknown_if_any_is_unknown() {
+        use Status::*;
+        assert_eq!(overall_status(Up, Up), Up);
+        assert_eq!(overall_status(Up, Unknown), Unknown);
+        assert_eq!(overall_status(Unknown, Up), Unknown);
+        assert_eq!(overall_status(Unknown, Unknown), Unknown);
+        assert_eq!(overall_status(Unknown, Down), Down);
+        assert_eq!(overall_status(Down, Unknown), Down);
+        assert_eq!(overall_status(Up, Down), Down);
+        assert_eq!(overall_status(Down, Down), Down);
+    }
+
+    #[test]
+    fn ready_payload_includes_the_bridge_name() {
+        assert_eq!(ready_payload(None), r#"{"status":"ready"}"#);
+        assert_eq!(
+            ready_payload(Some("c8y")),
+            r#"{"bridge":"c8y","status":"ready"}"#
+        );
+    }
+
+    #[test]
+    fn ready_payload_escapes_the_bridge_name() {
+        let bridge_name = "a \"quoted\" name\\with\u{1b}escapes";
+        let payload: JsonValue = serde_json::from_str(&ready_payload(Some(bridge_name))).unwrap();
+        assert_eq!(payload, json!({ "status": "ready", "bridge": bridge_name }));
+    }
+
+    #[test]
+    fn health_payload_includes_the_session_present_flags() {
+        let up = |session_present| {
+            Some(HalfBridgeHealth {
+                status: Status::Up,
+                session_present,
+            })
+        };
+
+        let healths = HashMap::from([("local", up(None)), ("cloud", up(None))]);
+        assert_eq!(
+            health_payload(Status::Up, None, &healths),
+            r#"{"status":"up"}"#
+        );
+
+        let healths = HashMap::from([("local", up(Some(true))), ("cloud", up(Some(false)))]);
+        assert_eq!(
+            health_payload(Status::Up, None, &healths),
+            r#"{"session_present":{"cloud":false,"local":true},"status":"up"}"#
+        );
+
+        let healths = HashMap::from([("local", up(Some(true))), ("cloud", None)]);
+        assert_eq!(
+            health_payload(Status::Down, None, &healths),
+            r#"{"session_present":{"local":true},"status":"down"}"#
+        );
+    }
+
+    #[test]
+    fn health_payload_includes_the_bridge_name() {
+        let up = |session_present| {
+            Some(HalfBridgeHealth {
+                status: Status::Up,
+                session_present,
+            })
+        };
+
+        let healths = HashMap::from([("local", up(None)), ("cloud", up(None))]);
+        assert_eq!(
+            health_payload(Status::Up, Some("c8y"), &healths),
+            r#"{"bridge":"c8y","status":"up"}"#
+        );
+
+        let healths = HashMap::from([("local", up(Some(true))), ("cloud", up(Some(false)))]);
+        assert_eq!(
+            health_payload(Status::Up, Some("c8y"), &healths),
+            r#"{"bridge":"c8y","session_present":{"cloud":false,"local":true},"status":"up"}"#
+        );
+
+        // The bridge name is escaped as a JSON string
+        let bridge_name = "a \"quoted\" name\\with\u{1b}escapes";
+        let payload = health_payload(Status::Up, Some(bridge_name), &healths);
+        let payload: JsonValue = serde_json::from_str(&payload).unwrap();
+        assert_eq!(
+            payload,
+            json!({ "status": "up", "bridge": bridge_name, "session_present": { "local": true, "cloud": false } })
+        );
     }
 }
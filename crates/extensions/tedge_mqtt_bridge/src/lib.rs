@@ -1,6 +1,7 @@
 mod backoff;
 mod config;
 mod health;
+mod rate_limit;
 mod topics;
 
 use async_trait::async_trait;
@@ -20,15 +21,20 @@ use rumqttc::PubAck;
 use rumqttc::PubRec;
 use rumqttc::Publish;
 use rumqttc::SubscribeFilter;
+use rumqttc::SubscribeReasonCode;
 use rumqttc::Transport;
 use std::borrow::Cow;
-use std::collections::hash_map;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::convert::Infallible;
+use std::num::NonZeroUsize;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
 use std::time::Duration;
 use std::time::Instant;
 use tedge_actors::futures::channel::mpsc;
@@ -39,13 +45,16 @@ use tedge_actors::NullSender;
 use tedge_actors::RuntimeError;
 use tedge_actors::RuntimeRequest;
 use tedge_actors::RuntimeRequestSink;
+use tokio::sync::Semaphore;
 use tracing::debug;
 use tracing::info;
+use tracing::warn;
 
 pub type MqttConfig = mqtt_channel::Config;
 
 use crate::health::BridgeHealth;
 use crate::health::BridgeHealthMonitor;
+use crate::rate_limit::RateLimiter;
 pub use mqtt_channel::DebugPayload;
 pub use mqtt_channel::MqttError;
 pub use mqtt_channel::MqttMessage;
@@ -53,16 +62,18 @@ pub use mqtt_channel::QoS;
 pub use mqtt_channel::Topic;
 use tedge_config::MqttAuthConfig;
 use tedge_config::TEdgeConfig;
-use tedge_config::TEdgeConfigReaderMqttBridgeReconnectPolicy;
 
 use crate::backoff::CustomBackoff;
+use crate::backoff::ReconnectPolicy;
 use crate::topics::matches_ignore_dollar_prefix;
 use crate::topics::TopicConverter;
 pub use config::*;
 
 const MAX_PACKET_SIZE: usize = 268435455; // maximum allowed MQTT payload size
 
-pub struct MqttBridgeActorBuilder {}
+pub struct MqttBridgeActorBuilder {
+    diagnostics: BridgeDiagnostics,
+}
 
 impl MqttBridgeActorBuilder {
     pub async fn new(
@@ -105,7 +116,8 @@ impl MqttBridgeActorBuilder {
         ));
         local_config.set_clean_session(false);
 
-        let reconnect_policy = tedge_config.mqtt.bridge.reconnect_policy.clone();
+        let [local_reconnect_policy, cloud_reconnect_policy] =
+            ReconnectPolicy::local_and_cloud(tedge_config);
 
         cloud_config.set_manual_acks(true);
         cloud_config.set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE);
@@ -124,21 +136,90 @@ impl MqttBridgeActorBuilder {
         let (local_client, local_event_loop) = AsyncClient::new(local_config, in_flight.into());
         let (cloud_client, cloud_event_loop) = AsyncClient::new(cloud_config, in_flight.into());
 
-        let local_topics: Vec<_> = rules
-            .local_subscriptions()
-            .map(|t| SubscribeFilter::new(t.to_owned(), QoS::AtLeastOnce))
-            .collect();
-        let cloud_topics: Vec<_> = rules
-            .remote_subscriptions()
-            .map(|t| SubscribeFilter::new(t.to_owned(), QoS::AtLeastOnce))
-            .collect();
+        for issue in rules.validate() {
+            warn!("Invalid bridge configuration: {issue}");
+        }
 
-        let [cloud_target, local_target] =
-            bidirectional_channel(cloud_client.clone(), local_client.clone(), in_flight.into());
+        let [local_topics, cloud_topics] = rules.subscription_filters();
+
+        let retained_cache_counters = [
+            RetainedCacheCounters::default(),
+            RetainedCacheCounters::default(),
+        ];
+        let retained_caches = rules.deduplicates_retained_messages().then(|| {
+            let capacity = rules
+                .retained_cache_size()
+                .unwrap_or(DEFAULT_RETAINED_CACHE_CAPACITY);
+            retained_cache_counters
+                .clone()
+                .map(|counters| RetainedMessageCache::new(capacity, counters))
+        });
+        let [local_retained_cache, cloud_retained_cache] = match retained_caches {
+            Some([local, cloud]) => [Some(local), Some(cloud)],
+            None => [None, None],
+        };
+        let subscription_chunks = rules.subscription_chunks();
+        let subscription_retry = rules.subscription_retry();
+        let startup_grace_period = rules.startup_grace_period();
+        let bridge_name = rules.name().map(str::to_owned);
+        let ready_topic = rules.ready_topic_name().map(str::to_owned);
+        let [transform_local, transform_cloud] = rules.message_transformers();
+        let rules_message = rules.rules_topic_name().map(|topic| {
+            let mut message = Publish::new(topic, QoS::AtLeastOnce, rules.rules_json().to_string());
+            message.retain = true;
+            message
+        });
+        let cloud_ack_timeout = rules.cloud_ack_timeout();
+        let max_pending = rules.max_pending();
+        let max_topic_length = rules.max_topic_len();
+        let source_topic_field = rules.source_topic_field().map(str::to_owned);
+        let local_rate_limiter = RateLimiter::new(rules.rate_limits());
+        let cloud_rate_limiter = RateLimiter::new(rules.rate_limits());
+        let local_name = half_bridge_name(bridge_name.as_deref(), "local");
+        let cloud_name = half_bridge_name(bridge_name.as_deref(), "cloud");
+        let [cloud_target, local_target] = bidirectional_channel(
+            cloud_client.clone(),
+            local_client.clone(),
+            in_flight.into(),
+            max_pending,
+        );
+        let cloud_ack_timeout = cloud_ack_timeout.map(|config| AckTimeoutHandler {
+            config,
+            republisher: cloud_target.clone_sender(),
+        });
         let [(convert_local, bidir_local), (convert_cloud, bidir_cloud)] =
             rules.converters_and_bidirectional_topic_filters();
+        let local_pending: Arc<Mutex<PendingAcks>> = Arc::default();
+        let cloud_pending: Arc<Mutex<PendingAcks>> = Arc::default();
+        let local_counters = HalfBridgeCounters::new(&cloud_target);
+        let cloud_counters = HalfBridgeCounters::new(&local_target);
+        let diagnostics = BridgeDiagnostics {
+            connections: vec![
+                (
+                    local_name.clone(),
+                    Arc::downgrade(&local_pending),
+                    local_counters.clone(),
+                ),
+                (
+                    cloud_name.clone(),
+                    Arc::downgrade(&cloud_pending),
+                    cloud_counters.clone(),
+                ),
+            ],
+            retained_caches: retained_cache_counters.to_vec(),
+        };
         let (tx_status, monitor) =
-            BridgeHealthMonitor::new(health_topic.name.clone(), &local_target);
+            BridgeHealthMonitor::new(health_topic.name.clone(), bridge_name, &local_target);
+        let monitor = monitor
+            .with_ready_topic(ready_topic)
+            .with_diagnostics(diagnostics.clone());
+        if let Some(rules_message) = rules_message {
+            // Published as a message generated by the bridge, as not to be acknowledged
+            local_target
+                .clone_sender()
+                .internal_publish(rules_message)
+                .await;
+        }
         tokio::spawn(monitor.monitor());
         tokio::spawn(half_bridge(
             local_event_loop,
@@ -146,10 +227,21 @@ impl MqttBridgeActorBuilder {
             cloud_target,
             convert_local,
             bidir_local,
-            tx_status.clone(),
-            "local",
+            BridgeHealth::new("local", tx_status.clone(), startup_grace_period)
+                .with_log_name(local_name.clone()),
+            local_name,
             local_topics,
-            reconnect_policy.clone(),
+            local_reconnect_policy,
+            local_retained_cache,
+            subscription_chunks,
+            subscription_retry,
+            source_topic_field.clone(),
+            transform_local,
+            local_rate_limiter,
+            max_topic_length,
+            None,
+            local_pending,
+            local_counters,
         ));
         tokio::spawn(half_bridge(
             cloud_event_loop,
@@ -157,13 +249,30 @@ impl MqttBridgeActorBuilder {
             local_target,
             convert_cloud,
             bidir_cloud,
-            tx_status.clone(),
-            "cloud",
+            BridgeHealth::new("cloud", tx_status.clone(), startup_grace_period)
+                .with_log_name(cloud_name.clone()),
+            cloud_name,
             cloud_topics,
-            reconnect_policy,
+            cloud_reconnect_policy,
+            cloud_retained_cache,
+            subscription_chunks,
+            subscription_retry,
+            source_topic_field,
+            transform_cloud,
+            cloud_rate_limiter,
+            max_topic_length,
+            cloud_ack_timeout,
+            cloud_pending,
+            cloud_counters,
         ));
 
-        Self {}
+        Self { diagnostics }
+    }
+
+    /// A handle to dump the messages forwarded by this bridge and still waiting for an acknowledgement,
+    /// and to poll the message counters of the bridge
+    pub fn diagnostics(&self) -> BridgeDiagnostics {
+        self.diagnostics.clone()
     }
 
     pub(crate) fn build_actor(self) -> MqttBridgeActor {
@@ -171,26 +280,72 @@ impl MqttBridgeActorBuilder {
     }
 }
 
+/// Add the source topic to a JSON object payload, unless the field is already set
+///
+/// Return `None` if the payload has to be forwarded unchanged, see [BridgeConfig::forward_source_topic_as].
+///
+/// The field is appended to the original text, rather than re-serializing the payload,
+/// so the other fields are forwarded unchanged and in the same order.
+fn with_source_topic(payload: &[u8], field: &str, source_topic: &str) -> Option<Vec<u8>> {
+    let json: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(payload).ok()?;
+    if json.contains_key(field) {
+        return None;
+    }
+
+    // The payload being a JSON object, it ends with a closing brace, possibly followed by whitespace
+    let end = payload.iter().rposition(|byte| *byte == b'}')?;
+    let separator = if json.is_empty() { "" } else { "," };
+    let field = serde_json::to_string(field).ok()?;
+    let source_topic = serde_json::to_string(source_topic).ok()?;
+
+    let mut updated = payload[..end].to_vec();
+    updated.extend_from_slice(format!("{separator}{field}:{source_topic}}}").as_bytes());
+    Some(updated)
+}
+
+/// The name of a bridge half, as used in the logs, e.g. `local` or `c8y/local` for a named bridge
+fn half_bridge_name(bridge_name: Option<&str>, half: &str) -> String {
+    match bridge_name {
+        Some(bridge_name) => format!("{bridge_name}/{half}"),
+        None => half.to_string(),
+    }
+}
+
 fn bidirectional_channel(
     cloud_client: AsyncClient,
     local_client: AsyncClient,
     buffer: usize,
+    max_pending: Option<NonZeroUsize>,
 ) -> [BridgeAsyncClient; 2] {
     let (tx_first, rx_first) = mpsc::channel(buffer);
     let (tx_second, rx_second) = mpsc::channel(buffer);
+    let local_to_cloud = PendingPermits::new(max_pending);
+    let cloud_to_local = PendingPermits::new(max_pending);
     [
-        BridgeAsyncClient::new(cloud_client, tx_first, rx_second),
-        BridgeAsyncClient::new(local_client, tx_second, rx_first),
+        BridgeAsyncClient::new(
+            cloud_client,
+            tx_first,
+            rx_second,
+            local_to_cloud.clone(),
+            cloud_to_local.clone(),
+        ),
+        BridgeAsyncClient::new(
+            local_client,
+            tx_second,
+            rx_first,
+            cloud_to_local,
+            local_to_cloud,
+        ),
     ]
 }
 
 enum BridgeMessage {
-    /// A message to be published to a given target topic
+    /// A message to be published on the target
     ///
-    /// This message will have to be acknowledged to its source by the companion half bridge
+    /// The original message will have to be acknowledged to its source by the companion half bridge
     BridgePub {
-        target_topic: String,
-        publish: Publish,
+        forwarded: Publish,
+        original: Publish,
     },
 
     /// A message to be acknowledged on the target
@@ -202,6 +357,23 @@ enum BridgeMessage {
     ///
     /// This message has not to be acknowledged, as not received by the bridge.
     Pub { publish: Publish },
+
+    /// A message to be published on the target, without coordination with the companion
+    ///
+    /// The original message has already been acknowledged to its source (see [BridgeRule::fast_ack]).
+    FastPub { forwarded: Publish },
+}
+
+/// A message waiting, in the background task of a [BridgeAsyncClient], to be forwarded
+struct PendingForward {
+    forwarded: Publish,
+
+    /// The original message, to be acknowledged once the forwarded one is,
+    /// or `None` if already acknowledged (see [BridgeRule::fast_ack])
+    original: Option<Publish>,
+
+    /// When this message can be published, as delayed by the rate limits
+    not_before: tokio::time::Instant,
 }
 
 /// Wraps the target of an half bridge with a channel to its half bridge companion.
@@ -214,7 +386,7 @@ struct BridgeAsyncClient {
     target: AsyncClient,
 
     /// Receives messages from the companion half bridge
-    rx: mpsc::Receiver<Option<(String, Publish)>>,
+    rx: mpsc::Receiver<Option<(Publish, Publish)>>,
 
     /// Sends messages to a background task that forwards the messages to the target and companion
     sender: BridgeMessageSender,
@@ -224,10 +396,16 @@ struct BridgeAsyncClient {
 
     /// Count of messages that have been acknowledged
     acknowledged: Arc<AtomicUsize>,
+
+    /// Sends the messages to be forwarded to a background task that awaits the rate limits and permits to publish them
+    forwarding_tx: mpsc::UnboundedSender<PendingForward>,
+
+    /// Caps the messages published by the companion, whose acknowledgements are received by this half
+    companion_permits: PendingPermits,
 }
 
 impl BridgeAsyncClient {
-    pub fn recv(&mut self) -> futures::stream::Next<mpsc::Receiver<Option<(String, Publish)>>> {
+    pub fn recv(&mut self) -> futures::stream::Next<mpsc::Receiver<Option<(Publish, Publish)>>> {
         self.rx.next()
     }
 
@@ -237,40 +415,84 @@ impl BridgeAsyncClient {
 
     fn new(
         target: AsyncClient,
-        tx: mpsc::Sender<Option<(String, Publish)>>,
-        rx: mpsc::Receiver<Option<(String, Publish)>>,
+        tx: mpsc::Sender<Option<(Publish, Publish)>>,
+        rx: mpsc::Receiver<Option<(Publish, Publish)>>,
+        forwarding_permits: PendingPermits,
+        companion_permits: PendingPermits,
     ) -> Self {
         let (unbounded_tx, unbounded_rx) = mpsc::unbounded();
+        let (forwarding_tx, forwarding_rx) = mpsc::unbounded();
         let companion_bridge_half = BridgeAsyncClient {
             target,
             rx,
             sender: BridgeMessageSender { unbounded_tx },
             published: Arc::new(AtomicUsize::new(0)),
             acknowledged: Arc::new(AtomicUsize::new(0)),
+            forwarding_tx,
+            companion_permits,
         };
         companion_bridge_half.spawn_publisher(tx, unbounded_rx);
+        companion_bridge_half.spawn_forwarder(forwarding_permits, forwarding_rx);
         companion_bridge_half
     }
 
-    async fn publish(&mut self, target_topic: String, publish: Publish) {
-        self.sender.publish(target_topic, publish).await
+    /// Publish a forwarded message, once delayed as requested by the rate limits
+    /// and once the number of messages waiting for an acknowledgement is below the cap
+    ///
+    /// This doesn't wait for the delay nor the permit, so the half bridge keeps polling its event loop meantime.
+    async fn publish(&mut self, forwarded: Publish, original: Publish, delay: Duration) {
+        self.forward(forwarded, Some(original), delay).await
     }
 
-    async fn ack(&mut self, publish: Publish) {
-        self.sender.ack(publish).await
+    /// Publish a forwarded message, which original has already been acknowledged, once delayed as requested by the rate limits
+    async fn fast_publish(&mut self, forwarded: Publish, delay: Duration) {
+        self.forward(forwarded, None, delay).await
+    }
+
+    async fn forward(&mut self, forwarded: Publish, original: Option<Publish>, delay: Duration) {
+        let not_before = tokio::time::Instant::now() + delay;
+        self.forwarding_tx
+            .send(PendingForward {
+                forwarded,
+                original,
+                not_before,
+            })
+            .await
+            .unwrap()
     }
 
-    fn published(&self) -> usize {
-        self.published.load(Ordering::Relaxed)
+    /// Pass the forwarded messages to the publisher, one by one, as delays expire and permits are released
+    ///
+    /// While a message is delayed or the cap is reached, the messages received from the source are held here.
+    /// These messages are not acknowledged (unless fast-acked), so the source stops sending more
+    /// once its own limit of messages in-flight is reached.
+    fn spawn_forwarder(
+        &self,
+        forwarding_permits: PendingPermits,
+        mut forwarding_rx: mpsc::UnboundedReceiver<PendingForward>,
+    ) {
+        let mut sender = self.sender.clone();
+        tokio::spawn(async move {
+            while let Some(pending) = forwarding_rx.next().await {
+                tokio::time::sleep_until(pending.not_before).await;
+                match pending.original {
+                    Some(original) => {
+                        forwarding_permits.acquire().await;
+                        sender.publish(pending.forwarded, original).await
+                    }
+                    None => sender.fast_publish(pending.forwarded).await,
+                }
+            }
+        });
     }
 
-    fn acknowledged(&self) -> usize {
-        self.acknowledged.load(Ordering::Relaxed)
+    async fn ack(&mut self, publish: Publish) {
+        self.sender.ack(publish).await
     }
 
     fn spawn_publisher(
         &self,
-        mut tx: mpsc::Sender<Option<(String, Publish)>>,
+        mut tx: mpsc::Sender<Option<(Publish, Publish)>>,
         mut unbounded_rx: mpsc::UnboundedReceiver<BridgeMessage>,
     ) {
         let target = self.target.clone();
@@ -280,13 +502,30 @@ impl BridgeAsyncClient {
             while let Some(message) = unbounded_rx.next().await {
                 match message {
                     BridgeMessage::BridgePub {
-                        target_topic,
-                        publish,
+                        forwarded,
+                        original,
                     } => {
-                        let duplicate = (target_topic.clone(), publish.clone());
-                        tx.send(Some(duplicate)).await.unwrap();
+                        tx.send(Some((forwarded.clone(), original))).await.unwrap();
                         target
-                            .publish(target_topic, publish.qos, publish.retain, publish.payload)
+                            .publish(
+                                forwarded.topic,
+                                forwarded.qos,
+                                forwarded.retain,
+                                forwarded.payload,
+                            )
+                            .await
+                            .unwrap();
+                        published.fetch_add(1, Ordering::Relaxed);
+                    }
+                    BridgeMessage::FastPub { forwarded } => {
+                        tx.send(None).await.unwrap();
+                        target
+                            .publish(
+                                forwarded.topic,
+                                forwarded.qos,
+                                forwarded.retain,
+                                forwarded.payload,
+                            )
                             .await
                             .unwrap();
                         published.fetch_add(1, Ordering::Relaxed);
@@ -308,6 +547,33 @@ impl BridgeAsyncClient {
     }
 }
 
+/// Caps the number of forwarded messages waiting for an acknowledgement, see [BridgeConfig::max_pending_messages]
+///
+/// A permit is acquired by the half bridge forwarding a message, and released by its companion
+/// once the message is no longer waiting for an acknowledgement.
+#[derive(Clone, Default)]
+struct PendingPermits(Option<Arc<Semaphore>>);
+
+impl PendingPermits {
+    fn new(max_pending: Option<NonZeroUsize>) -> Self {
+        PendingPermits(max_pending.map(|max| Arc::new(Semaphore::new(max.get()))))
+    }
+
+    /// Wait till the number of pending messages is below the cap, then count one more
+    async fn acquire(&self) {
+        if let Some(semaphore) = &self.0 {
+            semaphore.acquire().await.unwrap().forget()
+        }
+    }
+
+    /// Count one message less
+    fn release(&self) {
+        if let Some(semaphore) = &self.0 {
+            semaphore.add_permits(1)
+        }
+    }
+}
+
 #[derive(Clone)]
 struct BridgeMessageSender {
     unbounded_tx: mpsc::UnboundedSender<BridgeMessage>,
@@ -321,16 +587,23 @@ impl BridgeMessageSender {
             .unwrap()
     }
 
-    async fn publish(&mut self, target_topic: String, publish: Publish) {
+    async fn publish(&mut self, forwarded: Publish, original: Publish) {
         self.unbounded_tx
             .send(BridgeMessage::BridgePub {
-                target_topic,
-                publish,
+                forwarded,
+                original,
             })
             .await
             .unwrap()
     }
 
+    async fn fast_publish(&mut self, forwarded: Publish) {
+        self.unbounded_tx
+            .send(BridgeMessage::FastPub { forwarded })
+            .await
+            .unwrap()
+    }
+
     async fn ack(&mut self, publish: Publish) {
         self.unbounded_tx
             .send(BridgeMessage::BridgeAck { publish })
@@ -433,6 +706,20 @@ impl BridgeMessageSender {
 /// - The `half_bridge(cloud_event_loop,local_client)` receives cloud messages and publishes these message locally.
 /// - The `half_bridge(local_event_loop,cloud_client)` handles the acknowledgements: waiting for messages be acknowledged locally, before sending acks for the original messages.
 ///
+/// ## Fast acknowledgements
+///
+/// The messages received on the topics of the rules set to [BridgeRule::fast_ack] skip this
+/// coordination: these messages are acknowledged to the source as soon as handed to the target
+/// client, and the companion ignores their packet ids. Such messages are delivered at most once,
+/// as they are lost if the target connection is interrupted before they reach the target broker.
+///
+/// # Message transformations
+/// The messages are forwarded on the target topics derived by the bridge rules from the source topics.
+/// When a `message_transformer` is set, it is applied to each of these messages before forwarding,
+/// possibly changing the topic, payload, QoS and retain flag of the message or dropping it.
+/// It's the transformed message that is published on the target and checked for loops,
+/// while the original message is acknowledged to the source, even when dropped by the transformer.
+///
 /// # Health topics
 /// The bridge will publish health information to `health_topic` (if supplied) on `target` to enable
 /// other components to establish bridge health. This is intended to be used the half with cloud
@@ -440,6 +727,55 @@ impl BridgeMessageSender {
 /// mosquitto-based predecessor. The payload is either `1` (healthy) or `0` (unhealthy). When the
 /// connection is created, the last-will message is set to send the `0` payload when the connection
 /// is dropped.
+///
+/// The health payload also reports, for each bridge half, whether the broker resumed the session
+/// on the most recent connection, i.e. the `session_present` flag of the latest `ConnAck`.
+/// This tells whether messages not acknowledged before a reconnection will be replayed or not.
+///
+/// # Retained messages
+/// When a `retained_cache` is given, a retained message identical to the last retained message
+/// forwarded on the same target topic is acknowledged but not forwarded again.
+/// This avoids redundant writes on the target, when the retained messages are re-sent on reconnect.
+/// The cache outlives the connections, so these last values are remembered across reconnects.
+///
+/// The retained messages sent by the broker on subscription are not forwarded at all
+/// for the topics of the rules set to [BridgeRule::ignore_retained_on_subscribe].
+///
+/// # Subscriptions
+/// On each `ConnAck`, the half bridge subscribes to `topics`, either all at once or,
+/// when `subscription_chunks` is set, in chunks of filters with a delay between chunks.
+/// A subscription request that cannot be sent is retried as configured by `subscription_retry`.
+/// If it still fails, or if the broker refuses a subscription, the half bridge is reported down.
+///
+/// # Acknowledgement timeout
+/// When an `ack_timeout` handler is given, a forwarded message not acknowledged in time
+/// is removed from the messages waiting for an acknowledgement, and is either
+/// acknowledged to its source anyway or published again, as configured by [BridgeConfig::on_cloud_ack_timeout].
+/// An acknowledgement received later for such a message is ignored. Till then, its packet id is kept
+/// so the message re-sent by the MQTT client on reconnect is not mistaken for a new one.
+///
+/// # Pending messages cap
+/// When [BridgeConfig::max_pending_messages] is set, the half bridge waits, before forwarding a message,
+/// for the number of forwarded messages still waiting for an acknowledgement to be below the cap.
+/// Meanwhile, the source event loop is not polled: no more messages are received nor acknowledged,
+/// applying backpressure on the source broker. The companion releases the cap for each message
+/// acknowledged by the target, given up on timeout with [AckTimeoutAction::AckLocally] or published with QoS 0.
+///
+/// # Topic length
+/// A message whose target topic, as rewritten by the rules and the `message_transformer`,
+/// is longer than `max_topic_length` is acknowledged to the source but not forwarded,
+/// as it would be refused by the target broker (see [BridgeConfig::max_topic_length]).
+///
+/// # Rate limits
+/// When [BridgeConfig::with_rate_limit] is set for a prefix of the target topic of a message,
+/// the half bridge waits, before forwarding the message, for a token of the bucket of this prefix.
+/// As for the pending messages cap, the source event loop is not polled meanwhile,
+/// so the messages are forwarded, counted and acknowledged in the order they are received.
+///
+/// # MQTT versions
+/// Both connections use MQTT 3.1.1, whose publish packets have no properties.
+/// MQTT 5 properties set by a local publisher, such as the message expiry interval
+/// or the content type, are therefore not known to the bridge and cannot be forwarded.
 #[allow(clippy::too_many_arguments)]
 async fn half_bridge(
     mut recv_event_loop: EventLoop,
@@ -447,26 +783,38 @@ async fn half_bridge(
     mut target: BridgeAsyncClient,
     transformer: TopicConverter,
     bidirectional_topic_filters: Vec<Cow<'static, str>>,
-    tx_health: mpsc::Sender<(&'static str, Status)>,
-    name: &'static str,
+    mut bridge_health: BridgeHealth,
+    name: String,
     topics: Vec<SubscribeFilter>,
-    reconnect_policy: TEdgeConfigReaderMqttBridgeReconnectPolicy,
+    reconnect_policy: ReconnectPolicy,
+    mut retained_cache: Option<RetainedMessageCache>,
+    subscription_chunks: Option<SubscriptionChunks>,
+    subscription_retry: SubscriptionRetry,
+    source_topic_field: Option<String>,
+    message_transformer: Option<Arc<dyn BridgeTransformer>>,
+    mut rate_limiter: RateLimiter,
+    max_topic_length: usize,
+    ack_timeout: Option<AckTimeoutHandler>,
+    forward_pkid_to_received_msg: Arc<Mutex<PendingAcks>>,
+    counters: HalfBridgeCounters,
 ) {
     let mut backoff = CustomBackoff::new(
         ::backoff::SystemClock {},
-        reconnect_policy.initial_interval.duration(),
-        reconnect_policy.maximum_interval.duration(),
-        reconnect_policy.reset_window.duration(),
+        reconnect_policy.initial_interval,
+        reconnect_policy.maximum_interval,
+        reconnect_policy.reset_window,
     );
-    let mut forward_pkid_to_received_msg = HashMap::new();
-    let mut bridge_health = BridgeHealth::new(name, tx_health);
+    if let Some(ack_timeout) = ack_timeout {
+        ack_timeout.spawn(
+            name.clone(),
+            Arc::downgrade(&forward_pkid_to_received_msg),
+            target.clone_sender(),
+            target.companion_permits.clone(),
+        );
+    }
     let mut loop_breaker =
         MessageLoopBreaker::new(recv_client.clone(), bidirectional_topic_filters);
 
-    let mut received = 0; // Count of messages received by this half-bridge
-    let mut published = 0; // Count of messages published (by the companion)
-    let mut acknowledged = 0; // Count of messages acknowledged (by the MQTT end-point of the companion)
-
     loop {
         let res = recv_event_loop.poll().await;
         bridge_health.update(&res).await;
@@ -486,28 +834,106 @@ async fn half_bridge(
             }
         };
         debug!("Received notification ({name}) {notification:?}");
-        debug!("Bridge {name} connection: received={received} forwarded={forwarded} published={published} waiting={waiting} acknowledged={acknowledged} finalized={finalized}",
-            forwarded = target.published(),
-            waiting = forward_pkid_to_received_msg.len(),
-            finalized = target.acknowledged(),
-        );
+        // The lock is only taken when debug is enabled, as the arguments of `debug!` are evaluated lazily
+        debug!("Bridge {name} connection: {}", {
+            let waiting = forward_pkid_to_received_msg.lock().unwrap().messages.len();
+            counters.metrics(&name, waiting)
+        });
 
         match notification {
             Event::Incoming(Incoming::ConnAck(_)) => {
                 info!("Bridge {name} connection subscribing to {topics:?}");
                 let recv_client = recv_client.clone();
                 let topics = topics.clone();
+                let subscription_health = bridge_health.subscription_health();
                 // We have to subscribe to this asynchronously (i.e. in a task) since we might at
                 // this point have filled our cloud event loop with outgoing messages
-                tokio::spawn(async move { recv_client.subscribe_many(topics).await.unwrap() });
+                tokio::spawn(async move {
+                    if let Err(err) = subscribe_with_retry(
+                        &recv_client,
+                        topics,
+                        subscription_chunks,
+                        subscription_retry,
+                    )
+                    .await
+                    {
+                        subscription_health
+                            .failed(format!("failed to send the subscription request: {err}"))
+                            .await
+                    }
+                });
+            }
+
+            Event::Incoming(Incoming::SubAck(ack)) => {
+                let refused = ack
+                    .return_codes
+                    .iter()
+                    .filter(|code| matches!(code, SubscribeReasonCode::Failure))
+                    .count();
+                if refused > 0 {
+                    bridge_health
+                        .subscription_health()
+                        .failed(format!(
+                            "the broker refused {refused} of the {} subscriptions of packet {}",
+                            ack.return_codes.len(),
+                            ack.pkid
+                        ))
+                        .await
+                }
             }
 
             // Forward messages from event loop to target
             Event::Incoming(Incoming::Publish(publish)) => {
                 if let Some(publish) = loop_breaker.ensure_not_looped(publish).await {
                     if let Some(topic) = transformer.convert_topic(&publish.topic) {
-                        received += 1;
-                        target.publish(topic.to_string(), publish).await;
+                        counters.received.fetch_add(1, Ordering::Relaxed);
+                        if publish.retain
+                            && transformer.ignores_retained_on_subscribe(&publish.topic)
+                        {
+                            debug!("Bridge {name} connection ignoring retained message received on subscription to {}", publish.topic);
+                            recv_client.ack(&publish).await.unwrap();
+                            continue;
+                        }
+                        let mut forwarded =
+                            Publish::new(topic, publish.qos, publish.payload.clone());
+                        forwarded.retain = publish.retain;
+                        if let Some(field) = &source_topic_field {
+                            if let Some(payload) =
+                                with_source_topic(&forwarded.payload, field, &publish.topic)
+                            {
+                                forwarded.payload = payload.into();
+                            }
+                        }
+                        if let Some(message_transformer) = &message_transformer {
+                            match message_transformer.transform(forwarded.into()) {
+                                Some(message) => forwarded = message.into(),
+                                None => {
+                                    debug!("Bridge {name} connection dropping message received on {} as requested by the transformer", publish.topic);
+                                    recv_client.ack(&publish).await.unwrap();
+                                    continue;
+                                }
+                            }
+                        }
+                        if forwarded.topic.len() > max_topic_length {
+                            warn!("Bridge {name} connection dropping message received on {}, as forwarded on a topic longer than {max_topic_length} bytes: {}...", publish.topic, truncated(&forwarded.topic, 64));
+                            recv_client.ack(&publish).await.unwrap();
+                            continue;
+                        }
+                        if retained_cache
+                            .as_mut()
+                            .is_some_and(|cache| cache.is_duplicate(&forwarded.topic, &forwarded))
+                        {
+                            debug!("Bridge {name} connection skipping unchanged retained message on {}", forwarded.topic);
+                            recv_client.ack(&publish).await.unwrap();
+                            continue;
+                        }
+                        let delay = rate_limiter.delay(&forwarded.topic);
+                        if transformer.fast_acks(&publish.topic) {
+                            target.fast_publish(forwarded, delay).await;
+                            recv_client.ack(&publish).await.unwrap();
+                            continue;
+                        }
+                        target.publish(forwarded, publish, delay).await;
                     } else {
                         // Being not forwarded to this bridge target
                         // The message has to be acknowledged
@@ -521,9 +947,16 @@ async fn half_bridge(
                 Incoming::PubAck(PubAck { pkid: ack_pkid })
                 | Incoming::PubRec(PubRec { pkid: ack_pkid }),
             ) => {
-                if let Some(msg) = forward_pkid_to_received_msg.remove(&ack_pkid) {
-                    acknowledged += 1;
-                    target.ack(msg).await;
+                let pending = forward_pkid_to_received_msg
+                    .lock()
+                    .unwrap()
+                    .acknowledge(ack_pkid);
+                if let Some(Some(PendingAck { original, .. })) = pending {
+                    counters.acknowledged.fetch_add(1, Ordering::Relaxed);
+                    target.companion_permits.release();
+                    target.ack(original).await;
+                } else if pending.is_some() {
+                    info!("Bridge {name} connection received ack for pkid={ack_pkid} after its timeout");
                 } else {
                     info!("Bridge {name} connection received ack for unknown pkid={ack_pkid}");
                 }
@@ -531,16 +964,35 @@ async fn half_bridge(
 
             // Keep track of packet IDs so we can acknowledge messages
             Event::Outgoing(Outgoing::Publish(pkid)) => {
-                if let hash_map::Entry::Vacant(e) = forward_pkid_to_received_msg.entry(pkid) {
+                // The lock is not held while awaiting the companion, the timeout handler only removes entries
+                let is_known = forward_pkid_to_received_msg.lock().unwrap().is_known(pkid);
+                if !is_known {
                     match target.recv().await {
                         // A message was forwarded by the other bridge half, note the packet id
-                        Some(Some((topic, msg))) => {
-                            published += 1;
-                            loop_breaker.forward_on_topic(topic, &msg);
-                            if pkid != 0 {
-                                // Messages with pkid 0 (meaning QoS=0) should not be added to the hashmap
-                                // as multiple messages with the pkid=0 can be received
-                                e.insert(msg);
+                        Some(Some((forwarded, original))) => {
+                            counters.published.fetch_add(1, Ordering::Relaxed);
+                            loop_breaker.forward_on_topic(forwarded.topic.clone(), &forwarded);
+                            if pkid == 0 {
+                                // Messages with pkid 0 (meaning QoS=0) are not waiting for any acknowledgement
+                                // and should not be added to the hashmap as multiple messages with the pkid=0 can be received
+                                target.companion_permits.release();
+                                // The original message might have been received with a higher QoS,
+                                // the forwarded message being downgraded by a transformer:
+                                // with no acknowledgement to wait for, the original is acknowledged right away
+                                if original.qos != QoS::AtMostOnce {
+                                    target.ack(original).await;
+                                }
+                            } else {
+                                let pending = PendingAck {
+                                    forwarded,
+                                    original,
+                                    since: Instant::now(),
+                                };
+                                forward_pkid_to_received_msg
+                                    .lock()
+                                    .unwrap()
+                                    .messages
+                                    .insert(pkid, pending);
                             }
                         }
 
@@ -568,10 +1020,235 @@ async fn half_bridge(
     }
 }
 
+/// The first `max_length` bytes of a topic, cut on a char boundary
+fn truncated(topic: &str, max_length: usize) -> &str {
+    let mut end = max_length.min(topic.len());
+    while !topic.is_char_boundary(end) {
+        end -= 1;
+    }
+    &topic[..end]
+}
+
+/// The forwarded messages waiting for an acknowledgement, indexed by packet id
+#[derive(Default)]
+struct PendingAcks {
+    messages: HashMap<u16, PendingAck>,
+
+    /// The packet ids of the messages given up on timeout
+    ///
+    /// These messages are still in-flight for the MQTT client, which might re-send them on reconnect.
+    /// Their packet ids are kept till acknowledged, so these re-sent messages are not mistaken for new ones.
+    expired: HashSet<u16>,
+}
+
+impl PendingAcks {
+    fn is_known(&self, pkid: u16) -> bool {
+        self.messages.contains_key(&pkid) || self.expired.contains(&pkid)
+    }
+
+    /// Remove the message acknowledged with this packet id
+    ///
+    /// Return `None` if the packet id is unknown and `Some(None)` if the message has been given up on timeout.
+    fn acknowledge(&mut self, pkid: u16) -> Option<Option<PendingAck>> {
+        match self.messages.remove(&pkid) {
+            Some(message) => Some(Some(message)),
+            None => self.expired.remove(&pkid).then_some(None),
+        }
+    }
+
+    /// Remove the messages waiting for an acknowledgement for more than `timeout`
+    fn take_expired(&mut self, timeout: Duration) -> Vec<(u16, PendingAck)> {
+        let expired_pkids: Vec<u16> = self
+            .messages
+            .iter()
+            .filter(|(_, message)| message.since.elapsed() >= timeout)
+            .map(|(pkid, _)| *pkid)
+            .collect();
+        self.expired.extend(expired_pkids.iter().copied());
+        expired_pkids
+            .into_iter()
+            .filter_map(|pkid| self.messages.remove(&pkid).map(|message| (pkid, message)))
+            .collect()
+    }
+}
+
+/// A message forwarded to the target, waiting for the target acknowledgement
+struct PendingAck {
+    /// The message as published on the target
+    forwarded: Publish,
+
+    /// The message as received from the source, to be acknowledged once the forwarded message is
+    original: Publish,
+
+    /// When the forwarded message has been published
+    since: Instant,
+}
+
+/// Dumps the messages forwarded by a bridge and still waiting for an acknowledgement
+///
+/// This is meant for post-mortem debugging of a stuck bridge:
+/// the dump tells which messages are blocked, on which connection and for how long.
+/// The bridge logs such a dump each time its health status goes down.
+#[derive(Clone, Default)]
+pub struct BridgeDiagnostics {
+    /// The messages waiting for an acknowledgement and the message counters of each connection,
+    /// indexed by the connection name
+    connections: Vec<(String, Weak<Mutex<PendingAcks>>, HalfBridgeCounters)>,
+
+    /// The counters of the retained message cache of each direction
+    retained_caches: Vec<RetainedCacheCounters>,
+}
+
+impl BridgeDiagnostics {
+    /// The messages currently waiting for an acknowledgement, the oldest first
+    ///
+    /// Returns an empty list once the bridge is stopped.
+    pub fn pending_messages(&self) -> Vec<PendingMessage> {
+        let mut messages = Vec::new();
+        for (connection, pending, _) in &self.connections {
+            let Some(pending) = pending.upgrade() else {
+                continue;
+            };
+            let pending = pending.lock().unwrap();
+            messages.extend(
+                pending
+                    .messages
+                    .iter()
+                    .map(|(pkid, message)| PendingMessage {
+                        connection: connection.clone(),
+                        pkid: *pkid,
+                        topic: message.forwarded.topic.clone(),
+                        age: message.since.elapsed(),
+                    }),
+            );
+        }
+        messages.sort_by_key(|message| std::cmp::Reverse(message.age));
+        messages
+    }
+
+    /// A snapshot of the message counters of each connection
+    ///
+    /// The counters are kept once the bridge is stopped, but the messages waiting for an acknowledgement are not.
+    pub fn metrics(&self) -> Vec<BridgeMetrics> {
+        self.connections
+            .iter()
+            .map(|(connection, pending, counters)| {
+                let waiting_acks = pending
+                    .upgrade()
+                    .map_or(0, |pending| pending.lock().unwrap().messages.len());
+                counters.metrics(connection, waiting_acks)
+            })
+            .collect()
+    }
+
+    /// The hits and misses of the retained message caches, summed over both directions
+    ///
+    /// See [BridgeConfig::deduplicate_retained_messages].
+    pub fn retained_cache_stats(&self) -> RetainedCacheStats {
+        self.retained_caches
+            .iter()
+            .map(RetainedCacheCounters::stats)
+            .fold(RetainedCacheStats::default(), |total, stats| {
+                RetainedCacheStats {
+                    hits: total.hits + stats.hits,
+                    misses: total.misses + stats.misses,
+                }
+            })
+    }
+
+    /// Log the messages currently waiting for an acknowledgement, returning them
+    pub fn dump(&self) -> Vec<PendingMessage> {
+        let messages = self.pending_messages();
+        info!(
+            "Bridge diagnostic: {} message(s) waiting for an acknowledgement",
+            messages.len()
+        );
+        for message in &messages {
+            info!("Bridge diagnostic: {message}");
+        }
+        messages
+    }
+}
+
+/// A message forwarded by the bridge and waiting for an acknowledgement, as given by [BridgeDiagnostics]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PendingMessage {
+    /// The name of the connection on which the message has been published, e.g. `cloud` or `c8y/cloud`
+    pub connection: String,
+
+    /// The packet id of the forwarded message
+    pub pkid: u16,
+
+    /// The topic on which the message has been forwarded
+    pub topic: String,
+
+    /// For how long the message has been waiting for an acknowledgement
+    pub age: Duration,
+}
+
+impl std::fmt::Display for PendingMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} pkid={} topic={} waiting for {:?}",
+            self.connection, self.pkid, self.topic, self.age
+        )
+    }
+}
+
+/// Handles the forwarded messages not acknowledged in time
+struct AckTimeoutHandler {
+    config: AckTimeout,
+
+    /// Publishes again the forwarded messages, on the connection awaiting the acknowledgements
+    republisher: BridgeMessageSender,
+}
+
+impl AckTimeoutHandler {
+    /// Periodically check the pending messages for expired ones, till the half bridge owning them stops
+    fn spawn(
+        mut self,
+        name: String,
+        pending: Weak<Mutex<PendingAcks>>,
+        mut acknowledger: BridgeMessageSender,
+        permits: PendingPermits,
+    ) {
+        let AckTimeout { timeout, action } = self.config;
+        let period = (timeout / 2).max(Duration::from_millis(10));
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(period).await;
+                let Some(pending) = pending.upgrade() else {
+                    break;
+                };
+                let expired = pending.lock().unwrap().take_expired(timeout);
+                drop(pending);
+
+                for (pkid, msg) in expired {
+                    let topic = &msg.forwarded.topic;
+                    match action {
+                        AckTimeoutAction::AckLocally => {
+                            warn!("Bridge {name} connection received no ack for pkid={pkid} on {topic} after {timeout:?}, acknowledging the original message anyway");
+                            permits.release();
+                            acknowledger.ack(msg.original).await;
+                        }
+                        AckTimeoutAction::Republish => {
+                            warn!("Bridge {name} connection received no ack for pkid={pkid} on {topic} after {timeout:?}, publishing the message again");
+                            self.republisher.publish(msg.forwarded, msg.original).await;
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum Status {
     Up,
     Down,
+    /// Not known yet, i.e. before the first connection attempt completes
+    Unknown,
 }
 
 impl Status {
@@ -579,14 +1256,25 @@ impl Status {
         match self {
             Status::Up => r#"{"status":"up"}"#,
             Status::Down => r#"{"status":"down"}"#,
+            Status::Unknown => r#"{"status":"unknown"}"#,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Up => "up",
+            Status::Down => "down",
+            Status::Unknown => "unknown",
         }
     }
 }
 
-fn overall_status(lhs: Option<Status>, rhs: &Option<Status>) -> Option<Status> {
-    match (lhs?, rhs.as_ref()?) {
-        (Status::Up, Status::Up) => Some(Status::Up),
-        _ => Some(Status::Down),
+/// Combine the status of two components: down if any is down, else unknown if any is unknown
+fn overall_status(lhs: Status, rhs: Status) -> Status {
+    match (lhs, rhs) {
+        (Status::Down, _) | (_, Status::Down) => Status::Down,
+        (Status::Unknown, _) | (_, Status::Unknown) => Status::Unknown,
+        (Status::Up, Status::Up) => Status::Up,
     }
 }
 
@@ -619,6 +1307,63 @@ impl MqttAck for AsyncClient {
     }
 }
 
+/// Subscribe to the topics, either all at once or chunk by chunk
+async fn subscribe(
+    client: &impl MqttSubscribe,
+    topics: Vec<SubscribeFilter>,
+    chunks: Option<SubscriptionChunks>,
+) -> Result<(), ClientError> {
+    let Some(chunks) = chunks else {
+        return client.subscribe_many(topics).await;
+    };
+
+    let mut batches = topics.chunks(chunks.size.get()).peekable();
+    while let Some(batch) = batches.next() {
+        client.subscribe_many(batch.to_vec()).await?;
+        if batches.peek().is_some() {
+            tokio::time::sleep(chunks.delay).await;
+        }
+    }
+    Ok(())
+}
+
+/// Subscribe to the topics, retrying with an exponential backoff when the request cannot be sent
+async fn subscribe_with_retry(
+    client: &impl MqttSubscribe,
+    topics: Vec<SubscribeFilter>,
+    chunks: Option<SubscriptionChunks>,
+    retry: SubscriptionRetry,
+) -> Result<(), ClientError> {
+    let mut interval = retry.initial_interval;
+    let mut attempt = 1;
+    loop {
+        match subscribe(client, topics.clone(), chunks).await {
+            Ok(()) => return Ok(()),
+            Err(err) if attempt < retry.max_attempts.get() => {
+                warn!("Failed to subscribe (attempt {attempt}), retrying in {interval:?}: {err}");
+                tokio::time::sleep(interval).await;
+                interval *= 2;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait::async_trait]
+trait MqttSubscribe {
+    async fn subscribe_many(&self, topics: Vec<SubscribeFilter>) -> Result<(), ClientError>;
+}
+
+#[async_trait::async_trait]
+#[mutants::skip] // missed: replace <impl MqttSubscribe for AsyncClient>::subscribe_many -> Result<(), ClientError> with Ok(())
+impl MqttSubscribe for AsyncClient {
+    async fn subscribe_many(&self, topics: Vec<SubscribeFilter>) -> Result<(), ClientError> {
+        AsyncClient::subscribe_many(self, topics).await
+    }
+}
+
 struct SystemClock;
 
 #[cfg_attr(test, mockall::automock)]
@@ -642,7 +1387,13 @@ impl<Ack: MqttAck> MessageLoopBreaker<Ack, SystemClock> {
 }
 
 impl<Ack: MqttAck, Clock: MonotonicClock> MessageLoopBreaker<Ack, Clock> {
+    /// Return the received message unless this is the echo of a message forwarded on a bidirectional topic
+    ///
+    /// The messages received on other topics are returned as is, with no bookkeeping.
     async fn ensure_not_looped(&mut self, received: Publish) -> Option<Publish> {
+        if !self.is_bidirectional(&received.topic) {
+            return Some(received);
+        }
         self.clean_old_messages();
         if self
             .forwarded_messages
@@ -686,6 +1437,203 @@ impl<Ack: MqttAck, Clock: MonotonicClock> MessageLoopBreaker<Ack, Clock> {
     }
 }
 
+const DEFAULT_RETAINED_CACHE_CAPACITY: NonZeroUsize = match NonZeroUsize::new(10_000) {
+    Some(capacity) => capacity,
+    None => unreachable!(),
+};
+
+/// Remembers the last retained message forwarded on each target topic
+///
+/// This is not to be confused with the [MessageLoopBreaker]:
+/// the aim is not to detect echoes, but retained values that the target already holds.
+///
+/// The number of topics is bounded, the least recently forwarded topic being forgotten first.
+struct RetainedMessageCache {
+    capacity: NonZeroUsize,
+
+    /// The last retained message forwarded on each topic, along the tick of its last use
+    last_retained: HashMap<String, (Publish, u64)>,
+
+    /// The topics indexed by the tick of their last use, the least recently used first
+    recently_used: BTreeMap<u64, String>,
+
+    tick: u64,
+    counters: RetainedCacheCounters,
+}
+
+impl Default for RetainedMessageCache {
+    fn default() -> Self {
+        RetainedMessageCache::new(
+            DEFAULT_RETAINED_CACHE_CAPACITY,
+            RetainedCacheCounters::default(),
+        )
+    }
+}
+
+impl RetainedMessageCache {
+    fn new(capacity: NonZeroUsize, counters: RetainedCacheCounters) -> Self {
+        RetainedMessageCache {
+            capacity,
+            last_retained: HashMap::new(),
+            recently_used: BTreeMap::new(),
+            tick: 0,
+            counters,
+        }
+    }
+
+    /// Returns `true` if this message is a retained message
+    /// identical to the last retained message forwarded on the same target topic.
+    ///
+    /// Otherwise, the message is assumed to be forwarded and the cache is updated accordingly.
+    fn is_duplicate(&mut self, target_topic: &str, publish: &Publish) -> bool {
+        if !publish.retain {
+            // The retained value held by the target is no more known for sure
+            if let Some((_, tick)) = self.last_retained.remove(target_topic) {
+                self.recently_used.remove(&tick);
+            }
+            return false;
+        }
+
+        self.tick += 1;
+        let duplicate = match self.last_retained.get_mut(target_topic) {
+            Some((last, tick)) => {
+                self.recently_used.remove(tick);
+                *tick = self.tick;
+                let duplicate = have_same_content(last, publish);
+                if !duplicate {
+                    *last = publish.clone();
+                }
+                duplicate
+            }
+            None => {
+                if self.last_retained.len() >= self.capacity.get() {
+                    if let Some((_, oldest)) = self.recently_used.pop_first() {
+                        self.last_retained.remove(&oldest);
+                    }
+                }
+                self.last_retained
+                    .insert(target_topic.to_owned(), (publish.clone(), self.tick));
+                false
+            }
+        };
+        self.recently_used
+            .insert(self.tick, target_topic.to_owned());
+
+        let counter = if duplicate {
+            &self.counters.hits
+        } else {
+            &self.counters.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        duplicate
+    }
+}
+
+/// Counts the retained messages found or not in a [RetainedMessageCache]
+#[derive(Clone, Default)]
+struct RetainedCacheCounters {
+    hits: Arc<AtomicUsize>,
+    misses: Arc<AtomicUsize>,
+}
+
+impl RetainedCacheCounters {
+    fn stats(&self) -> RetainedCacheStats {
+        RetainedCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Statistics of the retained message deduplication, as given by [BridgeDiagnostics]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RetainedCacheStats {
+    /// Count of retained messages not forwarded, being identical to the last value forwarded on their topic
+    pub hits: usize,
+
+    /// Count of retained messages forwarded, their topic having no or another last value
+    pub misses: usize,
+}
+
+/// Counts the messages processed by a half bridge, see [BridgeMetrics]
+#[derive(Clone, Default)]
+struct HalfBridgeCounters {
+    received: Arc<AtomicUsize>,
+    forwarded: Arc<AtomicUsize>,
+    published: Arc<AtomicUsize>,
+    acknowledged: Arc<AtomicUsize>,
+    finalized: Arc<AtomicUsize>,
+}
+
+impl HalfBridgeCounters {
+    /// The counters of the half bridge forwarding messages to the given target
+    ///
+    /// The messages forwarded and finalized are counted by the target, when published and acknowledged.
+    fn new(target: &BridgeAsyncClient) -> Self {
+        HalfBridgeCounters {
+            forwarded: target.published.clone(),
+            finalized: target.acknowledged.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn metrics(&self, connection: &str, waiting_acks: usize) -> BridgeMetrics {
+        BridgeMetrics {
+            connection: connection.to_string(),
+            received: self.received.load(Ordering::Relaxed),
+            forwarded: self.forwarded.load(Ordering::Relaxed),
+            published: self.published.load(Ordering::Relaxed),
+            waiting_acks,
+            acknowledged: self.acknowledged.load(Ordering::Relaxed),
+            finalized: self.finalized.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A snapshot of the message counters of a half bridge, as given by [BridgeDiagnostics]
+///
+/// A message forwarded from the local broker to the cloud is received and forwarded by the `local` half,
+/// then published, acknowledged and finalized by the `cloud` half, which acknowledges the original message
+/// to the local broker once acknowledged by the cloud.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BridgeMetrics {
+    /// The name of the connection, e.g. `cloud` or `c8y/cloud`
+    pub connection: String,
+
+    /// Count of messages received on this connection and to be forwarded
+    pub received: usize,
+
+    /// Count of messages received on this connection and published on the companion connection
+    pub forwarded: usize,
+
+    /// Count of messages published on this connection, as forwarded by the companion
+    pub published: usize,
+
+    /// Count of messages published on this connection and still waiting for an acknowledgement
+    pub waiting_acks: usize,
+
+    /// Count of messages published on this connection and acknowledged
+    pub acknowledged: usize,
+
+    /// Count of original messages acknowledged to the companion connection, once acknowledged on this connection
+    pub finalized: usize,
+}
+
+impl std::fmt::Display for BridgeMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "received={} forwarded={} published={} waiting={} acknowledged={} finalized={}",
+            self.received,
+            self.forwarded,
+            self.published,
+            self.waiting_acks,
+            self.acknowledged,
+            self.finalized
+        )
+    }
+}
+
 impl Builder<MqttBridgeActor> for MqttBridgeActorBuilder {
     type Error = Infallible;
 
@@ -744,6 +1692,29 @@ mod tests {
             );
         }
 
+        #[tokio::test]
+        async fn unidirectional_messages_skip_the_loop_breaker() {
+            // Neither the clock nor the client are used for the messages on unidirectional topics
+            let client = MockMqttAck::new();
+            let clock = MockMonotonicClock::new();
+            let mut sut = MessageLoopBreaker {
+                client,
+                bidirectional_topics: vec!["inventory/#".into()],
+                forwarded_messages: <_>::default(),
+                clock,
+            };
+
+            for i in 0..10_000 {
+                let example_pub = Publish::new("measurements", QoS::AtLeastOnce, i.to_string());
+                sut.forward_on_topic("measurements", &example_pub);
+                assert_eq!(
+                    sut.ensure_not_looped(example_pub.clone()).await,
+                    Some(example_pub)
+                );
+            }
+            assert!(sut.forwarded_messages.is_empty());
+        }
+
         #[tokio::test]
         async fn skips_forwarded_messages() {
             let mut client = MockMqttAck::new();
@@ -818,6 +1789,85 @@ mod tests {
             assert_eq!(tc.local_subscriptions().collect::<Vec<_>>(), ["c8y/s/us"]);
         }
 
+        #[test]
+        fn subscribes_at_least_once_by_default() {
+            let mut tc = BridgeConfig::new();
+            tc.forward_from_local("s/us", "c8y/", "").unwrap();
+            tc.forward_from_remote("s/ds", "c8y/", "").unwrap();
+            assert_eq!(
+                tc.subscription_filters(),
+                [
+                    vec![SubscribeFilter::new("c8y/s/us".into(), QoS::AtLeastOnce)],
+                    vec![SubscribeFilter::new("s/ds".into(), QoS::AtLeastOnce)],
+                ]
+            );
+        }
+
+        #[test]
+        fn subscribes_with_the_qos_configured_for_each_direction() {
+            let mut tc = BridgeConfig::new();
+            tc.forward_from_local("s/us", "c8y/", "").unwrap();
+            tc.forward_from_remote("s/ds", "c8y/", "").unwrap();
+            tc.forward_from_remote("s/dat", "c8y/", "").unwrap();
+            tc.local_subscription_qos(QoS::ExactlyOnce);
+            tc.remote_subscription_qos(QoS::AtMostOnce);
+            assert_eq!(
+                tc.subscription_filters(),
+                [
+                    vec![SubscribeFilter::new("c8y/s/us".into(), QoS::ExactlyOnce)],
+                    vec![
+                        SubscribeFilter::new("s/ds".into(), QoS::AtMostOnce),
+                        SubscribeFilter::new("s/dat".into(), QoS::AtMostOnce),
+                    ],
+                ]
+            );
+        }
+
+        #[test]
+        fn describes_the_rules_as_json() {
+            let mut tc = BridgeConfig::new();
+            tc.bridge_name("c8y");
+            tc.forward_from_local("s/us", "c8y/", "")
+                .unwrap()
+                .fast_ack(true);
+            tc.forward_bidirectionally("inventory/#", "c8y/", "")
+                .unwrap();
+            assert_eq!(
+                tc.rules_json(),
+                serde_json::json!({
+                    "bridge": "c8y",
+                    "local_to_remote": [
+                        {
+                            "topic_filter": "c8y/s/us",
+                            "prefix_to_remove": "c8y/",
+                            "prefix_to_add": "",
+                            "bidirectional": false,
+                            "fast_ack": true,
+                            "ignore_retained_on_subscribe": false,
+                        },
+                        {
+                            "topic_filter": "c8y/inventory/#",
+                            "prefix_to_remove": "c8y/",
+                            "prefix_to_add": "",
+                            "bidirectional": true,
+                            "fast_ack": false,
+                            "ignore_retained_on_subscribe": false,
+                        },
+                    ],
+                    "remote_to_local": [
+                        {
+                            "topic_filter": "inventory/#",
+                            "prefix_to_remove": "",
+                            "prefix_to_add": "c8y/",
+                            "bidirectional": true,
+                            "fast_ack": false,
+                            "ignore_retained_on_subscribe": false,
+                        },
+                    ],
+                })
+            );
+        }
+
         #[test]
         fn includes_remote_rules_in_subscription_topics() {
             let mut tc = BridgeConfig::new();
@@ -838,6 +1888,30 @@ mod tests {
             );
         }
 
+        #[test]
+        fn only_bidirectional_rules_are_checked_for_loops() {
+            let mut tc = BridgeConfig::new();
+            tc.forward_from_local("s/us", "c8y/", "").unwrap();
+            tc.forward_from_remote("s/ds", "c8y/", "").unwrap();
+            tc.forward_bidirectionally("inventory/#", "c8y/", "")
+                .unwrap();
+            let [(_, local), (_, remote)] = tc.converters_and_bidirectional_topic_filters();
+            assert_eq!(local, ["c8y/inventory/#"]);
+            assert_eq!(remote, ["inventory/#"]);
+        }
+
+        #[test]
+        fn the_loop_breaker_can_be_disabled_per_rule() {
+            let mut tc = BridgeConfig::new();
+            tc.forward_bidirectionally("inventory/#", "c8y/", "")
+                .unwrap();
+            let [_, from_remote] = tc.forward_bidirectionally("shadow/#", "aws/", "").unwrap();
+            from_remote.loop_breaker(false);
+            let [(_, local), (_, remote)] = tc.converters_and_bidirectional_topic_filters();
+            assert_eq!(local, ["c8y/inventory/#", "aws/shadow/#"]);
+            assert_eq!(remote, ["inventory/#"]);
+        }
+
         #[test]
         fn applies_remote_rules_in_order() {
             let mut tc = BridgeConfig::new();
@@ -905,6 +1979,264 @@ mod tests {
         }
     }
 
+    mod subscribe {
+        use crate::subscribe;
+        use crate::subscribe_with_retry;
+        use crate::MockMqttSubscribe;
+        use crate::SubscriptionChunks;
+        use crate::SubscriptionRetry;
+        use mockall::Sequence;
+        use rumqttc::ClientError;
+        use rumqttc::Disconnect;
+        use rumqttc::QoS;
+        use rumqttc::Request;
+        use rumqttc::SubscribeFilter;
+        use std::num::NonZeroUsize;
+        use std::time::Duration;
+
+        fn filters(topics: &[&str]) -> Vec<SubscribeFilter> {
+            topics
+                .iter()
+                .map(|topic| SubscribeFilter::new(topic.to_string(), QoS::AtLeastOnce))
+                .collect()
+        }
+
+        #[tokio::test]
+        async fn subscribes_to_all_topics_at_once_by_default() {
+            let mut client = MockMqttSubscribe::new();
+            client
+                .expect_subscribe_many()
+                .withf(|topics| topics == &filters(&["a", "b", "c"]))
+                .times(1)
+                .returning(|_| Ok(()));
+
+            subscribe(&client, filters(&["a", "b", "c"]), None)
+                .await
+                .unwrap();
+        }
+
+        fn request_error() -> ClientError {
+            ClientError::Request(Request::Disconnect(Disconnect))
+        }
+
+        #[tokio::test]
+        async fn retries_a_failed_subscription() {
+            let mut client = MockMqttSubscribe::new();
+            let mut seq = Sequence::new();
+            client
+                .expect_subscribe_many()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| Err(request_error()));
+            client
+                .expect_subscribe_many()
+                .withf(|topics| topics == &filters(&["a", "b"]))
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| Ok(()));
+            let retry = SubscriptionRetry {
+                max_attempts: NonZeroUsize::new(3).unwrap(),
+                initial_interval: Duration::from_millis(1),
+            };
+
+            subscribe_with_retry(&client, filters(&["a", "b"]), None, retry)
+                .await
+                .unwrap();
+        }
+
+        #[tokio::test]
+        async fn gives_up_after_the_last_attempt() {
+            let mut client = MockMqttSubscribe::new();
+            client
+                .expect_subscribe_many()
+                .times(3)
+                .returning(|_| Err(request_error()));
+            let retry = SubscriptionRetry {
+                max_attempts: NonZeroUsize::new(3).unwrap(),
+                initial_interval: Duration::from_millis(1),
+            };
+
+            assert!(
+                subscribe_with_retry(&client, filters(&["a", "b"]), None, retry)
+                    .await
+                    .is_err()
+            );
+        }
+
+        #[tokio::test]
+        async fn subscribes_chunk_by_chunk() {
+            let mut client = MockMqttSubscribe::new();
+            let mut seq = Sequence::new();
+            for chunk in [vec!["a", "b"], vec!["c", "d"], vec!["e"]] {
+                client
+                    .expect_subscribe_many()
+                    .withf(move |topics| topics == &filters(&chunk))
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .returning(|_| Ok(()));
+            }
+            let chunks = SubscriptionChunks {
+                size: NonZeroUsize::new(2).unwrap(),
+                delay: Duration::from_millis(1),
+            };
+
+            subscribe(&client, filters(&["a", "b", "c", "d", "e"]), Some(chunks))
+                .await
+                .unwrap();
+        }
+    }
+
+    mod retained_message_cache {
+        use crate::RetainedCacheCounters;
+        use crate::RetainedCacheStats;
+        use crate::RetainedMessageCache;
+        use rumqttc::Publish;
+        use rumqttc::QoS;
+        use std::num::NonZeroUsize;
+
+        fn retained(topic: &str, payload: &'static str) -> Publish {
+            let mut publish = Publish::new(topic, QoS::AtLeastOnce, payload);
+            publish.retain = true;
+            publish
+        }
+
+        #[test]
+        fn forwards_the_first_retained_message() {
+            let mut cache = RetainedMessageCache::default();
+            assert!(!cache.is_duplicate("s/us", &retained("c8y/s/us", "101")));
+        }
+
+        #[test]
+        fn suppresses_an_identical_retained_republish() {
+            let mut cache = RetainedMessageCache::default();
+            let msg = retained("c8y/s/us", "101");
+            assert!(!cache.is_duplicate("s/us", &msg));
+            assert!(cache.is_duplicate("s/us", &msg));
+        }
+
+        #[test]
+        fn forwards_a_changed_retained_message() {
+            let mut cache = RetainedMessageCache::default();
+            assert!(!cache.is_duplicate("s/us", &retained("c8y/s/us", "101")));
+            assert!(!cache.is_duplicate("s/us", &retained("c8y/s/us", "102")));
+            assert!(cache.is_duplicate("s/us", &retained("c8y/s/us", "102")));
+        }
+
+        #[test]
+        fn tracks_retained_messages_per_topic() {
+            let mut cache = RetainedMessageCache::default();
+            assert!(!cache.is_duplicate("a", &retained("c8y/a", "value")));
+            assert!(!cache.is_duplicate("b", &retained("c8y/b", "value")));
+            assert!(cache.is_duplicate("a", &retained("c8y/a", "value")));
+        }
+
+        #[test]
+        fn never_suppresses_non_retained_messages() {
+            let mut cache = RetainedMessageCache::default();
+            let msg = Publish::new("c8y/s/us", QoS::AtLeastOnce, "101");
+            assert!(!cache.is_duplicate("s/us", &msg));
+            assert!(!cache.is_duplicate("s/us", &msg));
+        }
+
+        #[test]
+        fn counts_the_suppressed_and_forwarded_retained_messages() {
+            let counters = RetainedCacheCounters::default();
+            let mut cache =
+                RetainedMessageCache::new(NonZeroUsize::new(10).unwrap(), counters.clone());
+            cache.is_duplicate("s/us", &retained("c8y/s/us", "101"));
+            cache.is_duplicate("s/us", &retained("c8y/s/us", "101"));
+            cache.is_duplicate("s/us", &retained("c8y/s/us", "102"));
+            cache.is_duplicate("s/us", &Publish::new("c8y/s/us", QoS::AtLeastOnce, "103"));
+            assert_eq!(counters.stats(), RetainedCacheStats { hits: 1, misses: 2 });
+        }
+
+        #[test]
+        fn suppresses_the_unchanged_retained_values_resent_on_reconnect() {
+            let counters = RetainedCacheCounters::default();
+            let mut cache =
+                RetainedMessageCache::new(NonZeroUsize::new(10).unwrap(), counters.clone());
+            assert!(!cache.is_duplicate("a", &retained("c8y/a", "1")));
+            assert!(!cache.is_duplicate("b", &retained("c8y/b", "1")));
+
+            // On reconnect, the broker re-sends all the retained values, some having changed meanwhile
+            assert!(cache.is_duplicate("a", &retained("c8y/a", "1")));
+            assert!(!cache.is_duplicate("b", &retained("c8y/b", "2")));
+            assert_eq!(counters.stats(), RetainedCacheStats { hits: 1, misses: 3 });
+        }
+
+        #[test]
+        fn forgets_the_least_recently_forwarded_topic_once_full() {
+            let mut cache = RetainedMessageCache::new(
+                NonZeroUsize::new(2).unwrap(),
+                RetainedCacheCounters::default(),
+            );
+            assert!(!cache.is_duplicate("a", &retained("c8y/a", "1")));
+            assert!(!cache.is_duplicate("b", &retained("c8y/b", "1")));
+            assert!(cache.is_duplicate("a", &retained("c8y/a", "1")));
+
+            // b being the least recently used, it is evicted to make room for c
+            assert!(!cache.is_duplicate("c", &retained("c8y/c", "1")));
+            assert!(cache.is_duplicate("a", &retained("c8y/a", "1")));
+            assert!(cache.is_duplicate("c", &retained("c8y/c", "1")));
+            assert!(!cache.is_duplicate("b", &retained("c8y/b", "1")));
+        }
+
+        #[test]
+        fn forgets_the_retained_value_once_a_non_retained_message_is_forwarded() {
+            let mut cache = RetainedMessageCache::default();
+            let msg = retained("c8y/s/us", "101");
+            assert!(!cache.is_duplicate("s/us", &msg));
+            assert!(!cache.is_duplicate("s/us", &Publish::new("c8y/s/us", QoS::AtLeastOnce, "102")));
+            assert!(!cache.is_duplicate("s/us", &msg));
+        }
+    }
+
+    mod pending_acks {
+        use crate::PendingAck;
+        use crate::PendingAcks;
+        use rumqttc::Publish;
+        use rumqttc::QoS;
+        use std::time::Duration;
+        use std::time::Instant;
+
+        fn pending(since: Instant) -> PendingAck {
+            PendingAck {
+                forwarded: Publish::new("s/us", QoS::AtLeastOnce, "101"),
+                original: Publish::new("c8y/s/us", QoS::AtLeastOnce, "101"),
+                since,
+            }
+        }
+
+        #[test]
+        fn only_the_messages_waiting_for_too_long_are_expired() {
+            let mut acks = PendingAcks::default();
+            acks.messages
+                .insert(1, pending(Instant::now() - Duration::from_secs(10)));
+            acks.messages.insert(2, pending(Instant::now()));
+
+            let expired = acks.take_expired(Duration::from_secs(5));
+
+            assert_eq!(
+                expired.iter().map(|(pkid, _)| *pkid).collect::<Vec<_>>(),
+                vec![1]
+            );
+            assert!(acks.messages.contains_key(&2));
+        }
+
+        #[test]
+        fn expired_packet_ids_are_known_till_acknowledged() {
+            let mut acks = PendingAcks::default();
+            acks.messages
+                .insert(1, pending(Instant::now() - Duration::from_secs(10)));
+            acks.take_expired(Duration::from_secs(5));
+
+            assert!(acks.is_known(1));
+            assert!(matches!(acks.acknowledge(1), Some(None)));
+            assert!(!acks.is_known(1));
+            assert!(acks.acknowledge(1).is_none());
+        }
+    }
+
     mod have_same_content {
         use crate::have_same_content;
         use rumqttc::Publish;
@@ -944,4 +2276,48 @@ mod tests {
             assert!(!have_same_content(&msg, &msg2));
         }
     }
+
+    mod source_topic {
+        use crate::with_source_topic;
+        use serde_json::json;
+        use serde_json::Value;
+
+        #[test]
+        fn is_added_to_json_objects() {
+            let payload = with_source_topic(br#"{"temperature":23}"#, "src", "c8y/m").unwrap();
+            assert_eq!(
+                serde_json::from_slice::<Value>(&payload).unwrap(),
+                json!({"temperature": 23, "src": "c8y/m"})
+            );
+        }
+
+        #[test]
+        fn is_appended_without_reordering_the_other_fields() {
+            let payload =
+                with_source_topic(br#"{"time":1, "temperature":23} "#, "src", "c8y/m").unwrap();
+            assert_eq!(
+                std::str::from_utf8(&payload).unwrap(),
+                r#"{"time":1, "temperature":23,"src":"c8y/m"}"#
+            );
+
+            let payload = with_source_topic(b"{ }", "src", "c8y/m").unwrap();
+            assert_eq!(
+                std::str::from_utf8(&payload).unwrap(),
+                r#"{ "src":"c8y/m"}"#
+            );
+        }
+
+        #[test]
+        fn is_not_added_to_other_payloads() {
+            assert_eq!(with_source_topic(b"200,23", "src", "c8y/s/us"), None);
+            assert_eq!(with_source_topic(b"[1,2]", "src", "c8y/m"), None);
+            assert_eq!(with_source_topic(b"", "src", "c8y/m"), None);
+        }
+
+        #[test]
+        fn does_not_replace_the_source_topic_set_by_a_previous_bridge() {
+            let payload = br#"{"temperature":23,"src":"first/bridge"}"#;
+            assert_eq!(with_source_topic(payload, "src", "c8y/m"), None);
+        }
+    }
 }
@@ -0,0 +1,144 @@
+use std::num::NonZeroU32;
+use std::time::Duration;
+use std::time::Instant;
+use tracing::debug;
+
+/// Limits the rate of the messages forwarded by a half bridge, see [BridgeConfig::with_rate_limit]
+///
+/// [BridgeConfig::with_rate_limit]: crate::BridgeConfig::with_rate_limit
+pub(super) struct RateLimiter {
+    /// The token bucket of each target topic prefix, the longest prefixes first
+    buckets: Vec<(String, TokenBucket)>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &[(String, NonZeroU32)]) -> Self {
+        let now = Instant::now();
+        let mut buckets: Vec<_> = limits
+            .iter()
+            .map(|(prefix, max_per_second)| {
+                (prefix.clone(), TokenBucket::new(*max_per_second, now))
+            })
+            .collect();
+        buckets.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        RateLimiter { buckets }
+    }
+
+    /// Take a token for a message forwarded on the given target topic, returning how long this message has to be delayed
+    ///
+    /// A topic matching no rate-limited prefix is never delayed.
+    pub fn delay(&mut self, topic: &str) -> Duration {
+        let Some((prefix, bucket)) = self
+            .buckets
+            .iter_mut()
+            .find(|(prefix, _)| topic.starts_with(prefix.as_str()))
+        else {
+            return Duration::ZERO;
+        };
+
+        let delay = bucket.take(Instant::now());
+        if !delay.is_zero() {
+            debug!("Delaying by {delay:?} the message forwarded on {topic}, as rate-limited on {prefix:?}");
+        }
+        delay
+    }
+}
+
+/// A token bucket refilled at `rate` tokens per second, holding at most `rate` tokens
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: NonZeroU32, now: Instant) -> Self {
+        let rate = f64::from(rate.get());
+        TokenBucket {
+            rate,
+            tokens: rate,
+            last_refill: now,
+        }
+    }
+
+    /// Take a token, returning how long to wait for this token to be actually available
+    ///
+    /// The tokens taken in advance are a debt paid back by the next refills,
+    /// so successive callers are delayed one after the other.
+    fn take(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        self.last_refill = now;
+
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_burst_up_to_the_rate_is_not_delayed() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(NonZeroU32::new(5).unwrap(), start);
+
+        for _ in 0..5 {
+            assert_eq!(bucket.take(start), Duration::ZERO);
+        }
+        assert_eq!(bucket.take(start), Duration::from_millis(200));
+        assert_eq!(bucket.take(start), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn the_bucket_is_refilled_over_time() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(NonZeroU32::new(10).unwrap(), start);
+        for _ in 0..10 {
+            bucket.take(start);
+        }
+
+        // One token every 100 ms
+        let later = start + Duration::from_millis(300);
+        for _ in 0..3 {
+            assert_eq!(bucket.take(later), Duration::ZERO);
+        }
+        assert_eq!(bucket.take(later), Duration::from_millis(100));
+
+        // But no more than the rate
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..10 {
+            assert_eq!(bucket.take(much_later), Duration::ZERO);
+        }
+        assert_eq!(bucket.take(much_later), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn topics_are_limited_by_their_longest_prefix() {
+        let mut limiter = RateLimiter::new(&[
+            ("measurement/".to_string(), NonZeroU32::new(1000).unwrap()),
+            ("measurement/slow/".to_string(), NonZeroU32::new(1).unwrap()),
+        ]);
+
+        // The first message of a bucket is never delayed
+        assert_eq!(
+            limiter.delay("measurement/slow/temperature"),
+            Duration::ZERO
+        );
+        for _ in 0..100 {
+            assert_eq!(
+                limiter.delay("measurement/fast/temperature"),
+                Duration::ZERO
+            );
+            assert_eq!(limiter.delay("s/us"), Duration::ZERO);
+        }
+
+        let (_, slow) = &mut limiter.buckets[0];
+        assert!(slow.take(Instant::now()) > Duration::from_millis(500));
+    }
+}
@@ -23,4 +23,19 @@ impl TopicConverter {
                 None
             })
     }
+
+    /// Return `true` if the rule applied to this topic ignores the retained messages sent on subscription
+    pub fn ignores_retained_on_subscribe(&self, topic: &str) -> bool {
+        self.rule_for(topic)
+            .is_some_and(|rule| rule.ignores_retained_on_subscribe())
+    }
+
+    /// Return `true` if the messages received on this topic are acknowledged without waiting for the target
+    pub fn fast_acks(&self, topic: &str) -> bool {
+        self.rule_for(topic).is_some_and(|rule| rule.is_fast_ack())
+    }
+
+    fn rule_for(&self, topic: &str) -> Option<&BridgeRule> {
+        self.0.iter().find(|rule| rule.apply(topic).is_some())
+    }
 }
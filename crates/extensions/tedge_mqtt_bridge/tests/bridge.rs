@@ -1,28 +1,45 @@
 use anyhow::anyhow;
 use anyhow::Context;
+use bytes::BytesMut;
+use rumqttc::mqttbytes;
 use rumqttc::AsyncClient;
+use rumqttc::ConnAck;
+use rumqttc::ConnectReturnCode;
 use rumqttc::Event;
 use rumqttc::EventLoop;
 use rumqttc::Incoming;
 use rumqttc::MqttOptions;
 use rumqttc::Outgoing;
+use rumqttc::Packet;
+use rumqttc::PingResp;
+use rumqttc::PubAck;
 use rumqttc::Publish;
 use rumqttc::QoS;
+use rumqttc::SubAck;
+use rumqttc::SubscribeReasonCode;
 use rumqttd::Broker;
 use rumqttd::Config;
 use rumqttd::ConnectionSettings;
 use rumqttd::ConsoleSettings;
 use rumqttd::ServerSettings;
 use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::num::NonZeroUsize;
 use std::str::from_utf8;
 use std::time::Duration;
 use tedge_config::TEdgeConfig;
 use tedge_config::TEdgeConfigLocation;
+use tedge_mqtt_bridge::AckTimeoutAction;
 use tedge_mqtt_bridge::BridgeConfig;
+use tedge_mqtt_bridge::BridgeTransformer;
 use tedge_mqtt_bridge::MqttBridgeActorBuilder;
+use tedge_mqtt_bridge::MqttMessage;
+use tedge_mqtt_bridge::Topic;
 use tedge_test_utils::fs::TempTedgeDir;
+use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpListener;
+use tokio::net::TcpStream;
 use tokio::sync::oneshot;
 use tokio::time::sleep;
 use tokio::time::timeout;
@@ -42,7 +59,11 @@ fn new_broker_and_client(name: &str, port: u16) -> (AsyncClient, EventLoop) {
     AsyncClient::new(client_opts, 10)
 }
 
-async fn start_mqtt_bridge(local_port: u16, cloud_port: u16, rules: BridgeConfig) {
+async fn start_mqtt_bridge(
+    local_port: u16,
+    cloud_port: u16,
+    rules: BridgeConfig,
+) -> MqttBridgeActorBuilder {
     let cloud_config = MqttOptions::new("a-device-id", "127.0.0.1", cloud_port);
     let service_name = "tedge-mapper-test";
     let health_topic = format!("te/device/main/service/{service_name}/status/health")
@@ -56,7 +77,7 @@ async fn start_mqtt_bridge(local_port: u16, cloud_port: u16, rules: BridgeConfig
         rules,
         cloud_config,
     )
-    .await;
+    .await
 }
 
 const HEALTH: &str = "te/device/main/#";
@@ -451,6 +472,81 @@ async fn bidirectional_forwarding_avoids_infinite_loop() {
     timeout(DEFAULT_TIMEOUT, cloud).await.unwrap().unwrap();
 }
 
+#[tokio::test]
+async fn source_topic_is_carried_through_without_confusing_the_loop_breaker() {
+    let local_port = free_port().await;
+    let cloud_port = free_port().await;
+    let (local_client, mut local_ev_loop) = new_broker_and_client("local", local_port);
+    let (cloud_client, mut cloud_ev_loop) = new_broker_and_client("cloud", cloud_port);
+    let mut rules = BridgeConfig::new();
+    rules
+        .forward_bidirectionally("shadow/#", "aws/", "aws/things/my-device/")
+        .unwrap();
+    rules.forward_source_topic_as("sourceTopic");
+
+    start_mqtt_bridge(local_port, cloud_port, rules).await;
+
+    local_client
+        .subscribe(HEALTH, QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    wait_until_health_status_is("up", &mut local_ev_loop)
+        .await
+        .unwrap();
+    local_client.unsubscribe(HEALTH).await.unwrap();
+    local_client
+        .subscribe("aws/#", QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    await_subscription(&mut local_ev_loop).await;
+    cloud_client
+        .subscribe("aws/things/my-device/shadow/#", QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    await_subscription(&mut cloud_ev_loop).await;
+
+    cloud_client
+        .publish(
+            "aws/things/my-device/shadow/request",
+            QoS::AtLeastOnce,
+            false,
+            r#"{"state":"requested"}"#,
+        )
+        .await
+        .unwrap();
+
+    // The request, as updated with the source topic, is not forwarded back to the cloud
+    let cloud = tokio::spawn(async move {
+        loop {
+            let message = next_received_message(&mut cloud_ev_loop).await.unwrap();
+            let payload = from_utf8(&message.payload).unwrap();
+            if message.topic == "aws/things/my-device/shadow/response" {
+                assert_eq!(payload, "accepted");
+                break;
+            }
+            // Only the request as published by the cloud client is received
+            assert_eq!(payload, r#"{"state":"requested"}"#);
+        }
+    });
+
+    // The source topic is added to the JSON payloads
+    let request = next_received_message(&mut local_ev_loop).await.unwrap();
+    assert_eq!(request.topic, "aws/shadow/request");
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&request.payload).unwrap(),
+        serde_json::json!({"state": "requested", "sourceTopic": "aws/things/my-device/shadow/request"})
+    );
+
+    // The other payloads are forwarded unchanged
+    let _poll_local = EventPoller::run_in_bg(local_ev_loop);
+    local_client
+        .publish("aws/shadow/response", QoS::AtLeastOnce, false, "accepted")
+        .await
+        .unwrap();
+
+    timeout(DEFAULT_TIMEOUT, cloud).await.unwrap().unwrap();
+}
+
 async fn wait_until_health_status_is(
     status: &str,
     event_loop: &mut EventLoop,
@@ -470,8 +566,10 @@ async fn wait_until_health_status_is(
         let payload = from_utf8(&health.payload).context("decoding health payload")?;
         let json: serde_json::Value = serde_json::from_str(payload)?;
         match (status, json["status"].as_str()) {
-            ("up", Some("up")) | ("down", Some("down")) => break Ok(()),
-            (_, Some("up" | "down")) => continue,
+            ("up", Some("up")) | ("down", Some("down")) | ("unknown", Some("unknown")) => {
+                break Ok(())
+            }
+            (_, Some("up" | "down" | "unknown")) => continue,
             (_, Some(status)) => {
                 break Err(anyhow!(
                     "Unknown health status {status:?} in tedge-json: {payload}"
@@ -482,6 +580,636 @@ async fn wait_until_health_status_is(
     }
 }
 
+#[tokio::test]
+async fn bridge_ignores_retained_messages_received_on_subscription() {
+    std::env::set_var("RUST_LOG", "tedge_mqtt_bridge=info");
+    let _ = env_logger::try_init();
+    let local_broker_port = free_port().await;
+    let cloud_broker_port = free_port().await;
+    let (local, mut ev_local) = new_broker_and_client("local", local_broker_port);
+    let (cloud, mut ev_cloud) = new_broker_and_client("cloud", cloud_broker_port);
+
+    cloud.subscribe("status/#", QoS::AtLeastOnce).await.unwrap();
+    await_subscription(&mut ev_cloud).await;
+
+    // A stale state is retained by the local broker before the bridge connects
+    local
+        .publish("c8y/status/old", QoS::AtLeastOnce, true, "stale")
+        .await
+        .unwrap();
+    loop {
+        let event = timeout(DEFAULT_TIMEOUT, ev_local.poll()).await.unwrap();
+        if let Ok(Event::Incoming(Incoming::PubAck(_))) = event {
+            break;
+        }
+    }
+
+    let mut rules = BridgeConfig::new();
+    rules
+        .forward_from_local("status/#", "c8y/", "")
+        .unwrap()
+        .ignore_retained_on_subscribe(true);
+
+    start_mqtt_bridge(local_broker_port, cloud_broker_port, rules).await;
+    let _poll_local = EventPoller::run_in_bg(ev_local);
+
+    // The retained message sent to the bridge right after its ConnAck is not forwarded,
+    // while the live messages are forwarded once the bridge has subscribed
+    let forwarded = loop {
+        local
+            .publish("c8y/status/new", QoS::AtLeastOnce, false, "live")
+            .await
+            .unwrap();
+        if let Ok(Ok(msg)) = timeout(
+            Duration::from_millis(200),
+            next_received_message(&mut ev_cloud),
+        )
+        .await
+        {
+            break msg;
+        }
+    };
+    assert_eq!(forwarded.topic, "status/new");
+    assert_eq!(forwarded.payload, "live");
+}
+
+#[tokio::test]
+async fn bridge_name_is_published_on_the_health_topic() {
+    std::env::set_var("RUST_LOG", "tedge_mqtt_bridge=info");
+    let _ = env_logger::try_init();
+    let local_broker_port = free_port().await;
+    let cloud_broker_port = free_port().await;
+    let (local, mut ev_local) = new_broker_and_client("local", local_broker_port);
+    let (_cloud, ev_cloud) = new_broker_and_client("cloud", cloud_broker_port);
+    let _ev_cloud = EventPoller::run_in_bg(ev_cloud);
+
+    let mut rules = BridgeConfig::new();
+    rules.bridge_name("c8y");
+    rules.forward_from_local("s/us", "c8y/", "").unwrap();
+
+    start_mqtt_bridge(local_broker_port, cloud_broker_port, rules).await;
+
+    local.subscribe(HEALTH, QoS::AtLeastOnce).await.unwrap();
+    let health = next_received_message(&mut ev_local).await.unwrap();
+    let payload: serde_json::Value = serde_json::from_slice(&health.payload).unwrap();
+    assert_eq!(payload["bridge"], "c8y");
+}
+
+#[tokio::test]
+async fn bridge_rules_are_published_on_the_introspection_topic() {
+    std::env::set_var("RUST_LOG", "tedge_mqtt_bridge=info");
+    let _ = env_logger::try_init();
+    let local_broker_port = free_port().await;
+    let cloud_broker_port = free_port().await;
+    let (local, mut ev_local) = new_broker_and_client("local", local_broker_port);
+    let (_cloud, ev_cloud) = new_broker_and_client("cloud", cloud_broker_port);
+    let _ev_cloud = EventPoller::run_in_bg(ev_cloud);
+
+    let mut rules = BridgeConfig::new();
+    rules.bridge_name("c8y");
+    rules.forward_from_local("s/us", "c8y/", "").unwrap();
+    rules.forward_from_remote("s/ds", "c8y/", "").unwrap();
+    rules.publish_rules_on("te/device/main/service/tedge-mapper-bridge-c8y/rules");
+    let expected_rules = rules.rules_json();
+
+    start_mqtt_bridge(local_broker_port, cloud_broker_port, rules).await;
+
+    local
+        .subscribe(
+            "te/device/main/service/tedge-mapper-bridge-c8y/rules",
+            QoS::AtLeastOnce,
+        )
+        .await
+        .unwrap();
+    let published = next_received_message(&mut ev_local).await.unwrap();
+    let published_rules: serde_json::Value = serde_json::from_slice(&published.payload).unwrap();
+    assert_eq!(published_rules, expected_rules);
+}
+
+#[tokio::test]
+async fn bridge_forwards_messages_as_rewritten_by_the_transformer() {
+    struct Uppercase;
+    impl BridgeTransformer for Uppercase {
+        fn transform(&self, message: MqttMessage) -> Option<MqttMessage> {
+            let payload = message.payload_str().ok()?.to_uppercase();
+            Some(MqttMessage::new(&message.topic, payload).with_qos(message.qos))
+        }
+    }
+
+    let mut rules = BridgeConfig::new();
+    rules.forward_from_local("s/us", "c8y/", "").unwrap();
+    rules.transform_local_messages(Uppercase);
+
+    let forwarded = forward_local_messages(rules, &["a,fake,message"]).await;
+    assert_eq!(forwarded, vec!["A,FAKE,MESSAGE"]);
+}
+
+#[tokio::test]
+async fn bridge_does_not_forward_messages_dropped_by_the_transformer() {
+    struct DropDebugMessages;
+    impl BridgeTransformer for DropDebugMessages {
+        fn transform(&self, message: MqttMessage) -> Option<MqttMessage> {
+            let payload = message.payload_str().ok()?;
+            (!payload.starts_with("debug")).then_some(message)
+        }
+    }
+
+    let mut rules = BridgeConfig::new();
+    rules.forward_from_local("s/us", "c8y/", "").unwrap();
+    rules.transform_local_messages(DropDebugMessages);
+
+    let forwarded =
+        forward_local_messages(rules, &["debug,1", "info,2", "debug,3", "info,4"]).await;
+    assert_eq!(forwarded, vec!["info,2", "info,4"]);
+}
+
+/// Rewrite the topic of the messages with a `long,<length>` payload to a topic of that length
+struct LongTopics;
+impl BridgeTransformer for LongTopics {
+    fn transform(&self, message: MqttMessage) -> Option<MqttMessage> {
+        let payload = message.payload_str().ok()?;
+        let Some(length) = payload.strip_prefix("long,") else {
+            return Some(message);
+        };
+        let length: usize = length.parse().ok()?;
+        let topic = format!("s/us/{}", "x".repeat(length - 5));
+        Some(MqttMessage::new(&Topic::new_unchecked(&topic), payload).with_qos(message.qos))
+    }
+}
+
+#[tokio::test]
+async fn messages_rewritten_on_topics_longer_than_mqtt_allows_are_dropped() {
+    let mut rules = BridgeConfig::new();
+    rules.forward_from_local("s/us", "c8y/", "").unwrap();
+    rules.forward_from_remote("s/ds", "c8y/", "").unwrap();
+    rules.transform_local_messages(LongTopics);
+
+    let forwarded =
+        forward_local_messages(rules, &["200,1", "long,70000", "long,1000", "200,2"]).await;
+    assert_eq!(forwarded, vec!["200,1", "long,1000", "200,2"]);
+}
+
+#[tokio::test]
+async fn messages_rewritten_on_topics_longer_than_configured_are_dropped() {
+    let mut rules = BridgeConfig::new();
+    rules.forward_from_local("s/us", "c8y/", "").unwrap();
+    rules.forward_from_remote("s/ds", "c8y/", "").unwrap();
+    rules.transform_local_messages(LongTopics);
+    rules.max_topic_length(NonZeroUsize::new(32).unwrap());
+
+    let forwarded = forward_local_messages(rules, &["200,1", "long,33", "long,32", "200,2"]).await;
+    assert_eq!(forwarded, vec!["200,1", "long,32", "200,2"]);
+}
+
+#[tokio::test]
+async fn rate_limited_messages_are_delayed_but_forwarded_in_order() {
+    let mut rules = BridgeConfig::new();
+    rules.forward_from_local("s/us", "c8y/", "").unwrap();
+    // Keep the cloud connection up, as it fails to subscribe to an empty list of topics
+    rules.forward_from_remote("s/ds", "c8y/", "").unwrap();
+    rules.with_rate_limit("s/", NonZeroU32::new(10).unwrap());
+
+    // A burst of 10 messages, then one message every 100 ms
+    let payloads: Vec<String> = (0..20).map(|i| format!("200,temp,{i}")).collect();
+    let payloads: Vec<&str> = payloads.iter().map(String::as_str).collect();
+    let start = std::time::Instant::now();
+    let forwarded = forward_local_messages(rules, &payloads).await;
+
+    assert_eq!(forwarded, payloads);
+    assert!(start.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn bridge_acknowledges_messages_downgraded_to_qos_0_by_the_transformer() {
+    struct DowngradeToQoS0;
+    impl BridgeTransformer for DowngradeToQoS0 {
+        fn transform(&self, message: MqttMessage) -> Option<MqttMessage> {
+            Some(message.with_qos(QoS::AtMostOnce))
+        }
+    }
+
+    let mut rules = BridgeConfig::new();
+    rules.forward_from_local("s/us", "c8y/", "").unwrap();
+    rules.transform_local_messages(DowngradeToQoS0);
+
+    // More messages than the local broker sends without being acknowledged
+    let payloads: Vec<String> = (0..300).map(|i| format!("message {i}")).collect();
+    let payloads: Vec<&str> = payloads.iter().map(|payload| payload.as_str()).collect();
+    let forwarded = forward_local_messages(rules, &payloads).await;
+    assert_eq!(forwarded, payloads);
+}
+
+/// Publish messages on the local `c8y/s/us` topic and return the payloads received on the cloud `s/us/#` topics
+async fn forward_local_messages(rules: BridgeConfig, payloads: &[&str]) -> Vec<String> {
+    let local_broker_port = free_port().await;
+    let cloud_broker_port = free_port().await;
+    let (local, mut ev_local) = new_broker_and_client("local", local_broker_port);
+    let (cloud, mut ev_cloud) = new_broker_and_client("cloud", cloud_broker_port);
+
+    start_mqtt_bridge(local_broker_port, cloud_broker_port, rules).await;
+
+    local.subscribe(HEALTH, QoS::AtLeastOnce).await.unwrap();
+    wait_until_health_status_is("up", &mut ev_local)
+        .await
+        .unwrap();
+    local.unsubscribe(HEALTH).await.unwrap();
+    let _poll_local = EventPoller::run_in_bg(ev_local);
+
+    cloud.subscribe("s/us/#", QoS::AtLeastOnce).await.unwrap();
+    await_subscription(&mut ev_cloud).await;
+
+    for payload in payloads {
+        local
+            .publish("c8y/s/us", QoS::AtLeastOnce, false, *payload)
+            .await
+            .unwrap();
+    }
+    // Mark the end of the messages, expecting the transformers of the tests to forward it
+    local
+        .publish("c8y/s/us", QoS::AtLeastOnce, false, "end")
+        .await
+        .unwrap();
+
+    let mut forwarded = vec![];
+    loop {
+        let message = next_received_message(&mut ev_cloud).await.unwrap();
+        let payload = from_utf8(&message.payload).unwrap().to_string();
+        if payload.eq_ignore_ascii_case("end") {
+            break;
+        }
+        forwarded.push(payload);
+    }
+    forwarded
+}
+
+#[tokio::test]
+async fn fast_ack_messages_are_acknowledged_without_waiting_for_the_cloud() {
+    let local_ack = local_ack_while_cloud_never_acks(true).await;
+    assert_eq!(local_ack, Some(1));
+}
+
+#[tokio::test]
+async fn messages_are_acknowledged_only_once_acknowledged_by_the_cloud() {
+    let local_ack = local_ack_while_cloud_never_acks(false).await;
+    assert_eq!(local_ack, None);
+}
+
+#[tokio::test]
+async fn messages_are_acknowledged_anyway_on_cloud_ack_timeout() {
+    let mut rules = BridgeConfig::new();
+    rules
+        .forward_from_local("measurements/#", "c8y/", "")
+        .unwrap();
+    // Subscribing to the cloud, so the cloud connection is not closed on an empty subscription
+    rules.forward_from_remote("s/ds", "c8y/", "").unwrap();
+    rules.on_cloud_ack_timeout(Duration::from_millis(500), AckTimeoutAction::AckLocally);
+
+    let (mut local, mut cloud) = forward_to_non_acking_cloud(rules).await;
+
+    let local_ack = timeout(DEFAULT_TIMEOUT, local.next_puback()).await;
+    assert_eq!(local_ack.ok(), Some(1));
+
+    // The message is not published again
+    assert!(timeout(Duration::from_secs(1), cloud.next_publish())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn messages_are_republished_on_cloud_ack_timeout() {
+    let mut rules = BridgeConfig::new();
+    rules
+        .forward_from_local("measurements/#", "c8y/", "")
+        .unwrap();
+    // Subscribing to the cloud, so the cloud connection is not closed on an empty subscription
+    rules.forward_from_remote("s/ds", "c8y/", "").unwrap();
+    rules.on_cloud_ack_timeout(Duration::from_millis(500), AckTimeoutAction::Republish);
+
+    let (mut local, mut cloud) = forward_to_non_acking_cloud(rules).await;
+
+    let republished = cloud.next_publish().await;
+    assert_eq!(republished.topic, "measurements/temperature");
+    assert_eq!(republished.payload, "23.5");
+
+    // The original message is still waiting for the cloud to acknowledge it
+    assert!(timeout(Duration::from_secs(1), local.next_puback())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn forwarding_pauses_while_too_many_messages_are_waiting_for_an_ack() {
+    std::env::set_var("RUST_LOG", "tedge_mqtt_bridge=info");
+    let _ = env_logger::try_init();
+    let mut local = NonAckingBroker::start().await;
+    let mut cloud = NonAckingBroker::start().await;
+
+    let mut rules = BridgeConfig::new();
+    rules
+        .forward_from_local("measurements/#", "c8y/", "")
+        .unwrap();
+    rules.forward_from_remote("s/ds", "c8y/", "").unwrap();
+    rules.max_pending_messages(NonZeroUsize::new(2).unwrap());
+    start_mqtt_bridge(local.port, cloud.port, rules).await;
+    let local_bridge = local.subscriber("c8y/measurements/#").await;
+    let cloud_bridge = cloud.subscriber("s/ds").await;
+
+    for pkid in 1..=3 {
+        let mut publish = Publish::new(
+            "c8y/measurements/temperature",
+            QoS::AtLeastOnce,
+            format!("{pkid}"),
+        );
+        publish.pkid = pkid;
+        local_bridge.send(Packet::Publish(publish)).unwrap();
+    }
+
+    // Only two messages are forwarded, till acknowledged by the cloud
+    let first = cloud.next_publish().await;
+    assert_eq!(first.payload, "1");
+    let second = cloud.next_publish().await;
+    assert_eq!(second.payload, "2");
+    assert!(timeout(Duration::from_secs(1), cloud.next_publish())
+        .await
+        .is_err());
+
+    // Meanwhile, the bridge keeps polling the local connection, forwarding the messages received from the cloud
+    let mut publish = Publish::new("s/ds", QoS::AtLeastOnce, "cloud message");
+    publish.pkid = 1;
+    cloud_bridge.send(Packet::Publish(publish)).unwrap();
+    let forwarded = local.next_publish().await;
+    assert_eq!(forwarded.topic, "c8y/s/ds");
+
+    // Once a message is acknowledged, the forwarding resumes
+    cloud_bridge
+        .send(Packet::PubAck(PubAck::new(first.pkid)))
+        .unwrap();
+    let local_ack = timeout(DEFAULT_TIMEOUT, local.next_puback()).await;
+    assert_eq!(local_ack.ok(), Some(1));
+    let third = cloud.next_publish().await;
+    assert_eq!(third.payload, "3");
+}
+
+#[tokio::test]
+async fn diagnostic_dump_contains_the_messages_waiting_for_an_ack() {
+    std::env::set_var("RUST_LOG", "tedge_mqtt_bridge=info");
+    let _ = env_logger::try_init();
+    let mut local = NonAckingBroker::start().await;
+    let mut cloud = NonAckingBroker::start().await;
+
+    let mut rules = BridgeConfig::new();
+    rules
+        .forward_from_local("measurements/#", "c8y/", "")
+        .unwrap();
+    let diagnostics = start_mqtt_bridge(local.port, cloud.port, rules)
+        .await
+        .diagnostics();
+    let bridge = local.subscriber("c8y/measurements/#").await;
+
+    let mut publish = Publish::new("c8y/measurements/temperature", QoS::AtLeastOnce, "23.5");
+    publish.pkid = 1;
+    bridge.send(Packet::Publish(publish)).unwrap();
+    let forwarded = cloud.next_publish().await;
+
+    // The forwarded message is registered as pending once its packet id is known
+    let mut pending = diagnostics.dump();
+    for _ in 0..50 {
+        if !pending.is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+        pending = diagnostics.dump();
+    }
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].connection, "cloud");
+    assert_eq!(pending[0].pkid, forwarded.pkid);
+    assert_eq!(pending[0].topic, "measurements/temperature");
+}
+
+#[tokio::test]
+async fn metrics_count_the_messages_forwarded_and_acknowledged_on_each_connection() {
+    std::env::set_var("RUST_LOG", "tedge_mqtt_bridge=info");
+    let _ = env_logger::try_init();
+    let local_broker_port = free_port().await;
+    let cloud_broker_port = free_port().await;
+    let (local, mut ev_local) = new_broker_and_client("local", local_broker_port);
+    let (cloud, mut ev_cloud) = new_broker_and_client("cloud", cloud_broker_port);
+
+    let mut rules = BridgeConfig::new();
+    rules.forward_from_local("s/us", "c8y/", "").unwrap();
+    rules.forward_from_remote("s/ds", "c8y/", "").unwrap();
+    let diagnostics = start_mqtt_bridge(local_broker_port, cloud_broker_port, rules)
+        .await
+        .diagnostics();
+
+    local.subscribe(HEALTH, QoS::AtLeastOnce).await.unwrap();
+    wait_until_health_status_is("up", &mut ev_local)
+        .await
+        .unwrap();
+    local.unsubscribe(HEALTH).await.unwrap();
+    let _poll_local = EventPoller::run_in_bg(ev_local);
+    cloud.subscribe("s/us", QoS::AtLeastOnce).await.unwrap();
+    await_subscription(&mut ev_cloud).await;
+
+    for i in 0..3 {
+        local
+            .publish("c8y/s/us", QoS::AtLeastOnce, false, format!("200,{i}"))
+            .await
+            .unwrap();
+        next_received_message(&mut ev_cloud).await.unwrap();
+    }
+    let _poll_cloud = EventPoller::run_in_bg(ev_cloud);
+
+    // The original messages are acknowledged to the local broker once acknowledged by the cloud
+    let mut metrics = diagnostics.metrics();
+    for _ in 0..50 {
+        if metrics[1].finalized == 3 {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+        metrics = diagnostics.metrics();
+    }
+    let [local_metrics, cloud_metrics] = metrics.try_into().unwrap();
+    assert_eq!(local_metrics.connection, "local");
+    assert_eq!(local_metrics.received, 3);
+    assert_eq!(local_metrics.forwarded, 3);
+    assert_eq!(cloud_metrics.connection, "cloud");
+    assert_eq!(cloud_metrics.received, 0);
+    assert_eq!(cloud_metrics.published, 3);
+    assert_eq!(cloud_metrics.waiting_acks, 0);
+    assert_eq!(cloud_metrics.acknowledged, 3);
+    assert_eq!(cloud_metrics.finalized, 3);
+}
+
+/// Forward a QoS 1 message from the local broker to a cloud broker that never acknowledges messages,
+/// returning the packet id of the acknowledgement sent by the bridge to the local broker, if any
+async fn local_ack_while_cloud_never_acks(fast_ack: bool) -> Option<u16> {
+    let mut rules = BridgeConfig::new();
+    rules
+        .forward_from_local("measurements/#", "c8y/", "")
+        .unwrap()
+        .fast_ack(fast_ack);
+
+    let (mut local, _cloud) = forward_to_non_acking_cloud(rules).await;
+
+    timeout(Duration::from_secs(1), local.next_puback())
+        .await
+        .ok()
+}
+
+/// Forward a QoS 1 message from the local broker to a cloud broker that never acknowledges messages,
+/// returning the local and cloud brokers once the message received by the cloud broker
+async fn forward_to_non_acking_cloud(rules: BridgeConfig) -> (NonAckingBroker, NonAckingBroker) {
+    std::env::set_var("RUST_LOG", "tedge_mqtt_bridge=info");
+    let _ = env_logger::try_init();
+    let mut local = NonAckingBroker::start().await;
+    let mut cloud = NonAckingBroker::start().await;
+
+    start_mqtt_bridge(local.port, cloud.port, rules).await;
+    let bridge = local.subscriber("c8y/measurements/#").await;
+
+    let mut publish = Publish::new("c8y/measurements/temperature", QoS::AtLeastOnce, "23.5");
+    publish.pkid = 1;
+    bridge.send(Packet::Publish(publish)).unwrap();
+
+    let forwarded = cloud.next_publish().await;
+    assert_eq!(forwarded.topic, "measurements/temperature");
+    assert_eq!(forwarded.payload, "23.5");
+
+    (local, cloud)
+}
+
+/// A minimal MQTT broker that never acknowledges the messages published by its clients
+///
+/// This is used to hold the acknowledgements expected by the bridge
+/// and to observe the acknowledgements sent by the bridge.
+struct NonAckingBroker {
+    port: u16,
+    subscribers: tokio::sync::mpsc::UnboundedReceiver<(Vec<String>, PacketSender)>,
+    received: tokio::sync::mpsc::UnboundedReceiver<Packet>,
+}
+
+type PacketSender = tokio::sync::mpsc::UnboundedSender<Packet>;
+
+impl NonAckingBroker {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (subscribers_tx, subscribers) = tokio::sync::mpsc::unbounded_channel();
+        let (received_tx, received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(Self::serve(
+                    socket,
+                    subscribers_tx.clone(),
+                    received_tx.clone(),
+                ));
+            }
+        });
+
+        Self {
+            port,
+            subscribers,
+            received,
+        }
+    }
+
+    async fn serve(
+        socket: TcpStream,
+        subscribers: tokio::sync::mpsc::UnboundedSender<(Vec<String>, PacketSender)>,
+        received: PacketSender,
+    ) {
+        let (mut reader, mut writer) = socket.into_split();
+        let (outgoing, mut outgoing_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(packet) = outgoing_rx.recv().await {
+                let mut buffer = BytesMut::new();
+                match packet {
+                    Packet::ConnAck(connack) => connack.write(&mut buffer),
+                    Packet::SubAck(suback) => suback.write(&mut buffer),
+                    Packet::Publish(publish) => publish.write(&mut buffer),
+                    Packet::PubAck(puback) => puback.write(&mut buffer),
+                    Packet::PingResp => PingResp.write(&mut buffer),
+                    packet => panic!("Unexpected packet to send: {packet:?}"),
+                }
+                .unwrap();
+                if writer.write_all(&buffer).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut buffer = BytesMut::new();
+        loop {
+            let packet = match mqttbytes::v4::read(&mut buffer, 1024 * 1024) {
+                Ok(packet) => packet,
+                Err(mqttbytes::Error::InsufficientBytes(_)) => {
+                    match reader.read_buf(&mut buffer).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(_) => continue,
+                    }
+                }
+                Err(err) => panic!("Invalid MQTT packet: {err}"),
+            };
+            let response = match packet {
+                Packet::Connect(_) => {
+                    Packet::ConnAck(ConnAck::new(ConnectReturnCode::Success, false))
+                }
+                Packet::PingReq => Packet::PingResp,
+                Packet::Subscribe(subscribe) => {
+                    let return_codes = vec![
+                        SubscribeReasonCode::Success(QoS::AtLeastOnce);
+                        subscribe.filters.len()
+                    ];
+                    let filters = subscribe.filters.into_iter().map(|f| f.path).collect();
+                    let _ = subscribers.send((filters, outgoing.clone()));
+                    Packet::SubAck(SubAck::new(subscribe.pkid, return_codes))
+                }
+                packet => {
+                    let _ = received.send(packet);
+                    continue;
+                }
+            };
+            let _ = outgoing.send(response);
+        }
+    }
+
+    /// Wait for a client to subscribe to the given filter, returning a sender of packets to this client
+    async fn subscriber(&mut self, filter: &str) -> PacketSender {
+        loop {
+            let (filters, client) = timeout(DEFAULT_TIMEOUT, self.subscribers.recv())
+                .await
+                .expect("timed-out waiting for subscription")
+                .unwrap();
+            if filters.iter().any(|f| f == filter) {
+                return client;
+            }
+        }
+    }
+
+    async fn next_publish(&mut self) -> Publish {
+        loop {
+            let packet = timeout(DEFAULT_TIMEOUT, self.received.recv())
+                .await
+                .expect("timed-out waiting for a message")
+                .unwrap();
+            if let Packet::Publish(publish) = packet {
+                if !publish.topic.starts_with("te/") {
+                    return publish;
+                }
+            }
+        }
+    }
+
+    async fn next_puback(&mut self) -> u16 {
+        loop {
+            if let Some(Packet::PubAck(PubAck { pkid })) = self.received.recv().await {
+                return pkid;
+            }
+        }
+    }
+}
+
 /// A TCP proxy that allows the connection to be dropped upon request
 ///
 /// This is used to simulate a dropped connection between the client and MQTT broker
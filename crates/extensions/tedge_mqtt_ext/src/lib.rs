@@ -1,12 +1,18 @@
+mod retained;
+mod rpc;
 #[cfg(feature = "test-helpers")]
 pub mod test_helpers;
 #[cfg(test)]
 mod tests;
 
 use async_trait::async_trait;
+use mqtt_channel::Sink;
 use mqtt_channel::SinkExt;
 use mqtt_channel::StreamExt;
 use std::convert::Infallible;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::sync::Arc;
 use tedge_actors::futures::channel::mpsc;
 use tedge_actors::Actor;
 use tedge_actors::Builder;
@@ -25,9 +31,20 @@ pub type MqttConfig = mqtt_channel::Config;
 pub use mqtt_channel::DebugPayload;
 pub use mqtt_channel::MqttError;
 pub use mqtt_channel::MqttMessage;
+pub use mqtt_channel::PauseHandle;
 pub use mqtt_channel::QoS;
 pub use mqtt_channel::Topic;
 pub use mqtt_channel::TopicFilter;
+use retained::PendingReads;
+pub use retained::RetainedMessages;
+use retained::RetainedRead;
+pub use retained::DEFAULT_RETAINED_READ_TIMEOUT;
+pub use retained::DEFAULT_RETAINED_SETTLE_TIMEOUT;
+pub use rpc::MqttRpc;
+pub use rpc::MqttRpcError;
+
+/// The default maximum number of payload bytes logged for each message published or received
+pub const DEFAULT_LOG_PAYLOAD_MAX_LENGTH: usize = 256;
 
 pub struct MqttActorBuilder {
     mqtt_config: mqtt_channel::Config,
@@ -35,13 +52,60 @@ pub struct MqttActorBuilder {
     publish_sender: mpsc::Sender<MqttMessage>,
     pub subscriber_addresses: Vec<(TopicFilter, DynSender<MqttMessage>)>,
     signal_sender: mpsc::Sender<RuntimeRequest>,
+    pause_handle: PauseHandle,
+    publish_failure_policy: PublishFailurePolicy,
+    log_payload_max_length: usize,
+    retained_read_sender: mpsc::Sender<RetainedRead>,
+    retained_read_receiver: mpsc::Receiver<RetainedRead>,
+}
+
+/// What the MQTT actor does when an outgoing message cannot be published
+#[derive(Clone, Default)]
+pub enum PublishFailurePolicy {
+    /// Stop the MQTT actor with an error, hence the whole runtime (the default)
+    #[default]
+    FailFast,
+
+    /// Log the failure, notify the callback if any, and proceed with the next messages
+    LogAndContinue {
+        on_failure: Option<PublishFailureCallback>,
+    },
 }
 
+impl PublishFailurePolicy {
+    /// Handle the failure to publish a message
+    ///
+    /// Such a failure is either detected when the message is handed over to the connection,
+    /// or later reported by the connection when the MQTT client fails to publish the message.
+    fn handle(
+        &self,
+        message: &MqttMessage,
+        err: Box<dyn std::error::Error + Send + Sync>,
+        log_payload_max_length: usize,
+    ) -> Result<(), RuntimeError> {
+        match self {
+            PublishFailurePolicy::FailFast => Err(err.into()),
+            PublishFailurePolicy::LogAndContinue { on_failure } => {
+                let logged = LoggedMessage::new(message, log_payload_max_length);
+                tracing::error!(target: "MQTT pub", "Failed to publish {logged}: {err}");
+                if let Some(on_failure) = on_failure {
+                    on_failure(message, err.as_ref());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A callback invoked with the message that cannot be published and the cause of the failure
+pub type PublishFailureCallback = Arc<dyn Fn(&MqttMessage, &dyn std::error::Error) + Send + Sync>;
+
 impl MqttActorBuilder {
     pub fn new(config: mqtt_channel::Config) -> Self {
         let (publish_sender, publish_receiver) = mpsc::channel(10);
         let (signal_sender, signal_receiver) = mpsc::channel(10);
         let input_receiver = CombinedReceiver::new(publish_receiver, signal_receiver);
+        let (retained_read_sender, retained_read_receiver) = mpsc::channel(10);
 
         MqttActorBuilder {
             mqtt_config: config,
@@ -49,28 +113,92 @@ impl MqttActorBuilder {
             publish_sender,
             subscriber_addresses: Vec::new(),
             signal_sender,
+            pause_handle: PauseHandle::new(),
+            publish_failure_policy: PublishFailurePolicy::default(),
+            log_payload_max_length: DEFAULT_LOG_PAYLOAD_MAX_LENGTH,
+            retained_read_sender,
+            retained_read_receiver,
         }
     }
 
+    /// Set how the actor handles the failures to publish outgoing messages
+    ///
+    /// By default, the actor fails fast, stopping on the first failure.
+    pub fn set_publish_failure_policy(&mut self, policy: PublishFailurePolicy) {
+        self.publish_failure_policy = policy;
+    }
+
+    /// Set the maximum number of payload bytes logged for each message published or received
+    ///
+    /// Longer payloads are truncated in the logs, the number of omitted bytes being logged instead.
+    ///
+    /// By default, at most [DEFAULT_LOG_PAYLOAD_MAX_LENGTH] bytes are logged.
+    pub fn set_log_payload_max_length(&mut self, max_length: usize) {
+        self.log_payload_max_length = max_length;
+    }
+
+    /// A handle to pause and resume the delivery of the messages received from MQTT
+    ///
+    /// See [PauseHandle] for the effects on the MQTT session.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause_handle.clone()
+    }
+
+    /// A handle to clear and update the messages retained by the broker, e.g. those of a deregistered entity
+    ///
+    /// The handle uses the MQTT config of this builder, as set when the handle is created,
+    /// to read the retained messages to be cleared.
+    /// The updates are published using the MQTT actor, which also reads the values to be updated.
+    pub fn retained_messages(&self) -> RetainedMessages {
+        RetainedMessages::new(
+            &self.mqtt_config,
+            self.get_sender(),
+            self.retained_read_sender.clone(),
+        )
+    }
+
     pub(crate) fn build_actor(self) -> MqttActor {
-        let mut combined_topic_filter = TopicFilter::empty();
-        for (topic_filter, _) in self.subscriber_addresses.iter() {
-            combined_topic_filter.add_all(topic_filter.to_owned());
-        }
+        let topic_filters = self
+            .subscriber_addresses
+            .iter()
+            .map(|(topic_filter, _)| topic_filter.to_owned());
+        let patterns = minimal_subscription_set(topic_filters);
 
-        let removed = combined_topic_filter.remove_overlapping_patterns();
-        for pattern in combined_topic_filter.patterns() {
+        let mut combined_topic_filter = TopicFilter::empty();
+        for pattern in patterns.iter() {
             tracing::info!(target: "MQTT sub", "{pattern}");
+            combined_topic_filter.add_unchecked(pattern);
         }
-        for pattern in removed {
-            tracing::warn!(target: "MQTT sub", "ignoring overlapping subscription to {pattern}");
+        for (topic_filter, _) in self.subscriber_addresses.iter() {
+            for pattern in topic_filter.patterns() {
+                if !patterns.contains(pattern) {
+                    tracing::warn!(target: "MQTT sub", "ignoring overlapping subscription to {pattern}");
+                }
+            }
         }
 
         let mqtt_config = self.mqtt_config.with_subscriptions(combined_topic_filter);
-        MqttActor::new(mqtt_config, self.input_receiver, self.subscriber_addresses)
+        MqttActor::new(
+            mqtt_config,
+            self.input_receiver,
+            self.subscriber_addresses,
+            self.pause_handle,
+            self.publish_failure_policy,
+            self.log_payload_max_length,
+            self.retained_read_receiver,
+        )
     }
 }
 
+/// Compute the minimal set of patterns subscribing to all the topics matched by the given filters
+///
+/// A pattern is removed when overlapped by another one, e.g. `a/+` is removed when `#` is present.
+pub fn minimal_subscription_set(filters: impl IntoIterator<Item = TopicFilter>) -> Vec<String> {
+    let mut combined_topic_filter: TopicFilter = filters.into_iter().collect();
+    combined_topic_filter.remove_overlapping_patterns();
+    combined_topic_filter.patterns().clone()
+}
+
 impl AsMut<MqttConfig> for MqttActorBuilder {
     fn as_mut(&mut self) -> &mut MqttConfig {
         &mut self.mqtt_config
@@ -110,22 +238,67 @@ impl Builder<MqttActor> for MqttActorBuilder {
 
 pub struct FromPeers {
     input_receiver: CombinedReceiver<MqttMessage>,
+    publish_failure_policy: PublishFailurePolicy,
+    log_payload_max_length: usize,
 }
 
 pub struct ToPeers {
     peer_senders: Vec<(TopicFilter, DynSender<MqttMessage>)>,
+    log_payload_max_length: usize,
+    pending_reads: PendingReads,
+}
+
+/// Display a message as logged by the MQTT actor, with a payload truncated to a maximum number of bytes
+///
+/// e.g. `[te/device/main///e/big qos=1] {"text":"a very long... (1024 bytes truncated)`
+pub(crate) struct LoggedMessage<'a> {
+    message: &'a MqttMessage,
+    max_length: usize,
+}
+
+impl<'a> LoggedMessage<'a> {
+    pub(crate) fn new(message: &'a MqttMessage, max_length: usize) -> Self {
+        LoggedMessage {
+            message,
+            max_length,
+        }
+    }
+}
+
+impl Display for LoggedMessage<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let message = self.message;
+        let payload = match message.payload.as_str() {
+            Ok(payload) if payload.len() > self.max_length => payload,
+            _ => return Display::fmt(message, f),
+        };
+
+        let mut end = self.max_length;
+        while !payload.is_char_boundary(end) {
+            end -= 1;
+        }
+        write!(
+            f,
+            "[{} qos={}{}] {}... ({} bytes truncated)",
+            message.topic.name,
+            message.qos as u8,
+            if message.retain { " retained" } else { "" },
+            &payload[..end],
+            payload.len() - end
+        )
+    }
 }
 
 impl FromPeers {
-    async fn relay_messages_to(
-        &mut self,
-        outgoing_mqtt: &mut mpsc::UnboundedSender<MqttMessage>,
-    ) -> Result<(), RuntimeError> {
+    async fn relay_messages_to<S>(&mut self, outgoing_mqtt: &mut S) -> Result<(), RuntimeError>
+    where
+        S: Sink<MqttMessage> + Unpin,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
         while let Ok(Some(message)) = self.try_recv().await {
-            tracing::debug!(target: "MQTT pub", "{message}");
-            SinkExt::send(outgoing_mqtt, message)
-                .await
-                .map_err(Box::new)?;
+            let logged = LoggedMessage::new(&message, self.log_payload_max_length);
+            tracing::debug!(target: "MQTT pub", "{logged}");
+            self.publish(outgoing_mqtt, message).await?;
         }
 
         // On shutdown, first close input so no new messages can be pushed
@@ -133,12 +306,50 @@ impl FromPeers {
 
         // Then, publish all the messages awaiting to be sent over MQTT
         while let Some(message) = self.recv().await {
-            SinkExt::send(outgoing_mqtt, message)
-                .await
-                .map_err(Box::new)?;
+            self.publish(outgoing_mqtt, message).await?;
         }
         Ok(())
     }
+
+    /// Publish a message, handling a failure according to the publish failure policy
+    async fn publish<S>(
+        &self,
+        outgoing_mqtt: &mut S,
+        message: MqttMessage,
+    ) -> Result<(), RuntimeError>
+    where
+        S: Sink<MqttMessage> + Unpin,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        if let Err(err) = SinkExt::send(outgoing_mqtt, message.clone()).await {
+            self.publish_failure_policy.handle(
+                &message,
+                Box::new(err),
+                self.log_payload_max_length,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Handle the failures reported by the connection for the messages it failed to publish
+///
+/// The other errors, notably the connection errors, are already logged by the connection.
+/// This function returns only on a failure, when the policy is to fail fast.
+async fn handle_publish_failures(
+    errors: &mut mpsc::UnboundedReceiver<MqttError>,
+    publish_failure_policy: PublishFailurePolicy,
+    log_payload_max_length: usize,
+) -> Result<(), RuntimeError> {
+    while let Some(err) = errors.next().await {
+        if let MqttError::PublishFailure { message, .. } = &err {
+            let message = message.as_ref().clone();
+            publish_failure_policy.handle(&message, Box::new(err), log_payload_max_length)?;
+        }
+    }
+
+    // The connection is closed: the outcome is given by the loop publishing the messages
+    std::future::pending().await
 }
 
 impl ToPeers {
@@ -147,7 +358,9 @@ impl ToPeers {
         incoming_mqtt: &mut mpsc::UnboundedReceiver<MqttMessage>,
     ) -> Result<(), RuntimeError> {
         while let Some(message) = incoming_mqtt.next().await {
-            tracing::debug!(target: "MQTT recv", "{message}");
+            let logged = LoggedMessage::new(&message, self.log_payload_max_length);
+            tracing::debug!(target: "MQTT recv", "{logged}");
+            self.pending_reads.received(&message);
             self.send(message).await?;
         }
         Ok(())
@@ -182,6 +395,8 @@ pub struct MqttActor {
     mqtt_config: mqtt_channel::Config,
     from_peers: FromPeers,
     to_peers: ToPeers,
+    pause_handle: PauseHandle,
+    retained_reads: mpsc::Receiver<RetainedRead>,
 }
 
 impl MqttActor {
@@ -189,11 +404,25 @@ impl MqttActor {
         mqtt_config: mqtt_channel::Config,
         input_receiver: CombinedReceiver<MqttMessage>,
         peer_senders: Vec<(TopicFilter, DynSender<MqttMessage>)>,
+        pause_handle: PauseHandle,
+        publish_failure_policy: PublishFailurePolicy,
+        log_payload_max_length: usize,
+        retained_reads: mpsc::Receiver<RetainedRead>,
     ) -> Self {
         MqttActor {
             mqtt_config,
-            from_peers: FromPeers { input_receiver },
-            to_peers: ToPeers { peer_senders },
+            from_peers: FromPeers {
+                input_receiver,
+                publish_failure_policy,
+                log_payload_max_length,
+            },
+            to_peers: ToPeers {
+                peer_senders,
+                log_payload_max_length,
+                pending_reads: PendingReads::default(),
+            },
+            pause_handle,
+            retained_reads,
         }
     }
 }
@@ -206,7 +435,7 @@ impl Actor for MqttActor {
 
     async fn run(mut self) -> Result<(), RuntimeError> {
         let mut mqtt_client = tokio::select! {
-            connection = mqtt_channel::Connection::new(&self.mqtt_config) => {
+            connection = mqtt_channel::Connection::new_with_pause_handle(&self.mqtt_config, self.pause_handle.clone()) => {
                 connection.map_err(Box::new)?
             }
             Some(RuntimeRequest::Shutdown) = self.from_peers.recv_signal() => {
@@ -215,11 +444,45 @@ impl Actor for MqttActor {
             }
         };
 
-        tedge_utils::futures::select(
-            self.from_peers
-                .relay_messages_to(&mut mqtt_client.published),
-            self.to_peers.relay_messages_from(&mut mqtt_client.received),
-        )
-        .await
+        let retained_reads = retained::serve_retained_reads(
+            self.retained_reads,
+            mqtt_client.subscriptions.clone(),
+            self.mqtt_config.subscriptions.clone(),
+            self.to_peers.pending_reads.clone(),
+        );
+        let retained_reads = tokio::spawn(retained_reads);
+
+        // On shutdown, the pending messages are published before the incoming messages are dropped
+        let result = {
+            let failures = handle_publish_failures(
+                &mut mqtt_client.errors,
+                self.from_peers.publish_failure_policy.clone(),
+                self.from_peers.log_payload_max_length,
+            );
+            let outgoing = self
+                .from_peers
+                .relay_messages_to(&mut mqtt_client.published);
+            let incoming = self.to_peers.relay_messages_from(&mut mqtt_client.received);
+            tokio::pin!(outgoing);
+            tokio::pin!(incoming);
+            tokio::pin!(failures);
+            tokio::select! {
+                result = &mut outgoing => result,
+                result = &mut incoming => match result {
+                    // The connection no longer delivers messages, but can still publish
+                    Ok(()) => tokio::select! {
+                        result = &mut outgoing => result,
+                        result = &mut failures => result,
+                    },
+                    Err(err) => Err(err),
+                },
+                result = &mut failures => result,
+            }
+        };
+
+        // Wait for all the messages to be actually sent before closing the connection
+        mqtt_client.close().await;
+        retained_reads.abort();
+        result
     }
 }
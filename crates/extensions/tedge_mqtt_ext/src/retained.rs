@@ -0,0 +1,257 @@
+//! Clearing and updating retained messages
+use crate::MqttConfig;
+use crate::MqttError;
+use crate::MqttMessage;
+use crate::QoS;
+use crate::Topic;
+use crate::TopicFilter;
+use mqtt_channel::Connection;
+use mqtt_channel::SinkExt;
+use mqtt_channel::StreamExt;
+use mqtt_channel::SubscriptionHandle;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tedge_actors::futures::channel::mpsc;
+use tedge_actors::futures::channel::oneshot;
+use tedge_actors::ChannelError;
+use tedge_actors::CloneSender;
+use tedge_actors::DynSender;
+use tedge_actors::Sender;
+
+/// How long to wait for more retained messages, once the last one received
+pub const DEFAULT_RETAINED_SETTLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long to wait for the current retained value of a topic, before publishing a new value anyway
+pub const DEFAULT_RETAINED_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the broker to acknowledge the clearing messages, before disconnecting
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A handle to clear and update the messages retained by the broker, see [MqttActorBuilder::retained_messages]
+///
+/// The retained messages to be cleared are read over a dedicated short-lived connection,
+/// with no session, no last will and no initial message.
+/// The retained value of a single topic is read over the connection of the MQTT actor,
+/// which temporarily subscribes to that topic.
+///
+/// [MqttActorBuilder::retained_messages]: crate::MqttActorBuilder::retained_messages
+pub struct RetainedMessages {
+    mqtt_config: MqttConfig,
+    settle_timeout: Duration,
+    read_timeout: Duration,
+    publisher: DynSender<MqttMessage>,
+    reader: mpsc::Sender<RetainedRead>,
+}
+
+impl RetainedMessages {
+    pub(crate) fn new(
+        mqtt_config: &MqttConfig,
+        publisher: DynSender<MqttMessage>,
+        reader: mpsc::Sender<RetainedRead>,
+    ) -> Self {
+        let mut mqtt_config = mqtt_config.clone().with_no_session();
+        mqtt_config.subscriptions = TopicFilter::empty();
+        mqtt_config.last_will_message = None;
+        mqtt_config.initial_message = None;
+        RetainedMessages {
+            mqtt_config,
+            settle_timeout: DEFAULT_RETAINED_SETTLE_TIMEOUT,
+            read_timeout: DEFAULT_RETAINED_READ_TIMEOUT,
+            publisher,
+            reader,
+        }
+    }
+
+    /// Set how long to wait for more retained messages, once the last one received
+    pub fn with_settle_timeout(self, settle_timeout: Duration) -> Self {
+        RetainedMessages {
+            settle_timeout,
+            ..self
+        }
+    }
+
+    /// Set how long to wait for the current retained value of a topic, before publishing a new value anyway
+    pub fn with_read_timeout(self, read_timeout: Duration) -> Self {
+        RetainedMessages {
+            read_timeout,
+            ..self
+        }
+    }
+
+    /// Clear all the messages retained on the topics matching the given filter
+    ///
+    /// The retained messages are collected till none is received for the settle timeout,
+    /// then each is cleared by publishing an empty retained message on its exact topic.
+    /// The live messages received meanwhile are ignored.
+    ///
+    /// Return the number of retained messages that have been cleared.
+    pub async fn clear_retained(&self, topics: TopicFilter) -> Result<usize, MqttError> {
+        let (retained, mut connection) =
+            Connection::subscribe_and_settle(&self.mqtt_config, topics, self.settle_timeout)
+                .await?;
+
+        let mut cleared = 0;
+        for message in retained.iter().filter(|message| !message.is_empty()) {
+            tracing::debug!(target: "MQTT pub", "Clearing retained message on {}", message.topic.name);
+            let clear = MqttMessage::new(&message.topic, "")
+                .with_qos(QoS::AtLeastOnce)
+                .with_retain();
+            connection.published.send(clear).await?;
+            cleared += 1;
+        }
+
+        connection.close_gracefully(CLOSE_TIMEOUT).await;
+        Ok(cleared)
+    }
+
+    /// Publish a message, using the MQTT actor, unless its payload is the value already retained on its topic
+    ///
+    /// The current retained value is read first, using the MQTT actor too,
+    /// an empty payload standing for no retained value.
+    /// If this value cannot be read within the read timeout, the message is published anyway.
+    ///
+    /// Return `true` if the message has been published.
+    pub async fn publish_if_changed(&mut self, message: MqttMessage) -> Result<bool, ChannelError> {
+        let current = self.read_retained(&message.topic);
+        match tokio::time::timeout(self.read_timeout, current).await {
+            Ok(Ok(current)) if current == message.payload_bytes() => {
+                tracing::debug!(target: "MQTT pub", "Skipping unchanged retained message on {}", message.topic.name);
+                return Ok(false);
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => {
+                tracing::warn!(target: "MQTT pub", "Failed to read the retained value of {}, publishing anyway: {err}", message.topic.name);
+            }
+            Err(_) => {
+                tracing::warn!(target: "MQTT pub", "Failed to read the retained value of {} within {:?}, publishing anyway", message.topic.name, self.read_timeout);
+            }
+        }
+
+        self.publisher.send(message).await?;
+        Ok(true)
+    }
+
+    /// The payload currently retained on a topic, empty if none, as read by the MQTT actor
+    async fn read_retained(&self, topic: &Topic) -> Result<Vec<u8>, MqttError> {
+        let (reply, value) = oneshot::channel();
+        let read = RetainedRead {
+            topic: topic.clone(),
+            settle_timeout: self.settle_timeout,
+            reply,
+        };
+        self.reader
+            .clone()
+            .send(read)
+            .await
+            .map_err(|_| MqttError::SendOnClosedConnection)?;
+        value.await.map_err(|_| MqttError::SendOnClosedConnection)?
+    }
+}
+
+/// A request to the MQTT actor for the payload retained on a topic
+pub(crate) struct RetainedRead {
+    topic: Topic,
+    settle_timeout: Duration,
+    reply: oneshot::Sender<Result<Vec<u8>, MqttError>>,
+}
+
+/// The reads waiting, on each topic, for the retained message sent by the broker on subscription
+#[derive(Clone, Default)]
+pub(crate) struct PendingReads {
+    waiting: Arc<Mutex<HashMap<String, Vec<ReadReply>>>>,
+}
+
+type ReadReply = oneshot::Sender<Vec<u8>>;
+
+impl PendingReads {
+    /// Give a message received by the MQTT actor to the reads waiting for a value retained on its topic
+    pub(crate) fn received(&self, message: &MqttMessage) {
+        if !message.retain {
+            return;
+        }
+        let waiting = self.waiting.lock().unwrap().remove(&message.topic.name);
+        for reply in waiting.into_iter().flatten() {
+            let _ = reply.send(message.payload_bytes().to_vec());
+        }
+    }
+
+    fn wait_for(&self, topic: &Topic) -> oneshot::Receiver<Vec<u8>> {
+        let (reply, value) = oneshot::channel();
+        let mut waiting = self.waiting.lock().unwrap();
+        waiting.entry(topic.name.clone()).or_default().push(reply);
+        value
+    }
+
+    /// Forget the reads that are no longer waiting on a topic, notably on timeout
+    fn forget_abandoned(&self, topic: &Topic) {
+        let mut waiting = self.waiting.lock().unwrap();
+        if let Some(replies) = waiting.get_mut(&topic.name) {
+            replies.retain(|reply| !reply.is_canceled());
+            if replies.is_empty() {
+                waiting.remove(&topic.name);
+            }
+        }
+    }
+}
+
+/// Serve the reads of retained values, over the connection of the MQTT actor
+///
+/// For each read, the connection subscribes to the topic and waits for the settle timeout
+/// for the broker to send the retained message, before unsubscribing.
+/// The actor subscriptions are left unchanged: a topic subscribed by the actor is not unsubscribed.
+pub(crate) async fn serve_retained_reads(
+    mut reads: mpsc::Receiver<RetainedRead>,
+    subscriptions: SubscriptionHandle,
+    actor_subscriptions: TopicFilter,
+    pending_reads: PendingReads,
+) {
+    while let Some(read) = reads.next().await {
+        let subscriptions = subscriptions.clone();
+        let keep_subscribed = actor_subscriptions.patterns().contains(&read.topic.name);
+        let pending_reads = pending_reads.clone();
+        tokio::spawn(async move {
+            let value = read_retained(&read, &subscriptions, keep_subscribed, &pending_reads).await;
+            let _ = read.reply.send(value);
+        });
+    }
+}
+
+async fn read_retained(
+    read: &RetainedRead,
+    subscriptions: &SubscriptionHandle,
+    keep_subscribed: bool,
+    pending_reads: &PendingReads,
+) -> Result<Vec<u8>, MqttError> {
+    let topics = TopicFilter::new_unchecked(&read.topic.name);
+    // Registered before subscribing, as the retained message might be received before the acknowledgement
+    let value = pending_reads.wait_for(&read.topic);
+    let result = match subscriptions.subscribe(&topics).await {
+        Ok(()) => {
+            let value = tokio::time::timeout(read.settle_timeout, value).await;
+            Ok(value.ok().and_then(Result::ok).unwrap_or_default())
+        }
+        Err(err) => {
+            drop(value);
+            Err(err)
+        }
+    };
+    pending_reads.forget_abandoned(&read.topic);
+    if !keep_subscribed {
+        subscriptions.unsubscribe(&topics)?;
+    }
+    result
+}
+
+impl Clone for RetainedMessages {
+    fn clone(&self) -> Self {
+        RetainedMessages {
+            mqtt_config: self.mqtt_config.clone(),
+            settle_timeout: self.settle_timeout,
+            read_timeout: self.read_timeout,
+            publisher: self.publisher.sender_clone(),
+            reader: self.reader.clone(),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Request/response over MQTT
+use crate::MqttMessage;
+use crate::Topic;
+use crate::TopicFilter;
+use async_trait::async_trait;
+use mqtt_channel::Payload;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tedge_actors::futures::channel::oneshot;
+use tedge_actors::ChannelError;
+use tedge_actors::CloneSender;
+use tedge_actors::DynSender;
+use tedge_actors::MessageSink;
+use tedge_actors::MessageSource;
+use tedge_actors::Sender;
+
+/// Send requests over MQTT and await the correlated responses
+///
+/// Each request is given a generated correlation id and is published on `<request-topic>/<correlation-id>`.
+/// The response is expected on `<response-topic>/<correlation-id>`.
+///
+/// The subscriptions of the MQTT actor being set once built,
+/// a single subscription to `<response-topic>/+` is registered when the helper is created.
+/// A response is then forwarded to the request awaiting it, if any, and dropped otherwise.
+/// A request is forgotten as soon as responded or timed out, so late responses are dropped too.
+pub struct MqttRpc {
+    request_topic: String,
+    publisher: DynSender<MqttMessage>,
+    pending: PendingResponses,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MqttRpcError {
+    #[error("No response received for the request {correlation_id} within {timeout:?}")]
+    Timeout {
+        correlation_id: String,
+        timeout: Duration,
+    },
+
+    #[error(transparent)]
+    ChannelError(#[from] ChannelError),
+}
+
+type PendingResponses = Arc<Mutex<HashMap<String, oneshot::Sender<MqttMessage>>>>;
+
+impl MqttRpc {
+    /// Create a request/response helper, subscribing to the responses using the given MQTT actor builder
+    pub fn new(
+        mqtt: &mut (impl MessageSource<MqttMessage, TopicFilter> + MessageSink<MqttMessage>),
+        request_topic: &Topic,
+        response_topic: &Topic,
+    ) -> Self {
+        let pending = PendingResponses::default();
+        let dispatcher: DynSender<MqttMessage> = ResponseDispatcher {
+            response_topic: response_topic.name.clone(),
+            pending: pending.clone(),
+        }
+        .into();
+        mqtt.connect_sink(
+            TopicFilter::new_unchecked(&format!("{}/+", response_topic.name)),
+            &dispatcher,
+        );
+
+        MqttRpc {
+            request_topic: request_topic.name.clone(),
+            publisher: mqtt.get_sender(),
+            pending,
+        }
+    }
+
+    /// Publish a request and await the correlated response, at most for the given timeout
+    pub async fn request(
+        &mut self,
+        payload: impl Into<Payload>,
+        timeout: Duration,
+    ) -> Result<MqttMessage, MqttRpcError> {
+        let correlation_id = nanoid::nanoid!();
+        let topic = Topic::new_unchecked(&format!("{}/{correlation_id}", self.request_topic));
+
+        // Register the request before publishing it, not to miss a quick response
+        let (response_sender, response_receiver) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(correlation_id.clone(), response_sender);
+
+        let result = match self.publisher.send(MqttMessage::new(&topic, payload)).await {
+            Ok(()) => match tokio::time::timeout(timeout, response_receiver).await {
+                Ok(Ok(response)) => Ok(response),
+                Ok(Err(_)) => Err(ChannelError::ReceiveError().into()),
+                Err(_) => Err(MqttRpcError::Timeout {
+                    correlation_id: correlation_id.clone(),
+                    timeout,
+                }),
+            },
+            Err(err) => Err(err.into()),
+        };
+
+        self.pending.lock().unwrap().remove(&correlation_id);
+        result
+    }
+
+    #[cfg(test)]
+    pub(crate) fn pending_requests(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+}
+
+impl Clone for MqttRpc {
+    fn clone(&self) -> Self {
+        MqttRpc {
+            request_topic: self.request_topic.clone(),
+            publisher: self.publisher.sender_clone(),
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+/// Forward the responses received from MQTT to the pending requests
+#[derive(Clone)]
+struct ResponseDispatcher {
+    response_topic: String,
+    pending: PendingResponses,
+}
+
+#[async_trait]
+impl Sender<MqttMessage> for ResponseDispatcher {
+    async fn send(&mut self, message: MqttMessage) -> Result<(), ChannelError> {
+        let Some(correlation_id) = message
+            .topic
+            .name
+            .strip_prefix(&self.response_topic)
+            .and_then(|suffix| suffix.strip_prefix('/'))
+        else {
+            return Ok(());
+        };
+
+        let response_sender = self.pending.lock().unwrap().remove(correlation_id);
+        match response_sender {
+            Some(response_sender) => {
+                // The request might have been given up meanwhile
+                let _ = response_sender.send(message);
+            }
+            None => {
+                tracing::debug!(target: "MQTT rpc", "Ignoring uncorrelated response on {}", message.topic.name);
+            }
+        }
+        Ok(())
+    }
+}
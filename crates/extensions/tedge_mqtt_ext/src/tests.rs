@@ -1,5 +1,10 @@
 use crate::*;
+use futures::FutureExt;
 use mqtt_channel::Topic;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
 use std::time::Duration;
 use tedge_actors::Builder;
 use tedge_actors::SimpleMessageBox;
@@ -180,6 +185,467 @@ async fn communicate_over_mqtt() {
     assert_eq!(messages, vec!["1", "2", "3", "A", "B", "C"])
 }
 
+#[tokio::test]
+async fn paused_mqtt_actor_holds_messages_till_resumed() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = MqttConfig::default().with_port(broker.port);
+    let mut mqtt = MqttActorBuilder::new(mqtt_config);
+    let pause_handle = mqtt.pause_handle();
+
+    let topic = Topic::new_unchecked("messages/while/paused");
+    let mut client: MqttClient = MqttClientBuilder::new("Client", &topic)
+        .with_connection(&mut mqtt)
+        .build();
+
+    tokio::spawn(mqtt_actor(mqtt));
+
+    pause_handle.pause();
+    client.send(MqttMessage::new(&topic, "held")).await.unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), client.recv())
+            .await
+            .is_err(),
+        "no messages should be received while paused"
+    );
+
+    pause_handle.resume();
+    assert_eq!(
+        tokio::time::timeout(Duration::from_secs(1), client.recv())
+            .await
+            .expect("messages should be received once resumed"),
+        Some(MqttMessage::new(&topic, "held"))
+    );
+}
+
+#[tokio::test]
+async fn messages_published_right_before_shutdown_are_sent() {
+    // As many messages as can be queued by the actor
+    const MESSAGE_COUNT: usize = 10;
+
+    let broker = mqtt_tests::test_mqtt_broker();
+    let topic = "published/before/shutdown";
+    let mut messages = broker.messages_published_on(topic).await;
+
+    // The actor is run on its own runtime, which is dropped as soon as the actor returns,
+    // as when the process exits
+    std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let mqtt_config = MqttConfig::default().with_port(broker.port);
+                let mut mqtt = MqttActorBuilder::new(mqtt_config);
+                let ready = Topic::new_unchecked("actor/is/ready");
+                let mut client: MqttClient = MqttClientBuilder::new("Client", &ready)
+                    .with_connection(&mut mqtt)
+                    .build();
+                let mut signal_sender = mqtt.get_signal_sender();
+                let actor = tokio::spawn(mqtt_actor(mqtt));
+
+                // Wait for the actor to be connected
+                client.send(MqttMessage::new(&ready, "ping")).await.unwrap();
+                client.recv().await.unwrap();
+
+                // Queue the messages and request a shutdown, with no chance for the actor to run in-between:
+                // the messages fit in the actor input channel, so none of these sends is awaiting the actor
+                let topic = Topic::new_unchecked(topic);
+                for i in 0..MESSAGE_COUNT {
+                    client
+                        .send(MqttMessage::new(&topic, format!("message {i}")))
+                        .now_or_never()
+                        .expect("the message to be queued without waiting for the actor")
+                        .unwrap();
+                }
+                signal_sender
+                    .send(RuntimeRequest::Shutdown)
+                    .now_or_never()
+                    .expect("the shutdown request to be queued without waiting for the actor")
+                    .unwrap();
+                actor.await.unwrap();
+            });
+    });
+
+    let expected: Vec<_> = (0..MESSAGE_COUNT).map(|i| format!("message {i}")).collect();
+    mqtt_tests::assert_received(&mut messages, Duration::from_secs(5), expected).await;
+}
+
+#[tokio::test]
+async fn requests_are_correlated_to_their_responses() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = MqttConfig::default().with_port(broker.port);
+    let mut mqtt = MqttActorBuilder::new(mqtt_config);
+
+    let mut rpc = MqttRpc::new(
+        &mut mqtt,
+        &Topic::new_unchecked("rpc/echo/req"),
+        &Topic::new_unchecked("rpc/echo/res"),
+    );
+    let mut responder: MqttClient =
+        MqttClientBuilder::new("Responder", &TopicFilter::new_unchecked("rpc/echo/req/+"))
+            .with_connection(&mut mqtt)
+            .build();
+
+    tokio::spawn(mqtt_actor(mqtt));
+
+    // A fake responder, echoing the requests on the response topic with the same correlation id
+    tokio::spawn(async move {
+        while let Some(request) = responder.recv().await {
+            let correlation_id = request.topic.name.rsplit('/').next().unwrap();
+            let topic = Topic::new_unchecked(&format!("rpc/echo/res/{correlation_id}"));
+            let payload = format!("echo: {}", request.payload_str().unwrap());
+            responder
+                .send(MqttMessage::new(&topic, payload))
+                .await
+                .unwrap();
+        }
+    });
+
+    let mut other_rpc = rpc.clone();
+    let (first, second) = tokio::join!(
+        rpc.request("first", Duration::from_secs(5)),
+        other_rpc.request("second", Duration::from_secs(5)),
+    );
+    assert_eq!(first.unwrap().payload_str().unwrap(), "echo: first");
+    assert_eq!(second.unwrap().payload_str().unwrap(), "echo: second");
+    assert_eq!(rpc.pending_requests(), 0);
+}
+
+#[tokio::test]
+async fn unanswered_requests_time_out() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = MqttConfig::default().with_port(broker.port);
+    let mut mqtt = MqttActorBuilder::new(mqtt_config);
+
+    let mut rpc = MqttRpc::new(
+        &mut mqtt,
+        &Topic::new_unchecked("rpc/nobody/req"),
+        &Topic::new_unchecked("rpc/nobody/res"),
+    );
+    tokio::spawn(mqtt_actor(mqtt));
+
+    let err = rpc
+        .request("anyone?", Duration::from_millis(500))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, MqttRpcError::Timeout { timeout, .. } if timeout == Duration::from_millis(500))
+    );
+    assert_eq!(rpc.pending_requests(), 0);
+}
+
+#[tokio::test]
+async fn retained_messages_are_cleared() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = MqttConfig::default().with_port(broker.port);
+    for (topic, payload) in [
+        ("te/device/purged//", r#"{"@type":"child-device"}"#),
+        ("te/device/purged///m/", r#"{"unit":"°C"}"#),
+        ("te/device/kept//", r#"{"@type":"child-device"}"#),
+    ] {
+        broker
+            .publish_with_opts(topic, payload, mqtt_channel::QoS::AtLeastOnce, true)
+            .await
+            .unwrap();
+    }
+
+    let retained = MqttActorBuilder::new(mqtt_config.clone())
+        .retained_messages()
+        .with_settle_timeout(Duration::from_millis(200));
+    let cleared = retained
+        .clear_retained(TopicFilter::new_unchecked("te/device/purged/#"))
+        .await
+        .unwrap();
+    assert_eq!(cleared, 2);
+
+    // Only the messages retained on the other topics are left
+    let (left, _) = mqtt_channel::Connection::subscribe_and_settle(
+        &mqtt_config,
+        TopicFilter::new_unchecked("te/device/+/#"),
+        Duration::from_millis(200),
+    )
+    .await
+    .unwrap();
+    let left: Vec<_> = left
+        .iter()
+        .map(|message| message.topic.name.as_str())
+        .collect();
+    assert_eq!(left, vec!["te/device/kept//"]);
+}
+
+#[tokio::test]
+async fn unchanged_retained_values_are_not_published_again() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = MqttConfig::default().with_port(broker.port);
+    let topic = Topic::new_unchecked("te/device/unchanged///twin/firmware");
+    broker
+        .publish_with_opts(&topic.name, "1.0", mqtt_channel::QoS::AtLeastOnce, true)
+        .await
+        .unwrap();
+
+    let mqtt = MqttActorBuilder::new(mqtt_config.clone());
+    let mut retained = mqtt
+        .retained_messages()
+        .with_settle_timeout(Duration::from_millis(200));
+    tokio::spawn(mqtt_actor(mqtt));
+
+    let same = MqttMessage::new(&topic, "1.0").with_retain();
+    assert!(!retained.publish_if_changed(same).await.unwrap());
+
+    let changed = MqttMessage::new(&topic, "2.0").with_retain();
+    assert!(retained.publish_if_changed(changed.clone()).await.unwrap());
+
+    // The new value is published by the MQTT actor
+    let mut current = vec![];
+    for _ in 0..20 {
+        (current, _) = mqtt_channel::Connection::subscribe_and_settle(
+            &mqtt_config,
+            TopicFilter::new_unchecked(&topic.name),
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap();
+        if current.first().map(|message| message.payload_bytes()) == Some(b"2.0") {
+            break;
+        }
+    }
+    assert_eq!(current.first().unwrap().payload_str().unwrap(), "2.0");
+    assert!(!retained.publish_if_changed(changed).await.unwrap());
+}
+
+#[tokio::test]
+async fn messages_are_published_when_the_retained_value_cannot_be_read() {
+    let mqtt_config = MqttConfig::default().with_port(1);
+    let (publisher, mut published) = mpsc::channel(10);
+    let (reader, _no_actor) = mpsc::channel(10);
+    let mut retained = RetainedMessages::new(&mqtt_config, publisher.into(), reader)
+        .with_read_timeout(Duration::from_millis(200));
+
+    let message = MqttMessage::new(&Topic::new_unchecked("a/retained/topic"), "1.0").with_retain();
+    assert!(retained.publish_if_changed(message.clone()).await.unwrap());
+    assert_eq!(published.next().await, Some(message));
+}
+
+#[test]
+fn minimal_subscription_set_removes_overlapping_patterns() {
+    let filters = vec![
+        TopicFilter::new_unchecked("a/+"),
+        TopicFilter::new_unchecked("#"),
+    ];
+    assert_eq!(minimal_subscription_set(filters), vec!["#"]);
+
+    let filters = vec![
+        TopicFilter::new_unchecked("te/device/main///m/+"),
+        TopicFilter::new_unchecked("te/+/+/+/+/m/+"),
+        TopicFilter::new_unchecked("te/+/+/+/+/m/+"),
+    ];
+    assert_eq!(minimal_subscription_set(filters), vec!["te/+/+/+/+/m/+"]);
+}
+
+#[test]
+fn minimal_subscription_set_keeps_non_overlapping_patterns() {
+    let mut filter = TopicFilter::new_unchecked("te/+/+/+/+/cmd/+/+");
+    filter.add_unchecked("te/+/+/+/+/m/+");
+    let filters = vec![filter, TopicFilter::new_unchecked("c8y/s/ds")];
+
+    assert_eq!(
+        minimal_subscription_set(filters),
+        vec!["te/+/+/+/+/cmd/+/+", "te/+/+/+/+/m/+", "c8y/s/ds"]
+    );
+}
+
+#[test]
+fn short_payloads_are_logged_unchanged() {
+    let topic = Topic::new_unchecked("te/device/main///e/small");
+    let message = MqttMessage::new(&topic, "some text").with_retain();
+    let logged = LoggedMessage::new(&message, DEFAULT_LOG_PAYLOAD_MAX_LENGTH);
+
+    assert_eq!(logged.to_string(), message.to_string());
+}
+
+#[test]
+fn long_payloads_are_truncated_in_the_logs() {
+    let topic = Topic::new_unchecked("te/device/main///e/big");
+    let message = MqttMessage::new(&topic, "x".repeat(1000));
+    let logged = LoggedMessage::new(&message, DEFAULT_LOG_PAYLOAD_MAX_LENGTH);
+
+    assert_eq!(
+        logged.to_string(),
+        format!(
+            "[te/device/main///e/big qos=1] {}... (744 bytes truncated)",
+            "x".repeat(256)
+        )
+    );
+}
+
+#[test]
+fn payloads_are_truncated_on_a_char_boundary() {
+    let topic = Topic::new_unchecked("te/device/main///e/big");
+    let message = MqttMessage::new(&topic, "aéé").with_qos(QoS::AtMostOnce);
+    let logged = LoggedMessage::new(&message, 2);
+
+    assert_eq!(
+        logged.to_string(),
+        "[te/device/main///e/big qos=0] a... (4 bytes truncated)"
+    );
+}
+
+#[tokio::test]
+async fn publish_failures_stop_the_actor_by_default() {
+    let (mut from_peers, mut connection) =
+        flaky_connection(PublishFailurePolicy::default(), "boom", ["1", "boom", "3"]).await;
+
+    assert!(from_peers.relay_messages_to(&mut connection).await.is_err());
+    assert_eq!(connection.published, vec!["1"]);
+}
+
+#[tokio::test]
+async fn publish_failures_can_be_logged_and_ignored() {
+    let failures = Arc::new(Mutex::new(vec![]));
+    let on_failure = {
+        let failures = failures.clone();
+        Arc::new(move |message: &MqttMessage, _: &dyn std::error::Error| {
+            failures
+                .lock()
+                .unwrap()
+                .push(message.payload_str().unwrap().to_string())
+        })
+    };
+    let policy = PublishFailurePolicy::LogAndContinue {
+        on_failure: Some(on_failure),
+    };
+    let (mut from_peers, mut connection) =
+        flaky_connection(policy, "boom", ["1", "boom", "3"]).await;
+
+    assert!(from_peers.relay_messages_to(&mut connection).await.is_ok());
+    assert_eq!(connection.published, vec!["1", "3"]);
+    assert_eq!(*failures.lock().unwrap(), vec!["boom"]);
+}
+
+#[tokio::test]
+async fn publish_failures_reported_by_the_connection_are_notified() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = MqttConfig::default().with_port(broker.port);
+    let mut mqtt = MqttActorBuilder::new(mqtt_config);
+
+    let (failures, mut failed) = mpsc::unbounded();
+    mqtt.set_publish_failure_policy(PublishFailurePolicy::LogAndContinue {
+        on_failure: Some(Arc::new(
+            move |message: &MqttMessage, _: &dyn std::error::Error| {
+                let _ = failures.unbounded_send(message.topic.name.clone());
+            },
+        )),
+    });
+
+    let topic = Topic::new_unchecked("valid/topic");
+    let mut client: MqttClient = MqttClientBuilder::new("Client", &topic)
+        .with_connection(&mut mqtt)
+        .build();
+    tokio::spawn(mqtt_actor(mqtt));
+
+    // A message that is accepted by the actor, but that the MQTT client fails to publish
+    let invalid_topic = Topic {
+        name: "invalid/+/topic".to_string(),
+    };
+    client
+        .send(MqttMessage::new(&invalid_topic, "lost"))
+        .await
+        .unwrap();
+    client.send(MqttMessage::new(&topic, "sent")).await.unwrap();
+
+    // Is notified to the callback
+    assert_eq!(
+        tokio::time::timeout(Duration::from_secs(1), failed.next()).await,
+        Ok(Some("invalid/+/topic".to_string()))
+    );
+
+    // While the actor proceeds with the next messages
+    assert_eq!(
+        tokio::time::timeout(Duration::from_secs(1), client.recv()).await,
+        Ok(Some(MqttMessage::new(&topic, "sent")))
+    );
+}
+
+#[tokio::test]
+async fn publish_failures_reported_by_the_connection_stop_the_actor_by_default() {
+    let broker = mqtt_tests::test_mqtt_broker();
+    let mqtt_config = MqttConfig::default().with_port(broker.port);
+    let mut mqtt = MqttActorBuilder::new(mqtt_config);
+    let mut client: MqttClient =
+        MqttClientBuilder::new("Client", &Topic::new_unchecked("valid/topic"))
+            .with_connection(&mut mqtt)
+            .build();
+    let actor = tokio::spawn(mqtt.build().run());
+
+    let invalid_topic = Topic {
+        name: "invalid/+/topic".to_string(),
+    };
+    client
+        .send(MqttMessage::new(&invalid_topic, "lost"))
+        .await
+        .unwrap();
+
+    let result = tokio::time::timeout(Duration::from_secs(1), actor).await;
+    assert!(matches!(result, Ok(Ok(Err(_)))));
+}
+
+/// Build the peers of an MQTT connection that fails to publish the messages with a given payload
+async fn flaky_connection(
+    publish_failure_policy: PublishFailurePolicy,
+    failing_payload: &'static str,
+    payloads: impl IntoIterator<Item = &'static str>,
+) -> (FromPeers, FlakyConnection) {
+    let topic = Topic::new_unchecked("some/topic");
+    let (mut publish_sender, publish_receiver) = mpsc::channel(10);
+    let (_, signal_receiver) = mpsc::channel(10);
+    for payload in payloads {
+        SinkExt::send(&mut publish_sender, MqttMessage::new(&topic, payload))
+            .await
+            .unwrap();
+    }
+
+    let from_peers = FromPeers {
+        input_receiver: CombinedReceiver::new(publish_receiver, signal_receiver),
+        publish_failure_policy,
+        log_payload_max_length: DEFAULT_LOG_PAYLOAD_MAX_LENGTH,
+    };
+    let connection = FlakyConnection {
+        failing_payload,
+        published: vec![],
+    };
+    (from_peers, connection)
+}
+
+struct FlakyConnection {
+    failing_payload: &'static str,
+    published: Vec<String>,
+}
+
+impl Sink<MqttMessage> for FlakyConnection {
+    type Error = std::io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: MqttMessage) -> Result<(), Self::Error> {
+        let payload = message.payload_str().unwrap();
+        if payload == self.failing_payload {
+            return Err(std::io::Error::other("connection lost"));
+        }
+        self.get_mut().published.push(payload.to_string());
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
 async fn mqtt_actor(builder: MqttActorBuilder) {
     let mqtt_actor = builder.build();
     mqtt_actor.run().await.unwrap()